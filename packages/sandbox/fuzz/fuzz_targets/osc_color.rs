@@ -0,0 +1,12 @@
+//! Run with `cargo fuzz run osc_color` from `packages/sandbox`.
+
+#![no_main]
+
+use cmux_sandbox::mux::parse;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = parse::parse_any_osc_color(data);
+    let _ = parse::osc_color_reply(data);
+    let _ = parse::osc_color_spec(data);
+});
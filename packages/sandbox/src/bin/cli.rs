@@ -44,6 +44,15 @@ struct Cli {
     #[arg(long, env = "CMUX_SANDBOX_URL", default_value_t = default_base_url())]
     base_url: String,
 
+    /// Print color detection diagnostics for the current terminal and exit
+    #[arg(long)]
+    color_doctor: bool,
+
+    /// Command to run once the multiplexer opens, e.g. `--cmd "split-horizontal top"`.
+    /// Repeatable; runs after the `startup_commands` setting.
+    #[arg(long = "cmd", value_name = "COMMAND")]
+    cmd: Vec<String>,
+
     #[command(subcommand)]
     command: Option<Command>,
 }
@@ -378,6 +387,12 @@ async fn run() -> anyhow::Result<()> {
     if std::env::var("CMUX_DEBUG").is_ok() {
         eprintln!("cmux base url: {}", cli.base_url);
     }
+    if cli.color_doctor {
+        cmux_sandbox::mux::query_outer_terminal_colors();
+        cmux_sandbox::mux::capabilities::probe_capabilities();
+        println!("{}", cmux_sandbox::mux::colors::color_diagnostics());
+        return Ok(());
+    }
     let client = Client::builder()
         .timeout(Duration::from_secs(300))
         .no_proxy()
@@ -391,7 +406,9 @@ async fn run() -> anyhow::Result<()> {
             check_server_reachable(&client, &cli.base_url).await?;
             // Pass current working directory so the mux can upload it to the new sandbox
             let workspace_path = std::env::current_dir().ok();
-            cmux_sandbox::run_mux_tui(cli.base_url, workspace_path)
+            #[cfg(feature = "tracing")]
+            let _trace_guard = cmux_sandbox::trace::init_tracing_from_env();
+            cmux_sandbox::run_mux_tui(cli.base_url, workspace_path, cli.cmd)
                 .await
                 .map_err(|e| anyhow::anyhow!(e))?;
             return Ok(());
@@ -46,6 +46,86 @@ struct PtySessionHandle {
     child_pid: Option<u32>,
 }
 
+/// The connections attached to each PTY session, so an attach with
+/// `attach_detach_others` can detach the rest (like `tmux attach -d`).
+/// Each connection is told to detach through the channel it registered.
+#[derive(Default)]
+struct SessionClients {
+    clients: HashMap<PtySessionId, Vec<(Uuid, mpsc::UnboundedSender<PtySessionId>)>>,
+}
+
+impl SessionClients {
+    /// Record `client` as attached to `session_id`. With `detach_others`,
+    /// every other client of the session is told to detach first. Returns
+    /// how many were.
+    fn attach(
+        &mut self,
+        session_id: &PtySessionId,
+        client: Uuid,
+        detach_tx: mpsc::UnboundedSender<PtySessionId>,
+        detach_others: bool,
+    ) -> usize {
+        let clients = self.clients.entry(session_id.clone()).or_default();
+        clients.retain(|(id, _)| *id != client);
+        let mut detached = 0;
+        if detach_others {
+            for (_, other) in clients.drain(..) {
+                if other.send(session_id.clone()).is_ok() {
+                    detached += 1;
+                }
+            }
+        }
+        clients.push((client, detach_tx));
+        detached
+    }
+
+    /// Forget that `client` is attached to `session_id`.
+    fn detach(&mut self, session_id: &PtySessionId, client: Uuid) {
+        if let Some(clients) = self.clients.get_mut(session_id) {
+            clients.retain(|(id, _)| *id != client);
+            if clients.is_empty() {
+                self.clients.remove(session_id);
+            }
+        }
+    }
+
+    /// Forget every session of `client`, when its connection closes.
+    fn remove_client(&mut self, client: Uuid) {
+        self.clients.retain(|_, clients| {
+            clients.retain(|(id, _)| *id != client);
+            !clients.is_empty()
+        });
+    }
+
+    /// How many clients are attached to `session_id`.
+    fn client_count(&self, session_id: &PtySessionId) -> usize {
+        self.clients.get(session_id).map_or(0, Vec::len)
+    }
+}
+
+/// The shell sandbox sessions get as `$SHELL`.
+const LOGIN_SHELL: &str = "/bin/zsh";
+
+/// Wrap `command` as `shell -l -c '<cmd>'` so environment sourced from the
+/// user's profile (e.g. `PATH`) applies. Without a command, the shell itself
+/// is started as an interactive login shell.
+fn login_shell_command(shell: &str, command: Option<Vec<String>>) -> Vec<String> {
+    let mut argv = vec![shell.to_string(), "-l".to_string()];
+    match command {
+        Some(command) => {
+            let script: Vec<String> = command.iter().map(|arg| shell_quote(arg)).collect();
+            argv.extend(["-c".to_string(), script.join(" ")]);
+        }
+        None => argv.push("-i".to_string()),
+    }
+    argv
+}
+
+/// Quote `arg` as a single POSIX shell word.
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
 #[derive(Deserialize)]
 struct BwrapStatus {
     #[serde(rename = "child-pid")]
@@ -111,6 +191,7 @@ pub struct BubblewrapService {
     port: u16,
     next_index: AtomicUsize,
     docker: DockerConfig,
+    session_clients: Mutex<SessionClients>,
 }
 
 fn nsenter_args(pid: u32, workdir: Option<&str>, command: &[String]) -> Vec<String> {
@@ -159,6 +240,7 @@ impl BubblewrapService {
             port,
             next_index: AtomicUsize::new(0),
             docker,
+            session_clients: Mutex::new(SessionClients::default()),
         };
 
         service.setup_host_network().await?;
@@ -813,10 +895,12 @@ fi
         cols: u16,
         rows: u16,
         env: &[EnvVar],
+        client_env: &[EnvVar],
         tab_id: Option<String>,
         pane_id: Option<String>,
         output_tx: mpsc::UnboundedSender<MuxServerMessage>,
     ) -> SandboxResult<PtySessionHandle> {
+        let _span = crate::mux_span!("pane_spawn", ?tab_id, ?pane_id, cols, rows);
         let system = NativePtySystem::default();
         let pair = system
             .openpty(PtySize {
@@ -843,7 +927,7 @@ fi
             cmd.env("SSH_AUTH_SOCK", "/ssh-agent.sock");
         }
         // Apply sandbox-specific env vars
-        let session_env = session_env_with_overrides(env, tab_id, pane_id);
+        let session_env = session_env_with_overrides(env, client_env, tab_id, pane_id);
         for e in &session_env {
             cmd.env(&e.key, &e.value);
         }
@@ -851,7 +935,9 @@ fi
         let child = pair
             .slave
             .spawn_command(cmd)
-            .map_err(|e| SandboxError::Internal(format!("failed to spawn pty command: {e}")))?;
+            .map_err(|e| SandboxError::Internal(format!("failed to spawn pty command: {e}")));
+        crate::mux_event!(ok = child.is_ok(), "pane process spawned");
+        let child = child?;
         let child_pid = child.process_id();
         // Release slave so it closes when child exits
         drop(pair.slave);
@@ -1002,11 +1088,13 @@ fn build_effective_env(
 
 fn session_env_with_overrides(
     base_env: &[EnvVar],
+    client_env: &[EnvVar],
     tab_id: Option<String>,
     pane_id: Option<String>,
 ) -> Vec<EnvVar> {
     let mut merged: BTreeMap<String, String> = base_env
         .iter()
+        .chain(client_env)
         .map(|env| (env.key.clone(), env.value.clone()))
         .collect();
 
@@ -1637,6 +1725,11 @@ impl SandboxService for BubblewrapService {
         let sessions: Arc<Mutex<HashMap<PtySessionId, PtySessionHandle>>> =
             Arc::new(Mutex::new(HashMap::new()));
 
+        // Another connection attaching with `attach_detach_others` detaches
+        // this one from a session through this channel
+        let client_id = Uuid::new_v4();
+        let (detach_tx, mut detach_rx) = mpsc::unbounded_channel::<PtySessionId>();
+
         let (mut ws_write, mut ws_read) = socket.split();
 
         // Task to send output to WebSocket
@@ -1659,6 +1752,21 @@ impl SandboxService for BubblewrapService {
         loop {
             let msg = tokio::select! {
                 msg = ws_read.next() => msg,
+                Some(session_id) = detach_rx.recv() => {
+                    debug!(
+                        "mux_attach: detached from session={} by another client",
+                        session_id
+                    );
+                    if let Some(handle) = sessions.lock().await.remove(&session_id) {
+                        drop(handle);
+                        let _ = output_tx.send(MuxServerMessage::Detached {
+                            session_id,
+                            message: "Detached: session attached from another client"
+                                .to_string(),
+                        });
+                    }
+                    continue;
+                }
                 event = host_event_rx.recv() => {
                     if let Ok(event) = event {
                         match event {
@@ -1757,10 +1865,13 @@ impl SandboxService for BubblewrapService {
                             tty,
                             tab_id,
                             pane_id,
+                            env,
+                            attach_detach_others,
+                            login_shell,
                         } => {
                             debug!(
-                                "mux_attach: attach request session={} sandbox={} tty={}",
-                                session_id, sandbox_id, tty
+                                "mux_attach: attach request session={} sandbox={} tty={} detach_others={}",
+                                session_id, sandbox_id, tty, attach_detach_others
                             );
 
                             // Resolve sandbox
@@ -1789,8 +1900,13 @@ impl SandboxService for BubblewrapService {
                                 }
                             };
 
-                            let target_command = command
-                                .unwrap_or_else(|| vec!["/bin/zsh".to_string(), "-i".to_string()]);
+                            let target_command = if login_shell {
+                                login_shell_command(LOGIN_SHELL, command)
+                            } else {
+                                command.unwrap_or_else(|| {
+                                    vec!["/bin/zsh".to_string(), "-i".to_string()]
+                                })
+                            };
 
                             if !tty {
                                 // Non-PTY mode: not supported in mux for simplicity
@@ -1811,6 +1927,7 @@ impl SandboxService for BubblewrapService {
                                     cols,
                                     rows,
                                     &entry.env,
+                                    &env,
                                     tab_id,
                                     pane_id,
                                     output_tx.clone(),
@@ -1820,6 +1937,20 @@ impl SandboxService for BubblewrapService {
                                 Ok(handle) => {
                                     let mut sessions = sessions.lock().await;
                                     sessions.insert(session_id.clone(), handle);
+                                    let mut clients = self.session_clients.lock().await;
+                                    let detached = clients.attach(
+                                        &session_id,
+                                        client_id,
+                                        detach_tx.clone(),
+                                        attach_detach_others,
+                                    );
+                                    debug!(
+                                        "mux_attach: session={} has {} client(s), detached {}",
+                                        session_id,
+                                        clients.client_count(&session_id),
+                                        detached
+                                    );
+                                    drop(clients);
                                     let _ =
                                         output_tx.send(MuxServerMessage::Attached { session_id });
                                 }
@@ -1857,6 +1988,10 @@ impl SandboxService for BubblewrapService {
 
                         MuxClientMessage::Detach { session_id } => {
                             debug!("mux_attach: detach request session={}", session_id);
+                            self.session_clients
+                                .lock()
+                                .await
+                                .detach(&session_id, client_id);
                             let mut sessions = sessions.lock().await;
                             if let Some(handle) = sessions.remove(&session_id) {
                                 // Drop handle which will close channels and kill child
@@ -1963,6 +2098,7 @@ impl SandboxService for BubblewrapService {
             let mut sessions = sessions.lock().await;
             sessions.clear();
         }
+        self.session_clients.lock().await.remove_client(client_id);
 
         output_task.abort();
         Ok(())
@@ -2182,6 +2318,66 @@ mod tests {
         assert!(ns_if.len() <= 15);
     }
 
+    #[test]
+    fn attach_with_detach_others_detaches_existing_client() {
+        let session = "session-1".to_string();
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        let (first_tx, mut first_rx) = mpsc::unbounded_channel();
+        let (second_tx, mut second_rx) = mpsc::unbounded_channel();
+        let mut clients = SessionClients::default();
+
+        assert_eq!(clients.attach(&session, first, first_tx, false), 0);
+        assert_eq!(clients.attach(&session, second, second_tx, true), 1);
+
+        assert_eq!(first_rx.try_recv().ok(), Some(session.clone()));
+        assert!(second_rx.try_recv().is_err());
+        assert_eq!(clients.client_count(&session), 1);
+    }
+
+    #[test]
+    fn attach_without_detach_others_allows_co_attachment() {
+        let session = "session-1".to_string();
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        let (first_tx, mut first_rx) = mpsc::unbounded_channel();
+        let (second_tx, _second_rx) = mpsc::unbounded_channel();
+        let mut clients = SessionClients::default();
+
+        clients.attach(&session, first, first_tx, false);
+        assert_eq!(clients.attach(&session, second, second_tx, false), 0);
+
+        assert!(first_rx.try_recv().is_err());
+        assert_eq!(clients.client_count(&session), 2);
+
+        clients.detach(&session, first);
+        assert_eq!(clients.client_count(&session), 1);
+        clients.remove_client(second);
+        assert_eq!(clients.client_count(&session), 0);
+    }
+
+    #[test]
+    fn login_shell_wraps_command() {
+        assert_eq!(
+            login_shell_command(
+                "/bin/zsh",
+                Some(vec!["npm".into(), "run".into(), "dev".into()])
+            ),
+            ["/bin/zsh", "-l", "-c", "'npm' 'run' 'dev'"]
+        );
+        // Arguments survive the shell intact, quotes included
+        assert_eq!(
+            login_shell_command("/bin/bash", Some(vec!["echo".into(), "it's $HOME".into()])),
+            ["/bin/bash", "-l", "-c", r#"'echo' 'it'\''s $HOME'"#]
+        );
+    }
+
+    #[test]
+    fn login_shell_without_command_is_interactive() {
+        assert_eq!(
+            login_shell_command("/bin/zsh", None),
+            ["/bin/zsh", "-l", "-i"]
+        );
+    }
+
     #[test]
     fn nsenter_args_defaults() {
         let args = nsenter_args(123, None, &["ls".to_string()]);
@@ -2224,6 +2420,7 @@ mod tests {
 
         let session_env = session_env_with_overrides(
             &base_env,
+            &[],
             Some("new-tab".to_string()),
             Some("pane-1".to_string()),
         );
@@ -2237,4 +2434,31 @@ mod tests {
         assert_eq!(map.get("CMUX_TAB_ID"), Some(&"new-tab".to_string()));
         assert_eq!(map.get("CMUX_PANE_ID"), Some(&"pane-1".to_string()));
     }
+
+    #[test]
+    fn session_env_includes_client_env() {
+        let base_env = vec![EnvVar {
+            key: "PATH".to_string(),
+            value: "/bin".to_string(),
+        }];
+        let client_env = crate::mux::capabilities::TerminalCapabilities {
+            truecolor: true,
+            osc52: true,
+            ..Default::default()
+        }
+        .child_env();
+
+        let session_env = session_env_with_overrides(&base_env, &client_env, None, None);
+        let map: std::collections::HashMap<_, _> = session_env
+            .into_iter()
+            .map(|env| (env.key, env.value))
+            .collect();
+
+        assert_eq!(map.get("PATH"), Some(&"/bin".to_string()));
+        assert_eq!(
+            map.get("DMUX_VERSION"),
+            Some(&env!("CARGO_PKG_VERSION").to_string())
+        );
+        assert_eq!(map.get("DMUX_CAPS"), Some(&"truecolor,osc52".to_string()));
+    }
 }
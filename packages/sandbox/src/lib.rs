@@ -12,6 +12,7 @@ pub mod service;
 pub mod settings;
 pub mod sync_files;
 pub mod timing;
+pub mod trace;
 
 pub use acp_client::{
     load_last_provider, run_chat_tui, run_chat_tui_with_workspace_status, run_demo_tui,
@@ -267,6 +267,18 @@ pub enum MuxClientMessage {
         /// Pane identifier for this session (used for diagnostics/UI focus).
         #[serde(default)]
         pane_id: Option<String>,
+        /// Extra environment variables for the session's process.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        env: Vec<EnvVar>,
+        /// Detach every other client attached to this session first, like
+        /// `tmux attach -d`. Otherwise clients are attached side by side.
+        #[serde(default)]
+        attach_detach_others: bool,
+        /// Run the command through a login shell (`$SHELL -l -c '<cmd>'`), or
+        /// start the default shell as a login shell, so profile-sourced
+        /// environment such as `PATH` applies.
+        #[serde(default)]
+        login_shell: bool,
     },
     /// Send input data to a PTY session.
     Input {
@@ -316,6 +328,12 @@ pub enum MuxServerMessage {
     SandboxList { sandboxes: Vec<SandboxSummary> },
     /// PTY session was successfully attached.
     Attached { session_id: PtySessionId },
+    /// This client was detached from a session because another client
+    /// attached to it with `attach_detach_others`.
+    Detached {
+        session_id: PtySessionId,
+        message: String,
+    },
     /// Output data from a PTY session.
     Output {
         session_id: PtySessionId,
@@ -0,0 +1,602 @@
+//! Capabilities of the outer terminal, probed once at startup.
+//!
+//! `probe_capabilities` sends DA1 (primary device attributes), a DECRQM query
+//! for synchronized output and an OSC 11 query in a single round trip, then
+//! combines the replies with environment variables. Features ask
+//! `terminal_capabilities()` instead of probing on their own.
+
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use crate::models::EnvVar;
+use crate::mux::colors::{
+    probe_osc_set_support, set_reverse_video_detected, term_allows_osc_set, with_raw_mode,
+    CrosstermRawMode, OscIo, StdioOsc, OSC_QUERY_TIMEOUT,
+};
+
+/// DA1 attribute announcing clipboard access (OSC 52).
+const DA1_CLIPBOARD: u16 = 52;
+
+/// DEC private mode for synchronized output.
+const SYNC_OUTPUT_MODE: u16 = 2026;
+
+/// DEC private mode for reverse video (DECSCNM).
+const REVERSE_VIDEO_MODE: u16 = 5;
+
+/// Queries sent by the probe. OSC 11 goes last: terminals answer in order, so
+/// its ST-terminated reply marks the end of the batch.
+const PROBE_QUERIES: &[u8] = b"\x1b[c\x1b[?2026$p\x1b[?5$p\x1b]11;?\x1b\\";
+
+/// Terminals (by `$TERM_PROGRAM` or `$TERM` substring) with truecolor support.
+const TRUECOLOR_TERMINALS: &[&str] = &["iterm.app", "wezterm", "vscode", "ghostty", "kitty"];
+
+/// Terminals known to accept OSC 52 clipboard writes.
+const OSC52_TERMINALS: &[&str] = &[
+    "iterm.app",
+    "wezterm",
+    "ghostty",
+    "kitty",
+    "alacritty",
+    "foot",
+];
+
+/// Terminals known to render OSC 8 hyperlinks.
+const OSC8_TERMINALS: &[&str] = &["iterm.app", "wezterm", "vscode", "ghostty", "kitty", "foot"];
+
+/// Terminals known to support synchronized output, for when DECRQM goes unanswered.
+const SYNC_OUTPUT_TERMINALS: &[&str] = &["iterm.app", "wezterm", "ghostty", "kitty", "foot"];
+
+/// Terminals that speak kitty's OSC 21 color control protocol.
+const KITTY_COLOR_TERMINALS: &[&str] = &["kitty"];
+
+/// Terminals known to draw styled and colored underlines (SGR `4:n` and `58`).
+const STYLED_UNDERLINE_TERMINALS: &[&str] =
+    &["iterm.app", "wezterm", "vscode", "ghostty", "kitty", "foot"];
+
+/// Terminals known to blink text (SGR 5), for when terminfo can't be read.
+const BLINK_TERMINALS: &[&str] = &["xterm", "kitty", "wezterm", "foot"];
+
+/// Index of `blink` (enter_blink_mode) among terminfo's string capabilities.
+const TERMINFO_BLINK: usize = 26;
+
+/// Magic numbers of compiled terminfo entries: the original format, and the
+/// extended one with 32-bit numbers.
+const TERMINFO_MAGIC: i16 = 0o432;
+const TERMINFO_MAGIC_32BIT: i16 = 0o1036;
+
+/// Directories searched for compiled terminfo entries after `$TERMINFO`,
+/// `~/.terminfo` and `$TERMINFO_DIRS`.
+const TERMINFO_SYSTEM_DIRS: &[&str] = &[
+    "/etc/terminfo",
+    "/lib/terminfo",
+    "/usr/share/terminfo",
+    "/usr/lib/terminfo",
+];
+
+/// First VTE version with OSC 8 support.
+const VTE_OSC8_VERSION: u32 = 5000;
+
+/// First VTE version with styled and colored underlines.
+const VTE_STYLED_UNDERLINE_VERSION: u32 = 5102;
+
+/// Set on pane processes to dmux's version, e.g. `0.0.1`.
+pub const DMUX_VERSION_ENV: &str = "DMUX_VERSION";
+
+/// Set on pane processes to the outer terminal's capabilities (see
+/// `TerminalCapabilities::caps_list`).
+pub const DMUX_CAPS_ENV: &str = "DMUX_CAPS";
+
+static CAPABILITIES: OnceLock<TerminalCapabilities> = OnceLock::new();
+
+/// The environment-only guess, for when the terminal is never probed
+/// (headless and embedded use).
+static ENV_CAPABILITIES: OnceLock<TerminalCapabilities> = OnceLock::new();
+
+/// What the outer terminal supports.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TerminalCapabilities {
+    /// 24-bit color SGR sequences.
+    pub truecolor: bool,
+    /// Applying OSC 10/11 color changes (not just answering queries).
+    pub osc_set: bool,
+    /// OSC 52 clipboard writes.
+    pub osc52: bool,
+    /// Synchronized output (DEC mode 2026).
+    pub synchronized_output: bool,
+    /// OSC 8 hyperlinks.
+    pub osc8: bool,
+    /// Unicode box-drawing characters (a UTF-8 locale on a non-console terminal).
+    pub box_drawing: bool,
+    /// kitty's OSC 21 color control, for querying and setting the palette in bulk.
+    pub kitty_colors: bool,
+    /// Curly, dotted and dashed underlines (SGR `4:n`) and underline colors (SGR 58).
+    pub styled_underlines: bool,
+    /// Blinking text (SGR 5).
+    pub blink: bool,
+}
+
+/// Everything capability detection is based on.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProbeInputs {
+    /// DA1 attribute codes, if the terminal answered.
+    pub da1_attributes: Option<Vec<u16>>,
+    /// DECRQM answer for synchronized output, if the terminal answered.
+    pub sync_output_mode: Option<bool>,
+    /// Whether reverse video (DECSCNM) is on, if the terminal answered.
+    pub reverse_video_mode: Option<bool>,
+    /// Result of `probe_osc_set_support`, if it was conclusive.
+    pub osc_set_probe: Option<bool>,
+    pub term: Option<String>,
+    pub colorterm: Option<String>,
+    pub term_program: Option<String>,
+    pub vte_version: Option<u32>,
+    /// Effective locale (`LC_ALL`, then `LC_CTYPE`, then `LANG`).
+    pub locale: Option<String>,
+    /// Whether `$TERM`'s terminfo entry has the `blink` capability, if found.
+    pub terminfo_blink: Option<bool>,
+}
+
+impl ProbeInputs {
+    /// Inputs from environment variables alone, with no terminal probes.
+    pub fn from_env() -> Self {
+        let var = |name| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let term = var("TERM");
+        Self {
+            terminfo_blink: term.as_deref().and_then(terminfo_has_blink),
+            term,
+            colorterm: var("COLORTERM"),
+            term_program: var("TERM_PROGRAM"),
+            vte_version: var("VTE_VERSION").and_then(|v| v.parse().ok()),
+            locale: var("LC_ALL")
+                .or_else(|| var("LC_CTYPE"))
+                .or_else(|| var("LANG")),
+            ..Self::default()
+        }
+    }
+
+    /// Fill in the probe results by querying the terminal through `io`.
+    pub fn probe<T: OscIo>(&mut self, io: &mut T) {
+        if io.send(PROBE_QUERIES).is_err() {
+            return;
+        }
+        let reply = io.recv(OSC_QUERY_TIMEOUT);
+        self.da1_attributes = parse_da1_reply(&reply);
+        self.sync_output_mode = parse_decrqm_reply(&reply, SYNC_OUTPUT_MODE);
+        self.reverse_video_mode = parse_decrqm_mode_set(&reply, REVERSE_VIDEO_MODE);
+
+        // Only a terminal that answers OSC queries can be probed for OSC sets
+        if reply.windows(4).any(|window| window == b"]11;") {
+            self.osc_set_probe = probe_osc_set_support(io);
+        }
+    }
+
+    /// Whether `$TERM_PROGRAM` or `$TERM` names one of `terminals`.
+    fn is_one_of(&self, terminals: &[&str]) -> bool {
+        let program = self.term_program.as_deref().unwrap_or("").to_lowercase();
+        let term = self.term.as_deref().unwrap_or("").to_lowercase();
+        terminals
+            .iter()
+            .any(|&name| program == name || term.contains(name))
+    }
+}
+
+impl TerminalCapabilities {
+    /// Combine probe replies and environment variables into capabilities.
+    /// Probe answers take priority; the environment fills in what they can't tell.
+    pub fn from_inputs(inputs: &ProbeInputs) -> Self {
+        let term = inputs.term.as_deref();
+        let truecolor = matches!(inputs.colorterm.as_deref(), Some("truecolor" | "24bit"))
+            || term.is_some_and(|term| term.ends_with("-direct"))
+            || inputs.is_one_of(TRUECOLOR_TERMINALS);
+        let osc52 = inputs
+            .da1_attributes
+            .as_ref()
+            .is_some_and(|attrs| attrs.contains(&DA1_CLIPBOARD))
+            || inputs.is_one_of(OSC52_TERMINALS);
+        let osc8 = inputs.is_one_of(OSC8_TERMINALS)
+            || inputs.vte_version.is_some_and(|v| v >= VTE_OSC8_VERSION);
+        let locale = inputs.locale.as_deref().unwrap_or("").to_lowercase();
+        let box_drawing =
+            (locale.contains("utf-8") || locale.contains("utf8")) && term != Some("linux");
+
+        Self {
+            truecolor,
+            osc_set: inputs
+                .osc_set_probe
+                .unwrap_or_else(|| term_allows_osc_set(term)),
+            osc52,
+            synchronized_output: inputs
+                .sync_output_mode
+                .unwrap_or_else(|| inputs.is_one_of(SYNC_OUTPUT_TERMINALS)),
+            osc8,
+            box_drawing,
+            kitty_colors: inputs.is_one_of(KITTY_COLOR_TERMINALS),
+            styled_underlines: inputs.is_one_of(STYLED_UNDERLINE_TERMINALS)
+                || inputs
+                    .vte_version
+                    .is_some_and(|v| v >= VTE_STYLED_UNDERLINE_VERSION),
+            blink: inputs
+                .terminfo_blink
+                .unwrap_or_else(|| inputs.is_one_of(BLINK_TERMINALS)),
+        }
+    }
+
+    /// The supported capabilities as a comma-separated list, in this order:
+    /// `truecolor`, `osc_set`, `osc52`, `sync`, `osc8`, `box_drawing`,
+    /// `kitty_colors`, `styled_underlines`, `blink`. Unsupported ones are left out, so
+    /// the list is empty when nothing is supported. Names are never reused,
+    /// and new ones are only appended, so tools can split on `,` and look
+    /// names up.
+    pub fn caps_list(&self) -> String {
+        [
+            (self.truecolor, "truecolor"),
+            (self.osc_set, "osc_set"),
+            (self.osc52, "osc52"),
+            (self.synchronized_output, "sync"),
+            (self.osc8, "osc8"),
+            (self.box_drawing, "box_drawing"),
+            (self.kitty_colors, "kitty_colors"),
+            (self.styled_underlines, "styled_underlines"),
+            (self.blink, "blink"),
+        ]
+        .into_iter()
+        .filter_map(|(supported, name)| supported.then_some(name))
+        .collect::<Vec<_>>()
+        .join(",")
+    }
+
+    /// Environment variables that tell a pane's processes they run under
+    /// dmux: `DMUX_VERSION` and `DMUX_CAPS`.
+    pub fn child_env(&self) -> Vec<EnvVar> {
+        vec![
+            EnvVar {
+                key: DMUX_VERSION_ENV.to_string(),
+                value: env!("CARGO_PKG_VERSION").to_string(),
+            },
+            EnvVar {
+                key: DMUX_CAPS_ENV.to_string(),
+                value: self.caps_list(),
+            },
+        ]
+    }
+}
+
+/// Probe the real terminal and store the result for `terminal_capabilities`.
+///
+/// Like `query_outer_terminal_colors`, this must be called BEFORE entering the
+/// alternate screen. Later calls return the first result without probing again.
+pub fn probe_capabilities() -> TerminalCapabilities {
+    if let Some(capabilities) = CAPABILITIES.get() {
+        return *capabilities;
+    }
+
+    let mut inputs = ProbeInputs::from_env();
+    with_raw_mode(&mut CrosstermRawMode, || inputs.probe(&mut StdioOsc));
+    set_reverse_video_detected(inputs.reverse_video_mode == Some(true));
+    *CAPABILITIES.get_or_init(|| TerminalCapabilities::from_inputs(&inputs))
+}
+
+/// The startup probe's result, or `None` if the terminal hasn't been probed.
+pub fn probed_capabilities() -> Option<TerminalCapabilities> {
+    CAPABILITIES.get().copied()
+}
+
+/// The outer terminal's capabilities: the startup probe's result, or an
+/// environment-only guess if no probe has run.
+pub fn terminal_capabilities() -> TerminalCapabilities {
+    CAPABILITIES.get().copied().unwrap_or_else(|| {
+        // Built once: it reads terminfo from disk, and this runs every frame
+        *ENV_CAPABILITIES
+            .get_or_init(|| TerminalCapabilities::from_inputs(&ProbeInputs::from_env()))
+    })
+}
+
+/// Whether the compiled terminfo entry for `term` has the `blink` capability,
+/// or `None` if no entry could be found and read.
+fn terminfo_has_blink(term: &str) -> Option<bool> {
+    let first = term.chars().next()?;
+    let home = std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".terminfo"));
+    let extra = std::env::var("TERMINFO_DIRS").unwrap_or_default();
+    let dirs = std::env::var_os("TERMINFO")
+        .map(PathBuf::from)
+        .into_iter()
+        .chain(home)
+        .chain(
+            extra
+                .split(':')
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from),
+        )
+        .chain(TERMINFO_SYSTEM_DIRS.iter().map(PathBuf::from));
+    for dir in dirs {
+        // Entries are filed by first letter, or its hex code on macOS
+        for subdir in [first.to_string(), format!("{:x}", first as u32)] {
+            if let Ok(data) = std::fs::read(dir.join(&subdir).join(term)) {
+                return parse_terminfo_blink(&data);
+            }
+        }
+    }
+    None
+}
+
+/// Whether a compiled terminfo entry defines `blink`, or `None` if `data`
+/// isn't a terminfo entry.
+fn parse_terminfo_blink(data: &[u8]) -> Option<bool> {
+    let header = |index: usize| {
+        let bytes = data.get(index * 2..index * 2 + 2)?;
+        Some(i16::from_le_bytes([bytes[0], bytes[1]]))
+    };
+    let number_size = match header(0)? {
+        TERMINFO_MAGIC => 2,
+        TERMINFO_MAGIC_32BIT => 4,
+        _ => return None,
+    };
+    let count = |index| header(index).and_then(|n| usize::try_from(n).ok());
+    let (names, bools, numbers, strings) = (count(1)?, count(2)?, count(3)?, count(4)?);
+    if strings <= TERMINFO_BLINK {
+        return Some(false);
+    }
+    // Numbers start on an even offset
+    let mut offset = 12 + names + bools;
+    offset += offset % 2;
+    offset += numbers * number_size + TERMINFO_BLINK * 2;
+    let bytes = data.get(offset..offset + 2)?;
+    // -1 means absent and -2 cancelled; both mean no blink
+    Some(i16::from_le_bytes([bytes[0], bytes[1]]) >= 0)
+}
+
+/// Parse a DA1 reply (`CSI ? attr ; attr ... c`) out of `reply`.
+fn parse_da1_reply(reply: &[u8]) -> Option<Vec<u16>> {
+    csi_private_replies(reply)
+        .find_map(|(params, rest)| rest.starts_with(b"c").then_some(params))
+        .map(|params| {
+            params
+                .split(';')
+                .filter_map(|attr| attr.parse().ok())
+                .collect()
+        })
+}
+
+/// Parse a DECRQM reply (`CSI ? mode ; status $ y`) for `mode` out of `reply`.
+/// Status 1-3 mean the mode is recognised; 0 and 4 mean it isn't supported.
+fn parse_decrqm_reply(reply: &[u8], mode: u16) -> Option<bool> {
+    decrqm_status(reply, mode).map(|status| matches!(status, "1" | "2" | "3"))
+}
+
+/// Whether `mode` is currently set according to its DECRQM reply: status 1
+/// and 3 mean set, 2 and 4 reset. `None` if unanswered or not recognised.
+fn parse_decrqm_mode_set(reply: &[u8], mode: u16) -> Option<bool> {
+    match decrqm_status(reply, mode)? {
+        "1" | "3" => Some(true),
+        "2" | "4" => Some(false),
+        _ => None,
+    }
+}
+
+/// The status field of the DECRQM reply for `mode` in `reply`.
+fn decrqm_status(reply: &[u8], mode: u16) -> Option<&str> {
+    let prefix = format!("{};", mode);
+    csi_private_replies(reply).find_map(|(params, rest)| {
+        let status = params.strip_prefix(&prefix)?;
+        rest.starts_with(b"$y").then_some(status)
+    })
+}
+
+/// Each `CSI ?` sequence in `reply`, as its parameter string and the bytes after it.
+fn csi_private_replies(reply: &[u8]) -> impl Iterator<Item = (&str, &[u8])> {
+    (0..reply.len()).filter_map(move |start| {
+        let after = reply[start..].strip_prefix(b"\x1b[?")?;
+        let len = after
+            .iter()
+            .take_while(|&&b| b.is_ascii_digit() || b == b';')
+            .count();
+        let params = std::str::from_utf8(&after[..len]).ok()?;
+        Some((params, &after[len..]))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Terminal that answers the batched probe with a fixed reply.
+    struct MockProbeTerminal {
+        reply: &'static [u8],
+        pending: Vec<u8>,
+    }
+
+    impl OscIo for MockProbeTerminal {
+        fn send(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+            if bytes == PROBE_QUERIES {
+                self.pending = self.reply.to_vec();
+            }
+            Ok(())
+        }
+
+        fn recv(&mut self, _timeout: Duration) -> Vec<u8> {
+            std::mem::take(&mut self.pending)
+        }
+    }
+
+    #[test]
+    fn probe_reads_da1_and_decrqm_replies() {
+        let mut io = MockProbeTerminal {
+            reply: b"\x1b[?62;22;52c\x1b[?2026;2$y",
+            pending: Vec::new(),
+        };
+        let mut inputs = ProbeInputs::default();
+        inputs.probe(&mut io);
+
+        assert_eq!(inputs.da1_attributes, Some(vec![62, 22, 52]));
+        assert_eq!(inputs.sync_output_mode, Some(true));
+        // No OSC 11 reply, so the set probe is skipped
+        assert_eq!(inputs.osc_set_probe, None);
+
+        let caps = TerminalCapabilities::from_inputs(&inputs);
+        assert!(caps.osc52);
+        assert!(caps.synchronized_output);
+        assert!(!caps.truecolor);
+    }
+
+    #[test]
+    fn probe_answers_override_environment() {
+        let inputs = ProbeInputs {
+            da1_attributes: Some(vec![1, 2]),
+            sync_output_mode: Some(false),
+            osc_set_probe: Some(false),
+            term: Some("xterm-kitty".to_string()),
+            ..ProbeInputs::default()
+        };
+        let caps = TerminalCapabilities::from_inputs(&inputs);
+        // kitty is on the allowlists, but the terminal said otherwise
+        assert!(!caps.synchronized_output);
+        assert!(!caps.osc_set);
+        // DA1 doesn't cover OSC 52 for kitty, so the allowlist still applies
+        assert!(caps.osc52);
+        assert!(caps.truecolor);
+        assert!(caps.kitty_colors);
+        assert!(caps.styled_underlines);
+    }
+
+    #[test]
+    fn environment_alone_assembles_capabilities() {
+        let inputs = ProbeInputs {
+            term: Some("xterm-256color".to_string()),
+            colorterm: Some("truecolor".to_string()),
+            vte_version: Some(6800),
+            locale: Some("en_US.UTF-8".to_string()),
+            ..ProbeInputs::default()
+        };
+        assert_eq!(
+            TerminalCapabilities::from_inputs(&inputs),
+            TerminalCapabilities {
+                truecolor: true,
+                osc_set: true,
+                osc52: false,
+                synchronized_output: false,
+                osc8: true,
+                box_drawing: true,
+                kitty_colors: false,
+                styled_underlines: true,
+                blink: true,
+            }
+        );
+
+        let console = ProbeInputs {
+            term: Some("linux".to_string()),
+            locale: Some("C.UTF-8".to_string()),
+            ..ProbeInputs::default()
+        };
+        assert_eq!(
+            TerminalCapabilities::from_inputs(&console),
+            TerminalCapabilities::default()
+        );
+    }
+
+    #[test]
+    fn probe_detects_reverse_video() {
+        let mut io = MockProbeTerminal {
+            reply: b"\x1b[?62c\x1b[?2026;2$y\x1b[?5;1$y",
+            pending: Vec::new(),
+        };
+        let mut inputs = ProbeInputs::default();
+        inputs.probe(&mut io);
+        assert_eq!(inputs.reverse_video_mode, Some(true));
+
+        assert_eq!(parse_decrqm_mode_set(b"\x1b[?5;2$y", 5), Some(false));
+        assert_eq!(parse_decrqm_mode_set(b"\x1b[?5;0$y", 5), None);
+        // 2026 reset is still "supported", but a reset mode is not set
+        assert_eq!(parse_decrqm_mode_set(b"\x1b[?2026;2$y", 2026), Some(false));
+    }
+
+    #[test]
+    fn parse_decrqm_reply_statuses() {
+        assert_eq!(parse_decrqm_reply(b"\x1b[?2026;1$y", 2026), Some(true));
+        assert_eq!(parse_decrqm_reply(b"\x1b[?2026;0$y", 2026), Some(false));
+        assert_eq!(parse_decrqm_reply(b"\x1b[?1049;1$y", 2026), None);
+        assert_eq!(parse_da1_reply(b"\x1b[?2026;1$y"), None);
+    }
+
+    /// A compiled terminfo entry with `strings` string capabilities, where
+    /// `blink` is defined if `blink` is set.
+    fn terminfo_entry(magic: i16, strings: i16, blink: bool) -> Vec<u8> {
+        let (names, bools, numbers) = (b"test|fake\0".as_slice(), 3, 2);
+        let mut data = Vec::new();
+        for field in [magic, names.len() as i16, bools, numbers, strings, 4] {
+            data.extend_from_slice(&field.to_le_bytes());
+        }
+        data.extend_from_slice(names);
+        data.extend_from_slice(&[1, 0, 1]);
+        data.resize(data.len() + data.len() % 2, 0);
+        let number_size = if magic == TERMINFO_MAGIC { 2 } else { 4 };
+        data.resize(data.len() + numbers as usize * number_size, 0);
+        for index in 0..strings as usize {
+            let offset: i16 = if index == TERMINFO_BLINK && !blink {
+                -1
+            } else {
+                0
+            };
+            data.extend_from_slice(&offset.to_le_bytes());
+        }
+        data.extend_from_slice(b"\x1b[5m\0");
+        data
+    }
+
+    #[test]
+    fn terminfo_blink_capability_is_read() {
+        assert_eq!(
+            parse_terminfo_blink(&terminfo_entry(TERMINFO_MAGIC, 40, true)),
+            Some(true)
+        );
+        assert_eq!(
+            parse_terminfo_blink(&terminfo_entry(TERMINFO_MAGIC_32BIT, 40, true)),
+            Some(true)
+        );
+        assert_eq!(
+            parse_terminfo_blink(&terminfo_entry(TERMINFO_MAGIC, 40, false)),
+            Some(false)
+        );
+        // An entry too short to list blink doesn't have it
+        assert_eq!(
+            parse_terminfo_blink(&terminfo_entry(TERMINFO_MAGIC, 10, true)),
+            Some(false)
+        );
+        assert_eq!(parse_terminfo_blink(b"not terminfo"), None);
+    }
+
+    #[test]
+    fn terminfo_blink_overrides_terminal_list() {
+        let inputs = ProbeInputs {
+            term: Some("xterm-256color".to_string()),
+            terminfo_blink: Some(false),
+            ..ProbeInputs::default()
+        };
+        assert!(!TerminalCapabilities::from_inputs(&inputs).blink);
+        let inputs = ProbeInputs {
+            terminfo_blink: None,
+            ..inputs
+        };
+        assert!(TerminalCapabilities::from_inputs(&inputs).blink);
+    }
+
+    #[test]
+    fn child_env_reports_version_and_caps() {
+        let caps = TerminalCapabilities {
+            truecolor: true,
+            osc52: true,
+            synchronized_output: true,
+            ..TerminalCapabilities::default()
+        };
+        let env = caps.child_env();
+        let value = |key: &str| {
+            env.iter()
+                .find(|var| var.key == key)
+                .map(|var| var.value.as_str())
+        };
+
+        assert_eq!(value(DMUX_VERSION_ENV), Some(env!("CARGO_PKG_VERSION")));
+        assert_eq!(value(DMUX_CAPS_ENV), Some("truecolor,osc52,sync"));
+        assert_eq!(TerminalCapabilities::default().caps_list(), "");
+    }
+}
@@ -60,12 +60,54 @@ impl SharedStyles {
     }
 }
 
+/// Shape of an underline, as selected by `SGR 4:n`.
+/// `Modifier::UNDERLINED` still says whether a cell is underlined at all.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum UnderlineStyle {
+    #[default]
+    Single,
+    Double,
+    Curly,
+    Dotted,
+    Dashed,
+}
+
+impl UnderlineStyle {
+    /// The style for the `n` of `SGR 4:n`. `None` for 0 (no underline) and unknown values.
+    pub fn from_sgr(n: u16) -> Option<Self> {
+        match n {
+            1 => Some(UnderlineStyle::Single),
+            2 => Some(UnderlineStyle::Double),
+            3 => Some(UnderlineStyle::Curly),
+            4 => Some(UnderlineStyle::Dotted),
+            5 => Some(UnderlineStyle::Dashed),
+            _ => None,
+        }
+    }
+
+    /// The `n` of `SGR 4:n` selecting this style.
+    pub fn to_sgr(self) -> u16 {
+        match self {
+            UnderlineStyle::Single => 1,
+            UnderlineStyle::Double => 2,
+            UnderlineStyle::Curly => 3,
+            UnderlineStyle::Dotted => 4,
+            UnderlineStyle::Dashed => 5,
+        }
+    }
+}
+
 /// Character styles - similar to ratatui's Style but designed for sharing.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
 pub struct CharacterStyles {
     pub foreground: Option<Color>,
     pub background: Option<Color>,
     pub modifiers: Modifier,
+    /// Shape of the underline when `modifiers` has `UNDERLINED`.
+    /// ratatui cells have a single underline modifier, so this is drawn straight.
+    pub underline_style: UnderlineStyle,
+    /// Underline color (SGR 58); `None` underlines in the foreground color
+    pub underline_color: Option<Color>,
 }
 
 impl CharacterStyles {
@@ -75,6 +117,8 @@ impl CharacterStyles {
             foreground: style.fg,
             background: style.bg,
             modifiers: style.add_modifier,
+            underline_style: UnderlineStyle::default(),
+            underline_color: style.underline_color,
         }
     }
 
@@ -87,6 +131,9 @@ impl CharacterStyles {
         if let Some(bg) = self.background {
             style = style.bg(bg);
         }
+        if let Some(color) = self.underline_color {
+            style = style.underline_color(color);
+        }
         style = style.add_modifier(self.modifiers);
         style
     }
@@ -224,6 +271,33 @@ impl TerminalCharacter {
     }
 }
 
+/// Shell-integration boundary reported by OSC 133.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PromptMark {
+    /// `A`: the prompt starts here.
+    PromptStart,
+    /// `B`: the prompt ended and command input starts here.
+    CommandStart,
+    /// `C`: the command was submitted and its output starts here.
+    OutputStart,
+    /// `D`: the command finished, with its exit status if reported.
+    CommandFinished { exit_code: Option<i32> },
+}
+
+impl PromptMark {
+    /// Rank used when several marks land on the same row; the prompt start
+    /// wins so prompt navigation still finds lines like `$ ls` where A and B
+    /// share a row.
+    fn precedence(self) -> u8 {
+        match self {
+            PromptMark::PromptStart => 3,
+            PromptMark::CommandStart => 2,
+            PromptMark::OutputStart => 1,
+            PromptMark::CommandFinished { .. } => 0,
+        }
+    }
+}
+
 /// A single row in the terminal grid.
 /// Uses VecDeque for efficient insertion/deletion at both ends.
 #[derive(Clone, Debug)]
@@ -233,6 +307,8 @@ pub struct Row {
     /// True if this is the start of a logical line (after a newline).
     /// False if this row is a wrapped continuation of the previous line.
     pub is_canonical: bool,
+    /// OSC 133 boundary recorded on this row, if any.
+    pub prompt_mark: Option<PromptMark>,
 }
 
 impl Default for Row {
@@ -240,6 +316,7 @@ impl Default for Row {
         Self {
             columns: VecDeque::new(),
             is_canonical: true,
+            prompt_mark: None,
         }
     }
 }
@@ -261,6 +338,16 @@ impl Row {
         Self {
             columns: VecDeque::with_capacity(capacity),
             is_canonical: true,
+            prompt_mark: None,
+        }
+    }
+
+    /// Record an OSC 133 mark, keeping whichever mark ranks higher if one is
+    /// already present.
+    pub fn set_prompt_mark(&mut self, mark: PromptMark) {
+        match self.prompt_mark {
+            Some(existing) if existing.precedence() > mark.precedence() => {}
+            _ => self.prompt_mark = Some(mark),
         }
     }
 
@@ -482,6 +569,7 @@ impl Row {
         let mut result = Vec::new();
         let mut current_row = Row::with_capacity(max_row_length);
         current_row.is_canonical = self.is_canonical;
+        current_row.prompt_mark = self.prompt_mark;
         let mut current_width = 0;
 
         for character in &self.columns {
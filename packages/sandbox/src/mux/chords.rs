@@ -0,0 +1,350 @@
+//! Multi-key bindings ("chords"), such as `M-g s` for a git status pane.
+//!
+//! Bindings live in a trie keyed by one key press per level. Keys that start a
+//! chord are held back until the chord completes, can't complete, or the chord
+//! timeout passes. A chord that is also the start of a longer one waits for the
+//! longer one, and runs on its own if the next key doesn't continue it or
+//! nothing more is typed in time. Held keys that end up matching nothing are
+//! handed back to be handled as usual, in order.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crossterm::event::{KeyCode, KeyModifiers};
+
+use crate::mux::commands::{parse_command, CommandInvocation};
+use crate::mux::keys::parse_chord_key;
+use crate::settings::KeyChord;
+
+/// One key press in a chord.
+pub type ChordKey = (KeyModifiers, KeyCode);
+
+/// A key press with Shift folded into the character (terminals report
+/// Shift+g as `G`, with or without the modifier), for looking it up.
+fn normalize((modifiers, code): ChordKey) -> ChordKey {
+    match code {
+        KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => (
+            modifiers - KeyModifiers::SHIFT,
+            KeyCode::Char(c.to_ascii_uppercase()),
+        ),
+        _ => (modifiers, code),
+    }
+}
+
+/// Chord bindings, one trie level per key press.
+#[derive(Debug, Clone, Default)]
+pub struct ChordTrie {
+    command: Option<CommandInvocation>,
+    children: HashMap<ChordKey, ChordTrie>,
+}
+
+impl ChordTrie {
+    /// Build the trie for the `key_chords` setting. Invalid entries are
+    /// logged and skipped.
+    pub fn from_settings(chords: &[KeyChord]) -> Self {
+        let mut trie = Self::default();
+        for chord in chords {
+            let parsed = parse_chord_keys(&chord.keys)
+                .and_then(|keys| Ok((keys, parse_command(&chord.command)?)));
+            match parsed {
+                Ok((keys, command)) => {
+                    trie.insert(&keys, command);
+                }
+                Err(e) => tracing::warn!("Skipping key chord {:?}: {}", chord.keys, e),
+            }
+        }
+        trie
+    }
+
+    /// Bind `keys` to `command`, returning the command it replaces. An empty
+    /// sequence binds nothing.
+    pub fn insert(
+        &mut self,
+        keys: &[ChordKey],
+        command: CommandInvocation,
+    ) -> Option<CommandInvocation> {
+        if keys.is_empty() {
+            return None;
+        }
+        let node = keys.iter().fold(self, |node, &key| {
+            node.children.entry(normalize(key)).or_default()
+        });
+        node.command.replace(command)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    fn get(&self, keys: &[ChordKey]) -> Option<&ChordTrie> {
+        keys.iter()
+            .try_fold(self, |node, &key| node.children.get(&normalize(key)))
+    }
+}
+
+/// Parse space-separated key names, e.g. `M-g s`. Names are the ones
+/// `send-keys` takes, and a bare character is that key unmodified.
+pub fn parse_chord_keys(keys: &str) -> Result<Vec<ChordKey>, String> {
+    let keys: Vec<ChordKey> = keys
+        .split_whitespace()
+        .map(|name| parse_chord_key(name).ok_or_else(|| format!("unknown key '{}'", name)))
+        .collect::<Result<_, _>>()?;
+    if keys.is_empty() {
+        return Err("no keys".to_string());
+    }
+    Ok(keys)
+}
+
+/// What became of key presses fed to a `ChordMatcher`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChordStep {
+    /// A chord completed: run its command.
+    Matched(CommandInvocation),
+    /// A key that isn't part of any chord: handle it as usual.
+    Unmatched(ChordKey),
+}
+
+/// Matches key presses against a `ChordTrie`, holding keys while a chord is
+/// under way.
+#[derive(Debug, Clone, Default)]
+pub struct ChordMatcher {
+    trie: ChordTrie,
+    timeout: Duration,
+    /// Keys held so far, as pressed, the start of at least one chord
+    pending: Vec<ChordKey>,
+    /// When the first pending key was pressed
+    pending_since: Option<Instant>,
+}
+
+impl ChordMatcher {
+    pub fn new(trie: ChordTrie, timeout: Duration) -> Self {
+        Self {
+            trie,
+            timeout,
+            pending: Vec::new(),
+            pending_since: None,
+        }
+    }
+
+    /// Whether keys are held waiting for the rest of a chord.
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Whether `key` should go through `feed`: it continues the pending keys
+    /// or starts a chord. Other keys can be handled directly.
+    pub fn wants(&self, key: ChordKey) -> bool {
+        self.is_pending() || self.trie.get(&[key]).is_some()
+    }
+
+    /// Feed a key pressed at `now`. Returns what the keys resolved to, which
+    /// is nothing while a chord is still under way.
+    pub fn feed(&mut self, key: ChordKey, now: Instant) -> Vec<ChordStep> {
+        let mut steps = self.expire(now);
+        let mut input = vec![key];
+        while let Some(key) = input.pop() {
+            self.pending.push(key);
+            match self.trie.get(&self.pending) {
+                // The start of a longer chord (maybe a chord itself): wait
+                Some(node) if !node.is_empty() => {
+                    self.pending_since.get_or_insert(now);
+                }
+                Some(node) => {
+                    if let Some(command) = &node.command {
+                        steps.push(ChordStep::Matched(command.clone()));
+                    }
+                    self.clear();
+                }
+                // The new key broke off the chord: settle the keys before it,
+                // then go again with whatever is left over
+                None => {
+                    let keys = std::mem::take(&mut self.pending);
+                    self.pending_since = None;
+                    let (step, rest) = self.resolve_front(keys);
+                    steps.push(step);
+                    input.extend(rest.into_iter().rev());
+                }
+            }
+        }
+        steps
+    }
+
+    /// Settle the pending keys if the chord timeout has passed by `now`: the
+    /// longest chord among them runs, and the rest are handed back.
+    pub fn expire(&mut self, now: Instant) -> Vec<ChordStep> {
+        let timed_out = self
+            .pending_since
+            .is_some_and(|since| now.saturating_duration_since(since) >= self.timeout);
+        if !timed_out {
+            return Vec::new();
+        }
+        let mut steps = Vec::new();
+        let mut keys = std::mem::take(&mut self.pending);
+        self.pending_since = None;
+        while !keys.is_empty() {
+            let (step, rest) = self.resolve_front(keys);
+            steps.push(step);
+            keys = rest;
+        }
+        steps
+    }
+
+    fn clear(&mut self) {
+        self.pending.clear();
+        self.pending_since = None;
+    }
+
+    /// The longest chord at the start of `keys`, or the first key alone if
+    /// none is, and the keys after it.
+    fn resolve_front(&self, mut keys: Vec<ChordKey>) -> (ChordStep, Vec<ChordKey>) {
+        for len in (1..=keys.len()).rev() {
+            if let Some(command) = self.trie.get(&keys[..len]).and_then(|n| n.command.clone()) {
+                let rest = keys.split_off(len);
+                return (ChordStep::Matched(command), rest);
+            }
+        }
+        let rest = keys.split_off(1);
+        (ChordStep::Unmatched(keys[0]), rest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mux::commands::MuxCommand;
+
+    const TIMEOUT: Duration = Duration::from_millis(1000);
+
+    fn key(c: char) -> ChordKey {
+        (KeyModifiers::NONE, KeyCode::Char(c))
+    }
+
+    fn alt(c: char) -> ChordKey {
+        (KeyModifiers::ALT, KeyCode::Char(c))
+    }
+
+    fn invocation(line: &str) -> CommandInvocation {
+        parse_command(line).expect("valid command")
+    }
+
+    fn matcher(chords: &[(&str, &str)]) -> ChordMatcher {
+        let chords: Vec<KeyChord> = chords
+            .iter()
+            .map(|(keys, command)| KeyChord {
+                keys: keys.to_string(),
+                command: command.to_string(),
+            })
+            .collect();
+        ChordMatcher::new(ChordTrie::from_settings(&chords), TIMEOUT)
+    }
+
+    #[test]
+    fn two_key_chord_matches() {
+        let mut chords = matcher(&[("M-g s", "split-vertical git status")]);
+        let now = Instant::now();
+
+        assert!(!chords.wants(key('s')));
+        assert!(chords.wants(alt('g')));
+        assert!(chords.feed(alt('g'), now).is_empty());
+        assert!(chords.is_pending());
+        assert_eq!(
+            chords.feed(key('s'), now + Duration::from_millis(200)),
+            [ChordStep::Matched(invocation("split-vertical git status"))]
+        );
+        assert!(!chords.is_pending());
+
+        // A key that doesn't continue the chord is handed back with the start
+        assert!(chords.feed(alt('g'), now).is_empty());
+        assert_eq!(
+            chords.feed(key('x'), now),
+            [
+                ChordStep::Unmatched(alt('g')),
+                ChordStep::Unmatched(key('x'))
+            ]
+        );
+    }
+
+    #[test]
+    fn partial_chord_times_out() {
+        let mut chords = matcher(&[("M-g s t", "new-tab")]);
+        let start = Instant::now();
+
+        assert!(chords.feed(alt('g'), start).is_empty());
+        assert!(chords.feed(key('s'), start + TIMEOUT / 2).is_empty());
+        assert!(chords.expire(start + TIMEOUT / 2).is_empty());
+
+        // The timeout runs from the chord's first key
+        assert_eq!(
+            chords.expire(start + TIMEOUT),
+            [
+                ChordStep::Unmatched(alt('g')),
+                ChordStep::Unmatched(key('s'))
+            ]
+        );
+        assert!(!chords.is_pending());
+
+        // A key after the timeout starts afresh rather than finishing the chord
+        assert!(chords.feed(alt('g'), start).is_empty());
+        assert_eq!(
+            chords.feed(key('s'), start + TIMEOUT * 2),
+            [
+                ChordStep::Unmatched(alt('g')),
+                ChordStep::Unmatched(key('s'))
+            ]
+        );
+    }
+
+    #[test]
+    fn chord_that_prefixes_a_longer_one_waits_for_it() {
+        let mut chords = matcher(&[("M-g", "new-tab"), ("M-g s", "split-horizontal")]);
+        let start = Instant::now();
+
+        // The longer chord wins when completed
+        assert!(chords.feed(alt('g'), start).is_empty());
+        assert_eq!(
+            chords.feed(key('s'), start),
+            [ChordStep::Matched(invocation("split-horizontal"))]
+        );
+
+        // The shorter one runs when the next key doesn't continue it...
+        assert!(chords.feed(alt('g'), start).is_empty());
+        assert_eq!(
+            chords.feed(key('x'), start),
+            [
+                ChordStep::Matched(invocation("new-tab")),
+                ChordStep::Unmatched(key('x'))
+            ]
+        );
+
+        // ...or nothing follows in time
+        assert!(chords.feed(alt('g'), start).is_empty());
+        assert_eq!(
+            chords.expire(start + TIMEOUT),
+            [ChordStep::Matched(invocation("new-tab"))]
+        );
+
+        // Rebinding a chord replaces its command
+        let mut trie = ChordTrie::default();
+        assert_eq!(trie.insert(&[alt('g')], invocation("new-tab")), None);
+        let replaced = trie.insert(&[alt('g')], invocation("close-tab"));
+        assert_eq!(replaced.map(|i| i.command), Some(MuxCommand::NewTab));
+    }
+
+    #[test]
+    fn chord_keys_parse() {
+        assert_eq!(
+            parse_chord_keys("M-g S-s Enter"),
+            Ok(vec![
+                alt('g'),
+                (KeyModifiers::SHIFT, KeyCode::Char('s')),
+                (KeyModifiers::NONE, KeyCode::Enter)
+            ])
+        );
+        assert!(parse_chord_keys("").is_err());
+        assert!(parse_chord_keys("M-g bogus").is_err());
+
+        // Invalid settings entries are skipped
+        let chords = matcher(&[("M-g", "not-a-command"), ("", "new-tab")]);
+        assert!(!chords.wants(alt('g')));
+    }
+}
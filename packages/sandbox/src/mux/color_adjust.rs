@@ -0,0 +1,216 @@
+//! Slider state for the interactive color adjustment overlay.
+//!
+//! `ColorAdjust` holds the foreground, background and accent (selection
+//! highlight) being tuned, one RGB slider per channel, and which slider has
+//! focus. It knows nothing about rendering or the override stack: the app
+//! previews `colors()` with `push_color_override` and keeps or drops it.
+
+use crate::mux::colors::{fallback_bg, TerminalColors, FALLBACK_FG};
+
+/// A color the overlay adjusts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorTarget {
+    Foreground,
+    Background,
+    /// The selection highlight, the computed accent unless the terminal reported one
+    Accent,
+}
+
+impl ColorTarget {
+    pub const ALL: [ColorTarget; 3] = [
+        ColorTarget::Foreground,
+        ColorTarget::Background,
+        ColorTarget::Accent,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ColorTarget::Foreground => "Foreground",
+            ColorTarget::Background => "Background",
+            ColorTarget::Accent => "Accent",
+        }
+    }
+}
+
+/// An RGB channel of one color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Red,
+    Green,
+    Blue,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 3] = [Channel::Red, Channel::Green, Channel::Blue];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Channel::Red => "R",
+            Channel::Green => "G",
+            Channel::Blue => "B",
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            Channel::Red => 0,
+            Channel::Green => 1,
+            Channel::Blue => 2,
+        }
+    }
+}
+
+/// Step for a fine adjustment (Left/Right).
+pub const FINE_STEP: i16 = 1;
+/// Step for a coarse adjustment (Up/Down).
+pub const COARSE_STEP: i16 = 16;
+
+/// The sliders of the color adjustment overlay.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColorAdjust {
+    /// The colors the overlay was opened with, `colors()` keeps the rest
+    base: TerminalColors,
+    /// RGB per `ColorTarget::ALL` entry
+    values: [[u8; 3]; 3],
+    /// Focused slider, indexing targets then channels (0..9)
+    focus: usize,
+}
+
+impl ColorAdjust {
+    /// Sliders starting at `colors`, with unknown colors at their fallbacks.
+    pub fn new(colors: TerminalColors) -> Self {
+        let rgb = |(r, g, b): (u8, u8, u8)| [r, g, b];
+        let (_, accent) = colors.selection_colors();
+        Self {
+            base: colors,
+            values: [
+                rgb(colors.foreground.unwrap_or(FALLBACK_FG)),
+                rgb(colors.background.unwrap_or_else(fallback_bg)),
+                rgb(accent),
+            ],
+            focus: 0,
+        }
+    }
+
+    /// The focused slider.
+    pub fn selected(&self) -> (ColorTarget, Channel) {
+        (
+            ColorTarget::ALL[self.focus / 3],
+            Channel::ALL[self.focus % 3],
+        )
+    }
+
+    /// Focus the next slider (Tab), wrapping from the last to the first.
+    pub fn next_channel(&mut self) {
+        self.focus = (self.focus + 1) % 9;
+    }
+
+    /// Focus the previous slider (Shift+Tab), wrapping from the first to the last.
+    pub fn prev_channel(&mut self) {
+        self.focus = (self.focus + 8) % 9;
+    }
+
+    /// Move the focused slider by `delta`, clamped to 0..=255.
+    pub fn adjust(&mut self, delta: i16) {
+        let slot = &mut self.values[self.focus / 3][self.focus % 3];
+        *slot = (i16::from(*slot) + delta).clamp(0, 255) as u8;
+    }
+
+    /// A slider's current value.
+    pub fn value(&self, target: ColorTarget, channel: Channel) -> u8 {
+        self.values[target_index(target)][channel.index()]
+    }
+
+    /// A target's current color.
+    pub fn color(&self, target: ColorTarget) -> (u8, u8, u8) {
+        let [r, g, b] = self.values[target_index(target)];
+        (r, g, b)
+    }
+
+    /// The opening colors with the adjusted ones in place, for previewing
+    /// and applying as an override.
+    pub fn colors(&self) -> TerminalColors {
+        TerminalColors {
+            foreground: Some(self.color(ColorTarget::Foreground)),
+            background: Some(self.color(ColorTarget::Background)),
+            selection_background: Some(self.color(ColorTarget::Accent)),
+            ..self.base
+        }
+    }
+}
+
+fn target_index(target: ColorTarget) -> usize {
+    match target {
+        ColorTarget::Foreground => 0,
+        ColorTarget::Background => 1,
+        ColorTarget::Accent => 2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adjust_for(fg: (u8, u8, u8), bg: (u8, u8, u8)) -> ColorAdjust {
+        ColorAdjust::new(TerminalColors {
+            foreground: Some(fg),
+            background: Some(bg),
+            ..TerminalColors::default()
+        })
+    }
+
+    #[test]
+    fn sliders_clamp_at_both_ends() {
+        let mut adjust = adjust_for((250, 10, 0), (0, 0, 0));
+        adjust.adjust(COARSE_STEP);
+        assert_eq!(adjust.value(ColorTarget::Foreground, Channel::Red), 255);
+        adjust.adjust(FINE_STEP);
+        assert_eq!(adjust.value(ColorTarget::Foreground, Channel::Red), 255);
+
+        adjust.next_channel();
+        adjust.adjust(-COARSE_STEP);
+        assert_eq!(adjust.value(ColorTarget::Foreground, Channel::Green), 0);
+        adjust.adjust(FINE_STEP);
+        assert_eq!(adjust.value(ColorTarget::Foreground, Channel::Green), 1);
+    }
+
+    #[test]
+    fn channel_switching_walks_every_slider_and_wraps() {
+        let mut adjust = adjust_for((0, 0, 0), (0, 0, 0));
+        assert_eq!(adjust.selected(), (ColorTarget::Foreground, Channel::Red));
+        adjust.prev_channel();
+        assert_eq!(adjust.selected(), (ColorTarget::Accent, Channel::Blue));
+        adjust.next_channel();
+        assert_eq!(adjust.selected(), (ColorTarget::Foreground, Channel::Red));
+
+        for _ in 0..4 {
+            adjust.next_channel();
+        }
+        assert_eq!(adjust.selected(), (ColorTarget::Background, Channel::Green));
+        adjust.adjust(5);
+        assert_eq!(adjust.color(ColorTarget::Background), (0, 5, 0));
+        assert_eq!(adjust.color(ColorTarget::Foreground), (0, 0, 0));
+    }
+
+    #[test]
+    fn colors_keep_what_the_overlay_does_not_adjust() {
+        let cursor = Some((1, 2, 3));
+        let original = TerminalColors {
+            foreground: Some((200, 200, 200)),
+            background: Some((10, 10, 10)),
+            cursor,
+            ..TerminalColors::default()
+        };
+        let mut adjust = ColorAdjust::new(original);
+        adjust.adjust(-100);
+        let colors = adjust.colors();
+        assert_eq!(colors.foreground, Some((100, 200, 200)));
+        assert_eq!(colors.background, Some((10, 10, 10)));
+        assert_eq!(colors.cursor, cursor);
+        // The accent starts at the computed selection color
+        assert_eq!(
+            colors.selection_background,
+            Some(original.selection_colors().1)
+        );
+    }
+}
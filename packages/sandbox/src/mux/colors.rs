@@ -7,422 +7,5444 @@
 //! Theme changes are detected via SIGUSR1 signal (Unix) which triggers
 //! a re-query of terminal colors.
 
+use std::collections::HashMap;
 use std::io::Write;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::RwLock;
+use std::sync::{OnceLock, RwLock};
 use std::time::Duration;
-use tokio::sync::mpsc;
+use tokio::sync::{broadcast, mpsc};
+
+use crate::models::EnvVar;
+use crate::mux::capabilities::{probed_capabilities, terminal_capabilities};
+use crate::mux::events::MuxEvent;
+use crate::mux::screen::{active_screen_mode, ScreenMode};
+use crate::settings::{FallbackBackground, ThemeMode, ThemeSettings};
+
+/// Fallback foreground when the outer terminal's is unknown.
+pub const FALLBACK_FG: (u8, u8, u8) = (255, 255, 255); // White
+/// Fallback background when the outer terminal's is unknown.
+pub const FALLBACK_BG: (u8, u8, u8) = (53, 55, 49); // Dark gray (matches ghostty)
+/// Derived fallback background for a dark theme.
+pub const FALLBACK_BG_DARK: (u8, u8, u8) = (16, 16, 16); // Near black
+/// Derived fallback background for a light theme.
+pub const FALLBACK_BG_LIGHT: (u8, u8, u8) = (250, 250, 245); // Off-white
+
+/// Env var overriding the inherited foreground (`#rrggbb`).
+pub const OUTER_FG_ENV: &str = "DMUX_OUTER_FG";
+/// Env var overriding the inherited background (`#rrggbb`).
+pub const OUTER_BG_ENV: &str = "DMUX_OUTER_BG";
+
+/// Where resolved outer terminal colors came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSource {
+    /// Answered by the outer terminal via OSC 10/11 this session.
+    Queried,
+    /// Restored from a previous session (the pinned theme in settings).
+    Cached,
+    /// Set via `DMUX_OUTER_FG` / `DMUX_OUTER_BG`.
+    EnvOverride,
+    /// Derived from the ANSI indices in `$COLORFGBG`.
+    ColorFgBg,
+    /// Nothing known; built-in defaults.
+    Fallback,
+    /// Temporarily replaced with `push_color_override` (e.g. high visibility mode).
+    Override,
+    /// Imported from the `theme_file` setting.
+    ThemeFile,
+}
+
+/// Cached outer terminal colors, with where the foreground and background
+/// each came from.
+#[derive(Debug)]
+pub struct ColorCache {
+    colors: RwLock<(TerminalColors, (ColorSource, ColorSource))>,
+    /// What the last query found out about the background
+    bg_state: RwLock<BgState>,
+    /// Fallback background derived at the last query
+    derived_fallback_bg: RwLock<Option<(u8, u8, u8)>>,
+    initialized: AtomicBool,
+}
 
 /// Global storage for outer terminal colors.
 /// These are queried at startup and updated on theme change signals.
-static OUTER_FG_COLOR: RwLock<Option<(u8, u8, u8)>> = RwLock::new(None);
-static OUTER_BG_COLOR: RwLock<Option<(u8, u8, u8)>> = RwLock::new(None);
-static COLORS_INITIALIZED: AtomicBool = AtomicBool::new(false);
+static OUTER_COLORS: ColorCache = ColorCache::new();
+
+/// Temporary color overrides layered over the resolved colors; the top one wins.
+#[derive(Debug, Default)]
+pub struct ColorOverrideStack {
+    stack: RwLock<Vec<TerminalColors>>,
+}
+
+/// Global override stack consulted by the color accessors.
+static COLOR_OVERRIDES: ColorOverrideStack = ColorOverrideStack::new();
+
+/// High-contrast preset pushed by `ToggleHighVisibility`.
+pub const HIGH_VISIBILITY_COLORS: TerminalColors = TerminalColors {
+    foreground: Some((255, 255, 255)),
+    background: Some((0, 0, 0)),
+    cursor: Some((255, 255, 0)),
+    selection_background: None,
+    selection_foreground: None,
+};
+
+/// Outer terminal colors mapped to their nearest xterm 256-color indices, for
+/// terminals without truecolor.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Ansi256Colors {
+    pub foreground: Option<u8>,
+    pub background: Option<u8>,
+    pub cursor: Option<u8>,
+}
+
+/// Remembers the 256-color mapping of the last colors it was asked about, so
+/// the nearest-match search runs once per theme rather than once per frame.
+#[derive(Debug, Default)]
+pub struct Ansi256Cache {
+    entry: RwLock<Option<(TerminalColors, Ansi256Colors)>>,
+}
+
+/// Global 256-color mapping of the resolved outer colors.
+static OUTER_ANSI256: Ansi256Cache = Ansi256Cache::new();
+
+/// `$TERM` prefixes of terminals known to apply OSC 10/11 color changes.
+const OSC_SET_TERM_ALLOWLIST: &[&str] = &[
+    "xterm",
+    "alacritty",
+    "foot",
+    "wezterm",
+    "kitty",
+    "ghostty",
+    "contour",
+    "rio",
+    "tmux",
+];
+
+/// Blank cells painted for each color by `TerminalColors::swatch_string`.
+const SWATCH_CELLS: &str = "   ";
 
 /// Terminal colors queried from the outer terminal.
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub struct TerminalColors {
     pub foreground: Option<(u8, u8, u8)>,
     pub background: Option<(u8, u8, u8)>,
+    pub cursor: Option<(u8, u8, u8)>,
+    /// Highlight behind selected text (OSC 17), if the terminal reported one
+    pub selection_background: Option<(u8, u8, u8)>,
+    /// Selected text (OSC 19), if the terminal reported one
+    pub selection_foreground: Option<(u8, u8, u8)>,
 }
 
-/// Get the current outer terminal colors.
-/// Returns cached values if available, or default fallbacks.
-pub fn get_outer_colors() -> TerminalColors {
-    TerminalColors {
-        foreground: OUTER_FG_COLOR.read().ok().and_then(|g| *g),
-        background: OUTER_BG_COLOR.read().ok().and_then(|g| *g),
+/// What is known about the outer terminal's background, beyond the
+/// present-or-absent `TerminalColors::background`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BgState {
+    /// A concrete color.
+    Rgb((u8, u8, u8)),
+    /// The terminal reported a fully transparent background, so whatever
+    /// shows through is unknown.
+    Transparent,
+    /// The terminal's own default background, with no color named (e.g.
+    /// `$COLORFGBG` ending in `default`).
+    Default,
+    /// Not queried yet, or nothing answered.
+    #[default]
+    Unknown,
+}
+
+/// The standard 16 ANSI colors (xterm defaults), indexed 0-15.
+pub const ANSI_16: [(u8, u8, u8); 16] = [
+    (0, 0, 0),       // Black
+    (205, 0, 0),     // Red
+    (0, 205, 0),     // Green
+    (205, 205, 0),   // Yellow
+    (0, 0, 238),     // Blue
+    (205, 0, 205),   // Magenta
+    (0, 205, 205),   // Cyan
+    (229, 229, 229), // White
+    (127, 127, 127), // Bright Black (Gray)
+    (255, 0, 0),     // Bright Red
+    (0, 255, 0),     // Bright Green
+    (255, 255, 0),   // Bright Yellow
+    (92, 92, 255),   // Bright Blue
+    (255, 0, 255),   // Bright Magenta
+    (0, 255, 255),   // Bright Cyan
+    (255, 255, 255), // Bright White
+];
+
+/// How far a background's luminance may be from 0.5 and still be too
+/// ambiguous to call light or dark on its own (see `TerminalColors::mode`).
+/// Mid-gray (#808080) is about 0.22.
+const MODE_MIDTONE_BAND: f64 = 0.3;
+
+/// WCAG relative luminance of an sRGB color (0.0 = black, 1.0 = white).
+pub fn relative_luminance((r, g, b): (u8, u8, u8)) -> f64 {
+    let channel = |c: u8| {
+        let c = c as f64 / 255.0;
+        if c <= 0.03928 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * channel(r) + 0.7152 * channel(g) + 0.0722 * channel(b)
+}
+
+/// WCAG contrast ratio between two colors (1.0 to 21.0).
+pub fn contrast_ratio(a: (u8, u8, u8), b: (u8, u8, u8)) -> f64 {
+    let la = relative_luminance(a);
+    let lb = relative_luminance(b);
+    let (lighter, darker) = if la > lb { (la, lb) } else { (lb, la) };
+    (lighter + 0.05) / (darker + 0.05)
+}
+
+/// HSL saturation of a color (0.0 = gray, 1.0 = fully saturated).
+pub fn saturation((r, g, b): (u8, u8, u8)) -> f64 {
+    let max = r.max(g).max(b) as f64 / 255.0;
+    let min = r.min(g).min(b) as f64 / 255.0;
+    let delta = max - min;
+    if delta == 0.0 {
+        return 0.0;
     }
+    let lightness = (max + min) / 2.0;
+    delta / (1.0 - (2.0 * lightness - 1.0).abs())
 }
 
-/// Get the outer terminal's foreground color with fallback.
-pub fn get_outer_fg() -> (u8, u8, u8) {
-    OUTER_FG_COLOR
-        .read()
-        .ok()
-        .and_then(|g| *g)
-        .unwrap_or((255, 255, 255)) // White fallback
+/// Linearly interpolate between two colors; `t` is clamped to 0.0..=1.0.
+/// The endpoints are exact: `t = 0.0` gives `from` and `t = 1.0` gives `to`.
+pub fn lerp_color(from: (u8, u8, u8), to: (u8, u8, u8), t: f32) -> (u8, u8, u8) {
+    let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+    let channel = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+    (
+        channel(from.0, to.0),
+        channel(from.1, to.1),
+        channel(from.2, to.2),
+    )
 }
 
-/// Get the outer terminal's background color with fallback.
-pub fn get_outer_bg() -> (u8, u8, u8) {
-    OUTER_BG_COLOR
-        .read()
-        .ok()
-        .and_then(|g| *g)
-        .unwrap_or((53, 55, 49)) // Dark gray fallback (matches ghostty)
+/// An HSL color (hue in degrees, saturation and lightness 0.0-1.0) as RGB.
+fn hsl_to_rgb(hue: f64, saturation: f64, lightness: f64) -> (u8, u8, u8) {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let sector = hue.rem_euclid(360.0) / 60.0;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let (r, g, b) = match sector as u8 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    let channel = |c: f64| ((c + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+    (channel(r), channel(g), channel(b))
+}
+
+/// Background `pane_accent` guarantees contrast against in each theme mode:
+/// a fairly light dark background and a fairly dark light one, so real
+/// themes usually do better.
+const PANE_ACCENT_BG_DARK: (u8, u8, u8) = FALLBACK_BG;
+const PANE_ACCENT_BG_LIGHT: (u8, u8, u8) = (238, 238, 238);
+
+/// Least contrast ratio a pane accent keeps against the reference background.
+pub const PANE_ACCENT_MIN_CONTRAST: f64 = 4.5;
+
+/// Hue in degrees for `pane_accent`, from an FNV-1a hash of the input, so it
+/// is the same on every run and platform.
+fn pane_accent_hue(id_or_name: &str) -> f64 {
+    let hash = id_or_name
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    (hash % 360) as f64
+}
+
+/// A stable accent color identifying a pane by its id or name.
+///
+/// The hue comes from hashing the input; saturation and lightness suit the
+/// theme, light accents on dark themes and dark ones on light themes, pushed
+/// further if needed to keep `PANE_ACCENT_MIN_CONTRAST` against a typical
+/// background.
+pub fn pane_accent(id_or_name: &str, theme: ThemeMode) -> (u8, u8, u8) {
+    let hue = pane_accent_hue(id_or_name);
+    let (mut lightness, step, bg) = match theme {
+        ThemeMode::Dark => (0.65, 0.05, PANE_ACCENT_BG_DARK),
+        ThemeMode::Light => (0.4, -0.05, PANE_ACCENT_BG_LIGHT),
+    };
+    let mut color = hsl_to_rgb(hue, 0.65, lightness);
+    while contrast_ratio(color, bg) < PANE_ACCENT_MIN_CONTRAST && (0.05..=0.95).contains(&lightness)
+    {
+        lightness += step;
+        color = hsl_to_rgb(hue, 0.65, lightness);
+    }
+    color
+}
+
+/// Interpolate every slot of a 16-color palette with `lerp_color`.
+pub fn lerp_palette(
+    from: &[(u8, u8, u8); 16],
+    to: &[(u8, u8, u8); 16],
+    t: f32,
+) -> [(u8, u8, u8); 16] {
+    std::array::from_fn(|i| lerp_color(from[i], to[i], t))
 }
 
-/// Update the stored outer terminal colors.
-/// Only updates if BOTH fg and bg were successfully queried to avoid mismatched colors.
-pub fn set_outer_colors(colors: TerminalColors) {
-    // Only update if we got BOTH colors - otherwise we'd have mismatched theme
-    if let (Some(fg_color), Some(bg_color)) = (colors.foreground, colors.background) {
-        if let Ok(mut fg) = OUTER_FG_COLOR.write() {
-            *fg = Some(fg_color);
+impl BgState {
+    /// The background color, if one is known: the old `Option` semantics,
+    /// where every state but `Rgb` reads as `None`.
+    pub fn rgb(self) -> Option<(u8, u8, u8)> {
+        match self {
+            BgState::Rgb(color) => Some(color),
+            _ => None,
+        }
+    }
+
+    /// Whether the background is probably see-through, so painting it
+    /// would hide what the terminal shows behind its windows.
+    ///
+    /// Transparency can't be queried directly: this only catches terminals
+    /// that report a zero alpha, and `$COLORFGBG` ending in `default`, which
+    /// rxvt sets when its background is transparent or an image. Others need
+    /// the `transparent_background` setting.
+    pub fn likely_transparent(self) -> bool {
+        matches!(self, BgState::Transparent | BgState::Default)
+    }
+
+    /// Classify an OSC 11 reply.
+    ///
+    /// `rgba:` replies with a zero alpha and urxvt's `[0]` alpha prefix mean
+    /// transparent; a partially transparent reply keeps its color.
+    pub fn from_reply(reply: &[u8]) -> Self {
+        if let Some(start) = find_bytes(reply, b"rgba:") {
+            let spec = take_ascii(&reply[start + 5..], |b| b.is_ascii_hexdigit() || b == b'/');
+            let parts: Vec<&str> = spec.split('/').collect();
+            if let [r, g, b, a] = parts[..] {
+                if parse_hex_component(a) == Some(0) {
+                    return BgState::Transparent;
+                }
+                let color = (
+                    parse_hex_component(r),
+                    parse_hex_component(g),
+                    parse_hex_component(b),
+                );
+                if let (Some(r), Some(g), Some(b)) = color {
+                    return BgState::Rgb((r, g, b));
+                }
+            }
+            return BgState::Unknown;
+        }
+        if find_bytes(reply, b"[0]").is_some() {
+            return BgState::Transparent;
+        }
+        parse_osc_color_response(reply).into()
+    }
+
+    /// Classify the background field of a `$COLORFGBG` value.
+    fn from_colorfgbg(value: &str) -> Self {
+        match value.rsplit(';').next() {
+            Some("default") => BgState::Default,
+            _ => parse_colorfgbg(value).background.into(),
+        }
+    }
+
+    /// `self`, or `other` if nothing is known.
+    fn or(self, other: BgState) -> BgState {
+        match self {
+            BgState::Unknown => other,
+            known => known,
         }
-        if let Ok(mut bg) = OUTER_BG_COLOR.write() {
-            *bg = Some(bg_color);
+    }
+
+    /// A foreground that stays readable on this background, or `None` when the
+    /// terminal's own default foreground is the safer choice.
+    ///
+    /// A concrete color gets black or white, whichever contrasts more. On a
+    /// default or transparent background only the terminal knows what is
+    /// legible, so no color is forced. An unknown background is assumed to be
+    /// `fallback_bg()`, matching what gets rendered.
+    pub fn readable_foreground(self) -> Option<(u8, u8, u8)> {
+        let bg = match self {
+            BgState::Rgb(color) => color,
+            BgState::Transparent | BgState::Default => return None,
+            BgState::Unknown => fallback_bg(),
+        };
+        let (white, black) = ((255, 255, 255), (0, 0, 0));
+        if contrast_ratio(white, bg) >= contrast_ratio(black, bg) {
+            Some(white)
+        } else {
+            Some(black)
         }
-        COLORS_INITIALIZED.store(true, Ordering::SeqCst);
     }
 }
 
-/// Check if colors have been initialized.
-pub fn colors_initialized() -> bool {
-    COLORS_INITIALIZED.load(Ordering::SeqCst)
+impl From<Option<(u8, u8, u8)>> for BgState {
+    fn from(color: Option<(u8, u8, u8)>) -> Self {
+        color.map_or(BgState::Unknown, BgState::Rgb)
+    }
 }
 
-/// Query the outer terminal's colors via OSC 10/11.
-///
-/// This function must be called BEFORE entering the alternate screen buffer,
-/// as it temporarily enables raw mode to read the terminal's response.
+/// Minimum contrast ratio a split-view separator keeps against both panes.
+pub const SEPARATOR_MIN_CONTRAST: f64 = 3.0;
+
+/// Luminance at which white and black contrast equally with a background.
+const CONTRAST_PIVOT_LUMINANCE: f64 = 0.179;
+
+/// Number of steps `complementary_separator` takes from the average background
+/// to pure white or black.
+const SEPARATOR_STEPS: u8 = 20;
+
+/// A separator color that reads clearly against two neighbouring pane backgrounds.
 ///
-/// Returns `TerminalColors` with the queried colors, or `None` for colors
-/// that couldn't be queried (e.g., terminal doesn't support OSC queries).
-pub fn query_outer_terminal_colors() -> TerminalColors {
-    let mut colors = TerminalColors::default();
+/// Starts from the average of the two backgrounds and moves toward white when
+/// that average is dark (toward black when light), stopping at the first step
+/// that reaches `SEPARATOR_MIN_CONTRAST` against both. If no step gets there,
+/// returns the one with the best worst-case contrast.
+pub fn complementary_separator(bg_a: (u8, u8, u8), bg_b: (u8, u8, u8)) -> (u8, u8, u8) {
+    let average = lerp_color(bg_a, bg_b, 0.5);
+    let target = if relative_luminance(average) < CONTRAST_PIVOT_LUMINANCE {
+        (255, 255, 255)
+    } else {
+        (0, 0, 0)
+    };
+    let worst_contrast = |color| contrast_ratio(color, bg_a).min(contrast_ratio(color, bg_b));
 
-    // We need raw mode to read terminal responses
-    if crossterm::terminal::enable_raw_mode().is_err() {
-        return colors;
+    let mut best = (average, worst_contrast(average));
+    for step in 0..=SEPARATOR_STEPS {
+        let candidate = lerp_color(average, target, step as f32 / SEPARATOR_STEPS as f32);
+        let contrast = worst_contrast(candidate);
+        if contrast >= SEPARATOR_MIN_CONTRAST {
+            return candidate;
+        }
+        if contrast > best.1 {
+            best = (candidate, contrast);
+        }
     }
+    best.0
+}
 
-    // Query foreground (OSC 10) and background (OSC 11)
-    colors.foreground = query_osc_color(10);
-    colors.background = query_osc_color(11);
+/// Channel levels of the xterm 6x6x6 color cube (indices 16-231).
+const ANSI256_CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
 
-    // Restore normal mode
-    let _ = crossterm::terminal::disable_raw_mode();
+/// The nearest xterm 256-color index to an RGB color.
+///
+/// Only the color cube and the grayscale ramp (232-255) are considered: the
+/// first 16 indices follow the user's theme, so their RGB values aren't fixed.
+pub fn rgb_to_ansi256((r, g, b): (u8, u8, u8)) -> u8 {
+    let level = |v: u8| match v {
+        0..48 => 0,
+        48..115 => 1,
+        _ => (v - 35) / 40,
+    };
+    let (cr, cg, cb) = (level(r), level(g), level(b));
+    let cube = (
+        ANSI256_CUBE_LEVELS[cr as usize],
+        ANSI256_CUBE_LEVELS[cg as usize],
+        ANSI256_CUBE_LEVELS[cb as usize],
+    );
 
-    // Store for later use
-    set_outer_colors(colors);
+    let average = (r as u16 + g as u16 + b as u16) / 3;
+    let gray_step = (average.saturating_sub(3) / 10).min(23) as u8;
+    let gray_value = 8 + 10 * gray_step;
 
-    colors
+    let distance = |(pr, pg, pb): (u8, u8, u8)| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(pr, r) + d(pg, g) + d(pb, b)
+    };
+    if distance((gray_value, gray_value, gray_value)) < distance(cube) {
+        232 + gray_step
+    } else {
+        16 + 36 * cr + 6 * cg + cb
+    }
 }
 
-/// Drain any pending data from stdin (non-blocking).
-fn drain_stdin() {
-    use std::os::unix::io::AsRawFd;
+/// Format a color as a `#rrggbb` hex string.
+pub fn color_to_hex((r, g, b): (u8, u8, u8)) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
 
-    let stdin = std::io::stdin();
-    let stdin_handle = stdin.lock();
-    let stdin_fd = stdin_handle.as_raw_fd();
+/// How far the computed selection accent moves from the background toward
+/// the foreground, when the terminal didn't report a selection color.
+const SELECTION_ACCENT_MIX: f32 = 0.3;
 
-    // Set non-blocking
-    let flags = unsafe { libc::fcntl(stdin_fd, libc::F_GETFL) };
-    unsafe { libc::fcntl(stdin_fd, libc::F_SETFL, flags | libc::O_NONBLOCK) };
+/// Version byte of the `TerminalColors` wire format.
+pub const COLORS_WIRE_VERSION: u8 = 1;
 
-    // Drain all pending data
-    let mut buf = [0u8; 256];
-    loop {
-        let n = unsafe { libc::read(stdin_fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
-        if n <= 0 {
-            break;
+/// Size of a `TerminalColors` encoding: version, present flags, then an
+/// RGB triple each for foreground, background and cursor.
+pub const COLORS_WIRE_LEN: usize = 11;
+
+impl TerminalColors {
+    /// Encode the colors for the client/server attach path.
+    ///
+    /// Layout: `[version, flags, fg_r, fg_g, fg_b, bg_r, bg_g, bg_b, cur_r,
+    /// cur_g, cur_b]`. Bits 0-2 of `flags` mark fg, bg and cursor as present;
+    /// absent colors are zero-filled. Every field is a single byte, so the
+    /// encoding is the same on any endianness. Selection colors only matter
+    /// to the client drawing them and aren't encoded.
+    pub fn to_bytes(&self) -> [u8; COLORS_WIRE_LEN] {
+        let mut bytes = [0u8; COLORS_WIRE_LEN];
+        bytes[0] = COLORS_WIRE_VERSION;
+        for (slot, color) in [self.foreground, self.background, self.cursor]
+            .into_iter()
+            .enumerate()
+        {
+            if let Some((r, g, b)) = color {
+                bytes[1] |= 1 << slot;
+                bytes[2 + slot * 3..5 + slot * 3].copy_from_slice(&[r, g, b]);
+            }
         }
+        bytes
     }
 
-    // Restore blocking mode
-    unsafe { libc::fcntl(stdin_fd, libc::F_SETFL, flags) };
+    /// Decode colors written by `to_bytes`.
+    /// Returns `None` for another version, a wrong length or unknown flags.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != COLORS_WIRE_LEN || bytes[0] != COLORS_WIRE_VERSION || bytes[1] > 0b111 {
+            return None;
+        }
+        let color = |slot: usize| {
+            let rgb = &bytes[2 + slot * 3..5 + slot * 3];
+            (bytes[1] & (1 << slot) != 0).then_some((rgb[0], rgb[1], rgb[2]))
+        };
+        Some(Self {
+            foreground: color(0),
+            background: color(1),
+            cursor: color(2),
+            selection_background: None,
+            selection_foreground: None,
+        })
+    }
+
+    /// SGR sequence selecting the foreground as a truecolor fg, if known.
+    pub fn sgr_fg(&self) -> Option<String> {
+        let (r, g, b) = self.foreground?;
+        Some(format!("\x1b[38;2;{};{};{}m", r, g, b))
+    }
+
+    /// SGR sequence selecting the background as a truecolor bg, if known.
+    pub fn sgr_bg(&self) -> Option<String> {
+        let (r, g, b) = self.background?;
+        Some(format!("\x1b[48;2;{};{};{}m", r, g, b))
+    }
+
+    /// These colors with any they leave unset taken from `base`.
+    pub fn layered_over(&self, base: TerminalColors) -> TerminalColors {
+        TerminalColors {
+            foreground: self.foreground.or(base.foreground),
+            background: self.background.or(base.background),
+            cursor: self.cursor.or(base.cursor),
+            selection_background: self.selection_background.or(base.selection_background),
+            selection_foreground: self.selection_foreground.or(base.selection_foreground),
+        }
+    }
+
+    /// The colors mapped to their nearest 256-color indices.
+    pub fn to_ansi256(&self) -> Ansi256Colors {
+        Ansi256Colors {
+            foreground: self.foreground.map(rgb_to_ansi256),
+            background: self.background.map(rgb_to_ansi256),
+            cursor: self.cursor.map(rgb_to_ansi256),
+        }
+    }
+
+    /// SGR sequence resetting all attributes, to pair with `sgr_fg`/`sgr_bg`.
+    pub const fn sgr_reset() -> &'static str {
+        "\x1b[0m"
+    }
+
+    /// A line showing fg, bg and cursor as blocks of colored background,
+    /// separated by spaces, with `-` for each unknown color.
+    pub fn swatch_string(&self) -> String {
+        [self.foreground, self.background, self.cursor]
+            .into_iter()
+            .map(|color| {
+                let swatch = TerminalColors {
+                    background: color,
+                    ..TerminalColors::default()
+                };
+                match swatch.sgr_bg() {
+                    Some(sgr) => format!("{}{}{}", sgr, SWATCH_CELLS, Self::sgr_reset()),
+                    None => "-".to_string(),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Light or dark mode, judged by the background's luminance. A mid-tone
+    /// background (within `MODE_MIDTONE_BAND` of 0.5) is decided by the
+    /// foreground instead, when known: text darker than the background means
+    /// a light theme.
+    pub fn mode(&self) -> Option<ThemeMode> {
+        let bg = relative_luminance(self.background?);
+        let fg = self.foreground.map(relative_luminance);
+        let reference = match fg {
+            Some(fg) if (bg - 0.5).abs() < MODE_MIDTONE_BAND && fg != bg => fg,
+            _ => 0.5,
+        };
+        if bg < reference {
+            Some(ThemeMode::Dark)
+        } else {
+            Some(ThemeMode::Light)
+        }
+    }
+
+    /// Whether both fg and bg are near-gray, i.e. their R/G/B channels differ by at
+    /// most `tolerance`. Returns `None` if either color is unknown.
+    ///
+    /// Lets accent logic fall back to brightness-only accents on monochrome themes.
+    pub fn is_grayscale(&self, tolerance: u8) -> Option<bool> {
+        let is_gray = |(r, g, b): (u8, u8, u8)| r.max(g).max(b) - r.min(g).min(b) <= tolerance;
+        Some(is_gray(self.foreground?) && is_gray(self.background?))
+    }
+
+    /// Colors to highlight selected text with, as (fg, bg): the terminal's own
+    /// selection colors where it reported them (OSC 19/17), otherwise the
+    /// foreground over an accent mixed from the background toward it.
+    pub fn selection_colors(&self) -> ((u8, u8, u8), (u8, u8, u8)) {
+        let fg = self.foreground.unwrap_or(FALLBACK_FG);
+        let bg = self.background.unwrap_or_else(fallback_bg);
+        let accent = lerp_color(bg, fg, SELECTION_ACCENT_MIX);
+        (
+            self.selection_foreground.unwrap_or(fg),
+            self.selection_background.unwrap_or(accent),
+        )
+    }
+
+    /// The colors with foreground and background swapped, as a terminal in
+    /// reverse video shows them.
+    pub fn reversed(self) -> Self {
+        Self {
+            foreground: self.background,
+            background: self.foreground,
+            cursor: self.cursor,
+            selection_background: self.selection_background,
+            selection_foreground: self.selection_foreground,
+        }
+    }
+
+    /// Colors from a settings-file theme section; unparseable values are `None`.
+    pub fn from_theme_settings(theme: &ThemeSettings) -> Self {
+        let parse = |value: &Option<String>| value.as_deref().and_then(parse_hex_color);
+        Self {
+            foreground: parse(&theme.foreground),
+            background: parse(&theme.background),
+            cursor: parse(&theme.cursor),
+            selection_background: None,
+            selection_foreground: None,
+        }
+    }
+
+    /// The colors as a settings-file theme section.
+    pub fn to_theme_settings(&self) -> ThemeSettings {
+        ThemeSettings {
+            foreground: self.foreground.map(color_to_hex),
+            background: self.background.map(color_to_hex),
+            cursor: self.cursor.map(color_to_hex),
+            mode: self.mode(),
+        }
+    }
+
+    /// Pick the index of the most legible ANSI color against the background.
+    ///
+    /// Among the colors in `ANSI_16` whose contrast ratio meets `min_ratio`,
+    /// returns the most saturated one. If none meet it, returns the color with
+    /// the highest contrast. Returns `None` if the background is unknown.
+    pub fn best_ansi_for_contrast(&self, min_ratio: f64) -> Option<u8> {
+        let bg = self.background?;
+
+        let legible = ANSI_16
+            .iter()
+            .enumerate()
+            .filter(|(_, &color)| contrast_ratio(color, bg) >= min_ratio)
+            .max_by(|(_, a), (_, b)| saturation(**a).total_cmp(&saturation(**b)));
+
+        let (index, _) = legible.or_else(|| {
+            ANSI_16.iter().enumerate().max_by(|(_, a), (_, b)| {
+                contrast_ratio(**a, bg).total_cmp(&contrast_ratio(**b, bg))
+            })
+        })?;
+
+        Some(index as u8)
+    }
 }
 
-/// Query a specific OSC color (10=fg, 11=bg, 12=cursor).
-fn query_osc_color(code: u8) -> Option<(u8, u8, u8)> {
-    use std::os::unix::io::AsRawFd;
+impl ColorCache {
+    pub const fn new() -> Self {
+        Self {
+            colors: RwLock::new((
+                TerminalColors {
+                    foreground: None,
+                    background: None,
+                    cursor: None,
+                    selection_background: None,
+                    selection_foreground: None,
+                },
+                (ColorSource::Fallback, ColorSource::Fallback),
+            )),
+            bg_state: RwLock::new(BgState::Unknown),
+            derived_fallback_bg: RwLock::new(None),
+            initialized: AtomicBool::new(false),
+        }
+    }
+
+    /// The cached colors, or all-`None` if nothing has been committed.
+    pub fn get(&self) -> TerminalColors {
+        self.get_with_sources().0
+    }
 
-    // Drain any leftover data from previous queries or terminal events
-    drain_stdin();
+    /// The cached colors with the sources of the foreground and background,
+    /// read together (`Fallback` if nothing has been committed).
+    pub fn get_with_sources(&self) -> (TerminalColors, (ColorSource, ColorSource)) {
+        self.colors.read().map(|g| *g).unwrap_or((
+            TerminalColors::default(),
+            (ColorSource::Fallback, ColorSource::Fallback),
+        ))
+    }
 
-    let mut stdout = std::io::stdout();
-    let stdin = std::io::stdin();
+    /// Sources of the cached foreground and background (`Fallback` if nothing
+    /// has been committed).
+    pub fn sources(&self) -> (ColorSource, ColorSource) {
+        if !self.is_initialized() {
+            return (ColorSource::Fallback, ColorSource::Fallback);
+        }
+        self.colors
+            .read()
+            .map(|g| g.1)
+            .unwrap_or((ColorSource::Fallback, ColorSource::Fallback))
+    }
 
-    // Send query: OSC code ; ? ST
-    let query = format!("\x1b]{};?\x1b\\", code);
+    /// The background state recorded by the last `commit_query`.
+    pub fn bg_state(&self) -> BgState {
+        self.bg_state.read().map(|state| *state).unwrap_or_default()
+    }
 
-    if stdout.write_all(query.as_bytes()).is_err() {
-        return None;
+    /// The fallback background derived by the last `commit_query`, if any.
+    pub fn derived_fallback_bg(&self) -> Option<(u8, u8, u8)> {
+        self.derived_fallback_bg
+            .read()
+            .ok()
+            .and_then(|stored| *stored)
     }
-    if stdout.flush().is_err() {
-        return None;
+
+    /// Store the answer to a live query: colors it left unanswered are filled
+    /// from `colorfgbg` (a `$COLORFGBG` value) before committing, the fallback
+    /// background is derived from the result under `policy`, and the
+    /// background's state is recorded. The state and fallback are recorded
+    /// even if nothing answered. Returns whether the colors were committed.
+    pub fn commit_query(
+        &self,
+        queried: TerminalColors,
+        bg_state: BgState,
+        colorfgbg: Option<&str>,
+        policy: FallbackBackground,
+    ) -> bool {
+        let (resolved, sources) = apply_color_fallbacks(queried, colorfgbg);
+        if let Ok(mut fallback) = self.derived_fallback_bg.write() {
+            *fallback = Some(derive_fallback_background(&resolved, policy));
+        }
+        let bg_state = bg_state.or(colorfgbg.map(BgState::from_colorfgbg).unwrap_or_default());
+        if let Ok(mut state) = self.bg_state.write() {
+            *state = bg_state;
+        }
+        self.commit_with_sources(resolved, sources)
     }
 
-    // Read response with timeout using select/poll
-    // Response format: OSC code ; rgb:RRRR/GGGG/BBBB ST
-    let mut response = Vec::with_capacity(64);
-    let deadline = std::time::Instant::now() + Duration::from_millis(200);
+    /// Whether a candidate has ever been committed.
+    pub fn is_initialized(&self) -> bool {
+        self.initialized.load(Ordering::SeqCst)
+    }
 
-    let stdin_handle = stdin.lock();
-    let stdin_fd = stdin_handle.as_raw_fd();
+    /// Replace the cached colors with `candidate` if it is usable.
+    ///
+    /// A candidate is rejected if either fg or bg is missing (which would leave a
+    /// mismatched theme) or if fg equals bg (a degenerate reply that would make
+    /// text invisible). Returns whether the cache was updated.
+    pub fn commit(&self, candidate: TerminalColors) -> bool {
+        self.commit_with_source(candidate, ColorSource::Queried)
+    }
 
-    // Set stdin to non-blocking
-    let flags = unsafe { libc::fcntl(stdin_fd, libc::F_GETFL) };
-    unsafe { libc::fcntl(stdin_fd, libc::F_SETFL, flags | libc::O_NONBLOCK) };
+    /// Like `commit`, recording `source` as the colors' provenance.
+    pub fn commit_with_source(&self, candidate: TerminalColors, source: ColorSource) -> bool {
+        self.commit_with_sources(candidate, (source, source))
+    }
 
-    loop {
-        let remaining = deadline.saturating_duration_since(std::time::Instant::now());
-        if remaining.is_zero() {
-            break;
+    /// Like `commit`, recording where the foreground and background each came
+    /// from. The background's source is reported as the colors' overall source.
+    pub fn commit_with_sources(
+        &self,
+        candidate: TerminalColors,
+        (fg_source, bg_source): (ColorSource, ColorSource),
+    ) -> bool {
+        let (Some(fg), Some(bg)) = (candidate.foreground, candidate.background) else {
+            return false;
+        };
+        if fg == bg {
+            return false;
         }
 
-        // Use poll to wait for input
-        let mut pollfd = libc::pollfd {
-            fd: stdin_fd,
-            events: libc::POLLIN,
-            revents: 0,
+        let Ok(mut colors) = self.colors.write() else {
+            return false;
         };
+        *colors = (candidate, (fg_source, bg_source));
+        self.initialized.store(true, Ordering::SeqCst);
+        true
+    }
+}
 
-        let timeout_ms = remaining.as_millis().min(50) as i32;
-        let poll_result = unsafe { libc::poll(&mut pollfd, 1, timeout_ms) };
+impl Default for ColorCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-        if poll_result <= 0 {
-            continue; // Timeout or error, try again
-        }
+impl Ansi256Colors {
+    /// SGR sequence selecting the foreground as a 256-color fg, if known.
+    pub fn sgr_fg(&self) -> Option<String> {
+        Some(format!("\x1b[38;5;{}m", self.foreground?))
+    }
 
-        // Read available bytes
-        let mut buf = [0u8; 64];
-        let n = unsafe { libc::read(stdin_fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+    /// SGR sequence selecting the background as a 256-color bg, if known.
+    pub fn sgr_bg(&self) -> Option<String> {
+        Some(format!("\x1b[48;5;{}m", self.background?))
+    }
+}
 
-        if n > 0 {
-            let bytes = &buf[..n as usize];
-            response.extend_from_slice(bytes);
+impl Ansi256Cache {
+    pub const fn new() -> Self {
+        Self {
+            entry: RwLock::new(None),
+        }
+    }
 
-            // Check for ST (ESC \) or BEL terminator
-            if response.ends_with(&[0x1b, b'\\']) || response.ends_with(&[0x07]) {
-                break;
+    /// The 256-color mapping of `colors`, recomputed only when `colors`
+    /// differ from the last call's.
+    pub fn get(&self, colors: TerminalColors) -> Ansi256Colors {
+        if let Some((cached_for, mapped)) = self.entry.read().ok().and_then(|entry| *entry) {
+            if cached_for == colors {
+                return mapped;
             }
-        } else if n == 0 {
-            break;
         }
-        // n < 0 means EAGAIN/EWOULDBLOCK, continue polling
+        let mapped = colors.to_ansi256();
+        if let Ok(mut entry) = self.entry.write() {
+            *entry = Some((colors, mapped));
+        }
+        mapped
     }
 
-    // Restore blocking mode
-    unsafe { libc::fcntl(stdin_fd, libc::F_SETFL, flags) };
-
-    drop(stdin_handle);
+    /// Whether a mapping is currently cached.
+    pub fn is_cached(&self) -> bool {
+        self.entry.read().is_ok_and(|entry| entry.is_some())
+    }
 
-    // Parse response
-    parse_osc_color_response(&response)
+    /// Drop the cached mapping so the next `get` recomputes it.
+    pub fn invalidate(&self) {
+        if let Ok(mut entry) = self.entry.write() {
+            *entry = None;
+        }
+    }
 }
 
-/// Parse an OSC color response.
-/// Expected format: ESC ] code ; rgb:RRRR/GGGG/BBBB ESC \
-///                  or ESC ] code ; rgb:RR/GG/BB ESC \
-fn parse_osc_color_response(response: &[u8]) -> Option<(u8, u8, u8)> {
-    let s = std::str::from_utf8(response).ok()?;
+impl ColorOverrideStack {
+    pub const fn new() -> Self {
+        Self {
+            stack: RwLock::new(Vec::new()),
+        }
+    }
+
+    pub fn push(&self, colors: TerminalColors) {
+        if let Ok(mut stack) = self.stack.write() {
+            stack.push(colors);
+        }
+    }
 
-    // Find "rgb:" in the response
-    let rgb_start = s.find("rgb:")?;
-    let rgb_part = &s[rgb_start + 4..];
+    /// Remove the most recent override, returning it.
+    pub fn pop(&self) -> Option<TerminalColors> {
+        self.stack.write().ok()?.pop()
+    }
 
-    // Find the terminator (ESC \ or just the end before ESC)
-    let rgb_end = rgb_part.find('\x1b').unwrap_or(rgb_part.len());
-    let rgb_str = &rgb_part[..rgb_end];
+    /// The override currently in effect, if any.
+    pub fn top(&self) -> Option<TerminalColors> {
+        self.stack.read().ok()?.last().copied()
+    }
 
-    // Parse RRRR/GGGG/BBBB or RR/GG/BB format
-    let parts: Vec<&str> = rgb_str.split('/').collect();
-    if parts.len() != 3 {
-        return None;
+    /// Layer the active override over `resolved`. Colors the override leaves
+    /// unset keep their resolved values.
+    fn apply(&self, resolved: (TerminalColors, ColorSource)) -> (TerminalColors, ColorSource) {
+        let Some(top) = self.top() else {
+            return resolved;
+        };
+        (top.layered_over(resolved.0), ColorSource::Override)
     }
+}
 
-    let r = parse_hex_component(parts[0])?;
-    let g = parse_hex_component(parts[1])?;
-    let b = parse_hex_component(parts[2])?;
+/// Temporarily replace the outer colors (e.g. for a presentation). The
+/// accessors return `colors` until the matching `pop_color_override`.
+pub fn push_color_override(colors: TerminalColors) {
+    COLOR_OVERRIDES.push(colors);
+}
 
-    Some((r, g, b))
+/// Drop the most recent override, reverting to the one below it or to the
+/// queried colors. Returns the removed override.
+pub fn pop_color_override() -> Option<TerminalColors> {
+    COLOR_OVERRIDES.pop()
 }
 
-/// Parse a hex color component, handling both 2-digit and 4-digit formats.
-fn parse_hex_component(s: &str) -> Option<u8> {
-    let val = u16::from_str_radix(s, 16).ok()?;
-    if s.len() <= 2 {
-        // 8-bit value
-        Some(val as u8)
-    } else {
-        // 16-bit value, convert to 8-bit
-        Some((val >> 8) as u8)
+/// The color override currently in effect, if any.
+pub fn active_color_override() -> Option<TerminalColors> {
+    COLOR_OVERRIDES.top()
+}
+
+/// What is known about the outer background: an override or env-var color
+/// wins, then whatever the last query found.
+pub fn outer_bg_state() -> BgState {
+    let overridden = active_color_override()
+        .and_then(|colors| colors.background)
+        .or(env_override_colors().background);
+    if let Some(color) = overridden {
+        return BgState::Rgb(color);
     }
+    OUTER_COLORS.bg_state()
 }
 
-/// Query outer terminal colors via a subprocess.
-/// This spawns a helper process that queries colors independently,
-/// avoiding conflicts with the main EventStream.
-///
-/// This is faster and less disruptive than query_outer_terminal_colors()
-/// which requires exiting alternate screen.
-pub fn query_colors_via_subprocess() -> TerminalColors {
-    let colors = TerminalColors {
-        foreground: query_osc_color_via_subprocess(10),
-        background: query_osc_color_via_subprocess(11),
-    };
+/// How the fallback background is chosen, from the settings file.
+static FALLBACK_BACKGROUND: RwLock<FallbackBackground> = RwLock::new(FallbackBackground::Derived);
 
-    // Store for later use
-    set_outer_colors(colors);
+/// Choose how the fallback background is picked. Call before
+/// `query_outer_terminal_colors`, which derives it.
+pub fn set_fallback_background(policy: FallbackBackground) {
+    if let Ok(mut stored) = FALLBACK_BACKGROUND.write() {
+        *stored = policy;
+    }
+}
 
-    colors
+/// The background assumed where the outer terminal's is unknown:
+/// `FALLBACK_BG` until a query has derived one.
+pub fn fallback_bg() -> (u8, u8, u8) {
+    OUTER_COLORS.derived_fallback_bg().unwrap_or(FALLBACK_BG)
 }
 
-/// Query a specific OSC color via a subprocess.
-/// The subprocess opens /dev/tty directly, queries the color, and prints the RGB result.
-fn query_osc_color_via_subprocess(code: u8) -> Option<(u8, u8, u8)> {
-    use std::process::{Command, Stdio};
+/// The fallback background for colors whose background went unanswered.
+///
+/// `Derived` infers the theme mode from the foreground (which may itself come
+/// from `$COLORFGBG`): light text means a dark theme, so near black; dark text
+/// means off-white. With no foreground either, dark is assumed, as
+/// `FALLBACK_FG` is white.
+pub fn derive_fallback_background(
+    colors: &TerminalColors,
+    policy: FallbackBackground,
+) -> (u8, u8, u8) {
+    if policy == FallbackBackground::Ghostty {
+        return FALLBACK_BG;
+    }
+    let mode = colors.mode().or_else(|| {
+        let fg = relative_luminance(colors.foreground?);
+        Some(if fg > 0.5 {
+            ThemeMode::Dark
+        } else {
+            ThemeMode::Light
+        })
+    });
+    match mode {
+        Some(ThemeMode::Light) => FALLBACK_BG_LIGHT,
+        Some(ThemeMode::Dark) | None => FALLBACK_BG_DARK,
+    }
+}
 
-    // Use sh -c with a script that queries the color via /dev/tty
-    // The script:
-    // 1. Opens /dev/tty for input/output
-    // 2. Sets raw mode using stty
-    // 3. Sends OSC query
-    // 4. Reads response with timeout
-    // 5. Parses and prints RGB values
-    let script = format!(
-        r#"
-exec 3<>/dev/tty
-old_settings=$(stty -g <&3 2>/dev/null)
-stty raw -echo min 0 time 2 <&3 2>/dev/null
-printf '\033]{};?\033\\' >&3
-response=""
-while IFS= read -r -t 0.15 -n 1 char <&3 2>/dev/null; do
-    response="$response$char"
-    case "$response" in
-        *$'\033''\') break ;;
-        *$'\007') break ;;
-    esac
-done
-stty "$old_settings" <&3 2>/dev/null
-exec 3>&-
-echo "$response" | sed -n 's/.*rgb:\([0-9a-fA-F]*\)\/\([0-9a-fA-F]*\)\/\([0-9a-fA-F]*\).*/\1 \2 \3/p'
-"#,
-        code
+/// Whether the outer terminal was found in reverse video (DECSCNM) at startup.
+static REVERSE_VIDEO_DETECTED: AtomicBool = AtomicBool::new(false);
+
+/// Manual reverse video setting that replaces detection, if any.
+static REVERSE_VIDEO_OVERRIDE: RwLock<Option<bool>> = RwLock::new(None);
+
+/// Record whether the startup probe found reverse video active.
+pub fn set_reverse_video_detected(active: bool) {
+    REVERSE_VIDEO_DETECTED.store(active, Ordering::SeqCst);
+}
+
+/// Force reverse video handling on or off, or `None` to use detection.
+pub fn set_reverse_video_override(active: Option<bool>) {
+    if let Ok(mut stored) = REVERSE_VIDEO_OVERRIDE.write() {
+        *stored = active;
+    }
+}
+
+/// Whether the outer terminal shows its default colors swapped, so the
+/// inherited foreground and background must be swapped to match the screen.
+pub fn reverse_video_active() -> bool {
+    REVERSE_VIDEO_OVERRIDE
+        .read()
+        .ok()
+        .and_then(|stored| *stored)
+        .unwrap_or_else(|| REVERSE_VIDEO_DETECTED.load(Ordering::SeqCst))
+}
+
+/// Get the current outer terminal colors, with any override applied.
+/// Returns cached values if available, or default fallbacks.
+pub fn get_outer_colors() -> TerminalColors {
+    let colors = OUTER_COLORS.get();
+    let colors = if reverse_video_active() {
+        colors.reversed()
+    } else {
+        colors
+    };
+    COLOR_OVERRIDES.apply((colors, ColorSource::Queried)).0
+}
+
+/// Get the resolved outer terminal colors and where they came from.
+///
+/// Resolution order: a pushed override, then the env override, then the
+/// cache (queried or restored), then the built-in fallbacks. Missing values in
+/// an override are filled from the next source down.
+///
+/// In reverse video the inherited foreground and background are swapped
+/// (overrides are not, as they name the colors to draw).
+pub fn get_outer_colors_with_source() -> (TerminalColors, ColorSource) {
+    COLOR_OVERRIDES.apply(resolve_outer_colors(
+        &OUTER_COLORS,
+        env_override_colors(),
+        reverse_video_active(),
+    ))
+}
+
+/// `resolve_colors`, with foreground and background swapped if `reverse_video`.
+fn resolve_outer_colors(
+    cache: &ColorCache,
+    env: TerminalColors,
+    reverse_video: bool,
+) -> (TerminalColors, ColorSource) {
+    let (colors, source) = resolve_colors(cache, env);
+    if reverse_video {
+        (colors.reversed(), source)
+    } else {
+        (colors, source)
+    }
+}
+
+/// Where the resolved foreground and background each came from, e.g. to show
+/// whether colors were really inherited from the outer terminal.
+pub fn color_sources() -> (ColorSource, ColorSource) {
+    let (fg_source, bg_source) = resolve_color_sources(&OUTER_COLORS, env_override_colors());
+    let Some(top) = COLOR_OVERRIDES.top() else {
+        return (fg_source, bg_source);
+    };
+    let pick = |overridden: bool, source| {
+        if overridden {
+            ColorSource::Override
+        } else {
+            source
+        }
+    };
+    (
+        pick(top.foreground.is_some(), fg_source),
+        pick(top.background.is_some(), bg_source),
+    )
+}
+
+fn resolve_color_sources(cache: &ColorCache, env: TerminalColors) -> (ColorSource, ColorSource) {
+    let (fg_source, bg_source) = cache.sources();
+    let pick = |overridden: bool, source| {
+        if overridden {
+            ColorSource::EnvOverride
+        } else {
+            source
+        }
+    };
+    (
+        pick(env.foreground.is_some(), fg_source),
+        pick(env.background.is_some(), bg_source),
+    )
+}
+
+/// Colors set via `DMUX_OUTER_FG` / `DMUX_OUTER_BG`, read once per process.
+fn env_override_colors() -> TerminalColors {
+    static ENV_OVERRIDE: OnceLock<TerminalColors> = OnceLock::new();
+    *ENV_OVERRIDE.get_or_init(|| {
+        let read = |name| std::env::var(name).ok().and_then(|v| parse_hex_color(&v));
+        TerminalColors {
+            foreground: read(OUTER_FG_ENV),
+            background: read(OUTER_BG_ENV),
+            cursor: None,
+            selection_background: None,
+            selection_foreground: None,
+        }
+    })
+}
+
+/// The resolved colors and their source. Cached colors report the
+/// background's source, as the one that decides the theme.
+fn resolve_colors(cache: &ColorCache, env: TerminalColors) -> (TerminalColors, ColorSource) {
+    let (cached, (_, bg_source)) = cache.get_with_sources();
+    if env.foreground.is_some() || env.background.is_some() {
+        let colors = TerminalColors {
+            foreground: env.foreground.or(cached.foreground).or(Some(FALLBACK_FG)),
+            background: env
+                .background
+                .or(cached.background)
+                .or_else(|| Some(fallback_bg())),
+            cursor: cached.cursor,
+            selection_background: cached.selection_background,
+            selection_foreground: cached.selection_foreground,
+        };
+        return (colors, ColorSource::EnvOverride);
+    }
+    if cache.is_initialized() {
+        return (cached, bg_source);
+    }
+    let fallback = TerminalColors {
+        foreground: Some(FALLBACK_FG),
+        background: Some(fallback_bg()),
+        cursor: None,
+        selection_background: None,
+        selection_foreground: None,
+    };
+    (fallback, ColorSource::Fallback)
+}
+
+/// Parse a `#rrggbb` (or `rrggbb`) hex color.
+pub fn parse_hex_color(s: &str) -> Option<(u8, u8, u8)> {
+    let hex = s.trim().trim_start_matches('#');
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some((channel(0)?, channel(2)?, channel(4)?))
+}
+
+/// Get the outer terminal's foreground color with fallback.
+pub fn get_outer_fg() -> (u8, u8, u8) {
+    get_outer_colors_with_source()
+        .0
+        .foreground
+        .unwrap_or(FALLBACK_FG)
+}
+
+/// Get the outer terminal's background color with fallback.
+pub fn get_outer_bg() -> (u8, u8, u8) {
+    get_outer_colors_with_source()
+        .0
+        .background
+        .unwrap_or_else(fallback_bg)
+}
+
+/// The resolved outer colors as 256-color indices, for terminals without
+/// truecolor. Cached until the colors change.
+pub fn get_outer_ansi256() -> Ansi256Colors {
+    OUTER_ANSI256.get(get_outer_colors())
+}
+
+/// Commit a probed candidate to the global color cache.
+///
+/// Probe functions never touch the cache themselves, so a transient glitch can't
+/// clobber good cached values; callers invoke this once satisfied with a result.
+/// See `ColorCache::commit` for what is rejected. Returns whether the cache was updated.
+pub fn commit_colors(candidate: TerminalColors) -> bool {
+    commit_colors_with_source(candidate, ColorSource::Queried)
+}
+
+/// Commit colors from a source other than a live query (e.g. a pinned theme).
+pub fn commit_colors_with_source(candidate: TerminalColors, source: ColorSource) -> bool {
+    let committed = OUTER_COLORS.commit_with_source(candidate, source);
+    if committed {
+        OUTER_ANSI256.invalidate();
+    }
+    committed
+}
+
+/// Check if colors have been initialized.
+pub fn colors_initialized() -> bool {
+    OUTER_COLORS.is_initialized()
+}
+
+/// Query the outer terminal's colors via OSC 10/11 and commit them to the cache.
+///
+/// This function must be called BEFORE entering the alternate screen buffer,
+/// as it temporarily enables raw mode to read the terminal's response.
+/// `query_outer_terminal_colors_anytime` lifts that restriction by querying
+/// through its own `/dev/tty` handle.
+///
+/// Returns `TerminalColors` with the queried colors, or `None` for colors
+/// that couldn't be queried (e.g., terminal doesn't support OSC queries).
+/// A rate-limited query (see `ColorQueryLimiter`) is queued instead, and the
+/// cached colors are returned.
+pub fn query_outer_terminal_colors() -> TerminalColors {
+    if !acquire_color_query_slot() {
+        return OUTER_COLORS.get();
+    }
+    query_and_commit_outer_colors()
+}
+
+/// Query the outer terminal's colors and, if it answers, its cursor style and
+/// ANSI palette too (see `query_outer_cursor_style` and `query_outer_palette`),
+/// all under one slot of the color query rate limit so the later queries
+/// don't queue behind the first.
+///
+/// Same requirements as `query_outer_terminal_colors`, which this returns
+/// the result of.
+pub fn query_outer_terminal_theme() -> TerminalColors {
+    if !acquire_color_query_slot() {
+        return OUTER_COLORS.get();
+    }
+    let colors = query_and_commit_outer_colors();
+    if colors.background.is_some() {
+        query_and_store_cursor_style();
+        query_and_store_palette();
+    }
+    colors
+}
+
+/// `query_outer_terminal_colors` once the caller holds a query slot.
+fn query_and_commit_outer_colors() -> TerminalColors {
+    let _span = crate::mux_span!("color_query");
+    let started = std::time::Instant::now();
+    let (colors, bg_state) = probe_colors_and_bg_state();
+    crate::mux_event!(
+        foreground = colors.foreground.is_some(),
+        background = ?bg_state,
+        cursor = colors.cursor.is_some(),
+        elapsed_ms = started.elapsed().as_millis() as u64,
+        "color query finished"
     );
+    if let Ok(mut duration) = LAST_QUERY_DURATION.write() {
+        *duration = Some(started.elapsed());
+    }
+
+    commit_live_query(&OUTER_COLORS, colors, bg_state);
+    colors
+}
+
+/// Store a live query's answer in `cache` (see `ColorCache::commit_query`),
+/// with `$COLORFGBG` and the fallback background policy in effect. Every
+/// path that queries the terminal directly commits through here, so they
+/// agree on the background.
+fn commit_live_query(cache: &ColorCache, queried: TerminalColors, bg_state: BgState) -> bool {
+    let colorfgbg = std::env::var(COLORFGBG_ENV).ok();
+    let policy = FALLBACK_BACKGROUND
+        .read()
+        .map(|policy| *policy)
+        .unwrap_or_default();
+    cache.commit_query(queried, bg_state, colorfgbg.as_deref(), policy)
+}
+
+/// Variable set by rxvt, Konsole and others to the `fg;bg` ANSI color indices.
+const COLORFGBG_ENV: &str = "COLORFGBG";
+
+/// Colors from a `$COLORFGBG` value such as `15;0` or `15;default;0`.
+/// The background is the last field; indices map onto `ANSI_16`.
+fn parse_colorfgbg(value: &str) -> TerminalColors {
+    let ansi = |field: &str| {
+        field
+            .parse::<usize>()
+            .ok()
+            .and_then(|index| ANSI_16.get(index).copied())
+    };
+    let mut fields = value.split(';');
+    let foreground = fields.next().and_then(ansi);
+    let background = fields.next_back().and_then(ansi);
+    TerminalColors {
+        foreground,
+        background,
+        cursor: None,
+        selection_background: None,
+        selection_foreground: None,
+    }
+}
+
+/// Fill the colors a query didn't answer from `$COLORFGBG`, recording where
+/// the foreground and background each came from. Colors neither source knows
+/// stay `None` and are reported as `Fallback`.
+fn apply_color_fallbacks(
+    queried: TerminalColors,
+    colorfgbg: Option<&str>,
+) -> (TerminalColors, (ColorSource, ColorSource)) {
+    let derived = colorfgbg.map(parse_colorfgbg).unwrap_or_default();
+    let pick =
+        |queried: Option<(u8, u8, u8)>, derived: Option<(u8, u8, u8)>| match (queried, derived) {
+            (Some(color), _) => (Some(color), ColorSource::Queried),
+            (None, Some(color)) => (Some(color), ColorSource::ColorFgBg),
+            (None, None) => (None, ColorSource::Fallback),
+        };
+    let (foreground, fg_source) = pick(queried.foreground, derived.foreground);
+    let (background, bg_source) = pick(queried.background, derived.background);
+    let colors = TerminalColors {
+        foreground,
+        background,
+        cursor: queried.cursor,
+        selection_background: queried.selection_background,
+        selection_foreground: queried.selection_foreground,
+    };
+    (colors, (fg_source, bg_source))
+}
+
+/// Query the outer terminal's colors via OSC 10/11 without updating the cache.
+///
+/// Same requirements as `query_outer_terminal_colors`. Pass the result to
+/// `commit_colors` to store it. In headless mode the injected colors are
+/// returned instead.
+pub fn probe_outer_terminal_colors() -> TerminalColors {
+    probe_colors_and_bg_state().0
+}
+
+/// Like `probe_outer_terminal_colors`, also classifying the background reply.
+fn probe_colors_and_bg_state() -> (TerminalColors, BgState) {
+    if let Some(colors) = crate::mux::headless::headless_colors() {
+        return (colors, colors.background.into());
+    }
+    // We need raw mode to read terminal responses
+    with_raw_mode(&mut CrosstermRawMode, || {
+        query_colors_batched_with(&mut StdioOsc)
+    })
+    .unwrap_or_default()
+}
+
+/// Control over the terminal's raw mode.
+/// Abstracted so the enable/restore logic can be tested without a terminal.
+pub trait RawMode {
+    fn is_enabled(&self) -> std::io::Result<bool>;
+    fn enable(&mut self) -> std::io::Result<()>;
+    fn disable(&mut self) -> std::io::Result<()>;
+}
+
+/// `RawMode` through crossterm's process-wide raw mode state.
+pub struct CrosstermRawMode;
+
+impl RawMode for CrosstermRawMode {
+    fn is_enabled(&self) -> std::io::Result<bool> {
+        crossterm::terminal::is_raw_mode_enabled()
+    }
+
+    fn enable(&mut self) -> std::io::Result<()> {
+        crossterm::terminal::enable_raw_mode()
+    }
+
+    fn disable(&mut self) -> std::io::Result<()> {
+        crossterm::terminal::disable_raw_mode()
+    }
+}
+
+/// Run `f` with raw mode enabled, then restore the prior state.
+///
+/// Raw mode is only toggled if it was off, so a query made while the caller
+/// already has the terminal raw leaves it raw. Returns `None` without running
+/// `f` if raw mode was off and couldn't be enabled.
+pub fn with_raw_mode<M: RawMode, T>(mode: &mut M, f: impl FnOnce() -> T) -> Option<T> {
+    // If the state can't be read, assume cooked, as before this check existed
+    let was_enabled = mode.is_enabled().unwrap_or(false);
+    if !was_enabled && mode.enable().is_err() {
+        return None;
+    }
+    let result = f();
+    if !was_enabled {
+        let _ = mode.disable();
+    }
+    Some(result)
+}
+
+/// Query the outer terminal's colors via OSC 10/11 without touching raw mode.
+///
+/// For use from inside the main loop, where raw mode is already enabled.
+/// The caller is responsible for leaving the alternate screen first, since some
+/// terminals (e.g. VSCode) don't answer OSC queries while it is active.
+///
+/// A rate-limited query is queued instead, and the cached colors returned.
+pub fn query_outer_terminal_colors_assuming_raw() -> TerminalColors {
+    if !acquire_color_query_slot() {
+        return OUTER_COLORS.get();
+    }
+    let colors = query_colors_with(&mut StdioOsc);
+    commit_colors(colors);
+    colors
+}
+
+/// The controlling terminal, opened separately from stdin/stdout.
+const CONTROLLING_TTY: &str = "/dev/tty";
+
+/// Query the outer terminal's colors through a fresh `/dev/tty` handle.
+///
+/// The handle is put in raw mode for the duration of the query and its prior
+/// settings restored afterwards, whatever state the main terminal is in, so
+/// this can be called mid-session. Requires access to `/dev/tty` (there is
+/// none without a controlling terminal, e.g. under a daemon or in CI).
+/// Replies arrive on the same input queue as keystrokes, so pause any other
+/// reader of the terminal around the call. The result is not committed; pass
+/// it to `commit_colors` to store it. Fails with `WouldBlock` if the query is
+/// rate limited (see `ColorQueryLimiter`).
+pub fn query_outer_terminal_colors_anytime() -> std::io::Result<TerminalColors> {
+    if !acquire_color_query_slot() {
+        return Err(color_query_rate_limited());
+    }
+    query_colors_via_tty(std::path::Path::new(CONTROLLING_TTY))
+}
+
+/// Open the terminal at `path` and query its colors there.
+fn query_colors_via_tty(path: &std::path::Path) -> std::io::Result<TerminalColors> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)?;
+    let mut raw_mode = TtyRawMode {
+        fd: tty.as_raw_fd(),
+        saved: None,
+    };
+    with_raw_mode(&mut raw_mode, || {
+        query_colors_with(&mut TtyOsc { tty: &mut tty })
+    })
+    .ok_or_else(|| std::io::Error::other("failed to put the terminal in raw mode"))
+}
+
+/// Raw mode on one terminal handle via termios, saving and restoring its settings.
+struct TtyRawMode {
+    fd: std::os::unix::io::RawFd,
+    saved: Option<libc::termios>,
+}
+
+impl RawMode for TtyRawMode {
+    /// Always reports cooked, so the handle's own settings get saved and restored
+    /// even when another handle already put the terminal in raw mode.
+    fn is_enabled(&self) -> std::io::Result<bool> {
+        Ok(false)
+    }
+
+    fn enable(&mut self) -> std::io::Result<()> {
+        let mut termios = std::mem::MaybeUninit::<libc::termios>::uninit();
+        if unsafe { libc::tcgetattr(self.fd, termios.as_mut_ptr()) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let saved = unsafe { termios.assume_init() };
+        let mut raw = saved;
+        unsafe { libc::cfmakeraw(&mut raw) };
+        if unsafe { libc::tcsetattr(self.fd, libc::TCSANOW, &raw) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        self.saved = Some(saved);
+        Ok(())
+    }
+
+    fn disable(&mut self) -> std::io::Result<()> {
+        let Some(saved) = self.saved.take() else {
+            return Ok(());
+        };
+        if unsafe { libc::tcsetattr(self.fd, libc::TCSANOW, &saved) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// `OscIo` over an opened terminal handle. Raw mode must already be enabled.
+struct TtyOsc<'a> {
+    tty: &'a mut std::fs::File,
+}
+
+impl OscIo for TtyOsc<'_> {
+    fn send(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        use std::os::unix::io::AsRawFd;
+
+        // Discard leftover input so a stale reply isn't taken for this one
+        let mut poller = FdPoller {
+            fd: self.tty.as_raw_fd(),
+        };
+        let mut buf = [0u8; 256];
+        while let Some(1..) = poller.read_within(Duration::ZERO, &mut buf) {}
+
+        self.tty.write_all(bytes)?;
+        self.tty.flush()
+    }
+
+    fn recv(&mut self, timeout: Duration) -> Vec<u8> {
+        self.recv_expecting(timeout, None)
+    }
+
+    fn recv_expecting(&mut self, timeout: Duration, expected_len: Option<usize>) -> Vec<u8> {
+        use std::os::unix::io::AsRawFd;
+
+        read_osc_reply_expecting(
+            &mut FdPoller {
+                fd: self.tty.as_raw_fd(),
+            },
+            timeout,
+            expected_len,
+        )
+    }
+}
+
+/// Query foreground (OSC 10), background (OSC 11) and cursor (OSC 12) through the
+/// given terminal I/O. The cursor is skipped if the background query went unanswered,
+/// to avoid waiting out another timeout on terminals without OSC query support.
+fn query_colors_with<T: OscIo>(io: &mut T) -> TerminalColors {
+    query_colors_and_bg_state_with(io).0
+}
+
+/// Like `query_colors_with`, also classifying the OSC 11 reply.
+fn query_colors_and_bg_state_with<T: OscIo>(io: &mut T) -> (TerminalColors, BgState) {
+    let foreground = query_osc_color_with(io, 10);
+    let bg_reply = query_osc_reply_with(io, 11);
+    let background = parse_osc_color_response(&bg_reply);
+    let cursor = background.and_then(|_| query_osc_color_with(io, 12));
+    let colors = TerminalColors {
+        foreground,
+        background,
+        cursor,
+        selection_background: None,
+        selection_foreground: None,
+    };
+    (colors, BgState::from_reply(&bg_reply))
+}
+
+/// OSC color codes sent together by `query_colors_batched_with`, in order:
+/// fg, bg, cursor, then selection background and foreground.
+const BATCHED_COLOR_CODES: [u8; 5] = [10, 11, 12, 17, 19];
+
+/// How many of `BATCHED_COLOR_CODES`, from the front, are worth waiting the
+/// whole `OSC_QUERY_TIMEOUT` for. Fewer terminals answer the selection queries.
+const REQUIRED_BATCHED_CODES: usize = 3;
+
+/// How long to keep reading for the selection colors once the required
+/// replies are in. A terminal that answers them does so right behind the rest.
+const OPTIONAL_REPLY_GRACE: Duration = Duration::from_millis(20);
+
+/// Like `query_colors_and_bg_state_with`, but writes the OSC 10, 11, 12, 17
+/// and 19 queries in a single flush, so startup waits out one round trip
+/// instead of five.
+///
+/// Replies are read until all have arrived, `OPTIONAL_REPLY_GRACE` passes
+/// after the required ones, or `OSC_QUERY_TIMEOUT` passes, and each is
+/// matched to its query by code. A reply without a code answers the earliest
+/// query still unanswered, as terminals reply in order.
+fn query_colors_batched_with<T: OscIo>(io: &mut T) -> (TerminalColors, BgState) {
+    let queries: String = BATCHED_COLOR_CODES
+        .iter()
+        .map(|code| format!("\x1b]{};?\x1b\\", code))
+        .collect();
+    if io.send(queries.as_bytes()).is_err() {
+        return Default::default();
+    }
+
+    let mut deadline = std::time::Instant::now() + OSC_QUERY_TIMEOUT;
+    let mut replies: [Vec<u8>; BATCHED_COLOR_CODES.len()] = Default::default();
+    let mut buffered = Vec::new();
+    let mut in_grace = false;
+    while replies.iter().any(Vec::is_empty) {
+        if !in_grace && !replies[..REQUIRED_BATCHED_CODES].iter().any(Vec::is_empty) {
+            deadline = deadline.min(std::time::Instant::now() + OPTIONAL_REPLY_GRACE);
+            in_grace = true;
+        }
+        let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        let chunk = io.recv(remaining);
+        if chunk.is_empty() {
+            break;
+        }
+        buffered.extend_from_slice(&chunk);
+        for reply in take_osc_replies(&mut buffered) {
+            dispatch_batched_reply(reply, &mut replies);
+        }
+    }
+
+    let [fg_reply, bg_reply, cursor_reply, selection_bg_reply, selection_fg_reply] = replies;
+    let colors = TerminalColors {
+        foreground: parse_osc_color_response(&fg_reply),
+        background: parse_osc_color_response(&bg_reply),
+        cursor: parse_osc_color_response(&cursor_reply),
+        selection_background: parse_osc_color_response(&selection_bg_reply),
+        selection_foreground: parse_osc_color_response(&selection_fg_reply),
+    };
+    (colors, BgState::from_reply(&bg_reply))
+}
+
+/// File a batched reply under the query it answers, dropping replies to
+/// other codes and repeats of one already answered.
+fn dispatch_batched_reply(reply: Vec<u8>, replies: &mut [Vec<u8>; BATCHED_COLOR_CODES.len()]) {
+    let slot = match osc_reply_code(&reply) {
+        Some(code) => BATCHED_COLOR_CODES
+            .iter()
+            .position(|&queried| u16::from(queried) == code),
+        None => replies.iter().position(Vec::is_empty),
+    };
+    let Some(slot) = slot.filter(|&slot| replies[slot].is_empty()) else {
+        return;
+    };
+    notify_osc_observers(BATCHED_COLOR_CODES[slot], &reply);
+    replies[slot] = reply;
+}
+
+/// Split the complete OSC replies off the front of `buf`, leaving any
+/// unterminated tail for the next read. Each reply keeps its terminator and
+/// loses the CR/LF some terminals send around it.
+fn take_osc_replies(buf: &mut Vec<u8>) -> Vec<Vec<u8>> {
+    let mut replies = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    while i < buf.len() {
+        let end = match buf[i] {
+            0x07 => Some(i + 1),
+            0x1b if buf.get(i + 1) == Some(&b'\\') => Some(i + 2),
+            C1_ST if buf[start..i].contains(&C1_OSC) => Some(i + 1),
+            _ => None,
+        };
+        match end {
+            Some(end) => {
+                let mut reply = buf[start..end].to_vec();
+                trim_line_endings(&mut reply);
+                replies.push(reply);
+                start = end;
+                i = end;
+            }
+            None => i += 1,
+        }
+    }
+    buf.drain(..start);
+    replies
+}
+
+/// How OSC queries must be wrapped to reach the real terminal through a multiplexer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Passthrough {
+    /// Talk to the terminal directly.
+    #[default]
+    None,
+    /// Wrap in tmux's `DCS tmux; ... ST`, doubling inner ESCs.
+    Tmux,
+    /// Wrap in a GNU screen `DCS ... ST`.
+    Screen,
+}
+
+impl Passthrough {
+    /// Pick the passthrough for the multiplexer this process runs under, if any.
+    pub fn from_env() -> Self {
+        if std::env::var_os("TMUX").is_some() {
+            Passthrough::Tmux
+        } else if std::env::var_os("STY").is_some() {
+            Passthrough::Screen
+        } else {
+            Passthrough::None
+        }
+    }
+
+    /// Wrap an escape sequence so the multiplexer forwards it to the outer terminal.
+    pub fn wrap(self, sequence: &[u8]) -> Vec<u8> {
+        match self {
+            Passthrough::None => sequence.to_vec(),
+            Passthrough::Tmux => {
+                let mut wrapped = b"\x1bPtmux;".to_vec();
+                for &byte in sequence {
+                    if byte == 0x1b {
+                        wrapped.push(0x1b);
+                    }
+                    wrapped.push(byte);
+                }
+                wrapped.extend_from_slice(b"\x1b\\");
+                wrapped
+            }
+            Passthrough::Screen => [b"\x1bP", sequence, b"\x1b\\"].concat(),
+        }
+    }
+}
+
+/// Tuning for `query_outer_terminal_colors_remote`.
+#[derive(Clone, Copy, Debug)]
+pub struct RemoteQueryOptions {
+    /// How long a reply may go without new bytes before it is given up on.
+    /// Resets whenever part of a reply arrives.
+    pub idle_timeout: Duration,
+    /// How many times each query is sent before the color is treated as unknown.
+    pub attempts: u32,
+    pub passthrough: Passthrough,
+}
+
+impl Default for RemoteQueryOptions {
+    fn default() -> Self {
+        Self {
+            idle_timeout: Duration::from_secs(1),
+            attempts: 3,
+            passthrough: Passthrough::None,
+        }
+    }
+}
+
+/// Query a remote client's terminal colors over a PTY or serial link.
+///
+/// Unlike `query_outer_terminal_colors`, this never touches the local
+/// terminal: queries go to `writer` and replies are read from `reader`, which
+/// must already be in raw mode on the far side. Input pending on `reader` is
+/// drained before each query so stale replies can't be mistaken for fresh ones,
+/// and unanswered queries are retried. The result is not committed; pass it to
+/// `commit_colors` to store it.
+pub fn query_outer_terminal_colors_remote<R: std::os::unix::io::AsRawFd, W: Write>(
+    reader: &R,
+    writer: &mut W,
+    opts: &RemoteQueryOptions,
+) -> TerminalColors {
+    let mut io = RemoteOsc {
+        poller: FdPoller {
+            fd: reader.as_raw_fd(),
+        },
+        writer,
+        opts: *opts,
+    };
+    let mut query =
+        |code| (0..opts.attempts.max(1)).find_map(|_| query_osc_color_with(&mut io, code));
+    let foreground = query(10);
+    let background = query(11);
+    let cursor = background.and_then(|_| query(12));
+    TerminalColors {
+        foreground,
+        background,
+        cursor,
+        selection_background: None,
+        selection_foreground: None,
+    }
+}
+
+/// `OscIo` over a separate reader and writer, for high-latency links.
+struct RemoteOsc<'a, W: Write> {
+    poller: FdPoller,
+    writer: &'a mut W,
+    opts: RemoteQueryOptions,
+}
+
+impl<W: Write> OscIo for RemoteOsc<'_, W> {
+    fn send(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        // Discard late replies to earlier attempts and anything else in flight
+        let mut buf = [0u8; 256];
+        while let Some(1..) = self.poller.read_within(Duration::ZERO, &mut buf) {}
+
+        self.writer.write_all(&self.opts.passthrough.wrap(bytes))?;
+        self.writer.flush()
+    }
+
+    fn recv(&mut self, timeout: Duration) -> Vec<u8> {
+        // Keep reading while bytes trickle in, but stop at an overall deadline
+        // so a link that never finishes a reply can't stall the query. The
+        // caller's timeout is sized for a local terminal, so a slow link still
+        // gets at least one idle timeout.
+        let deadline = self.poller.now() + timeout.max(self.opts.idle_timeout);
+        let mut response = Vec::new();
+        loop {
+            let remaining = deadline.saturating_duration_since(self.poller.now());
+            if remaining.is_zero() {
+                return response;
+            }
+            let chunk = read_osc_reply(&mut self.poller, self.opts.idle_timeout.min(remaining));
+            response.extend_from_slice(&chunk);
+            if chunk.is_empty() || is_osc_reply_terminated(&response) {
+                return response;
+            }
+        }
+    }
+}
+
+/// Format the currently inherited colors as a settings-file snippet, so users
+/// can pin them by pasting it into `settings.json`.
+///
+/// Colors are exported as the terminal reports them, without the reverse
+/// video swap, since pinned colors are swapped again when they are used.
+pub fn export_theme_config() -> String {
+    let colors = COLOR_OVERRIDES
+        .apply((OUTER_COLORS.get(), ColorSource::Queried))
+        .0;
+    format_theme_config(&colors)
+}
+
+/// Format the given colors as a `{"theme": {...}}` settings snippet.
+fn format_theme_config(colors: &TerminalColors) -> String {
+    let snippet = serde_json::json!({ "theme": colors.to_theme_settings() });
+    serde_json::to_string_pretty(&snippet).unwrap_or_default()
+}
+
+/// How long the last `query_outer_terminal_colors` took, for diagnostics.
+static LAST_QUERY_DURATION: RwLock<Option<Duration>> = RwLock::new(None);
+
+/// The last reply read from the real terminal, for diagnostics.
+static LAST_RAW_RESPONSE: RwLock<Vec<u8>> = RwLock::new(Vec::new());
+
+/// Everything color detection knows, gathered for bug reports (`dmux --color-doctor`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColorDiagnostics {
+    /// `$TERM`, or empty if unset.
+    pub term: String,
+    /// The multiplexer queries are wrapped for.
+    pub multiplexer: Passthrough,
+    /// Whether the terminal applies OSC color sets; `None` before the startup probe.
+    pub supports_osc: Option<bool>,
+    /// How long the startup color query took; zero if it hasn't run.
+    pub last_query_duration: Duration,
+    pub fg: Option<(u8, u8, u8)>,
+    pub bg: Option<(u8, u8, u8)>,
+    /// Where the resolved colors came from.
+    pub source: ColorSource,
+    /// The last reply read from the terminal, unparsed.
+    pub raw_last_response: Vec<u8>,
+}
+
+impl std::fmt::Display for ColorDiagnostics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let color = |color: Option<(u8, u8, u8)>| color.map(color_to_hex);
+        writeln!(f, "term:                {}", self.term)?;
+        writeln!(f, "multiplexer:         {:?}", self.multiplexer)?;
+        writeln!(f, "supports osc:        {:?}", self.supports_osc)?;
+        writeln!(f, "last query duration: {:?}", self.last_query_duration)?;
+        writeln!(f, "fg:                  {:?}", color(self.fg))?;
+        writeln!(f, "bg:                  {:?}", color(self.bg))?;
+        writeln!(f, "source:              {:?}", self.source)?;
+        write!(
+            f,
+            "raw last response:   {:?}",
+            String::from_utf8_lossy(&self.raw_last_response)
+        )
+    }
+}
+
+/// Collect the color subsystem's current state into one report.
+pub fn color_diagnostics() -> ColorDiagnostics {
+    let (colors, source) = get_outer_colors_with_source();
+    ColorDiagnostics {
+        term: std::env::var("TERM").unwrap_or_default(),
+        multiplexer: Passthrough::from_env(),
+        supports_osc: probed_capabilities().map(|capabilities| capabilities.osc_set),
+        last_query_duration: LAST_QUERY_DURATION
+            .read()
+            .ok()
+            .and_then(|duration| *duration)
+            .unwrap_or_default(),
+        fg: colors.foreground,
+        bg: colors.background,
+        source,
+        raw_last_response: LAST_RAW_RESPONSE
+            .read()
+            .map(|response| response.clone())
+            .unwrap_or_default(),
+    }
+}
+
+/// Drain any pending data from stdin (non-blocking).
+fn drain_stdin() {
+    use std::os::unix::io::AsRawFd;
+
+    let stdin = std::io::stdin();
+    let stdin_handle = stdin.lock();
+    let stdin_fd = stdin_handle.as_raw_fd();
+
+    // Set non-blocking
+    let flags = unsafe { libc::fcntl(stdin_fd, libc::F_GETFL) };
+    unsafe { libc::fcntl(stdin_fd, libc::F_SETFL, flags | libc::O_NONBLOCK) };
+
+    // Drain all pending data
+    let mut buf = [0u8; 256];
+    loop {
+        let n = unsafe { libc::read(stdin_fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+        if n <= 0 {
+            break;
+        }
+    }
+
+    // Restore blocking mode
+    unsafe { libc::fcntl(stdin_fd, libc::F_SETFL, flags) };
+}
+
+/// How long to wait for the outer terminal to answer a single OSC query.
+pub(crate) const OSC_QUERY_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Byte-level access to the outer terminal for OSC queries.
+/// Abstracted so query and probe logic can run against mock terminals in tests.
+pub trait OscIo {
+    /// Write raw bytes to the terminal and flush them.
+    fn send(&mut self, bytes: &[u8]) -> std::io::Result<()>;
+    /// Read one reply, returning once an ST or BEL terminator arrives or `timeout` elapses.
+    fn recv(&mut self, timeout: Duration) -> Vec<u8>;
+    /// Like `recv`, but also return as soon as `expected_len` bytes have
+    /// arrived, for terminals whose reply length is fixed and known.
+    fn recv_expecting(&mut self, timeout: Duration, expected_len: Option<usize>) -> Vec<u8> {
+        let _ = expected_len;
+        self.recv(timeout)
+    }
+}
+
+/// `OscIo` over the process's stdin/stdout. Raw mode must already be enabled.
+pub struct StdioOsc;
+
+impl OscIo for StdioOsc {
+    fn send(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        // Drain any leftover data from previous queries or terminal events
+        drain_stdin();
+
+        let mut stdout = std::io::stdout();
+        stdout.write_all(bytes)?;
+        stdout.flush()
+    }
+
+    fn recv(&mut self, timeout: Duration) -> Vec<u8> {
+        self.recv_expecting(timeout, None)
+    }
+
+    fn recv_expecting(&mut self, timeout: Duration, expected_len: Option<usize>) -> Vec<u8> {
+        use std::os::unix::io::AsRawFd;
+
+        let stdin = std::io::stdin();
+        let stdin_handle = stdin.lock();
+        let stdin_fd = stdin_handle.as_raw_fd();
+
+        // Set stdin to non-blocking
+        let flags = unsafe { libc::fcntl(stdin_fd, libc::F_GETFL) };
+        unsafe { libc::fcntl(stdin_fd, libc::F_SETFL, flags | libc::O_NONBLOCK) };
+
+        let response =
+            read_osc_reply_expecting(&mut FdPoller { fd: stdin_fd }, timeout, expected_len);
+
+        // Restore blocking mode
+        unsafe { libc::fcntl(stdin_fd, libc::F_SETFL, flags) };
+
+        if let Ok(mut last) = LAST_RAW_RESPONSE.write() {
+            last.clone_from(&response);
+        }
+        response
+    }
+}
+
+/// First wait in the reply read loop; doubled after each empty wait.
+const INITIAL_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// A byte source that can wait a bounded time for input, with its own clock.
+/// Abstracted so the read loop's timing can be tested without a real terminal.
+trait TimedRead {
+    fn now(&self) -> std::time::Instant;
+    /// Wait up to `wait` for input and read it into `buf`.
+    /// Returns `None` if nothing arrived, `Some(0)` at EOF.
+    fn read_within(&mut self, wait: Duration, buf: &mut [u8]) -> Option<usize>;
+}
+
+/// Poll-based reader over a file descriptor.
+struct FdPoller {
+    fd: std::os::unix::io::RawFd,
+}
+
+impl TimedRead for FdPoller {
+    fn now(&self) -> std::time::Instant {
+        std::time::Instant::now()
+    }
+
+    fn read_within(&mut self, wait: Duration, buf: &mut [u8]) -> Option<usize> {
+        let mut pollfd = libc::pollfd {
+            fd: self.fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        // Round up so sub-millisecond waits still block instead of spinning
+        let timeout_ms = wait.as_micros().div_ceil(1000).min(i32::MAX as u128) as i32;
+        let poll_result = unsafe { libc::poll(&mut pollfd, 1, timeout_ms) };
+        if poll_result < 0
+            && std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted
+        {
+            // Most likely SIGWINCH: the terminal was resized mid-query
+            note_resize_during_query();
+        }
+        if poll_result <= 0 {
+            return None; // Timeout or error
+        }
+
+        let n = unsafe { libc::read(self.fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+        // n < 0 means EAGAIN/EWOULDBLOCK
+        usize::try_from(n).ok()
+    }
+}
+
+/// Longest OSC reply `read_osc_reply` accepts. Real color replies are a few
+/// dozen bytes; anything past this is a runaway stream, not an answer.
+pub(crate) const MAX_OSC_REPLY_LEN: usize = 1024;
+
+/// Read one OSC reply until an ST or BEL terminator, EOF, or `timeout` elapses.
+///
+/// A reply that grows past `MAX_OSC_REPLY_LEN` without a terminator is
+/// abandoned and reported as no reply at all, bounding both memory and time.
+///
+/// The wait between reads starts at `INITIAL_POLL_INTERVAL` and doubles while
+/// nothing arrives, so fast terminals are answered with low latency and slow ones
+/// don't cause frequent wakeups. Waits are clamped so the deadline is never overrun.
+fn read_osc_reply<R: TimedRead>(reader: &mut R, timeout: Duration) -> Vec<u8> {
+    read_osc_reply_expecting(reader, timeout, None)
+}
+
+/// `read_osc_reply` that also stops once `expected_len` bytes have been read,
+/// without waiting for the terminator or deadline. Without a hint it falls
+/// back to terminator and deadline detection.
+fn read_osc_reply_expecting<R: TimedRead>(
+    reader: &mut R,
+    timeout: Duration,
+    expected_len: Option<usize>,
+) -> Vec<u8> {
+    // Response format: OSC code ; rgb:RRRR/GGGG/BBBB ST
+    let mut response = Vec::with_capacity(64);
+    let deadline = reader.now() + timeout;
+    let mut interval = INITIAL_POLL_INTERVAL;
+
+    loop {
+        let remaining = deadline.saturating_duration_since(reader.now());
+        if remaining.is_zero() {
+            break;
+        }
+
+        let mut buf = [0u8; 64];
+        match reader.read_within(interval.min(remaining), &mut buf) {
+            Some(0) => break,
+            Some(n) => {
+                response.extend_from_slice(&buf[..n]);
+                strip_paste_markers(&mut response);
+                if strip_resize_reports(&mut response) {
+                    note_resize_during_query();
+                }
+                trim_line_endings(&mut response);
+
+                if is_osc_reply_terminated(&response)
+                    || expected_len.is_some_and(|len| response.len() >= len)
+                {
+                    break;
+                }
+                if response.len() > MAX_OSC_REPLY_LEN {
+                    return Vec::new();
+                }
+                // More of the reply is likely in flight; go back to short waits
+                interval = INITIAL_POLL_INTERVAL;
+            }
+            None => interval = interval.saturating_mul(2),
+        }
+    }
+
+    response
+}
+
+/// Drop CR/LF around a reply. Some terminals send `\r\n` after the
+/// terminator, so a reply read in one chunk would otherwise not end with it,
+/// and one that arrives late leads the next reply.
+fn trim_line_endings(buf: &mut Vec<u8>) {
+    let is_line_ending = |b: &u8| matches!(b, b'\r' | b'\n');
+    while buf.last().is_some_and(is_line_ending) {
+        buf.pop();
+    }
+    let leading = buf.iter().take_while(|b| is_line_ending(b)).count();
+    buf.drain(..leading);
+}
+
+/// Bracketed paste markers some terminals wrap OSC replies in.
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// Remove complete bracketed paste markers from `buf`, keeping what they wrap.
+/// A marker split across reads is removed once its tail arrives.
+fn strip_paste_markers(buf: &mut Vec<u8>) {
+    for marker in [PASTE_START, PASTE_END] {
+        while let Some(at) = find_bytes(buf, marker) {
+            buf.drain(at..at + marker.len());
+        }
+    }
+}
+
+/// Set when the terminal was resized while a query had it in raw mode, so
+/// the main loop can catch up on the size it missed.
+static RESIZED_DURING_QUERY: AtomicBool = AtomicBool::new(false);
+
+fn note_resize_during_query() {
+    RESIZED_DURING_QUERY.store(true, Ordering::Relaxed);
+}
+
+/// Whether the terminal was resized during a color query since the last call.
+pub fn take_resize_during_query() -> bool {
+    RESIZED_DURING_QUERY.swap(false, Ordering::Relaxed)
+}
+
+/// Remove complete in-band resize reports (`CSI 48 ; rows ; cols ; h ; w t`,
+/// sent by terminals with mode 2048 on) from a reply being read, returning
+/// whether there were any. A report cut off at the end is left to complete.
+fn strip_resize_reports(buf: &mut Vec<u8>) -> bool {
+    const REPORT_START: &[u8] = b"\x1b[48;";
+    let mut found = false;
+    let mut from = 0;
+    while let Some(at) = find_bytes(&buf[from..], REPORT_START).map(|i| from + i) {
+        let params = at + REPORT_START.len();
+        let len = buf[params..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit() || **b == b';')
+            .count();
+        match buf.get(params + len) {
+            Some(b't') => {
+                buf.drain(at..=params + len);
+                found = true;
+                from = at;
+            }
+            // Not a resize report after all
+            Some(_) => from = params,
+            None => break,
+        }
+    }
+    found
+}
+
+/// The OSC reply carried by a paste event, if the pasted text is one.
+///
+/// Under bracketed paste a few terminals deliver late OSC replies as pastes;
+/// those must not be forwarded to the pane as input.
+pub fn pasted_osc_reply(text: &str) -> Option<&[u8]> {
+    let bytes = text.as_bytes();
+    let is_osc = bytes.starts_with(b"\x1b]") || bytes.starts_with(&[C1_OSC]);
+    (is_osc && is_osc_reply_terminated(bytes)).then_some(bytes)
+}
+
+/// 8-bit C1 OSC introducer, the one-byte form of `ESC ]`.
+pub(crate) const C1_OSC: u8 = 0x9d;
+/// 8-bit C1 string terminator, the one-byte form of `ESC \`.
+const C1_ST: u8 = 0x9c;
+
+/// Whether `response` ends with an OSC terminator: ST (`ESC \`), BEL, or the
+/// C1 ST byte, ignoring trailing whitespace such as a CR/LF some terminals
+/// append. C1 ST only counts after a C1 OSC introducer, since 0x9C also
+/// occurs inside UTF-8 characters typed while a query is in flight.
+pub(crate) fn is_osc_reply_terminated(response: &[u8]) -> bool {
+    let response = response.trim_ascii_end();
+    response.ends_with(&[0x1b, b'\\'])
+        || response.ends_with(&[0x07])
+        || (response.ends_with(&[C1_ST]) && response.contains(&C1_OSC))
+}
+
+/// Screen-buffer control needed to re-query colors from inside the running TUI.
+pub trait ScreenIo: OscIo {
+    fn leave_alternate_screen(&mut self) -> std::io::Result<()>;
+    fn enter_alternate_screen(&mut self) -> std::io::Result<()>;
+}
+
+impl ScreenIo for StdioOsc {
+    // In soft screen mode dmux is already on the normal screen, where queries
+    // work, and switching buffers would hide the frame instead
+    fn leave_alternate_screen(&mut self) -> std::io::Result<()> {
+        if active_screen_mode() == Some(ScreenMode::Soft) {
+            return Ok(());
+        }
+        crossterm::execute!(std::io::stdout(), crossterm::terminal::LeaveAlternateScreen)
+    }
+
+    fn enter_alternate_screen(&mut self) -> std::io::Result<()> {
+        if active_screen_mode() == Some(ScreenMode::Soft) {
+            return Ok(());
+        }
+        crossterm::execute!(std::io::stdout(), crossterm::terminal::EnterAlternateScreen)
+    }
+}
+
+/// How long to wait after leaving the alternate screen before querying.
+const SCREEN_SETTLE_DELAY: Duration = Duration::from_millis(50);
+
+/// Leave the alternate screen, query colors, and re-enter it.
+///
+/// The alternate screen is always re-entered, even if leaving it failed part-way
+/// or the terminal didn't answer, so the UI is never stranded on the main screen.
+fn query_colors_outside_alt_screen<T: ScreenIo>(
+    io: &mut T,
+    settle: Duration,
+) -> std::io::Result<TerminalColors> {
+    if let Err(e) = io.leave_alternate_screen() {
+        let _ = io.enter_alternate_screen();
+        return Err(e);
+    }
+
+    // Small delay to let terminal settle
+    std::thread::sleep(settle);
+
+    let colors = query_colors_with(io);
+    io.enter_alternate_screen()?;
+    Ok(colors)
+}
+
+/// Re-read the outer terminal's theme from inside the already-raw main loop.
+///
+/// Intended to be bound to a key ("my theme changed, re-read it now"). Updates the
+/// stored colors and emits `MuxEvent::ThemeColorsRefreshed` so the caller can redraw.
+/// On error the alternate screen has still been re-entered where possible, and the
+/// cache is left untouched if the reply was partial or degenerate.
+pub fn force_refresh_colors_interactive(
+    tx: &mpsc::UnboundedSender<MuxEvent>,
+) -> std::io::Result<TerminalColors> {
+    if !acquire_color_query_slot() {
+        return Err(color_query_rate_limited());
+    }
+    let colors = query_colors_outside_alt_screen(&mut StdioOsc, SCREEN_SETTLE_DELAY)?;
+    if !commit_colors(colors) {
+        return Err(std::io::Error::other(
+            "terminal did not report usable colors",
+        ));
+    }
+    let _ = tx.send(MuxEvent::ThemeColorsRefreshed { colors });
+    Ok(colors)
+}
+
+/// Shortest time between two color queries by default.
+pub const DEFAULT_MIN_COLOR_QUERY_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Spaces out color queries so a slow terminal isn't flooded with OSC
+/// handshakes (each one toggling raw mode) when several re-queries fire at
+/// once: signal, manual, deferred and so on.
+///
+/// A query asked for too soon after the last one is queued rather than
+/// dropped, and any number of queued requests coalesce into a single query
+/// once the interval has passed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorQueryLimiter {
+    min_interval: Duration,
+    last_query: Option<std::time::Instant>,
+    queued: bool,
+}
+
+impl ColorQueryLimiter {
+    pub const fn new(min_interval: Duration) -> Self {
+        Self {
+            min_interval,
+            last_query: None,
+            queued: false,
+        }
+    }
+
+    /// Ask to query at `now`. Returns whether the query may go ahead (and
+    /// counts it as made); otherwise the request is queued.
+    pub fn request(&mut self, now: std::time::Instant) -> bool {
+        if self.ready(now) {
+            self.last_query = Some(now);
+            self.queued = false;
+            true
+        } else {
+            self.queued = true;
+            false
+        }
+    }
+
+    /// Whether a queued request may run at `now`. Taking it clears the queue,
+    /// so whatever runs it must still `request` the slot.
+    pub fn take_due(&mut self, now: std::time::Instant) -> bool {
+        let due = self.queued && self.ready(now);
+        if due {
+            self.queued = false;
+        }
+        due
+    }
+
+    fn ready(&self, now: std::time::Instant) -> bool {
+        self.last_query
+            .is_none_or(|last| now.saturating_duration_since(last) >= self.min_interval)
+    }
+}
+
+/// Rate limit shared by every query of the outer terminal's colors.
+static COLOR_QUERY_LIMITER: std::sync::Mutex<ColorQueryLimiter> =
+    std::sync::Mutex::new(ColorQueryLimiter::new(DEFAULT_MIN_COLOR_QUERY_INTERVAL));
+
+/// Set the shortest time between color queries (the
+/// `min_color_query_interval_ms` setting).
+pub fn set_min_color_query_interval(min_interval: Duration) {
+    if let Ok(mut limiter) = COLOR_QUERY_LIMITER.lock() {
+        limiter.min_interval = min_interval;
+    }
+}
+
+/// Claim the slot for a color query now, or queue one for later (see
+/// `take_due_color_query`). A poisoned lock lets the query through.
+fn acquire_color_query_slot() -> bool {
+    COLOR_QUERY_LIMITER
+        .lock()
+        .map(|mut limiter| limiter.request(std::time::Instant::now()))
+        .unwrap_or(true)
+}
+
+/// Run `query` if it may go ahead under the color query rate limit; `None`
+/// if it was rate limited, in which case a color re-query is queued.
+fn with_color_query_slot<R>(query: impl FnOnce() -> R) -> Option<R> {
+    acquire_color_query_slot().then(query)
+}
+
+/// Whether a color query that was rate limited may run at `now`. The caller
+/// should re-query then, e.g. by emitting `MuxEvent::ThemeChanged`.
+pub fn take_due_color_query(now: std::time::Instant) -> bool {
+    COLOR_QUERY_LIMITER
+        .lock()
+        .map(|mut limiter| limiter.take_due(now))
+        .unwrap_or(false)
+}
+
+/// The error a rate-limited query reports: it's been queued, not failed.
+fn color_query_rate_limited() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::WouldBlock,
+        "color query rate limited; queued",
+    )
+}
+
+/// How long after startup the colors are asked for again by default, when
+/// the startup query went unanswered.
+pub const DEFAULT_COLOR_REQUERY_DELAY: Duration = Duration::from_millis(500);
+
+/// A one-shot re-query of the outer terminal's colors shortly after startup.
+///
+/// Some terminals are still initializing when dmux starts and don't answer the
+/// startup query even though they support OSC 10/11, leaving dmux on fallback
+/// colors for the whole session. Asking once more after they've settled picks
+/// up the real theme.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeferredColorQuery {
+    due: Option<std::time::Instant>,
+}
+
+impl DeferredColorQuery {
+    /// Schedule a re-query `delay` after `started` if `startup_answer` (what
+    /// the startup query got back) is missing the foreground or background.
+    /// A zero `delay` disables the re-query.
+    pub fn schedule(
+        started: std::time::Instant,
+        delay: Duration,
+        startup_answer: &TerminalColors,
+    ) -> Self {
+        let answered = startup_answer.foreground.is_some() && startup_answer.background.is_some();
+        Self {
+            due: (!answered && !delay.is_zero()).then(|| started + delay),
+        }
+    }
+
+    /// Whether the re-query has yet to run.
+    pub fn is_pending(&self) -> bool {
+        self.due.is_some()
+    }
+
+    /// Run the re-query if it is due at `now`, committing the colors and
+    /// emitting `MuxEvent::ThemeColorsRefreshed` if the terminal answers this
+    /// time. Returns the new colors in that case. If color queries are rate
+    /// limited just then, the re-query joins the queue for the next slot.
+    pub fn poll(
+        &mut self,
+        now: std::time::Instant,
+        tx: &mpsc::UnboundedSender<MuxEvent>,
+    ) -> Option<TerminalColors> {
+        if !self.is_due(now) {
+            return None;
+        }
+        if !acquire_color_query_slot() {
+            self.due = None;
+            return None;
+        }
+        let colors = self.poll_with(now, &mut StdioOsc, SCREEN_SETTLE_DELAY, &OUTER_COLORS)?;
+        OUTER_ANSI256.invalidate();
+        let _ = tx.send(MuxEvent::ThemeColorsRefreshed { colors });
+        Some(colors)
+    }
+
+    /// `poll` through `io`, committing to `cache` the same way the startup
+    /// query does. The query runs at most once, whether or not it is answered.
+    fn poll_with<T: ScreenIo>(
+        &mut self,
+        now: std::time::Instant,
+        io: &mut T,
+        settle: Duration,
+        cache: &ColorCache,
+    ) -> Option<TerminalColors> {
+        if !self.is_due(now) {
+            return None;
+        }
+        self.due = None;
+        let colors = query_colors_outside_alt_screen(io, settle).ok()?;
+        let answered = colors.foreground.is_some() && colors.background.is_some();
+        let committed = commit_live_query(cache, colors, colors.background.into());
+        (answered && committed).then_some(colors)
+    }
+
+    fn is_due(&self, now: std::time::Instant) -> bool {
+        self.due.is_some_and(|due| now >= due)
+    }
+}
+
+/// Query one OSC color from the controlling terminal: 10 (fg), 11 (bg),
+/// 12 (cursor), 17 (selection bg) or 19 (selection fg).
+///
+/// Like `query_outer_terminal_colors_anytime`, this goes through its own
+/// `/dev/tty` handle, so pause other readers of the terminal around the call.
+/// Returns `None` if there is no terminal, it didn't answer, or the query was
+/// rate limited (see `ColorQueryLimiter`).
+pub fn query_osc_color(code: u8) -> Option<(u8, u8, u8)> {
+    query_osc_color_expecting(code, None)
+}
+
+/// `query_osc_color` for a terminal whose reply is known to be `expected_len`
+/// bytes long (see `xterm_color_reply_len`): the read stops as soon as that
+/// many bytes arrive instead of waiting for the terminator.
+pub fn query_osc_color_expecting(code: u8, expected_len: Option<usize>) -> Option<(u8, u8, u8)> {
+    with_color_query_slot(|| query_tty_osc_color(code, expected_len)).flatten()
+}
+
+/// `query_osc_color_expecting` once the caller holds a query slot.
+fn query_tty_osc_color(code: u8, expected_len: Option<usize>) -> Option<(u8, u8, u8)> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(CONTROLLING_TTY)
+        .ok()?;
+    let mut raw_mode = TtyRawMode {
+        fd: tty.as_raw_fd(),
+        saved: None,
+    };
+    with_raw_mode(&mut raw_mode, || {
+        let response = query_osc_reply_expecting(&mut TtyOsc { tty: &mut tty }, code, expected_len);
+        parse_osc_color_response(&response)
+    })
+    .flatten()
+}
+
+/// Length of a color reply in xterm's form, `ESC ] code ; rgb:RRRR/GGGG/BBBB ESC \`.
+pub fn xterm_color_reply_len(code: u8) -> usize {
+    format!("\x1b]{code};rgb:0000/0000/0000\x1b\\").len()
+}
+
+/// Query a specific OSC color (10=fg, 11=bg, 12=cursor, 17=selection bg,
+/// 19=selection fg) through the given terminal I/O.
+fn query_osc_color_with<T: OscIo>(io: &mut T, code: u8) -> Option<(u8, u8, u8)> {
+    parse_osc_color_response(&query_osc_reply_with(io, code))
+}
+
+/// Send an OSC color query and return the raw reply, or nothing if the query
+/// couldn't be sent or the reply was meant for a different query.
+fn query_osc_reply_with<T: OscIo>(io: &mut T, code: u8) -> Vec<u8> {
+    query_osc_reply_expecting(io, code, None)
+}
+
+/// `query_osc_reply_with`, reading only `expected_len` bytes if given.
+fn query_osc_reply_expecting<T: OscIo>(
+    io: &mut T,
+    code: u8,
+    expected_len: Option<usize>,
+) -> Vec<u8> {
+    // Send query: OSC code ; ? ST
+    let query = format!("\x1b]{};?\x1b\\", code);
+    if io.send(query.as_bytes()).is_err() {
+        return Vec::new();
+    }
+
+    let response = io.recv_expecting(OSC_QUERY_TIMEOUT, expected_len);
+    notify_osc_observers(code, &response);
+    if !osc_reply_matches_code(&response, code) {
+        return Vec::new();
+    }
+    response
+}
+
+/// The OSC code a color reply answers (the `11` in `ESC ] 11 ; rgb:...`), or
+/// `None` if the terminal left it out.
+pub(crate) fn osc_reply_code(response: &[u8]) -> Option<u16> {
+    let marker =
+        find_bytes(response, b"rgb:").or_else(|| find_bytes(response, b";#").map(|i| i + 1))?;
+    let prefix = &response[..marker];
+    let start = prefix.iter().rposition(|&b| b == b']' || b == C1_OSC)? + 1;
+    let code = take_ascii(&prefix[start..], |b| b.is_ascii_digit());
+    if prefix.get(start + code.len()) != Some(&b';') {
+        return None;
+    }
+    code.parse().ok()
+}
+
+/// Whether a color reply answers a query for OSC `code`.
+///
+/// A few terminals reply with a bare `rgb:RRRR/GGGG/BBBB`; a reply without a
+/// code matches any query, so only a code that is present can be wrong.
+pub(crate) fn osc_reply_matches_code(response: &[u8], code: u8) -> bool {
+    osc_reply_code(response).is_none_or(|found| found == u16::from(code))
+}
+
+/// Format an OSC color set sequence: OSC code ; rgb:RR/GG/BB ST
+fn format_osc_set(code: u8, (r, g, b): (u8, u8, u8)) -> String {
+    format!("\x1b]{};rgb:{:02x}/{:02x}/{:02x}\x1b\\", code, r, g, b)
+}
+
+/// Set one ANSI palette entry on the outer terminal.
+///
+/// Uses kitty's color protocol on kitty and OSC 4 everywhere else.
+pub fn set_ansi_color<W: Write>(
+    out: &mut W,
+    index: u8,
+    color: (u8, u8, u8),
+) -> std::io::Result<()> {
+    write_palette_entries(out, &[(index, color)], PaletteProtocol::detect())
+}
+
+/// OSC 110: reset the default foreground to the terminal's own.
+pub const OSC_RESET_FOREGROUND: &[u8] = b"\x1b]110\x1b\\";
+/// OSC 111: reset the default background to the terminal's own.
+pub const OSC_RESET_BACKGROUND: &[u8] = b"\x1b]111\x1b\\";
+/// OSC 112: reset the cursor color to the terminal's own.
+pub const OSC_RESET_CURSOR: &[u8] = b"\x1b]112\x1b\\";
+
+/// Reset the outer terminal's default foreground (OSC 110).
+pub fn reset_foreground<W: Write>(out: &mut W) -> std::io::Result<()> {
+    out.write_all(OSC_RESET_FOREGROUND)
+}
+
+/// Reset the outer terminal's default background (OSC 111).
+pub fn reset_background<W: Write>(out: &mut W) -> std::io::Result<()> {
+    out.write_all(OSC_RESET_BACKGROUND)
+}
+
+/// Reset the outer terminal's cursor color (OSC 112).
+pub fn reset_cursor_color<W: Write>(out: &mut W) -> std::io::Result<()> {
+    out.write_all(OSC_RESET_CURSOR)
+}
+
+/// Reset palette entries on the outer terminal (OSC 104): `indices`, or the
+/// whole palette if it's empty.
+pub fn reset_palette<W: Write>(out: &mut W, indices: &[u8]) -> std::io::Result<()> {
+    let mut sequence = String::from("\x1b]104");
+    for index in indices {
+        sequence.push_str(&format!(";{index}"));
+    }
+    sequence.push_str("\x1b\\");
+    out.write_all(sequence.as_bytes())
+}
+
+/// Reset everything dmux may have set on the outer terminal: foreground,
+/// background, cursor color and the whole palette.
+pub fn reset_all_colors<W: Write>(out: &mut W) -> std::io::Result<()> {
+    reset_foreground(out)?;
+    reset_background(out)?;
+    reset_cursor_color(out)?;
+    reset_palette(out, &[])
+}
+
+/// Emit one frame of a palette animation: the palette `t` of the way from `from`
+/// to `to`, flushed as a single write.
+pub fn emit_palette_frame<W: Write>(
+    out: &mut W,
+    from: &[(u8, u8, u8); 16],
+    to: &[(u8, u8, u8); 16],
+    t: f32,
+) -> std::io::Result<()> {
+    emit_palette_frame_via(out, from, to, t, PaletteProtocol::detect())
+}
+
+fn emit_palette_frame_via<W: Write>(
+    out: &mut W,
+    from: &[(u8, u8, u8); 16],
+    to: &[(u8, u8, u8); 16],
+    t: f32,
+    protocol: PaletteProtocol,
+) -> std::io::Result<()> {
+    let entries: Vec<(u8, (u8, u8, u8))> = lerp_palette(from, to, t)
+        .into_iter()
+        .enumerate()
+        .map(|(index, color)| (index as u8, color))
+        .collect();
+    let mut frame = Vec::with_capacity(16 * 24);
+    write_palette_entries(&mut frame, &entries, protocol)?;
+    out.write_all(&frame)?;
+    out.flush()
+}
+
+/// How palette entries are queried and set on the outer terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaletteProtocol {
+    /// One OSC 4 sequence per index.
+    Osc4,
+    /// kitty's OSC 21 color control, covering many indices in one sequence.
+    Kitty,
+}
+
+impl PaletteProtocol {
+    /// The protocol the outer terminal understands best.
+    pub fn detect() -> Self {
+        if terminal_capabilities().kitty_colors {
+            Self::Kitty
+        } else {
+            Self::Osc4
+        }
+    }
+}
+
+/// OSC code of kitty's color control protocol.
+const KITTY_COLOR_OSC: u8 = 21;
+
+/// Write palette sets for `entries` (index, color) in the given protocol.
+fn write_palette_entries<W: Write>(
+    out: &mut W,
+    entries: &[(u8, (u8, u8, u8))],
+    protocol: PaletteProtocol,
+) -> std::io::Result<()> {
+    match protocol {
+        PaletteProtocol::Osc4 => {
+            for &(index, (r, g, b)) in entries {
+                write!(
+                    out,
+                    "\x1b]4;{};rgb:{:02x}/{:02x}/{:02x}\x1b\\",
+                    index, r, g, b
+                )?;
+            }
+            Ok(())
+        }
+        PaletteProtocol::Kitty => out.write_all(format_kitty_palette_set(entries).as_bytes()),
+    }
+}
+
+/// Encode a kitty palette set: OSC 21 ; idx=rgb:RR/GG/BB ; ... ST.
+pub(crate) fn format_kitty_palette_set(entries: &[(u8, (u8, u8, u8))]) -> String {
+    let pairs: Vec<String> = entries
+        .iter()
+        .map(|&(index, (r, g, b))| format!("{index}=rgb:{r:02x}/{g:02x}/{b:02x}"))
+        .collect();
+    format!("\x1b]{KITTY_COLOR_OSC};{}\x1b\\", pairs.join(";"))
+}
+
+/// Encode a kitty query for ANSI colors 0-15: OSC 21 ; 0=? ; ... ; 15=? ST.
+pub(crate) fn kitty_palette_query() -> String {
+    let keys: Vec<String> = (0..16).map(|idx| format!("{idx}=?")).collect();
+    format!("\x1b]{KITTY_COLOR_OSC};{}\x1b\\", keys.join(";"))
+}
+
+/// Parse a kitty OSC 21 reply into palette entries 0-15.
+///
+/// The reply carries one `key=value` pair per queried key; a key kitty has no
+/// color for comes back with an empty value and stays `None`.
+pub(crate) fn parse_kitty_palette_response(response: &[u8]) -> AnsiPalette {
+    let mut palette = [None; 16];
+    let prefix = format!("]{KITTY_COLOR_OSC};");
+    let Some(start) = find_bytes(response, prefix.as_bytes()) else {
+        return palette;
+    };
+    let body = take_ascii(&response[start + prefix.len()..], |b| {
+        b.is_ascii() && b != 0x1b && b != 0x07
+    });
+    for (key, value) in body.split(';').filter_map(|pair| pair.split_once('=')) {
+        let Some(entry) = key
+            .parse::<usize>()
+            .ok()
+            .and_then(|idx| palette.get_mut(idx))
+        else {
+            continue;
+        };
+        *entry = if value.starts_with('#') {
+            parse_hex_color(value)
+        } else if value.starts_with("rgb:") {
+            parse_builtin_osc_color_response(value.as_bytes())
+        } else {
+            None
+        };
+    }
+    palette
+}
+
+/// Cursor shape reported by the outer terminal (DECSCUSR values 1-6).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorStyle {
+    BlinkingBlock,
+    SteadyBlock,
+    BlinkingUnderline,
+    SteadyUnderline,
+    BlinkingBar,
+    SteadyBar,
+}
+
+impl CursorStyle {
+    /// The DECSCUSR parameter selecting this style.
+    pub fn decscusr_param(self) -> u8 {
+        match self {
+            CursorStyle::BlinkingBlock => 1,
+            CursorStyle::SteadyBlock => 2,
+            CursorStyle::BlinkingUnderline => 3,
+            CursorStyle::SteadyUnderline => 4,
+            CursorStyle::BlinkingBar => 5,
+            CursorStyle::SteadyBar => 6,
+        }
+    }
+
+    /// Parse a DECSCUSR parameter. 0 means "terminal default", which is treated
+    /// as a blinking block as in xterm.
+    pub fn from_decscusr_param(param: u8) -> Option<Self> {
+        match param {
+            0 | 1 => Some(CursorStyle::BlinkingBlock),
+            2 => Some(CursorStyle::SteadyBlock),
+            3 => Some(CursorStyle::BlinkingUnderline),
+            4 => Some(CursorStyle::SteadyUnderline),
+            5 => Some(CursorStyle::BlinkingBar),
+            6 => Some(CursorStyle::SteadyBar),
+            _ => None,
+        }
+    }
+
+    /// The same shape with blinking turned on or off.
+    pub fn with_blinking(self, blinking: bool) -> Self {
+        match (self, blinking) {
+            (CursorStyle::BlinkingBlock | CursorStyle::SteadyBlock, true) => {
+                CursorStyle::BlinkingBlock
+            }
+            (CursorStyle::BlinkingBlock | CursorStyle::SteadyBlock, false) => {
+                CursorStyle::SteadyBlock
+            }
+            (CursorStyle::BlinkingUnderline | CursorStyle::SteadyUnderline, true) => {
+                CursorStyle::BlinkingUnderline
+            }
+            (CursorStyle::BlinkingUnderline | CursorStyle::SteadyUnderline, false) => {
+                CursorStyle::SteadyUnderline
+            }
+            (CursorStyle::BlinkingBar | CursorStyle::SteadyBar, true) => CursorStyle::BlinkingBar,
+            (CursorStyle::BlinkingBar | CursorStyle::SteadyBar, false) => CursorStyle::SteadyBar,
+        }
+    }
+
+    /// The DECSCUSR sequence selecting this style.
+    pub fn decscusr(self) -> String {
+        format!("\x1b[{} q", self.decscusr_param())
+    }
+}
+
+impl From<CursorStyle> for crossterm::cursor::SetCursorStyle {
+    fn from(style: CursorStyle) -> Self {
+        use crossterm::cursor::SetCursorStyle;
+        match style {
+            CursorStyle::BlinkingBlock => SetCursorStyle::BlinkingBlock,
+            CursorStyle::SteadyBlock => SetCursorStyle::SteadyBlock,
+            CursorStyle::BlinkingUnderline => SetCursorStyle::BlinkingUnderScore,
+            CursorStyle::SteadyUnderline => SetCursorStyle::SteadyUnderScore,
+            CursorStyle::BlinkingBar => SetCursorStyle::BlinkingBar,
+            CursorStyle::SteadyBar => SetCursorStyle::SteadyBar,
+        }
+    }
+}
+
+/// Cursor style queried from the outer terminal at startup.
+static OUTER_CURSOR_STYLE: RwLock<Option<CursorStyle>> = RwLock::new(None);
+
+/// Get the outer terminal's cursor style, if it was reported.
+pub fn get_outer_cursor_style() -> Option<CursorStyle> {
+    OUTER_CURSOR_STYLE.read().ok().and_then(|g| *g)
+}
+
+/// Query the outer terminal's cursor style via DECRQSS and store it.
+///
+/// Same requirements as `query_outer_terminal_colors`: call before entering
+/// the alternate screen. Returns `None` if the query was rate limited.
+pub fn query_outer_cursor_style() -> Option<CursorStyle> {
+    with_color_query_slot(query_and_store_cursor_style).flatten()
+}
+
+/// `query_outer_cursor_style` once the caller holds a query slot.
+fn query_and_store_cursor_style() -> Option<CursorStyle> {
+    let style = with_raw_mode(&mut CrosstermRawMode, || {
+        query_cursor_style_with(&mut StdioOsc)
+    })?;
+
+    if let Ok(mut stored) = OUTER_CURSOR_STYLE.write() {
+        *stored = style;
+    }
+    style
+}
+
+/// Request the DECSCUSR setting (DCS $ q SP q ST) through the given terminal I/O.
+fn query_cursor_style_with<T: OscIo>(io: &mut T) -> Option<CursorStyle> {
+    io.send(b"\x1bP$q q\x1b\\").ok()?;
+    parse_decrqss_cursor_response(&io.recv(OSC_QUERY_TIMEOUT))
+}
+
+/// Parse a DECRQSS reply for DECSCUSR: DCS 1 $ r Ps SP q ST.
+/// A `0 $ r` reply means the request was invalid.
+pub(crate) fn parse_decrqss_cursor_response(response: &[u8]) -> Option<CursorStyle> {
+    let start = find_bytes(response, b"1$r")? + 3;
+    let param = take_ascii(&response[start..], |b| b.is_ascii_digit());
+    if !response[start + param.len()..].starts_with(b" q") {
+        return None;
+    }
+    // An empty parameter means the default; an out-of-range one is invalid
+    let param = if param.is_empty() {
+        0
+    } else {
+        param.parse().ok()?
+    };
+    CursorStyle::from_decscusr_param(param)
+}
+
+/// Re-emit the outer terminal's cursor style so dmux's own cursor matches it.
+///
+/// Call after entering the alternate screen. Does nothing if the style is unknown.
+/// Returns whether a sequence was written.
+pub fn apply_inherited_cursor_style() -> std::io::Result<bool> {
+    write_cursor_style(&mut std::io::stdout(), get_outer_cursor_style())
+}
+
+fn write_cursor_style<W: Write>(out: &mut W, style: Option<CursorStyle>) -> std::io::Result<bool> {
+    let Some(style) = style else {
+        return Ok(false);
+    };
+    out.write_all(style.decscusr().as_bytes())?;
+    out.flush()?;
+    Ok(true)
+}
+
+/// The outer terminal's 16 ANSI colors; `None` where an entry wasn't reported.
+pub type AnsiPalette = [Option<(u8, u8, u8)>; 16];
+
+/// ANSI palette queried from the outer terminal at startup.
+static OUTER_PALETTE: RwLock<AnsiPalette> = RwLock::new([None; 16]);
+
+/// Get the outer terminal's color for ANSI index 0-15, if it was reported.
+pub fn get_ansi_color(idx: u8) -> Option<(u8, u8, u8)> {
+    let palette = OUTER_PALETTE.read().ok()?;
+    palette_color(&palette, idx)
+}
+
+/// Get the outer terminal's bright variant of ANSI color `idx` (0-7 maps to 8-15),
+/// so bold text can use the terminal's own bright color instead of a synthesized one.
+pub fn get_bright_ansi_color(idx: u8) -> Option<(u8, u8, u8)> {
+    let palette = OUTER_PALETTE.read().ok()?;
+    bright_palette_color(&palette, idx)
+}
+
+/// Replace the outer palette entries that `palette` sets, keeping the rest.
+pub fn merge_outer_palette(palette: &AnsiPalette) {
+    if let Ok(mut stored) = OUTER_PALETTE.write() {
+        for (entry, color) in stored.iter_mut().zip(palette) {
+            if color.is_some() {
+                *entry = *color;
+            }
+        }
+    }
+}
+
+fn palette_color(palette: &AnsiPalette, idx: u8) -> Option<(u8, u8, u8)> {
+    palette.get(idx as usize).copied().flatten()
+}
+
+fn bright_palette_color(palette: &AnsiPalette, idx: u8) -> Option<(u8, u8, u8)> {
+    if idx >= 8 {
+        return None;
+    }
+    palette_color(palette, idx + 8)
+}
+
+/// Query the outer terminal's ANSI colors 0-15 and store them, in one kitty
+/// request on kitty and via OSC 4 elsewhere.
+///
+/// Same requirements as `query_outer_terminal_colors`: call before entering
+/// the alternate screen. A rate-limited query leaves the stored palette as it
+/// was and returns it.
+pub fn query_outer_palette() -> AnsiPalette {
+    with_color_query_slot(query_and_store_palette).unwrap_or_else(|| {
+        OUTER_PALETTE
+            .read()
+            .map(|palette| *palette)
+            .unwrap_or([None; 16])
+    })
+}
+
+/// `query_outer_palette` once the caller holds a query slot.
+fn query_and_store_palette() -> AnsiPalette {
+    let protocol = PaletteProtocol::detect();
+    let palette = with_raw_mode(&mut CrosstermRawMode, || {
+        query_palette_via(&mut StdioOsc, protocol)
+    })
+    .unwrap_or([None; 16]);
+
+    if let Ok(mut stored) = OUTER_PALETTE.write() {
+        *stored = palette;
+    }
+    palette
+}
+
+/// Reset the outer terminal's colors (see `reset_all_colors`) and query them
+/// again, storing what the terminal reports now: its own defaults, rather
+/// than whatever was set on it before.
+///
+/// Same requirements as `query_outer_terminal_colors`: call before entering
+/// the alternate screen. If color queries are rate limited, only the resets
+/// are sent, the re-query is queued, and the cached colors are returned.
+pub fn reset_outer_colors() -> TerminalColors {
+    if !acquire_color_query_slot() {
+        let mut stdout = std::io::stdout();
+        let _ = reset_all_colors(&mut stdout).and_then(|()| stdout.flush());
+        return OUTER_COLORS.get();
+    }
+    let protocol = PaletteProtocol::detect();
+    let (colors, palette) = with_raw_mode(&mut CrosstermRawMode, || {
+        reset_and_query_with(&mut StdioOsc, protocol)
+    })
+    .unwrap_or_default();
+
+    commit_colors(colors);
+    if let Ok(mut stored) = OUTER_PALETTE.write() {
+        *stored = palette;
+    }
+    colors
+}
+
+/// Send the resets through `io`, then query the colors and palette.
+fn reset_and_query_with<T: OscIo>(
+    io: &mut T,
+    protocol: PaletteProtocol,
+) -> (TerminalColors, AnsiPalette) {
+    let mut resets = Vec::new();
+    if reset_all_colors(&mut resets).is_err() || io.send(&resets).is_err() {
+        return (TerminalColors::default(), [None; 16]);
+    }
+    (query_colors_with(io), query_palette_via(io, protocol))
+}
+
+/// Query the 16 ANSI colors in `protocol`. A kitty query that comes back empty
+/// falls back to OSC 4.
+fn query_palette_via<T: OscIo>(io: &mut T, protocol: PaletteProtocol) -> AnsiPalette {
+    if protocol == PaletteProtocol::Kitty {
+        let palette = query_palette_kitty_with(io);
+        if palette.iter().any(Option::is_some) {
+            return palette;
+        }
+    }
+    query_palette_with(io)
+}
+
+/// Query all 16 ANSI colors with a single kitty OSC 21 request.
+fn query_palette_kitty_with<T: OscIo>(io: &mut T) -> AnsiPalette {
+    if io.send(kitty_palette_query().as_bytes()).is_err() {
+        return [None; 16];
+    }
+    let response = io.recv(OSC_QUERY_TIMEOUT);
+    notify_osc_observers(KITTY_COLOR_OSC, &response);
+    parse_kitty_palette_response(&response)
+}
+
+/// Query each of the 16 ANSI colors (OSC 4 ; N ; ? ST) through the given terminal I/O.
+///
+/// Stops at the first unanswered index so a terminal that ignores OSC 4 costs
+/// one timeout rather than sixteen.
+fn query_palette_with<T: OscIo>(io: &mut T) -> AnsiPalette {
+    let mut palette = [None; 16];
+    for (idx, entry) in palette.iter_mut().enumerate() {
+        if io.send(format!("\x1b]4;{idx};?\x1b\\").as_bytes()).is_err() {
+            break;
+        }
+        let response = io.recv(OSC_QUERY_TIMEOUT);
+        notify_osc_observers(4, &response);
+        let Some(color) = parse_palette_response(&response, idx as u8) else {
+            break;
+        };
+        *entry = Some(color);
+    }
+    palette
+}
+
+/// Parse an OSC 4 reply for palette index `idx`: OSC 4 ; idx ; color ST.
+/// A reply for a different index is rejected.
+pub(crate) fn parse_palette_response(response: &[u8], idx: u8) -> Option<(u8, u8, u8)> {
+    let prefix = format!("]4;{idx};");
+    let start = find_bytes(response, prefix.as_bytes())?;
+    parse_osc_color_response(&response[start..])
+}
+
+/// Query the outer terminal's colors for arbitrary palette indices, including
+/// the 256-color entries 16-255 that `query_outer_palette` doesn't cover.
+/// Indices the terminal doesn't report are missing from the result.
+///
+/// Same requirements as `query_outer_terminal_colors`: call before entering
+/// the alternate screen. A rate-limited query reports nothing.
+pub fn query_palette_indices(indices: &[u8]) -> HashMap<u8, (u8, u8, u8)> {
+    with_color_query_slot(|| {
+        with_raw_mode(&mut CrosstermRawMode, || {
+            query_palette_indices_with(&mut StdioOsc, indices)
+        })
+    })
+    .flatten()
+    .unwrap_or_default()
+}
+
+/// Write an OSC 4 query for every index in `indices` in a single flush, then
+/// read replies until each index is answered or `OSC_QUERY_TIMEOUT` passes.
+/// Replies are matched by the index they carry, so their order doesn't matter.
+fn query_palette_indices_with<T: OscIo>(io: &mut T, indices: &[u8]) -> HashMap<u8, (u8, u8, u8)> {
+    let mut colors = HashMap::new();
+    let queries: String = indices
+        .iter()
+        .map(|idx| format!("\x1b]4;{idx};?\x1b\\"))
+        .collect();
+    if indices.is_empty() || io.send(queries.as_bytes()).is_err() {
+        return colors;
+    }
+
+    let deadline = std::time::Instant::now() + OSC_QUERY_TIMEOUT;
+    let mut buffered = Vec::new();
+    while indices.iter().any(|idx| !colors.contains_key(idx)) {
+        let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+        let chunk = io.recv(remaining);
+        if chunk.is_empty() {
+            break;
+        }
+        buffered.extend_from_slice(&chunk);
+        for reply in take_osc_replies(&mut buffered) {
+            notify_osc_observers(4, &reply);
+            if let Some((idx, color)) = parse_indexed_palette_response(&reply) {
+                if indices.contains(&idx) {
+                    colors.insert(idx, color);
+                }
+            }
+        }
+    }
+    colors
+}
+
+/// Parse an OSC 4 reply for any palette index: OSC 4 ; idx ; color ST.
+pub(crate) fn parse_indexed_palette_response(response: &[u8]) -> Option<(u8, (u8, u8, u8))> {
+    let start = find_bytes(response, b"]4;")? + 3;
+    let idx = take_ascii(&response[start..], |b| b.is_ascii_digit());
+    if response.get(start + idx.len()) != Some(&b';') {
+        return None;
+    }
+    let color = parse_osc_color_response(&response[start..])?;
+    Some((idx.parse().ok()?, color))
+}
+
+/// Probe whether the terminal applies OSC 11 background changes.
+///
+/// Sets a background one step away from the current one, queries it back, and
+/// restores the original. Returns `None` if the terminal doesn't answer queries
+/// at all, in which case the probe is inconclusive.
+pub fn probe_osc_set_support<T: OscIo>(io: &mut T) -> Option<bool> {
+    let original = query_osc_color_with(io, 11)?;
+    let (r, g, b) = original;
+    let probe = (r, g, b ^ 1);
+
+    if io.send(format_osc_set(11, probe).as_bytes()).is_err() {
+        return Some(false);
+    }
+    let readback = query_osc_color_with(io, 11);
+    let _ = io.send(format_osc_set(11, original).as_bytes());
+
+    Some(readback == Some(probe))
+}
+
+/// Check `$TERM` against the allowlist of terminals known to accept OSC color sets.
+pub(crate) fn term_allows_osc_set(term: Option<&str>) -> bool {
+    term.is_some_and(|term| {
+        OSC_SET_TERM_ALLOWLIST
+            .iter()
+            .any(|prefix| term.starts_with(prefix))
+    })
+}
+
+/// Whether the outer terminal accepts OSC color *setting* (not just querying).
+/// See `TerminalCapabilities::osc_set`.
+pub fn terminal_supports_osc_set() -> bool {
+    terminal_capabilities().osc_set
+}
+
+/// A parser for a reply format the built-in parser doesn't understand.
+type OscParseHook = Box<dyn Fn(&[u8]) -> Option<(u8, u8, u8)> + Send + Sync>;
+
+/// Hooks registered via `register_osc_parse_hook`, in registration order.
+static OSC_PARSE_HOOKS: RwLock<Vec<OscParseHook>> = RwLock::new(Vec::new());
+
+/// Register a parser for OSC color replies in a format dmux doesn't know.
+///
+/// Each hook receives the raw reply bytes exactly as read from the terminal
+/// (including the `ESC ]` introducer and terminator, and any surrounding
+/// noise) and returns the color it recognises, or `None` to fall through.
+/// Hooks are tried in registration order before the built-in formats; the
+/// first `Some` wins. They run on every query, so they should be cheap and
+/// must not panic.
+pub fn register_osc_parse_hook(
+    hook: impl Fn(&[u8]) -> Option<(u8, u8, u8)> + Send + Sync + 'static,
+) {
+    if let Ok(mut hooks) = OSC_PARSE_HOOKS.write() {
+        hooks.push(Box::new(hook));
+    }
+}
+
+/// An observer of raw OSC replies, given the OSC code and the reply bytes.
+type OscObserver = Box<dyn Fn(u8, &[u8]) + Send + Sync>;
+
+/// Observers registered via `register_osc_observer`, in registration order.
+static OSC_OBSERVERS: RwLock<Vec<OscObserver>> = RwLock::new(Vec::new());
+
+/// Register an observer for every OSC reply the query machinery parses.
+///
+/// The observer receives the OSC code that was queried (10, 11, 12, 4, 14)
+/// and the raw reply bytes as read from the terminal. Unlike parse hooks,
+/// observers can't influence the parsed result; they exist so scripts can
+/// react to terminal replies without owning the I/O. They run on the
+/// querying thread, so they should be cheap and must not panic.
+pub fn register_osc_observer(observer: impl Fn(u8, &[u8]) + Send + Sync + 'static) {
+    if let Ok(mut observers) = OSC_OBSERVERS.write() {
+        observers.push(Box::new(observer));
+    }
+}
+
+/// Hand a reply for OSC `code` to every registered observer. Empty replies
+/// (the terminal didn't answer) are not reported.
+pub(crate) fn notify_osc_observers(code: u8, response: &[u8]) {
+    if response.is_empty() {
+        return;
+    }
+    if let Ok(observers) = OSC_OBSERVERS.read() {
+        for observer in observers.iter() {
+            observer(code, response);
+        }
+    }
+}
+
+/// Parse an OSC color response, trying registered hooks first.
+fn parse_osc_color_response(response: &[u8]) -> Option<(u8, u8, u8)> {
+    let hooked = OSC_PARSE_HOOKS
+        .read()
+        .ok()
+        .and_then(|hooks| hooks.iter().find_map(|hook| hook(response)));
+    hooked.or_else(|| parse_builtin_osc_color_response(response))
+}
+
+/// Parse an OSC color response in one of the standard formats.
+/// Expected format: ESC ] code ; rgb:RRRR/GGGG/BBBB ESC \
+///                  or ESC ] code ; rgb:RR/GG/BB ESC \
+///                  or ESC ] code ; #RRGGBB ESC \
+/// The 8-bit C1 framing (0x9D ... 0x9C) is accepted as well.
+///
+/// Scans the raw bytes for the color markers, so a reply surrounded by
+/// non-UTF-8 noise (e.g. interleaved output) still parses.
+pub(crate) fn parse_builtin_osc_color_response(response: &[u8]) -> Option<(u8, u8, u8)> {
+    if let Some(rgb_start) = find_bytes(response, b"rgb:") {
+        // Parse RRRR/GGGG/BBBB or RR/GG/BB format
+        let rgb_str = take_ascii(&response[rgb_start + 4..], |b| {
+            b.is_ascii_hexdigit() || b == b'/'
+        });
+        let parts: Vec<&str> = rgb_str.split('/').collect();
+        if parts.len() != 3 {
+            return None;
+        }
+
+        let r = parse_hex_component(parts[0])?;
+        let g = parse_hex_component(parts[1])?;
+        let b = parse_hex_component(parts[2])?;
+
+        return Some((r, g, b));
+    }
+
+    // Some terminals answer with #RRGGBB (or #RRRRGGGGBBBB) instead
+    let hash_start = find_bytes(response, b";#")?;
+    let hex = take_ascii(&response[hash_start + 2..], |b| b.is_ascii_hexdigit());
+    if hex.is_empty() || hex.len() % 3 != 0 || hex.len() > 12 {
+        return None;
+    }
+    let width = hex.len() / 3;
+    let r = parse_hex_component(&hex[..width])?;
+    let g = parse_hex_component(&hex[width..2 * width])?;
+    let b = parse_hex_component(&hex[2 * width..])?;
+    Some((r, g, b))
+}
+
+/// Position of the first occurrence of `needle` in `haystack`.
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// The longest prefix of `bytes` whose bytes satisfy `keep`, as a string.
+/// `keep` must only accept ASCII bytes.
+fn take_ascii(bytes: &[u8], keep: impl Fn(u8) -> bool) -> &str {
+    let end = bytes.iter().position(|&b| !keep(b)).unwrap_or(bytes.len());
+    std::str::from_utf8(&bytes[..end]).unwrap_or_default()
+}
+
+/// Parse a 1- to 4-digit hex color component, scaled to 8 bits by its width.
+///
+/// Per XParseColor, `h`, `hh`, `hhh` and `hhhh` are fractions of 4, 8, 12 and
+/// 16 bits, so `f`, `fff` and `ffff` all mean full intensity.
+fn parse_hex_component(s: &str) -> Option<u8> {
+    if s.is_empty() || s.len() > 4 {
+        return None;
+    }
+    let val = u32::from_str_radix(s, 16).ok()?;
+    let max = (1u32 << (4 * s.len())) - 1;
+    Some(((val * 255 + max / 2) / max) as u8)
+}
+
+/// Query outer terminal colors via a subprocess.
+/// This spawns a helper process that queries colors independently,
+/// avoiding conflicts with the main EventStream.
+///
+/// This is faster and less disruptive than query_outer_terminal_colors()
+/// which requires exiting alternate screen. A rate-limited query is queued
+/// instead, and the cached colors returned.
+pub fn query_colors_via_subprocess() -> TerminalColors {
+    if !acquire_color_query_slot() {
+        return OUTER_COLORS.get();
+    }
+    let colors = TerminalColors {
+        foreground: query_osc_color_via_subprocess(10),
+        background: query_osc_color_via_subprocess(11),
+        cursor: query_osc_color_via_subprocess(12),
+        selection_background: None,
+        selection_foreground: None,
+    };
+
+    // Store for later use
+    commit_colors(colors);
+
+    colors
+}
+
+/// Query a specific OSC color via a subprocess.
+/// The subprocess opens /dev/tty directly, queries the color, and prints the RGB result.
+fn query_osc_color_via_subprocess(code: u8) -> Option<(u8, u8, u8)> {
+    use std::process::{Command, Stdio};
+
+    // Use sh -c with a script that queries the color via /dev/tty
+    // The script:
+    // 1. Opens /dev/tty for input/output
+    // 2. Sets raw mode using stty
+    // 3. Sends OSC query
+    // 4. Reads response with timeout
+    // 5. Parses and prints RGB values
+    let script = format!(
+        r#"
+exec 3<>/dev/tty
+old_settings=$(stty -g <&3 2>/dev/null)
+stty raw -echo min 0 time 2 <&3 2>/dev/null
+printf '\033]{};?\033\\' >&3
+response=""
+while IFS= read -r -t 0.15 -n 1 char <&3 2>/dev/null; do
+    response="$response$char"
+    case "$response" in
+        *$'\033''\') break ;;
+        *$'\007') break ;;
+    esac
+done
+stty "$old_settings" <&3 2>/dev/null
+exec 3>&-
+echo "$response" | sed -n 's/.*rgb:\([0-9a-fA-F]*\)\/\([0-9a-fA-F]*\)\/\([0-9a-fA-F]*\).*/\1 \2 \3/p'
+"#,
+        code
+    );
+
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(&script)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output();
+
+    match output {
+        Ok(output) => {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let trimmed = stdout.trim();
+
+            if trimmed.is_empty() {
+                return None;
+            }
+
+            // Parse "RRRR GGGG BBBB" format
+            let parts: Vec<&str> = trimmed.split_whitespace().collect();
+            if parts.len() != 3 {
+                return None;
+            }
+
+            let r = parse_hex_component(parts[0])?;
+            let g = parse_hex_component(parts[1])?;
+            let b = parse_hex_component(parts[2])?;
+
+            Some((r, g, b))
+        }
+        Err(_) => None,
+    }
+}
+
+/// Message sent when theme colors change.
+#[derive(Debug, Clone)]
+pub struct ThemeChangeEvent {
+    pub colors: TerminalColors,
+}
+
+/// Env var set to `1` in every pane dmux starts, marking it as inside dmux.
+pub const DMUX_ENV: &str = "DMUX";
+/// Env var holding the nesting depth of the panes a dmux starts (1 for panes
+/// of an outermost dmux).
+pub const DMUX_LEVEL_ENV: &str = "DMUX_LEVEL";
+
+/// Whether this dmux is running inside another dmux.
+pub fn is_nested_dmux() -> bool {
+    dmux_nesting_depth() > 0
+}
+
+/// How many dmux instances this one is running inside; 0 when not nested.
+pub fn dmux_nesting_depth() -> u32 {
+    nesting_depth_from_env(|key| std::env::var(key).ok())
+}
+
+/// Nesting depth from the env vars a pane's dmux set, looked up with `var`.
+/// The `DMUX` marker alone (e.g. from an older dmux) means one level.
+fn nesting_depth_from_env(var: impl Fn(&str) -> Option<String>) -> u32 {
+    let level = var(DMUX_LEVEL_ENV).and_then(|level| level.trim().parse::<u32>().ok());
+    match level {
+        Some(level) => level,
+        None if var(DMUX_ENV).is_some_and(|marker| marker.trim() == "1") => 1,
+        None => 0,
+    }
+}
+
+/// The nesting env vars for panes started from this dmux, sent with each
+/// attach so the sandbox sets them in the pane's environment.
+pub fn nesting_child_env() -> Vec<EnvVar> {
+    vec![
+        EnvVar {
+            key: DMUX_ENV.to_string(),
+            value: "1".to_string(),
+        },
+        EnvVar {
+            key: DMUX_LEVEL_ENV.to_string(),
+            value: (dmux_nesting_depth() + 1).to_string(),
+        },
+    ]
+}
+
+/// Spawn a background task that listens for theme change signals (SIGUSR1 on Unix).
+///
+/// When a signal is received, it sends a `ThemeChangeEvent` through the provided channel.
+/// This allows the application to re-query terminal colors and update accordingly.
+///
+/// Note: The actual re-query of colors must be done from the main thread after
+/// temporarily exiting the alternate screen buffer.
+///
+/// When nested inside another dmux this is a no-op: the outer instance owns the
+/// real terminal, and a signal fanning out to every level would trigger redundant,
+/// conflicting queries. Returns whether a listener was spawned.
+#[cfg(unix)]
+pub fn spawn_theme_change_listener(tx: mpsc::UnboundedSender<ThemeChangeEvent>) -> bool {
+    spawn_theme_change_listener_at_depth(tx, dmux_nesting_depth())
+}
+
+#[cfg(unix)]
+fn spawn_theme_change_listener_at_depth(
+    tx: mpsc::UnboundedSender<ThemeChangeEvent>,
+    nesting_depth: u32,
+) -> bool {
+    if nesting_depth > 0 {
+        return false;
+    }
+
+    tokio::spawn(async move {
+        let mut sigusr1 =
+            match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::user_defined1()) {
+                Ok(sig) => sig,
+                Err(e) => {
+                    eprintln!("Warning: Failed to register SIGUSR1 handler for theme changes: {e}");
+                    return;
+                }
+            };
+
+        loop {
+            sigusr1.recv().await;
+
+            // Signal received - notify that theme may have changed
+            // The actual color re-query happens in the main event loop
+            // because we need to temporarily exit the alternate screen
+            let colors = get_outer_colors();
+
+            if tx.send(ThemeChangeEvent { colors }).is_err() {
+                // Channel closed, exit the task
+                break;
+            }
+        }
+    });
+    true
+}
+
+/// Non-Unix platforms: no-op signal listener.
+#[cfg(not(unix))]
+pub fn spawn_theme_change_listener(_tx: mpsc::UnboundedSender<ThemeChangeEvent>) -> bool {
+    // Signal-based theme change detection not supported on this platform
+    false
+}
+
+/// How long a burst of theme changes is collected before one is broadcast.
+pub const THEME_DEBOUNCE_WINDOW: Duration = Duration::from_millis(150);
+
+/// Bridge the theme change listener to any number of subscribers.
+///
+/// Events from `rx` are debounced: once one arrives, each further event within
+/// `window` of the previous one replaces it, and only the last is broadcast.
+/// A rapid dark/light toggle therefore costs subscribers a single re-query and
+/// repaint with the final colors rather than one per intermediate state.
+pub fn spawn_theme_broadcast(
+    rx: mpsc::UnboundedReceiver<ThemeChangeEvent>,
+    window: Duration,
+) -> broadcast::Sender<ThemeChangeEvent> {
+    let (tx, _) = broadcast::channel(THEME_BROADCAST_CAPACITY);
+    tokio::spawn(coalesce_theme_changes(rx, tx.clone(), window));
+    tx
+}
+
+/// Events kept for subscribers that fall behind. After coalescing there is
+/// rarely more than one in flight.
+const THEME_BROADCAST_CAPACITY: usize = 4;
+
+async fn coalesce_theme_changes(
+    mut rx: mpsc::UnboundedReceiver<ThemeChangeEvent>,
+    tx: broadcast::Sender<ThemeChangeEvent>,
+    window: Duration,
+) {
+    while let Some(mut latest) = rx.recv().await {
+        let closed = loop {
+            match tokio::time::timeout(window, rx.recv()).await {
+                Ok(Some(event)) => latest = event,
+                Ok(None) => break true,
+                Err(_) => break false,
+            }
+        };
+        // Having no subscribers right now isn't a reason to stop
+        let _ = tx.send(latest);
+        if closed {
+            return;
+        }
+    }
+}
+
+/// Re-query terminal colors. This should be called from the main thread
+/// after receiving a ThemeChangeEvent, temporarily exiting the alternate screen.
+///
+/// Returns the new colors and updates the global state.
+pub fn refresh_outer_colors() -> TerminalColors {
+    query_outer_terminal_colors()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_osc_color_response_16bit() {
+        // Typical response: ESC ] 11 ; rgb:3535/3737/3131 ESC \
+        let response = b"\x1b]11;rgb:3535/3737/3131\x1b\\";
+        let result = parse_osc_color_response(response);
+        assert_eq!(result, Some((0x35, 0x37, 0x31))); // (53, 55, 49)
+    }
+
+    #[test]
+    fn test_hsl_to_rgb_primaries() {
+        assert_eq!(hsl_to_rgb(0.0, 1.0, 0.5), (255, 0, 0));
+        assert_eq!(hsl_to_rgb(120.0, 1.0, 0.5), (0, 255, 0));
+        assert_eq!(hsl_to_rgb(240.0, 1.0, 0.5), (0, 0, 255));
+        assert_eq!(hsl_to_rgb(60.0, 0.0, 1.0), (255, 255, 255));
+    }
+
+    #[test]
+    fn test_pane_accent_is_stable_and_distinct() {
+        let names = ["build", "test", "server", "logs", "db", "agent", "editor"];
+        for name in names {
+            assert_eq!(
+                pane_accent(name, ThemeMode::Dark),
+                pane_accent(name, ThemeMode::Dark)
+            );
+        }
+        let mut hues: Vec<u64> = names
+            .iter()
+            .map(|name| pane_accent_hue(name) as u64)
+            .collect();
+        hues.sort_unstable();
+        hues.dedup();
+        assert_eq!(hues.len(), names.len());
+        assert_ne!(
+            pane_accent("build", ThemeMode::Dark),
+            pane_accent("test", ThemeMode::Dark)
+        );
+    }
+
+    #[test]
+    fn test_pane_accent_meets_contrast_floor() {
+        for i in 0..200 {
+            let name = format!("pane-{}", i);
+            let dark = pane_accent(&name, ThemeMode::Dark);
+            let light = pane_accent(&name, ThemeMode::Light);
+            assert!(
+                contrast_ratio(dark, PANE_ACCENT_BG_DARK) >= PANE_ACCENT_MIN_CONTRAST,
+                "{name}: {dark:?}"
+            );
+            assert!(
+                contrast_ratio(light, PANE_ACCENT_BG_LIGHT) >= PANE_ACCENT_MIN_CONTRAST,
+                "{name}: {light:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_osc_color_response_c1_framing() {
+        let response = b"\x9d11;rgb:3535/3737/3131\x9c";
+        assert_eq!(parse_osc_color_response(response), Some((0x35, 0x37, 0x31)));
+        let response = b"\x9d10;#1e1e2e\x9c";
+        assert_eq!(parse_osc_color_response(response), Some((0x1e, 0x1e, 0x2e)));
+    }
+
+    #[test]
+    fn test_osc_reply_code() {
+        assert_eq!(
+            osc_reply_code(b"\x1b]11;rgb:3535/3737/3131\x1b\\"),
+            Some(11)
+        );
+        assert_eq!(osc_reply_code(b"\x9d10;#1e1e2e\x9c"), Some(10));
+        assert_eq!(osc_reply_code(b"rgb:3535/3737/3131\x1b\\"), None);
+        assert_eq!(osc_reply_code(b"\x1b]rgb:3535/3737/3131\x07"), None);
+    }
+
+    /// Terminal that answers every query with the same canned bytes.
+    struct CannedReply(&'static [u8]);
+
+    impl OscIo for CannedReply {
+        fn send(&mut self, _bytes: &[u8]) -> std::io::Result<()> {
+            Ok(())
+        }
+
+        fn recv(&mut self, _timeout: Duration) -> Vec<u8> {
+            self.0.to_vec()
+        }
+    }
+
+    #[test]
+    fn test_query_accepts_reply_without_code() {
+        let mut term = CannedReply(b"rgb:3535/3737/3131\x1b\\");
+        assert_eq!(query_osc_color_with(&mut term, 11), Some((53, 55, 49)));
+        assert_eq!(query_osc_color_with(&mut term, 10), Some((53, 55, 49)));
+    }
+
+    /// Terminal that returns scripted chunks, one per read, recording what was sent.
+    struct ChunkedReplies {
+        chunks: std::collections::VecDeque<&'static [u8]>,
+        sent: Vec<Vec<u8>>,
+    }
+
+    impl ChunkedReplies {
+        fn new(chunks: &[&'static [u8]]) -> Self {
+            Self {
+                chunks: chunks.iter().copied().collect(),
+                sent: Vec::new(),
+            }
+        }
+    }
+
+    impl OscIo for ChunkedReplies {
+        fn send(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+            self.sent.push(bytes.to_vec());
+            Ok(())
+        }
+
+        fn recv(&mut self, _timeout: Duration) -> Vec<u8> {
+            self.chunks.pop_front().unwrap_or_default().to_vec()
+        }
+    }
+
+    #[test]
+    fn test_batched_query_dispatches_interleaved_replies() {
+        // Out of order, split mid-reply, with CR/LF between replies
+        let mut term = ChunkedReplies::new(&[
+            b"\x1b]11;rgb:1e1e/1e1e/2e2e\x1b\\\r\n\x1b]12;rgb:ff",
+            b"ff/0000/0000\x07\x1b]10;rgb:cdcd/d6d6/f4f4\x1b\\",
+        ]);
+        let (colors, bg_state) = query_colors_batched_with(&mut term);
+
+        assert_eq!(
+            term.sent,
+            vec![
+                b"\x1b]10;?\x1b\\\x1b]11;?\x1b\\\x1b]12;?\x1b\\\x1b]17;?\x1b\\\x1b]19;?\x1b\\"
+                    .to_vec()
+            ]
+        );
+        assert_eq!(colors.foreground, Some((0xcd, 0xd6, 0xf4)));
+        assert_eq!(colors.background, Some((0x1e, 0x1e, 0x2e)));
+        assert_eq!(colors.cursor, Some((0xff, 0x00, 0x00)));
+        assert_eq!(bg_state, BgState::Rgb((0x1e, 0x1e, 0x2e)));
+        assert!(term.chunks.is_empty());
+    }
+
+    #[test]
+    fn test_batched_query_assigns_codeless_replies_in_order() {
+        let mut term = ChunkedReplies::new(&[
+            b"\x1b]rgb:ffff/ffff/ffff\x07\x1b]rgb:0000/0000/0000\x07",
+            b"\x1b]11;rgb:1111/1111/1111\x1b\\",
+        ]);
+        let (colors, _) = query_colors_batched_with(&mut term);
+        assert_eq!(colors.foreground, Some((0xff, 0xff, 0xff)));
+        assert_eq!(colors.background, Some((0x00, 0x00, 0x00)));
+        // The late coded reply repeats an answered query and is dropped
+        assert_eq!(colors.cursor, None);
+    }
+
+    #[test]
+    fn test_parse_selection_color_replies() {
+        let bg = b"\x1b]17;rgb:4444/4c4c/5e5e\x1b\\";
+        let fg = b"\x1b]19;#f8f8f2\x07";
+        assert_eq!(osc_reply_code(bg), Some(17));
+        assert_eq!(osc_reply_code(fg), Some(19));
+        assert_eq!(parse_osc_color_response(bg), Some((0x44, 0x4c, 0x5e)));
+        assert_eq!(parse_osc_color_response(fg), Some((0xf8, 0xf8, 0xf2)));
+        assert!(!osc_reply_matches_code(bg, 19));
+    }
+
+    #[test]
+    fn test_batched_query_picks_up_selection_colors() {
+        let mut term = ChunkedReplies::new(&[
+            b"\x1b]10;rgb:f8f8/f8f8/f2f2\x1b\\\x1b]11;rgb:2828/2a2a/3636\x1b\\",
+            b"\x1b]12;rgb:ffff/ffff/ffff\x1b\\\x1b]19;rgb:ffff/ffff/ffff\x1b\\",
+            b"\x1b]17;rgb:4444/4747/5a5a\x1b\\",
+        ]);
+        let (colors, _) = query_colors_batched_with(&mut term);
+        assert_eq!(colors.selection_background, Some((0x44, 0x47, 0x5a)));
+        assert_eq!(colors.selection_foreground, Some((0xff, 0xff, 0xff)));
+        assert_eq!(
+            colors.selection_colors(),
+            ((0xff, 0xff, 0xff), (0x44, 0x47, 0x5a))
+        );
+    }
+
+    #[test]
+    fn test_selection_colors_fall_back_to_accent() {
+        // Only the required replies arrive; the selection queries go unanswered
+        let mut term = ChunkedReplies::new(&[
+            b"\x1b]10;rgb:ffff/ffff/ffff\x1b\\\x1b]11;rgb:0000/0000/0000\x1b\\",
+            b"\x1b]12;rgb:ffff/ffff/ffff\x1b\\",
+        ]);
+        let (colors, _) = query_colors_batched_with(&mut term);
+        assert_eq!(colors.selection_background, None);
+        assert_eq!(colors.selection_foreground, None);
+
+        let (fg, bg) = colors.selection_colors();
+        assert_eq!(fg, (0xff, 0xff, 0xff));
+        assert_eq!(bg, lerp_color((0, 0, 0), (0xff, 0xff, 0xff), 0.3));
+        assert_ne!(bg, (0, 0, 0));
+    }
+
+    #[test]
+    fn test_take_osc_replies_keeps_unterminated_tail() {
+        let mut buf = b"\x1b]10;rgb:00/00/00\x07\r\n\x9d11;#102030\x9c\x1b]12;rgb:".to_vec();
+        let replies = take_osc_replies(&mut buf);
+        assert_eq!(
+            replies,
+            vec![
+                b"\x1b]10;rgb:00/00/00\x07".to_vec(),
+                b"\x9d11;#102030\x9c".to_vec()
+            ]
+        );
+        assert_eq!(buf, b"\x1b]12;rgb:");
+    }
+
+    #[test]
+    fn test_query_rejects_reply_with_mismatched_code() {
+        let mut term = CannedReply(b"\x1b]10;rgb:ffff/ffff/ffff\x1b\\");
+        assert_eq!(query_osc_color_with(&mut term, 11), None);
+        assert_eq!(query_osc_color_with(&mut term, 10), Some((255, 255, 255)));
+    }
+
+    #[test]
+    fn test_parse_osc_color_response_8bit() {
+        let response = b"\x1b]11;rgb:35/37/31\x1b\\";
+        let result = parse_osc_color_response(response);
+        assert_eq!(result, Some((0x35, 0x37, 0x31)));
+    }
+
+    #[test]
+    fn test_parse_osc_color_response_black() {
+        let response = b"\x1b]11;rgb:0000/0000/0000\x1b\\";
+        let result = parse_osc_color_response(response);
+        assert_eq!(result, Some((0, 0, 0)));
+    }
+
+    #[test]
+    fn test_parse_osc_color_response_white() {
+        let response = b"\x1b]10;rgb:ffff/ffff/ffff\x1b\\";
+        let result = parse_osc_color_response(response);
+        assert_eq!(result, Some((255, 255, 255)));
+    }
+
+    #[test]
+    fn test_osc_parse_hooks_run_in_order_before_builtin() {
+        // Only claim replies tagged with a made-up "hooktest" colorspace, so
+        // other tests parsing real replies are unaffected
+        register_osc_parse_hook(|reply| find_bytes(reply, b"hooktest:first").map(|_| (1, 2, 3)));
+        register_osc_parse_hook(|reply| find_bytes(reply, b"hooktest:").map(|_| (4, 5, 6)));
+
+        assert_eq!(
+            parse_osc_color_response(b"\x1b]11;hooktest:first\x1b\\"),
+            Some((1, 2, 3))
+        );
+        assert_eq!(
+            parse_osc_color_response(b"\x1b]11;hooktest:second\x1b\\"),
+            Some((4, 5, 6))
+        );
+        // Falls through to the built-in parser
+        assert_eq!(
+            parse_osc_color_response(b"\x1b]11;rgb:ffff/0000/0000\x1b\\"),
+            Some((255, 0, 0))
+        );
+    }
+
+    #[test]
+    fn test_osc_observer_sees_parsed_osc_11_reply() {
+        let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        // Other tests query too; only record the reply this test sends
+        register_osc_observer(move |code, reply| {
+            if find_bytes(reply, b"rgb:0b5e/0b5e/0b5e").is_some() {
+                if let Ok(mut seen) = sink.lock() {
+                    seen.push((code, reply.to_vec()));
+                }
+            }
+        });
+
+        let reply: &[u8] = b"\x1b]11;rgb:0b5e/0b5e/0b5e\x1b\\";
+        let mut term = CannedReply(reply);
+        assert_eq!(query_osc_color_with(&mut term, 11), Some((11, 11, 11)));
+
+        let seen = seen.lock().expect("observer lock");
+        assert_eq!(*seen, vec![(11, reply.to_vec())]);
+    }
+
+    #[test]
+    fn test_parse_hex_component() {
+        assert_eq!(parse_hex_component("ff"), Some(255));
+        assert_eq!(parse_hex_component("00"), Some(0));
+        assert_eq!(parse_hex_component("ffff"), Some(255));
+        assert_eq!(parse_hex_component("0000"), Some(0));
+        assert_eq!(parse_hex_component("3535"), Some(0x35)); // 53
+        assert_eq!(parse_hex_component("8080"), Some(0x80)); // 128
+    }
+
+    #[test]
+    fn test_parse_hex_component_scales_by_digit_width() {
+        assert_eq!(parse_hex_component("f"), Some(255));
+        assert_eq!(parse_hex_component("8"), Some(136));
+        assert_eq!(parse_hex_component("fff"), Some(255));
+        assert_eq!(parse_hex_component("800"), Some(128));
+        assert_eq!(parse_hex_component("000"), Some(0));
+        assert_eq!(parse_hex_component("fffe"), Some(255));
+        assert_eq!(parse_hex_component("00ff"), Some(1));
+        assert_eq!(parse_hex_component(""), None);
+        assert_eq!(parse_hex_component("fffff"), None);
+        assert_eq!(
+            parse_osc_color_response(b"\x1b]11;rgb:fff/800/000\x1b\\"),
+            Some((255, 128, 0))
+        );
+    }
+
+    /// Mock terminal that answers OSC 10/11 queries and optionally applies OSC 11 sets.
+    /// The background `MockOscTerminal` starts with and resets to.
+    const MOCK_DEFAULT_BACKGROUND: (u8, u8, u8) = (53, 55, 49);
+
+    struct MockOscTerminal {
+        foreground: (u8, u8, u8),
+        background: (u8, u8, u8),
+        applies_sets: bool,
+        responds: bool,
+        pending: Vec<u8>,
+    }
+
+    impl MockOscTerminal {
+        fn new(applies_sets: bool) -> Self {
+            Self {
+                foreground: (255, 255, 255),
+                background: MOCK_DEFAULT_BACKGROUND,
+                applies_sets,
+                responds: true,
+                pending: Vec::new(),
+            }
+        }
+    }
+
+    impl OscIo for MockOscTerminal {
+        fn send(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+            let s = String::from_utf8_lossy(bytes);
+            let queried = match s.as_ref() {
+                "\x1b]10;?\x1b\\" => Some((10, self.foreground)),
+                "\x1b]11;?\x1b\\" => Some((11, self.background)),
+                _ => None,
+            };
+            if let Some((code, (r, g, b))) = queried {
+                if self.responds {
+                    self.pending = format!(
+                        "\x1b]{};rgb:{:04x}/{:04x}/{:04x}\x1b\\",
+                        code,
+                        r as u16 * 257,
+                        g as u16 * 257,
+                        b as u16 * 257
+                    )
+                    .into_bytes();
+                }
+            } else if s.contains("\x1b]111\x1b\\") {
+                if self.applies_sets {
+                    self.background = MOCK_DEFAULT_BACKGROUND;
+                }
+            } else if let Some(spec) = s.strip_prefix("\x1b]11;") {
+                if self.applies_sets {
+                    let reply = format!("\x1b]11;{}", spec);
+                    if let Some(color) = parse_osc_color_response(reply.as_bytes()) {
+                        self.background = color;
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        fn recv(&mut self, _timeout: Duration) -> Vec<u8> {
+            std::mem::take(&mut self.pending)
+        }
+    }
+
+    /// Query a mock terminal, then resolve and commit like startup does.
+    fn query_sources(
+        term: &mut MockOscTerminal,
+        colorfgbg: Option<&str>,
+    ) -> (ColorSource, ColorSource) {
+        let cache = ColorCache::new();
+        let (colors, sources) = apply_color_fallbacks(query_colors_with(term), colorfgbg);
+        cache.commit_with_sources(colors, sources);
+        resolve_color_sources(&cache, TerminalColors::default())
+    }
+
+    #[test]
+    fn test_color_sources_after_successful_query() {
+        let mut term = MockOscTerminal::new(false);
+        assert_eq!(
+            query_sources(&mut term, Some("0;15")),
+            (ColorSource::Queried, ColorSource::Queried)
+        );
+    }
+
+    #[test]
+    fn test_color_sources_after_failed_query() {
+        let mut term = MockOscTerminal::new(false);
+        term.responds = false;
+        assert_eq!(
+            query_sources(&mut term, None),
+            (ColorSource::Fallback, ColorSource::Fallback)
+        );
+
+        let mut term = MockOscTerminal::new(false);
+        term.responds = false;
+        assert_eq!(
+            query_sources(&mut term, Some("15;default;0")),
+            (ColorSource::ColorFgBg, ColorSource::ColorFgBg)
+        );
+    }
+
+    #[test]
+    fn test_parse_colorfgbg() {
+        let colors = parse_colorfgbg("15;0");
+        assert_eq!(colors.foreground, Some(ANSI_16[15]));
+        assert_eq!(colors.background, Some(ANSI_16[0]));
+        assert_eq!(parse_colorfgbg("7;default;4").background, Some(ANSI_16[4]));
+        assert_eq!(
+            parse_colorfgbg("default;default"),
+            TerminalColors::default()
+        );
+        assert_eq!(parse_colorfgbg("15").background, None);
+        assert_eq!(parse_colorfgbg("99;0").foreground, None);
+    }
+
+    #[test]
+    fn test_bg_state_classification() {
+        assert_eq!(
+            BgState::from_reply(b"\x1b]11;rgb:3535/3737/3131\x1b\\"),
+            BgState::Rgb((53, 55, 49))
+        );
+        assert_eq!(
+            BgState::from_reply(b"\x1b]11;rgba:0000/0000/0000/0000\x1b\\"),
+            BgState::Transparent
+        );
+        assert_eq!(
+            BgState::from_reply(b"\x1b]11;[0]rgb:0000/0000/0000\x07"),
+            BgState::Transparent
+        );
+        // Partly transparent still names a color
+        assert_eq!(
+            BgState::from_reply(b"\x1b]11;rgba:ffff/0000/0000/8000\x1b\\"),
+            BgState::Rgb((255, 0, 0))
+        );
+        assert_eq!(BgState::from_reply(b""), BgState::Unknown);
+
+        assert_eq!(BgState::from_colorfgbg("15;default"), BgState::Default);
+        assert_eq!(BgState::from_colorfgbg("15;0"), BgState::Rgb(ANSI_16[0]));
+        assert_eq!(BgState::from_colorfgbg("15"), BgState::Unknown);
+        assert_eq!(BgState::Unknown.or(BgState::Default), BgState::Default);
+        assert_eq!(
+            BgState::Transparent.or(BgState::Default),
+            BgState::Transparent
+        );
+
+        // The compatibility accessor only yields a concrete color
+        assert_eq!(BgState::Rgb((1, 2, 3)).rgb(), Some((1, 2, 3)));
+        for state in [BgState::Transparent, BgState::Default, BgState::Unknown] {
+            assert_eq!(state.rgb(), None);
+        }
+    }
+
+    #[test]
+    fn test_bg_state_query_classifies_background_reply() {
+        let mut term = CannedReply(b"\x1b]11;rgba:0000/0000/0000/0000\x1b\\");
+        let (colors, state) = query_colors_and_bg_state_with(&mut term);
+        assert_eq!(state, BgState::Transparent);
+        assert_eq!(colors.background, None);
+
+        let mut term = MockOscTerminal::new(false);
+        let (colors, state) = query_colors_and_bg_state_with(&mut term);
+        assert_eq!(state, BgState::Rgb((53, 55, 49)));
+        assert_eq!(state.rgb(), colors.background);
+    }
+
+    #[test]
+    fn test_readable_foreground_per_bg_state() {
+        assert_eq!(
+            BgState::Rgb((0, 0, 0)).readable_foreground(),
+            Some((255, 255, 255))
+        );
+        assert_eq!(
+            BgState::Rgb((250, 250, 240)).readable_foreground(),
+            Some((0, 0, 0))
+        );
+        assert_eq!(BgState::Transparent.readable_foreground(), None);
+        assert_eq!(BgState::Default.readable_foreground(), None);
+        assert_eq!(
+            BgState::Unknown.readable_foreground(),
+            BgState::Rgb(FALLBACK_BG).readable_foreground()
+        );
+    }
+
+    #[test]
+    fn test_resolve_color_sources_env_override() {
+        let cache = ColorCache::new();
+        let env = TerminalColors {
+            background: parse_hex_color("#002b36"),
+            ..TerminalColors::default()
+        };
+        assert_eq!(
+            resolve_color_sources(&cache, env),
+            (ColorSource::Fallback, ColorSource::EnvOverride)
+        );
+    }
+
+    #[test]
+    fn test_probe_osc_set_support_echoing_terminal() {
+        let mut term = MockOscTerminal::new(true);
+        assert_eq!(probe_osc_set_support(&mut term), Some(true));
+        // The original background must be restored after probing
+        assert_eq!(term.background, (53, 55, 49));
+    }
+
+    #[test]
+    fn test_probe_osc_set_support_ignoring_terminal() {
+        let mut term = MockOscTerminal::new(false);
+        assert_eq!(probe_osc_set_support(&mut term), Some(false));
+        assert_eq!(term.background, (53, 55, 49));
+    }
+
+    #[test]
+    fn test_probe_osc_set_support_silent_terminal() {
+        let mut term = MockOscTerminal::new(true);
+        term.responds = false;
+        assert_eq!(probe_osc_set_support(&mut term), None);
+    }
+
+    /// Mock terminal that, like VSCode, only answers OSC queries outside the alternate screen.
+    struct MockScreenTerminal {
+        osc: MockOscTerminal,
+        in_alt_screen: bool,
+        fail_leave: bool,
+        transitions: Vec<&'static str>,
+    }
+
+    impl MockScreenTerminal {
+        fn new() -> Self {
+            Self {
+                osc: MockOscTerminal::new(true),
+                in_alt_screen: true,
+                fail_leave: false,
+                transitions: Vec::new(),
+            }
+        }
+    }
+
+    impl OscIo for MockScreenTerminal {
+        fn send(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+            self.transitions.push("query");
+            self.osc.responds = !self.in_alt_screen;
+            self.osc.send(bytes)
+        }
+
+        fn recv(&mut self, timeout: Duration) -> Vec<u8> {
+            self.osc.recv(timeout)
+        }
+    }
+
+    impl ScreenIo for MockScreenTerminal {
+        fn leave_alternate_screen(&mut self) -> std::io::Result<()> {
+            self.transitions.push("leave");
+            if self.fail_leave {
+                return Err(std::io::Error::other("leave failed"));
+            }
+            self.in_alt_screen = false;
+            Ok(())
+        }
+
+        fn enter_alternate_screen(&mut self) -> std::io::Result<()> {
+            self.transitions.push("enter");
+            self.in_alt_screen = true;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_deferred_color_query_captures_late_colors() {
+        // The terminal doesn't answer at startup...
+        let mut term = MockScreenTerminal::new();
+        let startup = query_colors_with(&mut term);
+        assert_eq!(startup.background, None);
+
+        let started = std::time::Instant::now();
+        let delay = Duration::from_millis(500);
+        let mut deferred = DeferredColorQuery::schedule(started, delay, &startup);
+        assert!(deferred.is_pending());
+
+        // ...nothing is asked before the delay is up...
+        let cache = ColorCache::new();
+        let early = started + Duration::from_millis(100);
+        let polled = deferred.poll_with(early, &mut term, Duration::ZERO, &cache);
+        assert!(polled.is_none());
+        assert!(!term.transitions.contains(&"leave"));
+
+        // ...and once it is, the terminal's answer is committed
+        let colors = deferred
+            .poll_with(started + delay, &mut term, Duration::ZERO, &cache)
+            .expect("deferred query answered");
+        assert_eq!(colors.background, Some((53, 55, 49)));
+        assert_eq!(cache.get().foreground, Some((255, 255, 255)));
+        assert!(cache.is_initialized());
+        // ...along with what the startup query would have recorded
+        assert_eq!(cache.bg_state(), BgState::Rgb((53, 55, 49)));
+        assert_eq!(cache.derived_fallback_bg(), Some(FALLBACK_BG_DARK));
+
+        // One shot only
+        assert!(!deferred.is_pending());
+        let later = started + delay * 4;
+        let polled = deferred.poll_with(later, &mut term, Duration::ZERO, &cache);
+        assert!(polled.is_none());
+    }
+
+    #[test]
+    fn test_deferred_color_query_only_when_startup_failed() {
+        let started = std::time::Instant::now();
+        let answered = TerminalColors {
+            foreground: Some((255, 255, 255)),
+            background: Some((0, 0, 0)),
+            ..TerminalColors::default()
+        };
+        let delay = DEFAULT_COLOR_REQUERY_DELAY;
+        assert!(!DeferredColorQuery::schedule(started, delay, &answered).is_pending());
+
+        let unanswered = TerminalColors::default();
+        assert!(DeferredColorQuery::schedule(started, delay, &unanswered).is_pending());
+        assert!(!DeferredColorQuery::schedule(started, Duration::ZERO, &unanswered).is_pending());
+    }
+
+    #[test]
+    fn test_rapid_requery_requests_coalesce_into_one_query() {
+        let interval = Duration::from_millis(250);
+        let mut limiter = ColorQueryLimiter::new(interval);
+        let start = std::time::Instant::now();
+
+        // A burst: signal, manual and deferred re-queries within a few ms
+        let queries = [0, 5, 10, 40]
+            .into_iter()
+            .filter(|&ms| limiter.request(start + Duration::from_millis(ms)))
+            .count();
+        assert_eq!(queries, 1);
+
+        // The queued requests aren't due until the interval has passed...
+        assert!(!limiter.take_due(start + Duration::from_millis(100)));
+        let slot = start + interval;
+        assert!(limiter.take_due(slot));
+        // ...and then make one query between them
+        assert!(!limiter.take_due(slot));
+        assert!(limiter.request(slot));
+        assert!(!limiter.request(slot + Duration::from_millis(1)));
+
+        // With nothing queued, nothing is due
+        let mut idle = ColorQueryLimiter::new(interval);
+        assert!(idle.request(start));
+        assert!(!idle.take_due(start + interval * 2));
+    }
+
+    #[test]
+    fn test_reset_helpers_emit_osc_resets() {
+        let emitted = |reset: fn(&mut Vec<u8>) -> std::io::Result<()>| {
+            let mut out = Vec::new();
+            reset(&mut out).expect("write to vec");
+            out
+        };
+        assert_eq!(emitted(reset_foreground), b"\x1b]110\x1b\\");
+        assert_eq!(emitted(reset_background), b"\x1b]111\x1b\\");
+        assert_eq!(emitted(reset_cursor_color), b"\x1b]112\x1b\\");
+        assert_eq!(emitted(|out| reset_palette(out, &[])), b"\x1b]104\x1b\\");
+        assert_eq!(
+            emitted(|out| reset_palette(out, &[1, 235])),
+            b"\x1b]104;1;235\x1b\\"
+        );
+        assert_eq!(
+            emitted(reset_all_colors),
+            b"\x1b]110\x1b\\\x1b]111\x1b\\\x1b]112\x1b\\\x1b]104\x1b\\"
+        );
+    }
+
+    #[test]
+    fn test_requery_after_reset_reports_restored_defaults() {
+        let mut term = MockOscTerminal::new(true);
+        term.send(format_osc_set(11, (1, 2, 3)).as_bytes())
+            .expect("set background");
+        assert_eq!(query_colors_with(&mut term).background, Some((1, 2, 3)));
+
+        let (colors, _) = reset_and_query_with(&mut term, PaletteProtocol::Osc4);
+        assert_eq!(colors.background, Some(MOCK_DEFAULT_BACKGROUND));
+        assert_eq!(colors.foreground, Some((255, 255, 255)));
+    }
+
+    #[test]
+    fn test_query_colors_outside_alt_screen_transitions() {
+        let mut term = MockScreenTerminal::new();
+        let colors = query_colors_outside_alt_screen(&mut term, Duration::ZERO)
+            .expect("refresh should succeed");
+
+        assert_eq!(
+            term.transitions,
+            vec!["leave", "query", "query", "query", "enter"]
+        );
+        assert!(term.in_alt_screen);
+        assert_eq!(colors.foreground, Some((255, 255, 255)));
+        assert_eq!(colors.background, Some((53, 55, 49)));
+    }
+
+    #[test]
+    fn test_query_colors_outside_alt_screen_reenters_on_leave_error() {
+        let mut term = MockScreenTerminal::new();
+        term.fail_leave = true;
+
+        assert!(query_colors_outside_alt_screen(&mut term, Duration::ZERO).is_err());
+        assert_eq!(term.transitions, vec!["leave", "enter"]);
+        assert!(term.in_alt_screen);
+    }
+
+    #[test]
+    fn test_term_allows_osc_set() {
+        assert!(term_allows_osc_set(Some("xterm-256color")));
+        assert!(term_allows_osc_set(Some("xterm-ghostty")));
+        assert!(term_allows_osc_set(Some("alacritty")));
+        assert!(!term_allows_osc_set(Some("linux")));
+        assert!(!term_allows_osc_set(Some("dumb")));
+        assert!(!term_allows_osc_set(None));
+    }
+
+    #[test]
+    fn test_contrast_ratio_extremes() {
+        let ratio = contrast_ratio((0, 0, 0), (255, 255, 255));
+        assert!((ratio - 21.0).abs() < 0.01);
+        assert!((contrast_ratio((53, 55, 49), (53, 55, 49)) - 1.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_complementary_separator_offsets_similar_backgrounds() {
+        let (a, b) = ((30, 32, 28), (38, 40, 36));
+        let separator = complementary_separator(a, b);
+        assert!(contrast_ratio(separator, a) >= SEPARATOR_MIN_CONTRAST);
+        assert!(contrast_ratio(separator, b) >= SEPARATOR_MIN_CONTRAST);
+        // Dark panes get a lighter separator, not pure white
+        assert!(relative_luminance(separator) > relative_luminance(b));
+        assert_ne!(separator, (255, 255, 255));
+
+        let (a, b) = ((250, 250, 245), (240, 240, 235));
+        let separator = complementary_separator(a, b);
+        assert!(contrast_ratio(separator, a) >= SEPARATOR_MIN_CONTRAST);
+        assert!(relative_luminance(separator) < relative_luminance(b));
+    }
+
+    #[test]
+    fn test_complementary_separator_between_very_different_backgrounds() {
+        let (a, b) = ((0, 0, 0), (255, 255, 255));
+        let separator = complementary_separator(a, b);
+        assert!(contrast_ratio(separator, a) >= SEPARATOR_MIN_CONTRAST);
+        assert!(contrast_ratio(separator, b) >= SEPARATOR_MIN_CONTRAST);
+
+        // No color clears the bar against both; take the best compromise
+        let (a, b) = ((20, 20, 20), (200, 200, 200));
+        let separator = complementary_separator(a, b);
+        let worst = contrast_ratio(separator, a).min(contrast_ratio(separator, b));
+        let average = lerp_color(a, b, 0.5);
+        assert!(worst >= contrast_ratio(average, a).min(contrast_ratio(average, b)));
+        assert!(worst > 1.5);
+    }
+
+    #[test]
+    fn test_best_ansi_for_contrast_dark_bg() {
+        let colors = TerminalColors {
+            foreground: Some((255, 255, 255)),
+            background: Some((53, 55, 49)),
+            cursor: None,
+            selection_background: None,
+            selection_foreground: None,
+        };
+        let index = colors.best_ansi_for_contrast(4.5).expect("bg is known");
+        // Dark ANSI colors (black, red, blue, bright black) must be avoided
+        assert!(
+            ![0, 1, 4, 8].contains(&index),
+            "picked dark color {}",
+            index
+        );
+        assert!(contrast_ratio(ANSI_16[index as usize], (53, 55, 49)) >= 4.5);
+    }
+
+    #[test]
+    fn test_best_ansi_for_contrast_falls_back_to_max_contrast() {
+        let colors = TerminalColors {
+            foreground: None,
+            background: Some((0, 0, 0)),
+            cursor: None,
+            selection_background: None,
+            selection_foreground: None,
+        };
+        // Nothing reaches 30:1, so the highest-contrast color (bright white) wins
+        assert_eq!(colors.best_ansi_for_contrast(30.0), Some(15));
+        assert_eq!(TerminalColors::default().best_ansi_for_contrast(4.5), None);
+    }
+
+    #[test]
+    fn test_export_theme_config_round_trips_through_settings() {
+        let colors = TerminalColors {
+            foreground: Some((255, 255, 255)),
+            background: Some((53, 55, 49)),
+            cursor: Some((0x81, 0xa1, 0xc1)),
+            selection_background: None,
+            selection_foreground: None,
+        };
+        let snippet = format_theme_config(&colors);
+
+        let parsed: crate::settings::Settings =
+            serde_json::from_str(&snippet).expect("snippet should parse as settings");
+        let theme = parsed
+            .theme
+            .expect("snippet should include a theme section");
+        assert_eq!(theme, colors.to_theme_settings());
+        assert_eq!(theme.background.as_deref(), Some("#353731"));
+        assert_eq!(theme.cursor.as_deref(), Some("#81a1c1"));
+        assert_eq!(theme.mode, Some(ThemeMode::Dark));
+    }
+
+    #[test]
+    fn test_mode_weighs_foreground_on_midtone_backgrounds() {
+        let mode = |foreground, background| {
+            TerminalColors {
+                foreground,
+                background: Some(background),
+                ..TerminalColors::default()
+            }
+            .mode()
+        };
+        let mid_gray = (140, 140, 140);
+        assert_eq!(mode(Some((20, 20, 20)), mid_gray), Some(ThemeMode::Light));
+        assert_eq!(mode(Some((240, 240, 240)), mid_gray), Some(ThemeMode::Dark));
+        // Without a foreground, or one as bright as the background, the
+        // background decides alone
+        assert_eq!(mode(None, mid_gray), Some(ThemeMode::Dark));
+        assert_eq!(mode(Some(mid_gray), mid_gray), Some(ThemeMode::Dark));
+
+        // Clearly dark or light backgrounds ignore the foreground
+        assert_eq!(mode(Some((0, 0, 0)), (10, 10, 10)), Some(ThemeMode::Dark));
+        assert_eq!(
+            mode(Some((255, 255, 255)), (250, 250, 250)),
+            Some(ThemeMode::Light)
+        );
+    }
+
+    #[test]
+    fn test_export_theme_config_omits_unknown_colors() {
+        let colors = TerminalColors {
+            foreground: None,
+            background: Some((250, 250, 250)),
+            cursor: None,
+            selection_background: None,
+            selection_foreground: None,
+        };
+        let snippet = format_theme_config(&colors);
+        assert!(!snippet.contains("foreground"));
+        assert!(!snippet.contains("cursor"));
+
+        let parsed: crate::settings::Settings = serde_json::from_str(&snippet).unwrap();
+        assert_eq!(
+            parsed.theme.and_then(|theme| theme.mode),
+            Some(ThemeMode::Light)
+        );
+    }
+
+    #[test]
+    fn test_color_diagnostics_reflect_tracked_state() {
+        let reply = b"\x1b]11;rgb:1010/2020/3030\x1b\\".to_vec();
+        *LAST_QUERY_DURATION.write().unwrap() = Some(Duration::from_millis(42));
+        *LAST_RAW_RESPONSE.write().unwrap() = reply.clone();
+
+        let diagnostics = color_diagnostics();
+        assert_eq!(diagnostics.last_query_duration, Duration::from_millis(42));
+        assert_eq!(diagnostics.raw_last_response, reply);
+        assert_eq!(diagnostics.term, std::env::var("TERM").unwrap_or_default());
+        assert_eq!(diagnostics.multiplexer, Passthrough::from_env());
+
+        let (colors, source) = get_outer_colors_with_source();
+        assert_eq!(diagnostics.fg, colors.foreground);
+        assert_eq!(diagnostics.bg, colors.background);
+        assert_eq!(diagnostics.source, source);
+
+        let report = diagnostics.to_string();
+        assert!(report.contains("last query duration: 42ms"));
+        assert!(report.contains("]11;rgb:1010/2020/3030"));
+    }
+
+    #[test]
+    fn test_wire_format_round_trips() {
+        let full = TerminalColors {
+            foreground: Some((255, 255, 255)),
+            background: Some((53, 55, 49)),
+            cursor: Some((1, 2, 3)),
+            selection_background: None,
+            selection_foreground: None,
+        };
+        assert_eq!(TerminalColors::from_bytes(&full.to_bytes()), Some(full));
+
+        let partial = TerminalColors {
+            foreground: None,
+            background: Some((0, 0, 0)),
+            cursor: None,
+            selection_background: None,
+            selection_foreground: None,
+        };
+        let bytes = partial.to_bytes();
+        assert_eq!(bytes, [1, 0b010, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(TerminalColors::from_bytes(&bytes), Some(partial));
+
+        let empty = TerminalColors::default();
+        assert_eq!(TerminalColors::from_bytes(&empty.to_bytes()), Some(empty));
+    }
+
+    #[test]
+    fn test_wire_format_rejects_unknown_versions() {
+        let mut bytes = TerminalColors::default().to_bytes();
+        bytes[0] = COLORS_WIRE_VERSION + 1;
+        assert_eq!(TerminalColors::from_bytes(&bytes), None);
+
+        let bytes = TerminalColors::default().to_bytes();
+        assert_eq!(TerminalColors::from_bytes(&bytes[..10]), None);
+        let mut flags = bytes;
+        flags[1] = 0b1000;
+        assert_eq!(TerminalColors::from_bytes(&flags), None);
+    }
+
+    #[test]
+    fn test_rgb_to_ansi256_nearest_match() {
+        assert_eq!(rgb_to_ansi256((0, 0, 0)), 16);
+        assert_eq!(rgb_to_ansi256((255, 255, 255)), 231);
+        assert_eq!(rgb_to_ansi256((255, 0, 0)), 196);
+        assert_eq!(rgb_to_ansi256((0, 135, 255)), 33);
+        // Near-grays land on the grayscale ramp rather than the cube
+        assert_eq!(rgb_to_ansi256((128, 128, 128)), 244);
+        assert_eq!(rgb_to_ansi256(FALLBACK_BG), 236);
+        assert_eq!(rgb_to_ansi256((250, 250, 250)), 231);
+
+        let mapped = TerminalColors {
+            foreground: Some((255, 0, 0)),
+            background: Some((0, 0, 0)),
+            cursor: None,
+            selection_background: None,
+            selection_foreground: None,
+        }
+        .to_ansi256();
+        assert_eq!(mapped.sgr_fg().as_deref(), Some("\x1b[38;5;196m"));
+        assert_eq!(mapped.sgr_bg().as_deref(), Some("\x1b[48;5;16m"));
+        assert_eq!(mapped.cursor, None);
+    }
+
+    #[test]
+    fn test_ansi256_cache_follows_committed_colors() {
+        let colors = ColorCache::new();
+        let mapping = Ansi256Cache::new();
+        let dark = TerminalColors {
+            foreground: Some((255, 255, 255)),
+            background: Some((0, 0, 0)),
+            cursor: None,
+            selection_background: None,
+            selection_foreground: None,
+        };
+        assert!(colors.commit(dark));
+        assert_eq!(mapping.get(colors.get()).background, Some(16));
+        assert!(mapping.is_cached());
+
+        // A theme change yields a fresh mapping
+        let light = TerminalColors {
+            foreground: Some((0, 0, 0)),
+            background: Some((255, 0, 0)),
+            cursor: None,
+            selection_background: None,
+            selection_foreground: None,
+        };
+        assert!(colors.commit(light));
+        assert_eq!(mapping.get(colors.get()).background, Some(196));
+
+        mapping.invalidate();
+        assert!(!mapping.is_cached());
+        assert_eq!(mapping.get(colors.get()).foreground, Some(16));
+    }
+
+    #[test]
+    fn test_sgr_sequences() {
+        let colors = TerminalColors {
+            foreground: Some((255, 0, 128)),
+            background: Some((30, 30, 46)),
+            cursor: None,
+            selection_background: None,
+            selection_foreground: None,
+        };
+        assert_eq!(colors.sgr_fg().as_deref(), Some("\x1b[38;2;255;0;128m"));
+        assert_eq!(colors.sgr_bg().as_deref(), Some("\x1b[48;2;30;30;46m"));
+        assert_eq!(TerminalColors::sgr_reset().as_bytes(), b"\x1b[0m");
+
+        let unknown = TerminalColors::default();
+        assert_eq!(unknown.sgr_fg(), None);
+        assert_eq!(unknown.sgr_bg(), None);
+    }
+
+    #[test]
+    fn test_swatch_string() {
+        let colors = TerminalColors {
+            foreground: Some((255, 0, 128)),
+            background: Some((30, 30, 46)),
+            cursor: None,
+            selection_background: None,
+            selection_foreground: None,
+        };
+        assert_eq!(
+            colors.swatch_string(),
+            "\x1b[48;2;255;0;128m   \x1b[0m \x1b[48;2;30;30;46m   \x1b[0m -"
+        );
+        assert_eq!(TerminalColors::default().swatch_string(), "- - -");
+    }
+
+    #[test]
+    fn test_is_grayscale() {
+        // ghostty default: white on dark gray
+        let gray = TerminalColors {
+            foreground: Some((255, 255, 255)),
+            background: Some((53, 55, 49)),
+            cursor: None,
+            selection_background: None,
+            selection_foreground: None,
+        };
+        assert_eq!(gray.is_grayscale(8), Some(true));
+        assert_eq!(gray.is_grayscale(2), Some(false));
+
+        // Solarized dark: colored fg and bg
+        let colored = TerminalColors {
+            foreground: Some((131, 148, 150)),
+            background: Some((0, 43, 54)),
+            cursor: None,
+            selection_background: None,
+            selection_foreground: None,
+        };
+        assert_eq!(colored.is_grayscale(8), Some(false));
+
+        assert_eq!(TerminalColors::default().is_grayscale(8), None);
+    }
+
+    /// Fake reader with a virtual clock. Waits always run to completion (no early
+    /// wakeup), so the loop's poll interval directly determines reply latency.
+    struct FakeTimedReader {
+        start: std::time::Instant,
+        now: std::time::Instant,
+        reply_at: Option<Duration>,
+        reply: Vec<u8>,
+        wakeups: usize,
+    }
+
+    impl FakeTimedReader {
+        fn new(reply_at: Option<Duration>) -> Self {
+            let start = std::time::Instant::now();
+            Self {
+                start,
+                now: start,
+                reply_at,
+                reply: b"\x1b]11;rgb:3535/3737/3131\x1b\\".to_vec(),
+                wakeups: 0,
+            }
+        }
+
+        fn elapsed(&self) -> Duration {
+            self.now - self.start
+        }
+    }
+
+    impl TimedRead for FakeTimedReader {
+        fn now(&self) -> std::time::Instant {
+            self.now
+        }
+
+        fn read_within(&mut self, wait: Duration, buf: &mut [u8]) -> Option<usize> {
+            self.now += wait;
+            self.wakeups += 1;
+            match self.reply_at {
+                Some(at) if self.elapsed() >= at && !self.reply.is_empty() => {
+                    let n = self.reply.len().min(buf.len());
+                    buf[..n].copy_from_slice(&self.reply[..n]);
+                    self.reply.drain(..n);
+                    Some(n)
+                }
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn test_read_osc_reply_fast_response_has_low_latency() {
+        let mut reader = FakeTimedReader::new(Some(Duration::from_millis(2)));
+        let reply = read_osc_reply(&mut reader, OSC_QUERY_TIMEOUT);
+
+        assert_eq!(parse_osc_color_response(&reply), Some((53, 55, 49)));
+        assert!(
+            reader.elapsed() <= Duration::from_millis(4),
+            "captured after {:?}",
+            reader.elapsed()
+        );
+    }
+
+    #[test]
+    fn test_read_osc_reply_stops_at_c1_terminator() {
+        let mut reader = FakeTimedReader::new(Some(Duration::from_millis(2)));
+        reader.reply = b"\x9d11;rgb:3535/3737/3131\x9c".to_vec();
+        let reply = read_osc_reply(&mut reader, OSC_QUERY_TIMEOUT);
+
+        assert_eq!(parse_osc_color_response(&reply), Some((53, 55, 49)));
+        assert!(
+            reader.elapsed() <= Duration::from_millis(4),
+            "captured after {:?}",
+            reader.elapsed()
+        );
+    }
+
+    #[test]
+    fn test_read_osc_reply_gives_up_on_unterminated_stream() {
+        let mut reader = FakeTimedReader::new(Some(Duration::from_millis(2)));
+        reader.reply = vec![b'x'; 64 * 1024];
+        let reply = read_osc_reply(&mut reader, OSC_QUERY_TIMEOUT);
+
+        assert!(reply.is_empty());
+        // Stopped at the cap, not the deadline
+        assert!(
+            reader.elapsed() < OSC_QUERY_TIMEOUT / 4,
+            "read until {:?}",
+            reader.elapsed()
+        );
+        assert!(reader.reply.len() >= 64 * 1024 - MAX_OSC_REPLY_LEN - 64);
+    }
+
+    #[test]
+    fn test_read_osc_reply_unwraps_bracketed_paste() {
+        let mut reader = FakeTimedReader::new(Some(Duration::from_millis(2)));
+        reader.reply = b"\x1b[200~\x1b]11;rgb:3535/3737/3131\x1b\\\x1b[201~".to_vec();
+        let reply = read_osc_reply(&mut reader, OSC_QUERY_TIMEOUT);
+
+        assert_eq!(reply, b"\x1b]11;rgb:3535/3737/3131\x1b\\");
+        assert_eq!(parse_osc_color_response(&reply), Some((53, 55, 49)));
+        // The trailing marker doesn't hide the terminator until the deadline
+        assert!(reader.elapsed() <= Duration::from_millis(4));
+    }
+
+    #[test]
+    fn test_read_osc_reply_skips_interleaved_resize_report() {
+        let mut reader = FakeTimedReader::new(Some(Duration::from_millis(2)));
+        reader.reply = b"\x1b]11;rgb:3535/\x1b[48;40;120;800;1920t3737/3131\x1b\\".to_vec();
+        let reply = read_osc_reply(&mut reader, OSC_QUERY_TIMEOUT);
+
+        assert_eq!(reply, b"\x1b]11;rgb:3535/3737/3131\x1b\\");
+        assert_eq!(parse_osc_color_response(&reply), Some((53, 55, 49)));
+        // The main loop hears about the resize afterwards, once
+        assert!(take_resize_during_query());
+        assert!(!take_resize_during_query());
+
+        // A report cut off mid-read is held until it completes
+        let mut partial = b"\x1b]11;rgb:3535/\x1b[48;40".to_vec();
+        assert!(!strip_resize_reports(&mut partial));
+        partial.extend_from_slice(b";120;0;0t3737/3131\x1b\\");
+        assert!(strip_resize_reports(&mut partial));
+        assert_eq!(partial, b"\x1b]11;rgb:3535/3737/3131\x1b\\");
+    }
+
+    #[test]
+    fn test_read_osc_reply_drops_trailing_line_ending() {
+        let mut reader = FakeTimedReader::new(Some(Duration::from_millis(2)));
+        reader.reply = b"\x1b]11;rgb:3535/3737/3131\x1b\\\r\n".to_vec();
+        let reply = read_osc_reply(&mut reader, OSC_QUERY_TIMEOUT);
+
+        assert_eq!(reply, b"\x1b]11;rgb:3535/3737/3131\x1b\\");
+        assert_eq!(osc_reply_code(&reply), Some(11));
+        // Stopped at the terminator instead of waiting out the deadline
+        assert!(reader.elapsed() <= Duration::from_millis(4));
+    }
+
+    #[test]
+    fn test_read_osc_reply_skips_line_ending_left_by_earlier_reply() {
+        let mut reader = FakeTimedReader::new(Some(Duration::from_millis(2)));
+        reader.reply = b"\r\n\x1b]10;rgb:ffff/ffff/ffff\x07\r\n".to_vec();
+        let reply = read_osc_reply(&mut reader, OSC_QUERY_TIMEOUT);
+
+        assert_eq!(reply, b"\x1b]10;rgb:ffff/ffff/ffff\x07");
+        assert!(osc_reply_matches_code(&reply, 10));
+    }
+
+    #[test]
+    fn test_read_osc_reply_stops_at_expected_len() {
+        let unterminated = b"\x1b]11;rgb:3535/3737/3131".to_vec();
+
+        let mut reader = FakeTimedReader::new(Some(Duration::from_millis(2)));
+        reader.reply.clone_from(&unterminated);
+        let reply =
+            read_osc_reply_expecting(&mut reader, OSC_QUERY_TIMEOUT, Some(unterminated.len()));
+        assert_eq!(reply, unterminated);
+        assert!(
+            reader.elapsed() <= Duration::from_millis(4),
+            "captured after {:?}",
+            reader.elapsed()
+        );
+
+        // Without the hint the same reply waits out the deadline
+        let mut reader = FakeTimedReader::new(Some(Duration::from_millis(2)));
+        reader.reply.clone_from(&unterminated);
+        assert_eq!(read_osc_reply(&mut reader, OSC_QUERY_TIMEOUT), unterminated);
+        assert_eq!(reader.elapsed(), OSC_QUERY_TIMEOUT);
+    }
+
+    #[test]
+    fn test_xterm_color_reply_len() {
+        assert_eq!(
+            xterm_color_reply_len(11),
+            b"\x1b]11;rgb:3535/3737/3131\x1b\\".len()
+        );
+        assert_eq!(xterm_color_reply_len(4), 24);
+    }
+
+    #[test]
+    fn test_pasted_osc_reply() {
+        let reply = pasted_osc_reply("\x1b]11;rgb:3535/3737/3131\x1b\\").expect("an OSC reply");
+        assert_eq!(parse_osc_color_response(reply), Some((53, 55, 49)));
+        assert!(pasted_osc_reply("\x1b]10;rgb:ff/ff/ff\x07").is_some());
+        // Ordinary pastes pass through
+        assert_eq!(pasted_osc_reply("ls -la\n"), None);
+        assert_eq!(pasted_osc_reply("\x1b]11;rgb:3535/3737/3131"), None);
+    }
+
+    #[test]
+    fn test_osc_reply_terminators() {
+        assert!(is_osc_reply_terminated(b"\x1b]11;rgb:00/00/00\x1b\\"));
+        assert!(is_osc_reply_terminated(b"\x1b]11;rgb:00/00/00\x07"));
+        assert!(is_osc_reply_terminated(b"\x9d11;rgb:00/00/00\x9c"));
+        assert!(!is_osc_reply_terminated(b"\x1b]11;rgb:00/00/00"));
+        assert!(is_osc_reply_terminated(b"\x1b]11;rgb:00/00/00\x1b\\\r\n"));
+        assert!(is_osc_reply_terminated(b"\x1b]11;rgb:00/00/00\x07\n"));
+        assert!(!is_osc_reply_terminated(b"\x1b]11;rgb:00/00/00\r\n"));
+        // A UTF-8 continuation byte is not an ST without a C1 introducer
+        assert!(!is_osc_reply_terminated("\x1b]11;rgb:00/00ꜜ".as_bytes()));
+    }
+
+    #[test]
+    fn test_read_osc_reply_deadline_is_authoritative() {
+        let mut reader = FakeTimedReader::new(None);
+        let reply = read_osc_reply(&mut reader, OSC_QUERY_TIMEOUT);
+
+        assert!(reply.is_empty());
+        assert_eq!(reader.elapsed(), OSC_QUERY_TIMEOUT);
+        // Backoff keeps a silent terminal from causing a wakeup every millisecond
+        assert!(reader.wakeups < 12, "woke {} times", reader.wakeups);
+    }
+
+    #[test]
+    fn test_read_osc_reply_slow_response_before_deadline() {
+        let mut reader = FakeTimedReader::new(Some(Duration::from_millis(150)));
+        let reply = read_osc_reply(&mut reader, OSC_QUERY_TIMEOUT);
+
+        assert_eq!(parse_osc_color_response(&reply), Some((53, 55, 49)));
+        assert!(reader.elapsed() <= OSC_QUERY_TIMEOUT);
+    }
+
+    #[test]
+    fn test_passthrough_wraps_queries() {
+        let query = b"\x1b]11;?\x1b\\";
+        assert_eq!(Passthrough::None.wrap(query), query.to_vec());
+        assert_eq!(
+            Passthrough::Tmux.wrap(query),
+            b"\x1bPtmux;\x1b\x1b]11;?\x1b\x1b\\\x1b\\".to_vec()
+        );
+        assert_eq!(
+            Passthrough::Screen.wrap(query),
+            b"\x1bP\x1b]11;?\x1b\\\x1b\\".to_vec()
+        );
+    }
+
+    /// Raw mode stand-in that records every toggle.
+    struct MockRawMode {
+        enabled: bool,
+        fail_enable: bool,
+        calls: Vec<&'static str>,
+    }
 
-    let output = Command::new("sh")
-        .arg("-c")
-        .arg(&script)
-        .stdin(Stdio::null())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .output();
+    impl MockRawMode {
+        fn new(enabled: bool) -> Self {
+            Self {
+                enabled,
+                fail_enable: false,
+                calls: Vec::new(),
+            }
+        }
+    }
 
-    match output {
-        Ok(output) => {
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            let trimmed = stdout.trim();
+    impl RawMode for MockRawMode {
+        fn is_enabled(&self) -> std::io::Result<bool> {
+            Ok(self.enabled)
+        }
 
-            if trimmed.is_empty() {
-                return None;
+        fn enable(&mut self) -> std::io::Result<()> {
+            self.calls.push("enable");
+            if self.fail_enable {
+                return Err(std::io::Error::other("not a tty"));
             }
+            self.enabled = true;
+            Ok(())
+        }
 
-            // Parse "RRRR GGGG BBBB" format
-            let parts: Vec<&str> = trimmed.split_whitespace().collect();
-            if parts.len() != 3 {
-                return None;
+        fn disable(&mut self) -> std::io::Result<()> {
+            self.calls.push("disable");
+            self.enabled = false;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_with_raw_mode_restores_cooked_terminal() {
+        let mut mode = MockRawMode::new(false);
+        assert_eq!(with_raw_mode(&mut mode, || 7), Some(7));
+        assert_eq!(mode.calls, vec!["enable", "disable"]);
+        assert!(!mode.enabled);
+    }
+
+    #[test]
+    fn test_with_raw_mode_leaves_raw_terminal_raw() {
+        let mut mode = MockRawMode::new(true);
+        assert_eq!(with_raw_mode(&mut mode, || 7), Some(7));
+        assert!(mode.calls.is_empty());
+        assert!(mode.enabled);
+    }
+
+    #[test]
+    fn test_with_raw_mode_skips_query_if_enable_fails() {
+        let mut mode = MockRawMode::new(false);
+        mode.fail_enable = true;
+        let mut ran = false;
+        assert_eq!(with_raw_mode(&mut mode, || ran = true), None);
+        assert!(!ran);
+        assert_eq!(mode.calls, vec!["enable"]);
+    }
+
+    #[test]
+    fn test_color_cache_untouched_until_commit() {
+        let cache = ColorCache::new();
+        let candidate = query_colors_with(&mut MockOscTerminal::new(true));
+
+        assert_eq!(candidate.background, Some((53, 55, 49)));
+        assert_eq!(cache.get().background, None);
+        assert!(!cache.is_initialized());
+
+        assert!(cache.commit(candidate));
+        assert_eq!(cache.get().foreground, Some((255, 255, 255)));
+        assert_eq!(cache.get().background, Some((53, 55, 49)));
+        assert!(cache.is_initialized());
+    }
+
+    #[test]
+    fn test_color_cache_rejects_partial_and_degenerate_candidates() {
+        let cache = ColorCache::new();
+        let good = TerminalColors {
+            foreground: Some((255, 255, 255)),
+            background: Some((53, 55, 49)),
+            cursor: None,
+            selection_background: None,
+            selection_foreground: None,
+        };
+        assert!(cache.commit(good));
+
+        let mut silent = MockOscTerminal::new(true);
+        silent.responds = false;
+        assert!(!cache.commit(query_colors_with(&mut silent)));
+
+        let partial = TerminalColors {
+            background: Some((0, 0, 0)),
+            ..TerminalColors::default()
+        };
+        assert!(!cache.commit(partial));
+
+        let degenerate = TerminalColors {
+            foreground: Some((10, 10, 10)),
+            background: Some((10, 10, 10)),
+            cursor: None,
+            selection_background: None,
+            selection_foreground: None,
+        };
+        assert!(!cache.commit(degenerate));
+
+        assert_eq!(cache.get().foreground, good.foreground);
+        assert_eq!(cache.get().background, good.background);
+    }
+
+    #[test]
+    fn test_lerp_palette_midpoint_and_endpoints() {
+        let from = [(0, 0, 0); 16];
+        let mut to = [(255, 255, 255); 16];
+        to[1] = (200, 100, 0);
+
+        let mid = lerp_palette(&from, &to, 0.5);
+        assert_eq!(mid[0], (128, 128, 128));
+        assert_eq!(mid[1], (100, 50, 0));
+
+        assert_eq!(lerp_palette(&from, &to, 0.0), from);
+        assert_eq!(lerp_palette(&from, &to, 1.0), to);
+        // t is clamped
+        assert_eq!(lerp_palette(&from, &to, -1.0), from);
+        assert_eq!(lerp_palette(&from, &to, 7.5), to);
+    }
+
+    #[test]
+    fn test_emit_palette_frame_writes_osc4_per_slot() {
+        let mut out = Vec::new();
+        emit_palette_frame_via(&mut out, &ANSI_16, &ANSI_16, 0.5, PaletteProtocol::Osc4)
+            .expect("write to vec");
+        let out = String::from_utf8(out).expect("ascii output");
+
+        assert_eq!(out.matches("\x1b]4;").count(), 16);
+        assert!(out.starts_with("\x1b]4;0;rgb:00/00/00\x1b\\"));
+        assert!(out.ends_with("\x1b]4;15;rgb:ff/ff/ff\x1b\\"));
+    }
+
+    #[test]
+    fn test_parse_osc_color_response_in_invalid_utf8_noise() {
+        let mut response = vec![0xff, 0xfe, b'x', 0xc3];
+        response.extend_from_slice(b"\x1b]11;rgb:3535/3737/3131\x1b\\");
+        response.extend_from_slice(&[0x80, 0xbf, 0xff]);
+        assert_eq!(parse_osc_color_response(&response), Some((53, 55, 49)));
+
+        let mut response = vec![0xe2, 0x28, 0xa1];
+        response.extend_from_slice(b"\x1b]10;#ffcc00\x07");
+        assert_eq!(parse_osc_color_response(&response), Some((255, 204, 0)));
+    }
+
+    #[test]
+    fn test_nesting_depth_from_env() {
+        let depth = |vars: &[(&str, &str)]| {
+            nesting_depth_from_env(|key| {
+                vars.iter()
+                    .find(|(k, _)| *k == key)
+                    .map(|(_, v)| v.to_string())
+            })
+        };
+        assert_eq!(depth(&[]), 0);
+        assert_eq!(depth(&[(DMUX_ENV, "1"), (DMUX_LEVEL_ENV, "1")]), 1);
+        assert_eq!(depth(&[(DMUX_ENV, "1"), (DMUX_LEVEL_ENV, "2")]), 2);
+        // The marker alone still means nested
+        assert_eq!(depth(&[(DMUX_ENV, "1")]), 1);
+        assert_eq!(depth(&[(DMUX_ENV, "1"), (DMUX_LEVEL_ENV, "x")]), 1);
+        assert_eq!(depth(&[(DMUX_ENV, "0")]), 0);
+        assert_eq!(depth(&[(DMUX_LEVEL_ENV, "3")]), 3);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_theme_listener_not_spawned_when_nested() {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        // No tokio runtime here, so actually spawning would panic
+        assert!(!spawn_theme_change_listener_at_depth(tx, 1));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_remote_recv_stops_at_deadline_while_bytes_trickle_in() {
+        use std::os::unix::io::AsRawFd;
+        use std::os::unix::net::UnixStream;
+
+        let (reader, mut far_side) = UnixStream::pair().expect("socket pair");
+        // An unterminated reply, one byte at a time, well inside the idle timeout
+        let link = std::thread::spawn(move || {
+            for _ in 0..40 {
+                if far_side.write_all(b"1").is_err() {
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(10));
             }
+        });
 
-            let r = parse_hex_component(parts[0])?;
-            let g = parse_hex_component(parts[1])?;
-            let b = parse_hex_component(parts[2])?;
+        let mut sink = Vec::new();
+        let mut io = RemoteOsc {
+            poller: FdPoller {
+                fd: reader.as_raw_fd(),
+            },
+            writer: &mut sink,
+            opts: RemoteQueryOptions {
+                idle_timeout: Duration::from_millis(100),
+                ..RemoteQueryOptions::default()
+            },
+        };
+        let started = std::time::Instant::now();
+        let response = io.recv(Duration::from_millis(50));
+        let elapsed = started.elapsed();
 
-            Some((r, g, b))
+        assert!(!response.is_empty());
+        // Bounded by the idle timeout (the larger of the two), not the 400ms stream
+        assert!(elapsed < Duration::from_millis(300), "took {elapsed:?}");
+        drop(reader);
+        link.join().expect("link thread");
+    }
+
+    #[tokio::test]
+    async fn test_theme_broadcast_coalesces_rapid_changes() {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let broadcast = spawn_theme_broadcast(rx, Duration::from_millis(50));
+        let mut subscriber = broadcast.subscribe();
+
+        // Dark, light, dark again, all inside one window
+        for bg in [(0, 0, 0), (255, 255, 255), (30, 30, 46)] {
+            let colors = TerminalColors {
+                background: Some(bg),
+                ..TerminalColors::default()
+            };
+            tx.send(ThemeChangeEvent { colors })
+                .expect("bridge running");
+            tokio::time::sleep(Duration::from_millis(10)).await;
         }
-        Err(_) => None,
+
+        let event = subscriber.recv().await.expect("one broadcast");
+        assert_eq!(event.colors.background, Some((30, 30, 46)));
+
+        // Nothing else follows once the burst has been flushed
+        drop(tx);
+        drop(broadcast);
+        assert!(matches!(
+            subscriber.recv().await,
+            Err(broadcast::error::RecvError::Closed)
+        ));
     }
-}
 
-/// Message sent when theme colors change.
-#[derive(Debug, Clone)]
-pub struct ThemeChangeEvent {
-    pub colors: TerminalColors,
-}
+    #[test]
+    fn test_resolve_colors_env_override() {
+        let cache = ColorCache::new();
+        let env = TerminalColors {
+            background: parse_hex_color("#002b36"),
+            ..TerminalColors::default()
+        };
 
-/// Spawn a background task that listens for theme change signals (SIGUSR1 on Unix).
-///
-/// When a signal is received, it sends a `ThemeChangeEvent` through the provided channel.
-/// This allows the application to re-query terminal colors and update accordingly.
-///
-/// Note: The actual re-query of colors must be done from the main thread after
-/// temporarily exiting the alternate screen buffer.
-#[cfg(unix)]
-pub fn spawn_theme_change_listener(tx: mpsc::UnboundedSender<ThemeChangeEvent>) {
-    tokio::spawn(async move {
-        let mut sigusr1 =
-            match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::user_defined1()) {
-                Ok(sig) => sig,
-                Err(e) => {
-                    eprintln!("Warning: Failed to register SIGUSR1 handler for theme changes: {e}");
-                    return;
+        let (colors, source) = resolve_colors(&cache, env);
+        assert_eq!(source, ColorSource::EnvOverride);
+        assert_eq!(colors.background, Some((0, 43, 54)));
+        assert_eq!(colors.foreground, Some(FALLBACK_FG));
+
+        // The override wins even over queried colors, filling gaps from the cache
+        cache.commit(TerminalColors {
+            foreground: Some((1, 2, 3)),
+            background: Some((4, 5, 6)),
+            cursor: None,
+            selection_background: None,
+            selection_foreground: None,
+        });
+        let (colors, source) = resolve_colors(&cache, env);
+        assert_eq!(source, ColorSource::EnvOverride);
+        assert_eq!(colors.foreground, Some((1, 2, 3)));
+        assert_eq!(colors.background, Some((0, 43, 54)));
+    }
+
+    #[test]
+    fn test_reverse_video_swaps_inherited_colors() {
+        let cache = ColorCache::new();
+        cache.commit(TerminalColors {
+            foreground: Some((1, 2, 3)),
+            background: Some((4, 5, 6)),
+            cursor: Some((7, 8, 9)),
+            selection_background: None,
+            selection_foreground: None,
+        });
+
+        let (colors, source) = resolve_outer_colors(&cache, TerminalColors::default(), true);
+        assert_eq!(source, ColorSource::Queried);
+        assert_eq!(colors.foreground, Some((4, 5, 6)));
+        assert_eq!(colors.background, Some((1, 2, 3)));
+        assert_eq!(colors.cursor, Some((7, 8, 9)));
+
+        let (colors, _) = resolve_outer_colors(&cache, TerminalColors::default(), false);
+        assert_eq!(colors.foreground, Some((1, 2, 3)));
+        assert_eq!(colors.background, Some((4, 5, 6)));
+    }
+
+    #[test]
+    fn test_color_override_stack_layers_over_queried_colors() {
+        let cache = ColorCache::new();
+        let queried = TerminalColors {
+            foreground: Some((1, 2, 3)),
+            background: Some((4, 5, 6)),
+            cursor: Some((7, 8, 9)),
+            selection_background: None,
+            selection_foreground: None,
+        };
+        cache.commit(queried);
+        let overrides = ColorOverrideStack::new();
+        let resolve = || overrides.apply(resolve_colors(&cache, TerminalColors::default()));
+        assert_eq!(resolve(), (queried, ColorSource::Queried));
+
+        overrides.push(HIGH_VISIBILITY_COLORS);
+        assert_eq!(resolve(), (HIGH_VISIBILITY_COLORS, ColorSource::Override));
+
+        // A partial override on top keeps the colors it doesn't set from below
+        let dim_background = TerminalColors {
+            background: Some((30, 30, 30)),
+            ..TerminalColors::default()
+        };
+        overrides.push(dim_background);
+        let (colors, source) = resolve();
+        assert_eq!(source, ColorSource::Override);
+        assert_eq!(colors.background, Some((30, 30, 30)));
+        assert_eq!(colors.foreground, queried.foreground);
+
+        assert_eq!(overrides.pop(), Some(dim_background));
+        assert_eq!(resolve(), (HIGH_VISIBILITY_COLORS, ColorSource::Override));
+        assert_eq!(overrides.pop(), Some(HIGH_VISIBILITY_COLORS));
+        assert_eq!(resolve(), (queried, ColorSource::Queried));
+        assert_eq!(overrides.pop(), None);
+    }
+
+    #[test]
+    fn test_derived_fallback_background_follows_mode() {
+        let with_fg = |fg| TerminalColors {
+            foreground: fg,
+            ..TerminalColors::default()
+        };
+        let derived = |colors: &TerminalColors| {
+            derive_fallback_background(colors, FallbackBackground::Derived)
+        };
+
+        // Light text means a dark theme, dark text a light one
+        let dark = derived(&with_fg(Some((230, 230, 230))));
+        let light = derived(&with_fg(Some((30, 30, 30))));
+        assert_eq!(dark, FALLBACK_BG_DARK);
+        assert_eq!(light, FALLBACK_BG_LIGHT);
+        assert!(relative_luminance(dark) < 0.01);
+        assert!(relative_luminance(light) > 0.9);
+
+        // $COLORFGBG supplies the foreground when the query doesn't
+        let (colors, _) = apply_color_fallbacks(TerminalColors::default(), Some("0;default"));
+        assert_eq!(derived(&colors), FALLBACK_BG_LIGHT);
+        // Nothing known: dark, to match the white fallback foreground
+        assert_eq!(derived(&TerminalColors::default()), FALLBACK_BG_DARK);
+    }
+
+    #[test]
+    fn test_ghostty_fallback_background_is_kept_on_request() {
+        let colors = TerminalColors {
+            foreground: Some((30, 30, 30)),
+            ..TerminalColors::default()
+        };
+        assert_eq!(
+            derive_fallback_background(&colors, FallbackBackground::Ghostty),
+            FALLBACK_BG
+        );
+    }
+
+    #[test]
+    fn test_resolve_colors_fallback_and_cache_sources() {
+        let cache = ColorCache::new();
+        let (colors, source) = resolve_colors(&cache, TerminalColors::default());
+        assert_eq!(source, ColorSource::Fallback);
+        assert_eq!(colors.foreground, Some(FALLBACK_FG));
+        assert_eq!(colors.background, Some(FALLBACK_BG));
+
+        let pinned = TerminalColors {
+            foreground: Some((10, 20, 30)),
+            background: Some((40, 50, 60)),
+            cursor: None,
+            selection_background: None,
+            selection_foreground: None,
+        };
+        cache.commit_with_source(pinned, ColorSource::Cached);
+        let (_, source) = resolve_colors(&cache, TerminalColors::default());
+        assert_eq!(source, ColorSource::Cached);
+
+        cache.commit(pinned);
+        let (_, source) = resolve_colors(&cache, TerminalColors::default());
+        assert_eq!(source, ColorSource::Queried);
+    }
+
+    #[test]
+    fn test_parse_hex_color() {
+        assert_eq!(parse_hex_color("#81a1c1"), Some((0x81, 0xa1, 0xc1)));
+        assert_eq!(parse_hex_color("ffffff"), Some((255, 255, 255)));
+        assert_eq!(parse_hex_color("#fff"), None);
+        assert_eq!(parse_hex_color("#gg0000"), None);
+    }
+
+    #[test]
+    fn test_cursor_style_decscusr_params() {
+        let cases = [
+            (CursorStyle::BlinkingBlock, 1),
+            (CursorStyle::SteadyBlock, 2),
+            (CursorStyle::BlinkingUnderline, 3),
+            (CursorStyle::SteadyUnderline, 4),
+            (CursorStyle::BlinkingBar, 5),
+            (CursorStyle::SteadyBar, 6),
+        ];
+        for (style, param) in cases {
+            assert_eq!(style.decscusr_param(), param);
+            assert_eq!(style.decscusr(), format!("\x1b[{} q", param));
+            assert_eq!(CursorStyle::from_decscusr_param(param), Some(style));
+        }
+        assert_eq!(
+            CursorStyle::from_decscusr_param(0),
+            Some(CursorStyle::BlinkingBlock)
+        );
+        assert_eq!(CursorStyle::from_decscusr_param(7), None);
+    }
+
+    #[test]
+    fn test_parse_decrqss_cursor_response() {
+        assert_eq!(
+            parse_decrqss_cursor_response(b"\x1bP1$r6 q\x1b\\"),
+            Some(CursorStyle::SteadyBar)
+        );
+        assert_eq!(
+            parse_decrqss_cursor_response(b"\x1bP1$r q\x1b\\"),
+            Some(CursorStyle::BlinkingBlock)
+        );
+        assert_eq!(parse_decrqss_cursor_response(b"\x1bP0$r\x1b\\"), None);
+        assert_eq!(parse_decrqss_cursor_response(b""), None);
+    }
+
+    #[test]
+    fn test_write_cursor_style_leaves_unknown_style_alone() {
+        let mut out = Vec::new();
+        assert!(!write_cursor_style(&mut out, None).expect("write to vec"));
+        assert!(out.is_empty());
+
+        assert!(write_cursor_style(&mut out, Some(CursorStyle::SteadyUnderline)).expect("write"));
+        assert_eq!(out, b"\x1b[4 q");
+    }
+
+    /// Mock terminal that answers OSC 4 queries for the first `answered` indices.
+    struct MockPaletteTerminal {
+        palette: [(u8, u8, u8); 16],
+        answered: usize,
+        queried: Vec<u8>,
+        pending: Vec<u8>,
+    }
+
+    impl OscIo for MockPaletteTerminal {
+        fn send(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+            let s = String::from_utf8_lossy(bytes);
+            let idx = s
+                .strip_prefix("\x1b]4;")
+                .and_then(|rest| rest.strip_suffix(";?\x1b\\"))
+                .and_then(|idx| idx.parse::<u8>().ok());
+            if let Some(idx) = idx {
+                self.queried.push(idx);
+                if (idx as usize) < self.answered {
+                    let (r, g, b) = self.palette[idx as usize];
+                    self.pending =
+                        format!("\x1b]4;{idx};rgb:{r:02x}/{g:02x}/{b:02x}\x1b\\").into_bytes();
                 }
-            };
+            }
+            Ok(())
+        }
 
-        loop {
-            sigusr1.recv().await;
+        fn recv(&mut self, _timeout: Duration) -> Vec<u8> {
+            std::mem::take(&mut self.pending)
+        }
+    }
 
-            // Signal received - notify that theme may have changed
-            // The actual color re-query happens in the main event loop
-            // because we need to temporarily exit the alternate screen
-            let colors = get_outer_colors();
+    #[test]
+    fn test_palette_query_stores_bright_colors_separately() {
+        let mut palette = ANSI_16;
+        palette[1] = (0xaa, 0x00, 0x00);
+        palette[9] = (0xff, 0x55, 0x55);
+        let mut term = MockPaletteTerminal {
+            palette,
+            answered: 16,
+            queried: Vec::new(),
+            pending: Vec::new(),
+        };
 
-            if tx.send(ThemeChangeEvent { colors }).is_err() {
-                // Channel closed, exit the task
-                break;
+        let stored = query_palette_with(&mut term);
+        assert_eq!(term.queried, (0..16).collect::<Vec<u8>>());
+        assert_eq!(palette_color(&stored, 1), Some((0xaa, 0x00, 0x00)));
+        assert_eq!(bright_palette_color(&stored, 1), Some((0xff, 0x55, 0x55)));
+        assert_eq!(palette_color(&stored, 9), bright_palette_color(&stored, 1));
+        for idx in 0..8 {
+            assert_eq!(
+                bright_palette_color(&stored, idx),
+                Some(palette[idx as usize + 8])
+            );
+        }
+        // Only 0-7 have a bright variant
+        assert_eq!(bright_palette_color(&stored, 8), None);
+        assert_eq!(palette_color(&stored, 16), None);
+    }
+
+    #[test]
+    fn test_palette_query_stops_at_first_unanswered_index() {
+        let mut term = MockPaletteTerminal {
+            palette: ANSI_16,
+            answered: 8,
+            queried: Vec::new(),
+            pending: Vec::new(),
+        };
+
+        let stored = query_palette_with(&mut term);
+        assert_eq!(term.queried, (0..=8).collect::<Vec<u8>>());
+        assert_eq!(palette_color(&stored, 7), Some(ANSI_16[7]));
+        assert_eq!(bright_palette_color(&stored, 1), None);
+    }
+
+    /// Mock terminal that answers a batch of OSC 4 queries in one read, for
+    /// the indices it has colors for.
+    struct MockIndexedPaletteTerminal {
+        colors: HashMap<u8, (u8, u8, u8)>,
+        sends: usize,
+        pending: Vec<u8>,
+    }
+
+    impl OscIo for MockIndexedPaletteTerminal {
+        fn send(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+            self.sends += 1;
+            let s = String::from_utf8_lossy(bytes);
+            for query in s.split("\x1b]4;").skip(1) {
+                let Some(idx) = query
+                    .strip_suffix(";?\x1b\\")
+                    .and_then(|idx| idx.parse::<u8>().ok())
+                else {
+                    continue;
+                };
+                if let Some((r, g, b)) = self.colors.get(&idx) {
+                    self.pending.extend_from_slice(
+                        format!(
+                            "\x1b]4;{idx};rgb:{r:02x}{r:02x}/{g:02x}{g:02x}/{b:02x}{b:02x}\x1b\\"
+                        )
+                        .as_bytes(),
+                    );
+                }
             }
+            Ok(())
         }
-    });
-}
 
-/// Non-Unix platforms: no-op signal listener.
-#[cfg(not(unix))]
-pub fn spawn_theme_change_listener(_tx: mpsc::UnboundedSender<ThemeChangeEvent>) {
-    // Signal-based theme change detection not supported on this platform
-}
+        fn recv(&mut self, _timeout: Duration) -> Vec<u8> {
+            std::mem::take(&mut self.pending)
+        }
+    }
 
-/// Re-query terminal colors. This should be called from the main thread
-/// after receiving a ThemeChangeEvent, temporarily exiting the alternate screen.
-///
-/// Returns the new colors and updates the global state.
-pub fn refresh_outer_colors() -> TerminalColors {
-    query_outer_terminal_colors()
-}
+    #[test]
+    fn test_palette_indices_query_is_batched_beyond_sixteen() {
+        let colors = HashMap::from([
+            (16, (0x00, 0x00, 0x00)),
+            (100, (0x87, 0x87, 0x00)),
+            (255, (0xee, 0xee, 0xee)),
+        ]);
+        let mut term = MockIndexedPaletteTerminal {
+            colors: colors.clone(),
+            sends: 0,
+            pending: Vec::new(),
+        };
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        assert_eq!(
+            query_palette_indices_with(&mut term, &[16, 100, 255]),
+            colors
+        );
+        assert_eq!(term.sends, 1, "all queries go out in one write");
+    }
 
     #[test]
-    fn test_parse_osc_color_response_16bit() {
-        // Typical response: ESC ] 11 ; rgb:3535/3737/3131 ESC \
-        let response = b"\x1b]11;rgb:3535/3737/3131\x1b\\";
-        let result = parse_osc_color_response(response);
-        assert_eq!(result, Some((0x35, 0x37, 0x31))); // (53, 55, 49)
+    fn test_palette_indices_query_leaves_out_unanswered_indices() {
+        let mut term = MockIndexedPaletteTerminal {
+            colors: HashMap::from([(100, (0x87, 0x87, 0x00))]),
+            sends: 0,
+            pending: Vec::new(),
+        };
+
+        let found = query_palette_indices_with(&mut term, &[16, 100, 255]);
+        assert_eq!(found, HashMap::from([(100, (0x87, 0x87, 0x00))]));
+        assert!(query_palette_indices_with(&mut term, &[]).is_empty());
+        assert_eq!(term.sends, 1, "an empty query sends nothing");
     }
 
     #[test]
-    fn test_parse_osc_color_response_8bit() {
-        let response = b"\x1b]11;rgb:35/37/31\x1b\\";
-        let result = parse_osc_color_response(response);
-        assert_eq!(result, Some((0x35, 0x37, 0x31)));
+    fn test_indexed_palette_reply_parsing() {
+        assert_eq!(
+            parse_indexed_palette_response(b"\x1b]4;255;rgb:eeee/eeee/eeee\x1b\\"),
+            Some((255, (0xee, 0xee, 0xee)))
+        );
+        assert_eq!(
+            parse_indexed_palette_response(b"\x1b]4;16;rgb:00/00/00\x07"),
+            Some((16, (0, 0, 0)))
+        );
+        assert_eq!(
+            parse_indexed_palette_response(b"\x1b]11;rgb:00/00/00\x1b\\"),
+            None
+        );
+        assert_eq!(
+            parse_indexed_palette_response(b"\x1b]4;256;rgb:00/00/00\x1b\\"),
+            None
+        );
     }
 
     #[test]
-    fn test_parse_osc_color_response_black() {
-        let response = b"\x1b]11;rgb:0000/0000/0000\x1b\\";
-        let result = parse_osc_color_response(response);
-        assert_eq!(result, Some((0, 0, 0)));
+    fn test_kitty_palette_request_encoding() {
+        let query = kitty_palette_query();
+        assert!(query.starts_with("\x1b]21;0=?;1=?;"));
+        assert!(query.ends_with(";15=?\x1b\\"));
+        assert_eq!(query.matches("=?").count(), 16);
+
+        assert_eq!(
+            format_kitty_palette_set(&[(1, (0xaa, 0, 0)), (9, (0xff, 0x55, 0x55))]),
+            "\x1b]21;1=rgb:aa/00/00;9=rgb:ff/55/55\x1b\\"
+        );
+
+        let mut out = Vec::new();
+        emit_palette_frame_via(&mut out, &ANSI_16, &ANSI_16, 0.0, PaletteProtocol::Kitty)
+            .expect("write to vec");
+        let out = String::from_utf8(out).expect("ascii output");
+        assert_eq!(out.matches("\x1b]").count(), 1);
+        assert!(out.contains(";15=rgb:ff/ff/ff\x1b\\"));
     }
 
     #[test]
-    fn test_parse_osc_color_response_white() {
-        let response = b"\x1b]10;rgb:ffff/ffff/ffff\x1b\\";
-        let result = parse_osc_color_response(response);
-        assert_eq!(result, Some((255, 255, 255)));
+    fn test_kitty_palette_response_parsing() {
+        let reply = b"\x1b]21;0=rgb:00/00/00;1=#aa0000;9=rgb:ffff/5555/5555;2=;foreground=rgb:ff/ff/ff;99=rgb:11/11/11\x1b\\";
+        let palette = parse_kitty_palette_response(reply);
+        assert_eq!(palette[0], Some((0, 0, 0)));
+        assert_eq!(palette[1], Some((0xaa, 0, 0)));
+        assert_eq!(palette[9], Some((0xff, 0x55, 0x55)));
+        // Empty values and keys outside 0-15 are skipped
+        assert_eq!(palette[2], None);
+        assert_eq!(palette.iter().flatten().count(), 3);
+
+        assert_eq!(
+            parse_kitty_palette_response(b"\x1b]4;1;rgb:aa/00/00\x1b\\"),
+            [None; 16]
+        );
     }
 
     #[test]
-    fn test_parse_hex_component() {
-        assert_eq!(parse_hex_component("ff"), Some(255));
-        assert_eq!(parse_hex_component("00"), Some(0));
-        assert_eq!(parse_hex_component("ffff"), Some(255));
-        assert_eq!(parse_hex_component("0000"), Some(0));
-        assert_eq!(parse_hex_component("3535"), Some(0x35)); // 53
-        assert_eq!(parse_hex_component("8080"), Some(0x80)); // 128
+    fn test_kitty_palette_query_falls_back_to_osc4() {
+        let mut kitty = CannedReply(b"\x1b]21;0=rgb:00/00/00;1=rgb:aa/00/00\x1b\\");
+        let palette = query_palette_via(&mut kitty, PaletteProtocol::Kitty);
+        assert_eq!(palette[1], Some((0xaa, 0, 0)));
+
+        // A terminal that ignores OSC 21 is still queried with OSC 4
+        let mut term = MockPaletteTerminal {
+            palette: ANSI_16,
+            answered: 16,
+            queried: Vec::new(),
+            pending: Vec::new(),
+        };
+        let palette = query_palette_via(&mut term, PaletteProtocol::Kitty);
+        assert_eq!(term.queried.len(), 16);
+        assert_eq!(palette[15], Some(ANSI_16[15]));
+    }
+
+    #[test]
+    fn test_palette_response_must_match_the_queried_index() {
+        let reply = b"\x1b]4;9;rgb:ff/55/55\x1b\\";
+        assert_eq!(parse_palette_response(reply, 9), Some((0xff, 0x55, 0x55)));
+        assert_eq!(parse_palette_response(reply, 1), None);
+        assert_eq!(
+            parse_palette_response(b"\x1b]11;rgb:ff/55/55\x1b\\", 1),
+            None
+        );
+    }
+
+    /// Open a pseudo-terminal pair, returning the master and the slave's path.
+    fn open_pty() -> (std::fs::File, std::path::PathBuf) {
+        use std::os::unix::io::FromRawFd;
+
+        let master = unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY) };
+        assert!(master >= 0, "posix_openpt failed");
+        assert_eq!(unsafe { libc::grantpt(master) }, 0);
+        assert_eq!(unsafe { libc::unlockpt(master) }, 0);
+        let mut name = [0 as libc::c_char; 128];
+        assert_eq!(
+            unsafe { libc::ptsname_r(master, name.as_mut_ptr(), name.len()) },
+            0
+        );
+        let path = unsafe { std::ffi::CStr::from_ptr(name.as_ptr()) }
+            .to_string_lossy()
+            .into_owned();
+        (unsafe { std::fs::File::from_raw_fd(master) }, path.into())
+    }
+
+    fn is_canonical(file: &std::fs::File) -> bool {
+        use std::os::unix::io::AsRawFd;
+
+        let mut termios = std::mem::MaybeUninit::<libc::termios>::uninit();
+        assert_eq!(
+            unsafe { libc::tcgetattr(file.as_raw_fd(), termios.as_mut_ptr()) },
+            0
+        );
+        unsafe { termios.assume_init() }.c_lflag & libc::ICANON != 0
+    }
+
+    #[test]
+    fn test_tty_query_uses_its_own_handle() {
+        use std::io::Read;
+
+        let (mut master, slave_path) = open_pty();
+        // Held open so the slave's settings can be checked after the query
+        let slave = std::fs::File::open(&slave_path).expect("open slave");
+        assert!(is_canonical(&slave));
+
+        // Play the terminal on the master side: answer each query as it arrives
+        let terminal = std::thread::spawn(move || {
+            let mut seen = Vec::new();
+            let mut buf = [0u8; 256];
+            for _ in 0..3 {
+                let n = master.read(&mut buf).expect("read query");
+                let query = &buf[..n];
+                seen.extend_from_slice(query);
+                let reply: &[u8] = match query {
+                    b"\x1b]10;?\x1b\\" => b"\x1b]10;rgb:dddd/dddd/dddd\x1b\\",
+                    b"\x1b]11;?\x1b\\" => b"\x1b]11;rgb:1010/2020/3030\x1b\\",
+                    _ => b"\x1b]12;rgb:ffff/0000/0000\x1b\\",
+                };
+                master.write_all(reply).expect("write reply");
+            }
+            // Closing the master would hang up the slave and drop the last reply
+            (seen, master)
+        });
+
+        let colors = query_colors_via_tty(&slave_path).expect("query tty");
+        assert_eq!(colors.foreground, Some((0xdd, 0xdd, 0xdd)));
+        assert_eq!(colors.background, Some((0x10, 0x20, 0x30)));
+        assert_eq!(colors.cursor, Some((0xff, 0x00, 0x00)));
+
+        // Every query went out through the opened handle, and its prior
+        // (cooked) settings were restored afterwards
+        let (seen, _master) = terminal.join().expect("terminal thread");
+        assert_eq!(seen, b"\x1b]10;?\x1b\\\x1b]11;?\x1b\\\x1b]12;?\x1b\\");
+        assert!(is_canonical(&slave));
+    }
+
+    #[test]
+    fn test_tty_query_fails_without_a_terminal() {
+        let missing = std::path::Path::new("/nonexistent/tty");
+        assert!(query_colors_via_tty(missing).is_err());
     }
 }
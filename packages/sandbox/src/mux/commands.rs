@@ -31,6 +31,11 @@ pub enum MuxCommand {
     SplitHorizontal,
     SplitVertical,
     ClosePane,
+    RespawnPane,
+    ResetPane,
+    MonitorSilence,
+    SendKeys,
+    PipePane,
     ToggleZoom,
     SwapPaneLeft,
     SwapPaneRight,
@@ -47,6 +52,7 @@ pub enum MuxCommand {
     RenameTab,
     MoveTabLeft,
     MoveTabRight,
+    SpawnWorkspace,
 
     // Sidebar
     ToggleSidebar,
@@ -80,6 +86,12 @@ pub enum MuxCommand {
     EnableDeltaPager,
     DisableDeltaPager,
     CopyScrollback,
+    RefreshThemeColors,
+    CopyThemeConfig,
+    ToggleHighVisibility,
+    AdjustColors,
+    SendNextKey,
+    CopyMode,
 
     // External tools
     OpenEditor,
@@ -131,6 +143,11 @@ impl MuxCommand {
             MuxCommand::SplitHorizontal,
             MuxCommand::SplitVertical,
             MuxCommand::ClosePane,
+            MuxCommand::RespawnPane,
+            MuxCommand::ResetPane,
+            MuxCommand::MonitorSilence,
+            MuxCommand::SendKeys,
+            MuxCommand::PipePane,
             MuxCommand::ToggleZoom,
             MuxCommand::SwapPaneLeft,
             MuxCommand::SwapPaneRight,
@@ -146,6 +163,7 @@ impl MuxCommand {
             MuxCommand::RenameTab,
             MuxCommand::MoveTabLeft,
             MuxCommand::MoveTabRight,
+            MuxCommand::SpawnWorkspace,
             // Sidebar
             MuxCommand::ToggleSidebar,
             MuxCommand::SelectSandbox,
@@ -174,6 +192,12 @@ impl MuxCommand {
             MuxCommand::EnableDeltaPager,
             MuxCommand::DisableDeltaPager,
             MuxCommand::CopyScrollback,
+            MuxCommand::RefreshThemeColors,
+            MuxCommand::CopyThemeConfig,
+            MuxCommand::ToggleHighVisibility,
+            MuxCommand::AdjustColors,
+            MuxCommand::SendNextKey,
+            MuxCommand::CopyMode,
             // External tools
             MuxCommand::OpenEditor,
             MuxCommand::OpenWith,
@@ -215,6 +239,11 @@ impl MuxCommand {
             MuxCommand::SplitHorizontal => "Split Horizontal",
             MuxCommand::SplitVertical => "Split Vertical",
             MuxCommand::ClosePane => "Close Pane",
+            MuxCommand::RespawnPane => "Respawn Pane",
+            MuxCommand::ResetPane => "Reset Pane",
+            MuxCommand::MonitorSilence => "Monitor Silence",
+            MuxCommand::SendKeys => "Send Keys",
+            MuxCommand::PipePane => "Pipe Pane",
             MuxCommand::ToggleZoom => "Toggle Zoom",
             MuxCommand::SwapPaneLeft => "Swap Pane Left",
             MuxCommand::SwapPaneRight => "Swap Pane Right",
@@ -229,6 +258,7 @@ impl MuxCommand {
             MuxCommand::RenameTab => "Rename Tab",
             MuxCommand::MoveTabLeft => "Move Tab Left",
             MuxCommand::MoveTabRight => "Move Tab Right",
+            MuxCommand::SpawnWorkspace => "Spawn Workspace",
             MuxCommand::ToggleSidebar => "Toggle Sidebar",
             MuxCommand::SelectSandbox => "Select Sandbox",
             MuxCommand::NextSandbox => "Next Sandbox",
@@ -252,6 +282,12 @@ impl MuxCommand {
             MuxCommand::EnableDeltaPager => "Enable Delta Pager",
             MuxCommand::DisableDeltaPager => "Disable Delta Pager",
             MuxCommand::CopyScrollback => "Copy Scrollback",
+            MuxCommand::RefreshThemeColors => "Refresh Theme Colors",
+            MuxCommand::CopyThemeConfig => "Copy Theme Config",
+            MuxCommand::ToggleHighVisibility => "Toggle High Visibility",
+            MuxCommand::AdjustColors => "Adjust Colors",
+            MuxCommand::SendNextKey => "Send Next Key",
+            MuxCommand::CopyMode => "Copy Mode",
             MuxCommand::OpenEditor => "Open Editor",
             MuxCommand::OpenWith => "Open With...",
             MuxCommand::OpenWithVSCode => "VS Code",
@@ -276,6 +312,12 @@ impl MuxCommand {
             MuxCommand::SplitHorizontal => &["divide", "new pane", "hsplit"],
             MuxCommand::SplitVertical => &["divide", "new pane", "vsplit"],
             MuxCommand::ToggleZoom => &["maximize", "fullscreen", "expand"],
+            MuxCommand::RespawnPane => &["restart", "rerun", "relaunch"],
+            MuxCommand::ResetPane => &["garbled", "charset", "attributes", "ris", "decstr", "fix"],
+            MuxCommand::MonitorSilence => &["quiet", "idle", "finished", "alert", "watch"],
+            MuxCommand::SendKeys => &["type", "input", "inject", "script"],
+            MuxCommand::PipePane => &["tee", "stream", "output", "forward"],
+            MuxCommand::SpawnWorkspace => &["template", "preset", "layout", "project"],
             MuxCommand::FocusLeft => &["move left", "navigate left", "go left"],
             MuxCommand::FocusRight => &["move right", "navigate right", "go right"],
             MuxCommand::FocusUp => &["move up", "navigate up", "go up"],
@@ -298,6 +340,14 @@ impl MuxCommand {
             MuxCommand::EnableDeltaPager => &["git diff", "syntax highlighting", "pretty diff"],
             MuxCommand::DisableDeltaPager => &["git diff", "plain diff", "default pager"],
             MuxCommand::CopyScrollback => &["copy", "clipboard", "terminal output", "history"],
+            MuxCommand::RefreshThemeColors => &["theme", "colors", "reload theme", "re-read"],
+            MuxCommand::CopyThemeConfig => &["theme", "colors", "export", "pin", "settings"],
+            MuxCommand::ToggleHighVisibility => {
+                &["presentation", "demo", "contrast", "theme", "accessibility"]
+            }
+            MuxCommand::AdjustColors => &["theme", "colors", "tweak", "slider", "rgb", "accent"],
+            MuxCommand::SendNextKey => &["nested", "passthrough", "prefix", "inner dmux"],
+            MuxCommand::CopyMode => &["select", "keyboard", "yank", "cursor", "vi"],
             MuxCommand::OpenEditor => &["editor", "ide", "code", "remote", "ssh"],
             MuxCommand::OpenWith => &["editor", "ide", "code", "remote", "ssh", "choose"],
             MuxCommand::OpenWithVSCode => &["vscode", "code", "remote", "editor", "ide"],
@@ -339,6 +389,11 @@ impl MuxCommand {
             MuxCommand::SplitHorizontal => "Split the current pane horizontally",
             MuxCommand::SplitVertical => "Split the current pane vertically",
             MuxCommand::ClosePane => "Close the current pane",
+            MuxCommand::RespawnPane => "Restart the current pane's command in place",
+            MuxCommand::ResetPane => "Reset the current pane's garbled terminal state",
+            MuxCommand::MonitorSilence => "Notify when the current pane stops producing output",
+            MuxCommand::SendKeys => "Type keys into the current pane (send-keys C-c, Enter, ...)",
+            MuxCommand::PipePane => "Pipe the current pane's output to a command (pipe-pane CMD)",
             MuxCommand::ToggleZoom => "Toggle zoom on the current pane",
             MuxCommand::SwapPaneLeft => "Swap current pane with the one on the left",
             MuxCommand::SwapPaneRight => "Swap current pane with the one on the right",
@@ -353,6 +408,9 @@ impl MuxCommand {
             MuxCommand::RenameTab => "Rename the current tab",
             MuxCommand::MoveTabLeft => "Move current tab to the left",
             MuxCommand::MoveTabRight => "Move current tab to the right",
+            MuxCommand::SpawnWorkspace => {
+                "Open a tab laid out from a workspace template (spawn-workspace NAME)"
+            }
             MuxCommand::ToggleSidebar => "Toggle focus between sidebar and main workspace",
             MuxCommand::SelectSandbox => "Select a sandbox from the list",
             MuxCommand::NextSandbox => "Switch to the next sandbox workspace",
@@ -376,6 +434,16 @@ impl MuxCommand {
             MuxCommand::EnableDeltaPager => "Use delta for syntax-highlighted git diffs",
             MuxCommand::DisableDeltaPager => "Use default pager for git diffs",
             MuxCommand::CopyScrollback => "Copy entire terminal scrollback to clipboard",
+            MuxCommand::RefreshThemeColors => "Re-read colors from the outer terminal's theme",
+            MuxCommand::CopyThemeConfig => "Copy the inherited theme as a settings.json snippet",
+            MuxCommand::ToggleHighVisibility => {
+                "Temporarily switch to a high-contrast theme for presenting"
+            }
+            MuxCommand::AdjustColors => {
+                "Tune foreground, background and accent colors with a live preview"
+            }
+            MuxCommand::SendNextKey => "Pass the next key to the pane, e.g. to reach a nested dmux",
+            MuxCommand::CopyMode => "Select and copy pane text with the keyboard",
             MuxCommand::OpenEditor => "Open default editor connected to sandbox via SSH",
             MuxCommand::OpenWith => "Choose editor to open sandbox with",
             MuxCommand::OpenWithVSCode => "Open VS Code connected to sandbox via SSH",
@@ -419,6 +487,11 @@ impl MuxCommand {
             MuxCommand::SplitHorizontal
             | MuxCommand::SplitVertical
             | MuxCommand::ClosePane
+            | MuxCommand::RespawnPane
+            | MuxCommand::ResetPane
+            | MuxCommand::MonitorSilence
+            | MuxCommand::SendKeys
+            | MuxCommand::PipePane
             | MuxCommand::ToggleZoom
             | MuxCommand::SwapPaneLeft
             | MuxCommand::SwapPaneRight
@@ -433,7 +506,8 @@ impl MuxCommand {
             | MuxCommand::CloseTab
             | MuxCommand::RenameTab
             | MuxCommand::MoveTabLeft
-            | MuxCommand::MoveTabRight => "Tabs",
+            | MuxCommand::MoveTabRight
+            | MuxCommand::SpawnWorkspace => "Tabs",
 
             MuxCommand::ToggleSidebar
             | MuxCommand::SelectSandbox
@@ -461,7 +535,13 @@ impl MuxCommand {
 
             MuxCommand::EnableDeltaPager
             | MuxCommand::DisableDeltaPager
-            | MuxCommand::CopyScrollback => "Terminal",
+            | MuxCommand::CopyScrollback
+            | MuxCommand::RefreshThemeColors
+            | MuxCommand::CopyThemeConfig
+            | MuxCommand::ToggleHighVisibility
+            | MuxCommand::AdjustColors
+            | MuxCommand::SendNextKey
+            | MuxCommand::CopyMode => "Terminal",
 
             MuxCommand::OpenEditor
             | MuxCommand::OpenWith
@@ -525,6 +605,11 @@ impl MuxCommand {
             MuxCommand::SplitHorizontal => Some((KeyModifiers::ALT, KeyCode::Char('-'))),
             MuxCommand::SplitVertical => Some((KeyModifiers::ALT, KeyCode::Char('\\'))),
             MuxCommand::ClosePane => Some((KeyModifiers::ALT, KeyCode::Char('w'))),
+            MuxCommand::RespawnPane => None, // Access via command palette
+            MuxCommand::ResetPane => Some((KeyModifiers::ALT, KeyCode::Char('C'))), // Alt+Shift+C
+            MuxCommand::MonitorSilence => None, // Access via command palette
+            MuxCommand::SendKeys => None,    // Takes keys, so command lines only
+            MuxCommand::PipePane => None,    // Takes a command, so command lines only
             MuxCommand::ToggleZoom => Some((KeyModifiers::ALT, KeyCode::Char('z'))),
 
             // Swap panes
@@ -563,6 +648,7 @@ impl MuxCommand {
             MuxCommand::MoveTabRight => {
                 Some((KeyModifiers::ALT | KeyModifiers::SHIFT, KeyCode::Char(']')))
             }
+            MuxCommand::SpawnWorkspace => None, // Takes a template name, so command lines only
 
             // Sidebar - Alt+S toggles focus between sidebar and main area
             MuxCommand::ToggleSidebar => Some((KeyModifiers::ALT, KeyCode::Char('s'))),
@@ -603,6 +689,12 @@ impl MuxCommand {
             MuxCommand::EnableDeltaPager => None,
             MuxCommand::DisableDeltaPager => None,
             MuxCommand::CopyScrollback => None,
+            MuxCommand::RefreshThemeColors => Some((KeyModifiers::ALT, KeyCode::Char('T'))), // Alt+Shift+T
+            MuxCommand::CopyThemeConfig => None,
+            MuxCommand::ToggleHighVisibility => None,
+            MuxCommand::AdjustColors => None,
+            MuxCommand::SendNextKey => Some((KeyModifiers::ALT, KeyCode::Char('a'))),
+            MuxCommand::CopyMode => Some((KeyModifiers::ALT, KeyCode::Char('v'))),
 
             // External tools
             MuxCommand::OpenEditor => Some((KeyModifiers::ALT, KeyCode::Char('e'))),
@@ -729,6 +821,46 @@ impl MuxCommand {
         )
     }
 
+    /// Returns the name used to run the command from a command line: the label
+    /// in kebab case, e.g. `split-horizontal`. Submenu items are prefixed with
+    /// their menu, e.g. `open-with-zed`.
+    pub fn name(&self) -> String {
+        let label = Self::all()
+            .iter()
+            .find(|menu| {
+                menu.submenu_items()
+                    .is_some_and(|items| items.contains(self))
+            })
+            .map(|menu| format!("{} {}", menu.label(), self.label()))
+            .unwrap_or_else(|| self.label().to_string());
+        label
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_ascii_lowercase)
+            .collect::<Vec<_>>()
+            .join("-")
+    }
+
+    /// Returns true if this command opens a new pane, which a command line can
+    /// give a program to run.
+    pub fn opens_pane(&self) -> bool {
+        matches!(
+            self,
+            MuxCommand::SplitHorizontal | MuxCommand::SplitVertical | MuxCommand::NewTab
+        )
+    }
+
+    /// Returns true if a command line can give this command arguments: a
+    /// program for commands that open a pane, keys for `send-keys`, a shell
+    /// command for `pipe-pane`, a template name for `spawn-workspace`.
+    pub fn takes_args(&self) -> bool {
+        self.opens_pane()
+            || matches!(
+                self,
+                MuxCommand::SendKeys | MuxCommand::PipePane | MuxCommand::SpawnWorkspace
+            )
+    }
+
     /// Returns commands visible in the main palette (excludes submenu items).
     pub fn main_palette_commands() -> Vec<MuxCommand> {
         Self::all()
@@ -795,6 +927,62 @@ impl MuxCommand {
     }
 }
 
+/// A command line such as `split-horizontal top`, as given in the
+/// `startup_commands` setting or with `--cmd`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandInvocation {
+    pub command: MuxCommand,
+    /// Program (and its arguments) to run in the pane the command opens, the
+    /// keys for `send-keys`, the shell command for `pipe-pane`, or the
+    /// template name for `spawn-workspace`
+    pub args: Vec<String>,
+}
+
+/// Parse a command line: a command name (see `MuxCommand::name`), followed by
+/// a whitespace-separated program to run for commands that open a pane, the
+/// keys to send for `send-keys`, the shell command for `pipe-pane`, or the
+/// template to open for `spawn-workspace`.
+pub fn parse_command(line: &str) -> Result<CommandInvocation, String> {
+    let mut words = line.split_whitespace();
+    let name = words.next().ok_or("empty command")?;
+    let command = MuxCommand::all()
+        .iter()
+        .copied()
+        .find(|cmd| cmd.name().eq_ignore_ascii_case(name))
+        .ok_or_else(|| format!("unknown command '{}'", name))?;
+    let args: Vec<String> = words.map(str::to_string).collect();
+    if !args.is_empty() && !command.takes_args() {
+        return Err(format!("'{}' does not take a program to run", name));
+    }
+    Ok(CommandInvocation { command, args })
+}
+
+/// Try to match a key event to a command, for a dmux running `nesting_depth`
+/// levels inside others (0 = not nested, where this is just `from_key`).
+///
+/// With `remap`, every Alt binding takes an extra Ctrl (Alt+d becomes
+/// Ctrl+Alt+d) so the plain ones still reach the outer dmux, and keys without
+/// Ctrl+Alt go to the pane. Bindings that already use Ctrl stay with the outer
+/// dmux; `SendNextKey` there reaches them. Without `remap` the bindings are
+/// unchanged, so each one has to follow the outer dmux's `SendNextKey`, and
+/// Ctrl+Alt chords go to the pane for a dmux nested deeper still.
+pub fn nested_command_for_key(
+    modifiers: KeyModifiers,
+    keycode: KeyCode,
+    nesting_depth: u32,
+    remap: bool,
+) -> Option<MuxCommand> {
+    if nesting_depth == 0 {
+        return MuxCommand::from_key(modifiers, keycode);
+    }
+    let ctrl_alt = modifiers.contains(KeyModifiers::CONTROL | KeyModifiers::ALT);
+    match (remap, ctrl_alt) {
+        (true, true) => MuxCommand::from_key(modifiers - KeyModifiers::CONTROL, keycode),
+        (true, false) | (false, true) => None,
+        (false, false) => MuxCommand::from_key(modifiers, keycode),
+    }
+}
+
 /// Implement the shared palette command trait for MuxCommand.
 impl PaletteCommand for MuxCommand {
     fn label(&self) -> &str {
@@ -1142,4 +1330,81 @@ mod tests {
         // "shortcuts" should match ToggleHelp
         assert!(MuxCommand::ToggleHelp.matches("shortcuts"));
     }
+
+    #[test]
+    fn parse_command_reads_names_and_programs() {
+        assert_eq!(MuxCommand::GoToTab1.name(), "go-to-tab-1");
+        assert_eq!(MuxCommand::OpenWithVSCode.name(), "open-with-vs-code");
+        assert_eq!(
+            parse_command("split-horizontal top -d 1"),
+            Ok(CommandInvocation {
+                command: MuxCommand::SplitHorizontal,
+                args: vec!["top".to_string(), "-d".to_string(), "1".to_string()],
+            })
+        );
+        assert_eq!(
+            parse_command("  Focus-Left ").map(|inv| inv.command),
+            Ok(MuxCommand::FocusLeft)
+        );
+        assert!(parse_command("").is_err());
+        assert!(parse_command("split-diagonally").is_err());
+        assert_eq!(
+            parse_command("send-keys -l ls").map(|inv| inv.args),
+            Ok(vec!["-l".to_string(), "ls".to_string()])
+        );
+        assert_eq!(
+            parse_command("pipe-pane cat").map(|inv| inv.args),
+            Ok(vec!["cat".to_string()])
+        );
+        assert!(parse_command("focus-left top").is_err());
+    }
+
+    #[test]
+    fn command_names_are_unique() {
+        let mut names: Vec<String> = MuxCommand::all().iter().map(|cmd| cmd.name()).collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), MuxCommand::all().len());
+    }
+
+    #[test]
+    fn nested_dmux_takes_ctrl_alt_bindings() {
+        let ctrl_alt = KeyModifiers::CONTROL | KeyModifiers::ALT;
+        // Outside dmux, Ctrl+Alt chords keep their Alt bindings
+        assert_eq!(
+            MuxCommand::from_key(ctrl_alt, KeyCode::Char('h')),
+            Some(MuxCommand::FocusLeft)
+        );
+        assert_eq!(
+            nested_command_for_key(ctrl_alt, KeyCode::Char('h'), 0, true),
+            Some(MuxCommand::FocusLeft)
+        );
+        // Nested, with remapping, they're the only way to the Alt bindings
+        assert_eq!(
+            nested_command_for_key(ctrl_alt, KeyCode::Char('h'), 1, true),
+            Some(MuxCommand::FocusLeft)
+        );
+        assert_eq!(
+            nested_command_for_key(ctrl_alt, KeyCode::Char('d'), 1, true),
+            Some(MuxCommand::SplitVertical)
+        );
+        assert_eq!(
+            nested_command_for_key(KeyModifiers::ALT, KeyCode::Char('d'), 1, true),
+            None
+        );
+        // Nested without remapping the bindings are unchanged, and Ctrl+Alt
+        // chords are left for a dmux nested deeper
+        assert_eq!(
+            nested_command_for_key(KeyModifiers::ALT, KeyCode::Char('d'), 1, false),
+            Some(MuxCommand::SplitVertical)
+        );
+        assert_eq!(
+            nested_command_for_key(ctrl_alt, KeyCode::Char('d'), 1, false),
+            None
+        );
+        assert_eq!(
+            MuxCommand::from_key(KeyModifiers::ALT, KeyCode::Char('a')),
+            Some(MuxCommand::SendNextKey)
+        );
+    }
 }
@@ -0,0 +1,88 @@
+//! Which panes need redrawing in the next frame.
+//!
+//! PTY output only marks its pane dirty; the render loop draws once per
+//! frame-limiter tick, compositing every pane that produced output since the
+//! last frame in a single pass. Anything else that changes the screen (input,
+//! layout, theme) marks the whole frame dirty.
+
+use std::collections::HashSet;
+
+use crate::mux::layout::PaneId;
+
+/// Panes with output since the last frame.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DirtyPanes {
+    panes: HashSet<PaneId>,
+    /// Every pane is redrawn, not just the ones in `panes`
+    all: bool,
+}
+
+impl DirtyPanes {
+    /// Record output from `pane_id`.
+    pub fn mark(&mut self, pane_id: PaneId) {
+        self.panes.insert(pane_id);
+    }
+
+    /// Every pane dirty, e.g. for a first frame.
+    pub fn everything() -> Self {
+        Self {
+            panes: HashSet::new(),
+            all: true,
+        }
+    }
+
+    /// Redraw every pane in the next frame.
+    pub fn mark_all(&mut self) {
+        self.all = true;
+    }
+
+    /// Whether a frame is due.
+    pub fn is_empty(&self) -> bool {
+        !self.all && self.panes.is_empty()
+    }
+
+    /// Whether `pane_id` must be re-read from its terminal buffer this frame.
+    pub fn contains(&self, pane_id: PaneId) -> bool {
+        self.all || self.panes.contains(&pane_id)
+    }
+
+    /// The panes to redraw in the frame being drawn, leaving nothing dirty.
+    pub fn take(&mut self) -> DirtyPanes {
+        std::mem::take(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_from_two_panes_is_drawn_in_one_frame() {
+        let (first, second, quiet) = (PaneId::new(), PaneId::new(), PaneId::new());
+        let mut dirty = DirtyPanes::default();
+        assert!(dirty.is_empty());
+
+        dirty.mark(first);
+        dirty.mark(second);
+        dirty.mark(first);
+
+        let frame = dirty.take();
+        assert!(frame.contains(first) && frame.contains(second));
+        assert!(
+            !frame.contains(quiet),
+            "a pane without output isn't re-read"
+        );
+        assert!(dirty.is_empty(), "both panes were covered by that frame");
+    }
+
+    #[test]
+    fn mark_all_redraws_every_pane_once() {
+        let pane = PaneId::new();
+        assert!(DirtyPanes::everything().contains(pane));
+        let mut dirty = DirtyPanes::default();
+        dirty.mark_all();
+        assert!(!dirty.is_empty());
+        assert!(dirty.take().contains(pane));
+        assert!(!dirty.contains(pane));
+    }
+}
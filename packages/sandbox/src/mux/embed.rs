@@ -0,0 +1,600 @@
+//! Driving dmux from another application.
+//!
+//! `Mux` is the multiplexer without a terminal of its own. The embedding
+//! application owns the event loop and the terminal (raw mode, the screen,
+//! reading input) and hands dmux events as they arrive; dmux manages the
+//! panes, their connections and rendering, drawing frames into whatever
+//! writer it is given. The standalone TUI (`run_mux_tui`) is itself a loop
+//! around a `Mux`.
+
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+
+use crossterm::cursor::SetCursorStyle;
+use crossterm::event::Event;
+use crossterm::execute;
+use ratatui::backend::Backend;
+use ratatui::layout::Rect;
+use ratatui::{Terminal, TerminalOptions, Viewport};
+use tokio::sync::mpsc;
+
+use crate::mux::colors::{
+    force_refresh_colors_interactive, get_outer_colors, get_outer_cursor_style,
+    take_due_color_query, take_resize_during_query, CursorStyle, ThemeChangeEvent,
+};
+use crate::mux::events::MuxEvent;
+use crate::mux::layout::{LayoutNode, Pane, PaneId, TabId};
+use crate::mux::osc::osc52_copy;
+use crate::mux::output::AnsiOutput;
+use crate::mux::runner::{
+    connect_sandbox_pane, create_sandbox_with_workspace, handle_input, handle_onboard_event,
+    handle_terminal_exit_for_pane, respawn_pane_terminal, run_chord_steps, sync_terminal_sizes,
+    try_consume_pending_connection,
+};
+use crate::mux::sgr_diff::SgrDiffBackend;
+use crate::mux::state::MuxApp;
+use crate::mux::terminal::{
+    create_terminal_manager, request_list_sandboxes, send_signal_to_children, SharedTerminalManager,
+};
+use crate::mux::ui::ui;
+
+/// What an embedded `Mux` starts with.
+#[derive(Debug, Clone)]
+pub struct MuxConfig {
+    /// The sandbox service dmux talks to.
+    pub base_url: String,
+    /// Directory uploaded to sandboxes created during the session.
+    pub workspace_path: PathBuf,
+    /// Where on the embedder's screen `Mux::render` draws.
+    pub area: Rect,
+    /// Command lines run once the first tab opens (see `parse_command`).
+    pub startup_commands: Vec<String>,
+}
+
+impl MuxConfig {
+    /// A mux filling a `cols` x `rows` screen.
+    pub fn new(base_url: impl Into<String>, workspace_path: PathBuf, cols: u16, rows: u16) -> Self {
+        Self {
+            base_url: base_url.into(),
+            workspace_path,
+            area: Rect::new(0, 0, cols, rows),
+            startup_commands: Vec::new(),
+        }
+    }
+}
+
+/// Screen `Mux::render` draws through, buffering each frame's bytes.
+type RenderTerminal = Terminal<SgrDiffBackend<AnsiOutput<Vec<u8>>>>;
+
+/// The multiplexer, driven by its embedder.
+///
+/// Feed it events from `next_event` (and any others) through `handle_event`,
+/// input through `handle_input`, and call `tick` a few times a second for
+/// timers. Draw with `render` (or `draw`, into a ratatui terminal) whenever
+/// `needs_redraw` says a frame is due. The `handle_*` and `tick` methods
+/// return true once the user has asked to quit.
+///
+/// dmux never touches the embedder's terminal. When the outer theme may have
+/// changed, `take_theme_requery` says so; the embedder queries the colors
+/// its own way, commits them (see `commit_colors`) and passes
+/// `MuxEvent::ThemeColorsRefreshed` back in.
+pub struct Mux<'a> {
+    app: MuxApp<'a>,
+    event_rx: mpsc::UnboundedReceiver<MuxEvent>,
+    terminal_manager: SharedTerminalManager,
+    area: Rect,
+    redraw_needed: bool,
+    /// Whether the next frame repaints the whole screen
+    clear_needed: bool,
+    /// OSC 52 sequences to write with the next frame
+    clipboard_writes: Vec<String>,
+    /// Screen for `render`, created on first use
+    screen: Option<RenderTerminal>,
+    /// Whether dmux owns the real terminal and may query it (standalone)
+    owns_terminal: bool,
+    /// A theme re-query the embedder has yet to pick up
+    theme_requery: bool,
+}
+
+impl<'a> Mux<'a> {
+    /// A mux with its own terminal manager. Sandboxes are listed and
+    /// connected as the embedder feeds it events.
+    pub fn new(config: MuxConfig) -> Self {
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        let mut app = MuxApp::new(
+            config.base_url.clone(),
+            event_tx.clone(),
+            config.workspace_path,
+        );
+        app.startup_commands.extend(config.startup_commands);
+        let terminal_manager = create_terminal_manager(config.base_url, event_tx);
+        app.set_terminal_manager(terminal_manager.clone());
+        let mut mux = Self::from_parts(app, event_rx, terminal_manager);
+        mux.area = config.area;
+        mux.owns_terminal = false;
+        mux
+    }
+
+    /// A mux around an app already set up, as the standalone TUI does. It
+    /// owns the terminal, so theme changes are re-queried on the spot.
+    pub(crate) fn from_parts(
+        app: MuxApp<'a>,
+        event_rx: mpsc::UnboundedReceiver<MuxEvent>,
+        terminal_manager: SharedTerminalManager,
+    ) -> Self {
+        Self {
+            app,
+            event_rx,
+            terminal_manager,
+            area: Rect::default(),
+            redraw_needed: true,
+            clear_needed: false,
+            clipboard_writes: Vec::new(),
+            screen: None,
+            owns_terminal: true,
+            theme_requery: false,
+        }
+    }
+
+    pub fn app(&self) -> &MuxApp<'a> {
+        &self.app
+    }
+
+    pub fn app_mut(&mut self) -> &mut MuxApp<'a> {
+        &mut self.app
+    }
+
+    pub fn terminal_manager(&self) -> &SharedTerminalManager {
+        &self.terminal_manager
+    }
+
+    /// The active tab's layout, if a tab is open.
+    pub fn layout(&self) -> Option<&LayoutNode> {
+        self.app.active_tab().map(|tab| &tab.layout)
+    }
+
+    /// The active tab's panes.
+    pub fn panes(&self) -> Vec<&Pane> {
+        self.layout().map(LayoutNode::panes).unwrap_or_default()
+    }
+
+    pub fn active_pane_id(&self) -> Option<PaneId> {
+        self.app.active_pane_id()
+    }
+
+    /// A sender for events into this mux, e.g. for the embedder's own tasks.
+    pub fn event_sender(&self) -> mpsc::UnboundedSender<MuxEvent> {
+        self.app.event_tx.clone()
+    }
+
+    /// The next event from dmux's own tasks (pane output, connections,
+    /// sandbox refreshes), to pass to `handle_event`.
+    pub async fn next_event(&mut self) -> Option<MuxEvent> {
+        self.event_rx.recv().await
+    }
+
+    /// Whether the outer terminal's theme may have changed since the last
+    /// call, so the embedder should query its colors again.
+    pub fn take_theme_requery(&mut self) -> bool {
+        std::mem::take(&mut self.theme_requery)
+    }
+
+    /// Whether something changed since the last frame.
+    pub fn needs_redraw(&self) -> bool {
+        self.redraw_needed || !self.app.dirty_panes.is_empty()
+    }
+
+    /// Move or resize the area `render` draws into.
+    pub fn resize(&mut self, area: Rect) -> io::Result<()> {
+        self.area = area;
+        self.redraw_needed = true;
+        match &mut self.screen {
+            Some(screen) => screen.resize(area),
+            None => Ok(()),
+        }
+    }
+
+    /// Run timers due at `now`: status expiry, silence monitoring, chord
+    /// timeouts and deferred color queries.
+    pub fn tick(&mut self, now: Instant) -> bool {
+        let app = &mut self.app;
+        let had_status = app.status_message.is_some();
+        app.clear_expired_status();
+        app.check_pane_silence(now);
+        // A chord nobody finished in time runs or hands its keys back
+        let expired = app.key_chords.expire(now);
+        if !expired.is_empty() {
+            if run_chord_steps(app, expired, &self.terminal_manager) {
+                return true;
+            }
+            self.redraw_needed = true;
+        }
+        // On success this emits ThemeColorsRefreshed, which triggers the redraw
+        if app.deferred_color_query.is_pending() {
+            app.deferred_color_query.poll(now, &app.event_tx);
+        }
+        // Re-queries held back by the rate limit run together once it allows
+        if take_due_color_query(now) {
+            let _ = app.event_tx.send(MuxEvent::ThemeChanged {
+                colors: get_outer_colors(),
+            });
+        }
+        // A resize during a color query may have gone unseen: repaint at
+        // whatever size the terminal is now
+        if take_resize_during_query() {
+            self.clear_needed = true;
+            self.redraw_needed = true;
+        }
+        if had_status && app.status_message.is_none() {
+            self.redraw_needed = true;
+        }
+        false
+    }
+
+    /// Handle an input event from the embedder's terminal.
+    pub fn handle_input(&mut self, event: Event) -> bool {
+        // Skip focus events - we don't forward them to inner ptys because:
+        // 1. Bash and most shells don't handle focus tracking and just echo ^[[I
+        // 2. Querying colors on focus is problematic (responses leak or cause flicker)
+        // Colors are queried at startup before entering alt screen.
+        if matches!(event, Event::FocusGained | Event::FocusLost) {
+            return false;
+        }
+        if handle_input(&mut self.app, event, &self.terminal_manager) {
+            return true;
+        }
+        self.redraw_needed = true;
+        false
+    }
+
+    /// Handle an event from `next_event` or the embedder.
+    pub async fn handle_event(&mut self, event: MuxEvent) -> bool {
+        let app = &mut self.app;
+        let terminal_manager = &self.terminal_manager;
+        match &event {
+            MuxEvent::ConnectToSandbox { sandbox_id } => {
+                app.pending_connects.push_back(sandbox_id.clone());
+                try_consume_pending_connection(app, terminal_manager);
+            }
+            MuxEvent::CreateSandboxWithWorkspace {
+                workspace_path,
+                tab_id,
+            } => {
+                let event_tx = app.event_tx.clone();
+                let base_url = app.base_url.clone();
+                let workspace_path = workspace_path.clone();
+                let tab_id_value = tab_id.clone().unwrap_or_else(|| TabId::new().to_string());
+                tokio::spawn(async move {
+                    if let Err(error) = create_sandbox_with_workspace(
+                        base_url,
+                        workspace_path,
+                        Some(tab_id_value),
+                        event_tx.clone(),
+                    )
+                    .await
+                    {
+                        let _ = event_tx.send(MuxEvent::Error(format!(
+                            "Failed to create sandbox: {}",
+                            error
+                        )));
+                    }
+                });
+            }
+            MuxEvent::ConnectActivePaneToSandbox => {
+                // Use selected sandbox, or fall back to first item in pending queue
+                let target = app
+                    .selected_sandbox_id_string()
+                    .or_else(|| app.pending_connects.front().cloned());
+                if let Some(sandbox_id) = target {
+                    app.pending_connects.push_back(sandbox_id);
+                    try_consume_pending_connection(app, terminal_manager);
+                }
+            }
+            MuxEvent::ConnectPaneToSandbox {
+                sandbox_id,
+                pane_id,
+            } => {
+                connect_sandbox_pane(app, terminal_manager, sandbox_id, *pane_id);
+            }
+            MuxEvent::StatusMessage { message } if message.contains("Refreshing sandboxes") => {
+                // Request sandbox list via WebSocket - server responds with SandboxList
+                let manager = terminal_manager.clone();
+                let event_tx = app.event_tx.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = request_list_sandboxes(manager).await {
+                        let _ = event_tx.send(MuxEvent::Error(format!(
+                            "Failed to refresh sandboxes: {}",
+                            e
+                        )));
+                    }
+                });
+                app.set_status(message.clone());
+            }
+            MuxEvent::RespawnPane { pane_id } => {
+                respawn_pane_terminal(app, terminal_manager, *pane_id);
+            }
+            MuxEvent::TerminalExited {
+                pane_id,
+                sandbox_id,
+            } => {
+                let quit =
+                    handle_terminal_exit_for_pane(app, terminal_manager, *pane_id, sandbox_id);
+                if quit {
+                    return true;
+                }
+            }
+            MuxEvent::ThemeChanged { colors: _ } if !self.owns_terminal => {
+                // The terminal is the embedder's to query
+                self.theme_requery = true;
+            }
+            MuxEvent::ThemeChanged { colors: _ } => {
+                // Theme change signal received - re-query colors from outer terminal
+                // VSCode terminal doesn't respond to OSC 10/11 while in alternate screen,
+                // so we need to leave alt screen, query, then re-enter.
+                // On success this emits ThemeColorsRefreshed, which triggers the redraw.
+                match force_refresh_colors_interactive(&app.event_tx) {
+                    // Rate limited: queued, and retried from `tick`
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                    Err(e) => {
+                        self.clear_needed = true;
+                        app.set_status(format!("Failed to refresh theme colors: {}", e));
+                    }
+                    Ok(_) => {}
+                }
+            }
+            MuxEvent::ThemeColorsRefreshed { colors } => {
+                // Force full terminal redraw
+                self.clear_needed = true;
+
+                app.set_status(format!(
+                    "Theme updated: bg={:?}",
+                    colors
+                        .background
+                        .map(|(r, g, b)| format!("#{:02x}{:02x}{:02x}", r, g, b))
+                ));
+
+                // Hand the new colors to the sessions that take them up, and
+                // invalidate all render caches so terminal buffers re-render with them
+                let theme_change = ThemeChangeEvent { colors: *colors };
+                terminal_manager
+                    .lock()
+                    .await
+                    .apply_theme_change(&theme_change, app.active_pane_id());
+
+                // Send SIGWINCH to trigger re-render in TUI apps
+                // Note: We don't send SIGUSR1 because most apps don't handle it
+                // and the default action would kill them
+                let manager_clone = terminal_manager.clone();
+                tokio::spawn(async move {
+                    let _ = send_signal_to_children(manager_clone, libc::SIGWINCH).await;
+                });
+            }
+            MuxEvent::CopyToClipboard { text } => match osc52_copy(text) {
+                // Written to the terminal with the next frame
+                Ok(seq) => {
+                    self.clipboard_writes.push(seq);
+                    app.set_status("Copied selection to clipboard");
+                }
+                Err(e) => app.set_status(format!("Failed to copy selection: {}", e)),
+            },
+            MuxEvent::Onboard(onboard_event) => {
+                let event_tx_for_handler = app.event_tx.clone();
+                handle_onboard_event(app, onboard_event.clone(), &event_tx_for_handler);
+            }
+            MuxEvent::SendTerminalInput { pane_id, input } => {
+                if let Ok(mut manager) = terminal_manager.try_lock() {
+                    if !manager.send_input(*pane_id, input.clone()) {
+                        tracing::warn!("Failed to send terminal input to pane {:?}", pane_id);
+                    }
+                }
+            }
+            MuxEvent::ExecInSandbox {
+                sandbox_id,
+                command,
+            } => {
+                // Execute command in sandbox silently via exec API
+                let base_url = app.base_url.clone();
+                let sandbox_id = sandbox_id.clone();
+                let command = command.clone();
+                tokio::spawn(async move {
+                    let client = reqwest::Client::new();
+                    let url = format!(
+                        "{}/sandboxes/{}/exec",
+                        base_url.trim_end_matches('/'),
+                        sandbox_id
+                    );
+                    let body = crate::models::ExecRequest {
+                        command,
+                        workdir: None,
+                        env: Vec::new(),
+                    };
+                    if let Err(e) = client.post(&url).json(&body).send().await {
+                        tracing::warn!("Failed to exec in sandbox: {}", e);
+                    }
+                });
+            }
+            _ => {}
+        }
+        // Output only redraws its own pane, on the next frame
+        let output_only = matches!(event, MuxEvent::TerminalOutput { .. });
+        app.handle_event(event);
+        try_consume_pending_connection(app, terminal_manager);
+        if !output_only {
+            self.redraw_needed = true;
+        }
+        false
+    }
+
+    /// Draw a frame into `terminal`, which must be sized to the screen dmux
+    /// owns. Under heavy load some calls skip the frame to shed work (see
+    /// `FrameBudget`).
+    pub fn draw<B: Backend + Write>(&mut self, terminal: &mut Terminal<B>) -> io::Result<()> {
+        let app = &mut self.app;
+        // Under heavy load, alternate ticks are skipped to coalesce more output
+        if app.frame_budget.skip_tick() {
+            return Ok(());
+        }
+        if std::mem::take(&mut self.clear_needed) {
+            let _ = terminal.clear();
+            self.redraw_needed = true;
+        }
+        if self.redraw_needed {
+            app.dirty_panes.mark_all();
+        }
+        // One pass composites every pane with output since the last frame
+        app.frame_damage = app.dirty_panes.take();
+        {
+            let _span = crate::mux_span!("render_frame");
+            let started = Instant::now();
+            terminal.draw(|f| ui(f, app))?;
+            if app.frame_budget.record(started.elapsed()) {
+                // Show the new quality level in the status bar
+                app.dirty_panes.mark_all();
+            }
+        }
+        // Apply cursor style based on terminal's cursor blink mode,
+        // keeping the outer terminal's cursor shape if inherited
+        let shape = app
+            .settings
+            .inherit_cursor_style
+            .then(get_outer_cursor_style)
+            .flatten()
+            .unwrap_or(CursorStyle::SteadyBlock);
+        let cursor_style = SetCursorStyle::from(shape.with_blinking(app.cursor_blink));
+        let _ = execute!(terminal.backend_mut(), cursor_style);
+        for seq in std::mem::take(&mut self.clipboard_writes) {
+            let backend = terminal.backend_mut();
+            let written = backend
+                .write_all(seq.as_bytes())
+                .and_then(|()| Write::flush(backend));
+            if let Err(e) = written {
+                app.set_status(format!("Failed to copy selection: {}", e));
+            }
+        }
+        sync_terminal_sizes(app, &self.terminal_manager);
+        // Keep redrawing if we have a blinking colored cursor (we manage the blink ourselves),
+        // unless load has turned animations off
+        self.redraw_needed = app.cursor_blink
+            && app.cursor_color.is_some()
+            && app.frame_budget.quality().animations();
+        // Startup commands wait for the first render, so new panes are sized
+        if app.run_startup_commands() {
+            self.redraw_needed = true;
+        }
+        Ok(())
+    }
+
+    /// Draw a frame into the configured area of the embedder's screen,
+    /// writing the escape sequences for it to `out`. Only cells that changed
+    /// since the last frame are written.
+    pub fn render<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        let mut screen = match self.screen.take() {
+            Some(screen) => screen,
+            None => Terminal::with_options(
+                SgrDiffBackend::new(AnsiOutput::new(Vec::new())),
+                TerminalOptions {
+                    viewport: Viewport::Fixed(self.area),
+                },
+            )?,
+        };
+        let drawn = self.draw(&mut screen);
+        let frame = std::mem::take(screen.backend_mut().output_mut().get_mut());
+        self.screen = Some(screen);
+        drawn?;
+        out.write_all(&frame)?;
+        out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mux::colors::TerminalColors;
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    fn embedded() -> Mux<'static> {
+        let mut mux = Mux::new(MuxConfig::new(
+            "http://localhost",
+            PathBuf::from("."),
+            100,
+            30,
+        ));
+        mux.app_mut()
+            .add_sandbox(&uuid::Uuid::new_v4().to_string(), "embedded");
+        mux
+    }
+
+    fn render(mux: &mut Mux) -> String {
+        let mut out = Vec::new();
+        mux.render(&mut out).expect("render");
+        String::from_utf8_lossy(&out).into_owned()
+    }
+
+    fn key(modifiers: KeyModifiers, code: KeyCode) -> Event {
+        Event::Key(KeyEvent::new(code, modifiers))
+    }
+
+    #[tokio::test]
+    async fn renders_pane_output_fed_through_events() {
+        let mut mux = embedded();
+        assert_eq!(mux.panes().len(), 1);
+        let pane_id = mux.active_pane_id().expect("sandbox workspace has a pane");
+        assert!(mux.needs_redraw());
+        render(&mut mux);
+        assert!(!mux.needs_redraw());
+
+        mux.terminal_manager()
+            .lock()
+            .await
+            .handle_output(pane_id, b"hello-from-the-pane".to_vec());
+        assert!(!mux.handle_event(MuxEvent::TerminalOutput { pane_id }).await);
+        assert!(mux.needs_redraw());
+
+        assert!(render(&mut mux).contains("hello-from-the-pane"));
+        // Nothing changed since, so the next frame is empty
+        assert!(!mux.needs_redraw());
+        assert!(!render(&mut mux).contains("hello-from-the-pane"));
+    }
+
+    #[tokio::test]
+    async fn input_drives_the_layout_and_quits() {
+        let mut mux = embedded();
+        render(&mut mux);
+
+        assert!(!mux.handle_input(key(KeyModifiers::ALT, KeyCode::Char('d'))));
+        assert_eq!(mux.panes().len(), 2);
+        assert!(mux.layout().is_some());
+
+        assert!(!mux.handle_input(key(KeyModifiers::ALT, KeyCode::Char('p'))));
+        assert!(render(&mut mux).contains("Command Palette"));
+        assert!(!mux.handle_input(key(KeyModifiers::NONE, KeyCode::Esc)));
+
+        assert!(mux.handle_input(key(KeyModifiers::CONTROL, KeyCode::Char('q'))));
+    }
+
+    #[tokio::test]
+    async fn theme_changes_are_left_to_the_embedder() {
+        let mut mux = embedded();
+        assert!(!mux.take_theme_requery());
+
+        let changed = MuxEvent::ThemeChanged {
+            colors: TerminalColors::default(),
+        };
+        assert!(!mux.handle_event(changed).await);
+        assert!(mux.take_theme_requery());
+        assert!(!mux.take_theme_requery());
+    }
+
+    #[tokio::test]
+    async fn renders_into_the_configured_area() {
+        let mut mux = embedded();
+        mux.resize(Rect::new(10, 5, 60, 20)).expect("resize");
+        render(&mut mux);
+
+        let panes = mux.panes();
+        let area = panes[0].area.expect("pane drawn");
+        assert!(area.x >= 10 && area.y >= 5);
+        assert!(area.right() <= 70 && area.bottom() <= 25);
+    }
+}
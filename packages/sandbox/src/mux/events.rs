@@ -30,6 +30,8 @@ pub enum MuxEvent {
     SandboxConnectionChanged { sandbox_id: String, connected: bool },
     /// Terminal output received.
     TerminalOutput { pane_id: crate::mux::layout::PaneId },
+    /// A monitored pane produced no output for its silence timeout.
+    PaneSilence(PaneId),
     /// An error occurred.
     Error(String),
     /// A system notification to display.
@@ -46,12 +48,22 @@ pub enum MuxEvent {
     ConnectToSandbox { sandbox_id: String },
     /// Request to connect the active pane to the active sandbox's terminal
     ConnectActivePaneToSandbox,
+    /// Request to connect a pane in any tab of a sandbox's workspace to its terminal
+    ConnectPaneToSandbox { sandbox_id: String, pane_id: PaneId },
+    /// A pane's process couldn't be started
+    PaneSpawnFailed { pane_id: PaneId, message: String },
     /// Terminal connection closed for a pane
     TerminalExited { pane_id: PaneId, sandbox_id: String },
-    /// Outer terminal theme changed (received SIGUSR1)
+    /// Relaunch a pane's recorded command in its existing slot
+    RespawnPane { pane_id: PaneId },
+    /// Outer terminal theme changed (received SIGUSR1 or a manual refresh was requested)
     ThemeChanged { colors: TerminalColors },
+    /// Outer terminal colors were re-queried and stored; the UI should redraw
+    ThemeColorsRefreshed { colors: TerminalColors },
     /// Onboarding event (image check, download progress, etc.)
     Onboard(OnboardEvent),
+    /// Copy text to the outer terminal's clipboard via OSC 52
+    CopyToClipboard { text: String },
     /// Send input to a terminal pane
     SendTerminalInput { pane_id: PaneId, input: Vec<u8> },
     /// Execute a command in a sandbox silently via exec API
@@ -0,0 +1,192 @@
+//! Adaptive render quality under load.
+//!
+//! The render loop reports how long each frame took to draw. When frames keep
+//! overrunning the budget (a slow machine, or a pane flooding output), the
+//! quality level drops and the loop does less per frame; once frames are
+//! comfortably fast again it steps back up.
+
+use std::time::Duration;
+
+/// Time one frame may take to draw: a 60 Hz refresh.
+pub const FRAME_BUDGET: Duration = Duration::from_millis(16);
+
+/// Consecutive over-budget frames before quality drops a level.
+const SLOW_FRAMES_TO_DEGRADE: u32 = 3;
+
+/// Consecutive fast frames (under half the budget) before quality recovers a
+/// level. Longer than the way down, so quality doesn't flap under uneven load.
+const FAST_FRAMES_TO_RECOVER: u32 = 30;
+
+/// How much work each frame does, from most to least.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum RenderQuality {
+    /// Everything, every render tick.
+    #[default]
+    Full,
+    /// No animations: a blinking colored cursor stops redrawing on its own.
+    Reduced,
+    /// No animations, and only every other render tick draws, so more output
+    /// is coalesced into each frame.
+    Minimal,
+}
+
+impl RenderQuality {
+    pub fn label(self) -> &'static str {
+        match self {
+            RenderQuality::Full => "full",
+            RenderQuality::Reduced => "reduced",
+            RenderQuality::Minimal => "minimal",
+        }
+    }
+
+    /// Whether animations keep redrawing frames.
+    pub fn animations(self) -> bool {
+        self == RenderQuality::Full
+    }
+
+    fn lower(self) -> Self {
+        match self {
+            RenderQuality::Full => RenderQuality::Reduced,
+            RenderQuality::Reduced | RenderQuality::Minimal => RenderQuality::Minimal,
+        }
+    }
+
+    fn higher(self) -> Self {
+        match self {
+            RenderQuality::Full | RenderQuality::Reduced => RenderQuality::Full,
+            RenderQuality::Minimal => RenderQuality::Reduced,
+        }
+    }
+}
+
+/// Tracks frame render times and the quality level they call for.
+#[derive(Debug, Clone)]
+pub struct FrameBudget {
+    budget: Duration,
+    quality: RenderQuality,
+    slow_streak: u32,
+    fast_streak: u32,
+    /// Toggled every render tick, for skipping alternate ticks at `Minimal`
+    skip_next: bool,
+}
+
+impl Default for FrameBudget {
+    fn default() -> Self {
+        Self::new(FRAME_BUDGET)
+    }
+}
+
+impl FrameBudget {
+    pub fn new(budget: Duration) -> Self {
+        Self {
+            budget,
+            quality: RenderQuality::Full,
+            slow_streak: 0,
+            fast_streak: 0,
+            skip_next: false,
+        }
+    }
+
+    pub fn quality(&self) -> RenderQuality {
+        self.quality
+    }
+
+    /// Record how long a frame took to draw. Returns whether the quality
+    /// level changed.
+    pub fn record(&mut self, render_time: Duration) -> bool {
+        let before = self.quality;
+        if render_time > self.budget {
+            self.fast_streak = 0;
+            self.slow_streak += 1;
+            if self.slow_streak >= SLOW_FRAMES_TO_DEGRADE {
+                self.slow_streak = 0;
+                self.quality = self.quality.lower();
+            }
+        } else if render_time < self.budget / 2 {
+            self.slow_streak = 0;
+            self.fast_streak += 1;
+            if self.fast_streak >= FAST_FRAMES_TO_RECOVER {
+                self.fast_streak = 0;
+                self.quality = self.quality.higher();
+            }
+        } else {
+            // Within budget but not comfortably: hold the current level
+            self.slow_streak = 0;
+            self.fast_streak = 0;
+        }
+        self.quality != before
+    }
+
+    /// Whether this render tick should be skipped to shed load. Only
+    /// `Minimal` skips, and then every other tick.
+    pub fn skip_tick(&mut self) -> bool {
+        if self.quality != RenderQuality::Minimal {
+            self.skip_next = false;
+            return false;
+        }
+        self.skip_next = !self.skip_next;
+        !self.skip_next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SLOW: Duration = Duration::from_millis(40);
+    const FAST: Duration = Duration::from_millis(2);
+
+    #[test]
+    fn slow_frames_lower_quality() {
+        let mut budget = FrameBudget::default();
+        assert_eq!(budget.quality(), RenderQuality::Full);
+
+        // A couple of slow frames are noise
+        budget.record(SLOW);
+        budget.record(SLOW);
+        budget.record(FAST);
+        assert_eq!(budget.quality(), RenderQuality::Full);
+
+        let changes: Vec<bool> = (0..3).map(|_| budget.record(SLOW)).collect();
+        assert_eq!(changes, [false, false, true]);
+        assert_eq!(budget.quality(), RenderQuality::Reduced);
+        assert!(!budget.quality().animations());
+
+        for _ in 0..10 {
+            budget.record(SLOW);
+        }
+        assert_eq!(budget.quality(), RenderQuality::Minimal);
+    }
+
+    #[test]
+    fn fast_frames_restore_quality() {
+        let mut budget = FrameBudget::default();
+        for _ in 0..6 {
+            budget.record(SLOW);
+        }
+        assert_eq!(budget.quality(), RenderQuality::Minimal);
+
+        for _ in 0..FAST_FRAMES_TO_RECOVER {
+            budget.record(FAST);
+        }
+        assert_eq!(budget.quality(), RenderQuality::Reduced);
+        for _ in 0..FAST_FRAMES_TO_RECOVER {
+            budget.record(FAST);
+        }
+        assert_eq!(budget.quality(), RenderQuality::Full);
+        assert!(budget.quality().animations());
+    }
+
+    #[test]
+    fn minimal_quality_skips_every_other_tick() {
+        let mut budget = FrameBudget::default();
+        let ticks: Vec<bool> = (0..4).map(|_| budget.skip_tick()).collect();
+        assert_eq!(ticks, [false; 4]);
+
+        for _ in 0..6 {
+            budget.record(SLOW);
+        }
+        let ticks: Vec<bool> = (0..4).map(|_| budget.skip_tick()).collect();
+        assert_eq!(ticks, [false, true, false, true]);
+    }
+}
@@ -0,0 +1,161 @@
+//! The outer terminal's window size in pixels, for sizing images and sixels.
+//!
+//! `query_terminal_pixel_size` asks via OSC 14 the first time it's needed and
+//! caches the answer until the window is resized.
+
+use std::sync::RwLock;
+
+use crate::mux::colors::{
+    notify_osc_observers, with_raw_mode, CrosstermRawMode, OscIo, StdioOsc, OSC_QUERY_TIMEOUT,
+};
+
+/// OSC 14 query for the window size in pixels.
+const PIXEL_SIZE_QUERY: &[u8] = b"\x1b]14;?\x1b\\";
+
+/// Last queried pixel size. `Some(None)` records that the terminal didn't
+/// answer, so callers don't pay the query timeout again before the next resize.
+static PIXEL_SIZE: RwLock<Option<Option<(u16, u16)>>> = RwLock::new(None);
+
+/// The outer terminal's window size in pixels as `(width, height)`.
+///
+/// Queries the terminal on first use and after each resize; otherwise returns
+/// the cached answer. A query reads the reply straight from stdin, so it must
+/// not run while another reader is consuming terminal input.
+pub fn query_terminal_pixel_size() -> Option<(u16, u16)> {
+    if let Some(cached) = PIXEL_SIZE.read().ok().and_then(|size| *size) {
+        return cached;
+    }
+
+    let size = with_raw_mode(&mut CrosstermRawMode, || {
+        query_pixel_size_with(&mut StdioOsc)
+    })
+    .flatten();
+    if let Ok(mut cached) = PIXEL_SIZE.write() {
+        *cached = Some(size);
+    }
+    size
+}
+
+/// Forget the cached pixel size so the next `query_terminal_pixel_size` asks again.
+pub fn invalidate_terminal_pixel_size() {
+    if let Ok(mut cached) = PIXEL_SIZE.write() {
+        *cached = None;
+    }
+}
+
+/// Invalidate the cached pixel size on every SIGWINCH.
+///
+/// The re-query itself waits for the next `query_terminal_pixel_size` call:
+/// answering it from the signal task would race the UI's own stdin reader.
+/// Returns whether a listener was spawned.
+#[cfg(unix)]
+pub fn spawn_pixel_size_listener() -> bool {
+    let mut sigwinch =
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::window_change()) {
+            Ok(sig) => sig,
+            Err(e) => {
+                tracing::warn!("Failed to register SIGWINCH handler for pixel size: {e}");
+                return false;
+            }
+        };
+
+    tokio::spawn(async move {
+        while sigwinch.recv().await.is_some() {
+            invalidate_terminal_pixel_size();
+        }
+    });
+    true
+}
+
+/// Non-Unix platforms: no-op signal listener.
+#[cfg(not(unix))]
+pub fn spawn_pixel_size_listener() -> bool {
+    false
+}
+
+/// Send the OSC 14 query through the given terminal I/O and parse the reply.
+fn query_pixel_size_with<T: OscIo>(io: &mut T) -> Option<(u16, u16)> {
+    io.send(PIXEL_SIZE_QUERY).ok()?;
+    let response = io.recv(OSC_QUERY_TIMEOUT);
+    notify_osc_observers(14, &response);
+    parse_pixel_size_response(&response)
+}
+
+/// Parse an OSC 14 reply, `OSC 14 ; height ; width ST` (or BEL-terminated),
+/// into `(width, height)`. A zero dimension means the size is unknown.
+pub(crate) fn parse_pixel_size_response(response: &[u8]) -> Option<(u16, u16)> {
+    let start = response.windows(4).position(|window| window == b"]14;")? + 4;
+    let body = &response[start..];
+    let end = body
+        .iter()
+        .position(|&b| b == 0x07 || b == 0x1b)
+        .unwrap_or(body.len());
+    let body = std::str::from_utf8(&body[..end]).ok()?;
+
+    let (height, width) = body.split_once(';')?;
+    let height: u16 = height.parse().ok()?;
+    let width: u16 = width.parse().ok()?;
+    if width == 0 || height == 0 {
+        return None;
+    }
+    Some((width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    struct MockIo {
+        sent: Vec<u8>,
+        reply: Vec<u8>,
+    }
+
+    impl OscIo for MockIo {
+        fn send(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+            self.sent.extend_from_slice(bytes);
+            Ok(())
+        }
+
+        fn recv(&mut self, _timeout: Duration) -> Vec<u8> {
+            std::mem::take(&mut self.reply)
+        }
+    }
+
+    #[test]
+    fn parses_pixel_size_replies() {
+        assert_eq!(
+            parse_pixel_size_response(b"\x1b]14;1080;1920\x1b\\"),
+            Some((1920, 1080))
+        );
+        assert_eq!(
+            parse_pixel_size_response(b"\x1b]14;600;800\x07"),
+            Some((800, 600))
+        );
+        // Leftover input before the reply is skipped
+        assert_eq!(
+            parse_pixel_size_response(b"x\x1b]14;600;800\x1b\\"),
+            Some((800, 600))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_pixel_size_replies() {
+        assert_eq!(parse_pixel_size_response(b""), None);
+        assert_eq!(parse_pixel_size_response(b"\x1b]14;?\x1b\\"), None);
+        assert_eq!(parse_pixel_size_response(b"\x1b]14;600\x1b\\"), None);
+        assert_eq!(parse_pixel_size_response(b"\x1b]14;0;800\x1b\\"), None);
+        assert_eq!(parse_pixel_size_response(b"\x1b]14;600;99999\x1b\\"), None);
+        assert_eq!(parse_pixel_size_response(b"\x1b]11;600;800\x1b\\"), None);
+    }
+
+    #[test]
+    fn query_sends_osc_14() {
+        let mut io = MockIo {
+            sent: Vec::new(),
+            reply: b"\x1b]14;768;1024\x1b\\".to_vec(),
+        };
+        assert_eq!(query_pixel_size_with(&mut io), Some((1024, 768)));
+        assert_eq!(io.sent, PIXEL_SIZE_QUERY);
+    }
+}
@@ -1,24 +1,22 @@
 //! Terminal grid implementation with tripartite design.
 //!
 //! This module implements a zellij-inspired grid structure:
-//! - lines_above: VecDeque of rows in scrollback (above the viewport)
+//! - lines_above: bounded scrollback of rows above the viewport
 //! - viewport: Vec of rows currently visible
 //! - lines_below: Vec of rows below the viewport (when scrolled up)
 //!
 //! This design enables efficient scrolling without reallocating large buffers.
 
-use std::collections::{HashSet, VecDeque};
+use std::collections::HashSet;
 
-use super::character::{CharacterStyles, Row, SharedStyles, TerminalCharacter};
-
-/// Maximum number of lines to keep in scrollback.
-const MAX_SCROLLBACK_LINES: usize = 10_000;
+use super::character::{CharacterStyles, PromptMark, Row, SharedStyles, TerminalCharacter};
+use super::scrollback::Scrollback;
 
 /// Terminal grid with tripartite design for efficient scrolling.
 #[derive(Clone, Debug)]
 pub struct Grid {
     /// Lines that have scrolled above the viewport (scrollback buffer).
-    pub lines_above: VecDeque<Row>,
+    pub lines_above: Scrollback,
     /// Currently visible lines.
     pub viewport: Vec<Row>,
     /// Lines that are below the viewport (when user scrolls up to view history).
@@ -53,7 +51,7 @@ impl Grid {
         let viewport: Vec<Row> = (0..rows).map(|_| Row::filled(cols)).collect();
 
         Self {
-            lines_above: VecDeque::new(),
+            lines_above: Scrollback::default(),
             viewport,
             lines_below: Vec::new(),
             cols,
@@ -177,6 +175,20 @@ impl Grid {
         (self.cursor_row, self.cursor_col)
     }
 
+    /// Mark whether a viewport row starts a logical line (false = soft-wrapped continuation).
+    pub fn set_row_canonical(&mut self, row: usize, is_canonical: bool) {
+        if let Some(line) = self.viewport.get_mut(row) {
+            line.is_canonical = is_canonical;
+        }
+    }
+
+    /// Record an OSC 133 mark on a viewport row.
+    pub fn set_row_prompt_mark(&mut self, row: usize, mark: PromptMark) {
+        if let Some(line) = self.viewport.get_mut(row) {
+            line.set_prompt_mark(mark);
+        }
+    }
+
     /// Move to a new line, scrolling if necessary.
     pub fn newline(&mut self) {
         let (_top, bottom) = self.scroll_region;
@@ -293,9 +305,6 @@ impl Grid {
 
     /// Push a line to the scrollback buffer, respecting the maximum size.
     fn push_to_scrollback(&mut self, line: Row) {
-        if self.lines_above.len() >= MAX_SCROLLBACK_LINES {
-            self.lines_above.pop_front();
-        }
         self.lines_above.push_back(line);
     }
 
@@ -468,21 +477,8 @@ impl Grid {
         }
         self.viewport = new_viewport;
 
-        // Also rewrap scrollback
-        let old_lines_above = std::mem::take(&mut self.lines_above);
-        for row in old_lines_above {
-            if new_cols < self.cols {
-                let split_rows = row.split_to_rows_of_length(new_cols);
-                for split_row in split_rows {
-                    self.lines_above.push_back(split_row);
-                }
-            } else {
-                let mut new_row = row;
-                new_row.fill_to_width(new_cols);
-                new_row.truncate(new_cols);
-                self.lines_above.push_back(new_row);
-            }
-        }
+        // Reflow scrollback so soft-wrapped lines re-wrap at the new width
+        self.lines_above.reflow(new_cols);
     }
 
     /// Fix wide characters that are split at the edge after resize.
@@ -0,0 +1,364 @@
+//! Running dmux without a TTY, for integration tests and CI.
+//!
+//! `HeadlessTerminal` stands in for the outer terminal: it has a fixed size,
+//! draws into an in-memory screen, captures every escape sequence written to
+//! it, and answers OSC color queries with injected colors. Input is fed
+//! through a `HeadlessHandle` and delivered to the event loop by
+//! `HeadlessInput`, so `run_headless` drives the same loop as the real TUI.
+
+use std::io::Write;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, RwLock};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use anyhow::Result;
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use ratatui::backend::{Backend, ClearType, TestBackend, WindowSize};
+use ratatui::buffer::Cell;
+use ratatui::layout::{Position, Size};
+use ratatui::Terminal;
+use tokio::sync::mpsc;
+
+use crate::mux::colors::{OscIo, RawMode, TerminalColors};
+use crate::mux::events::MuxEvent;
+use crate::mux::state::MuxApp;
+use crate::mux::terminal::SharedTerminalManager;
+
+/// Colors injected for headless mode; `None` when dmux talks to a real terminal.
+static HEADLESS_COLORS: RwLock<Option<TerminalColors>> = RwLock::new(None);
+
+/// How often `HeadlessHandle::wait_for_text` re-checks the screen.
+const SCREEN_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Enter headless mode: `query_outer_terminal_colors` returns `colors` instead
+/// of querying stdin/stdout.
+pub fn enable_headless_mode(colors: TerminalColors) {
+    if let Ok(mut injected) = HEADLESS_COLORS.write() {
+        *injected = Some(colors);
+    }
+}
+
+/// Leave headless mode, so color queries go to the real terminal again.
+pub fn disable_headless_mode() {
+    if let Ok(mut injected) = HEADLESS_COLORS.write() {
+        *injected = None;
+    }
+}
+
+/// The injected colors, if headless mode is enabled.
+pub fn headless_colors() -> Option<TerminalColors> {
+    HEADLESS_COLORS.read().ok().and_then(|injected| *injected)
+}
+
+/// Run the dmux event loop against a headless terminal until the user quits.
+pub async fn run_headless(
+    terminal: &mut Terminal<HeadlessTerminal>,
+    app: MuxApp<'_>,
+    event_rx: mpsc::UnboundedReceiver<MuxEvent>,
+    terminal_manager: SharedTerminalManager,
+    input: HeadlessInput,
+) -> Result<()> {
+    crate::mux::runner::run_app(terminal, app, event_rx, terminal_manager, input).await
+}
+
+/// An outer terminal that exists only in memory.
+pub struct HeadlessTerminal {
+    backend: TestBackend,
+    colors: TerminalColors,
+    written: Vec<u8>,
+    pending_reply: Vec<u8>,
+    screen: Arc<Mutex<String>>,
+}
+
+impl HeadlessTerminal {
+    /// A `cols` x `rows` terminal answering color queries with `colors`, plus
+    /// the input stream for the event loop and a handle for driving it.
+    pub fn new(
+        cols: u16,
+        rows: u16,
+        colors: TerminalColors,
+    ) -> (Self, HeadlessInput, HeadlessHandle) {
+        let (input_tx, input_rx) = mpsc::unbounded_channel();
+        let screen = Arc::new(Mutex::new(String::new()));
+        let terminal = Self {
+            backend: TestBackend::new(cols, rows),
+            colors,
+            written: Vec::new(),
+            pending_reply: Vec::new(),
+            screen: Arc::clone(&screen),
+        };
+        let handle = HeadlessHandle {
+            input: input_tx,
+            screen,
+        };
+        (terminal, HeadlessInput { events: input_rx }, handle)
+    }
+
+    /// The current screen contents, one line per row.
+    pub fn screen_text(&self) -> String {
+        buffer_text(&self.backend)
+    }
+
+    /// Every byte written to the terminal outside of cell drawing (cursor
+    /// styles, OSC queries and sets).
+    pub fn written(&self) -> &[u8] {
+        &self.written
+    }
+}
+
+fn buffer_text(backend: &TestBackend) -> String {
+    let buffer = backend.buffer();
+    let width = buffer.area.width as usize;
+    let mut text = String::new();
+    for (index, cell) in buffer.content.iter().enumerate() {
+        if index > 0 && index % width == 0 {
+            text.push('\n');
+        }
+        text.push_str(cell.symbol());
+    }
+    text
+}
+
+impl Backend for HeadlessTerminal {
+    fn draw<'a, I>(&mut self, content: I) -> std::io::Result<()>
+    where
+        I: Iterator<Item = (u16, u16, &'a Cell)>,
+    {
+        self.backend.draw(content)
+    }
+
+    fn hide_cursor(&mut self) -> std::io::Result<()> {
+        self.backend.hide_cursor()
+    }
+
+    fn show_cursor(&mut self) -> std::io::Result<()> {
+        self.backend.show_cursor()
+    }
+
+    fn get_cursor_position(&mut self) -> std::io::Result<Position> {
+        self.backend.get_cursor_position()
+    }
+
+    fn set_cursor_position<P: Into<Position>>(&mut self, position: P) -> std::io::Result<()> {
+        self.backend.set_cursor_position(position)
+    }
+
+    fn clear(&mut self) -> std::io::Result<()> {
+        self.backend.clear()
+    }
+
+    fn clear_region(&mut self, clear_type: ClearType) -> std::io::Result<()> {
+        self.backend.clear_region(clear_type)
+    }
+
+    fn append_lines(&mut self, n: u16) -> std::io::Result<()> {
+        self.backend.append_lines(n)
+    }
+
+    fn size(&self) -> std::io::Result<Size> {
+        self.backend.size()
+    }
+
+    fn window_size(&mut self) -> std::io::Result<WindowSize> {
+        self.backend.window_size()
+    }
+
+    /// Publish the finished frame to `HeadlessHandle`.
+    fn flush(&mut self) -> std::io::Result<()> {
+        if let Ok(mut screen) = self.screen.lock() {
+            *screen = buffer_text(&self.backend);
+        }
+        Ok(())
+    }
+}
+
+impl Write for HeadlessTerminal {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.written.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Answers OSC 10/11/12 queries with the injected colors; other sequences get no reply.
+impl OscIo for HeadlessTerminal {
+    fn send(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.written.extend_from_slice(bytes);
+        let color = match bytes {
+            b"\x1b]10;?\x1b\\" => self.colors.foreground.map(|color| (10, color)),
+            b"\x1b]11;?\x1b\\" => self.colors.background.map(|color| (11, color)),
+            b"\x1b]12;?\x1b\\" => self.colors.cursor.map(|color| (12, color)),
+            _ => None,
+        };
+        if let Some((code, (r, g, b))) = color {
+            self.pending_reply =
+                format!("\x1b]{code};rgb:{r:02x}/{g:02x}/{b:02x}\x1b\\").into_bytes();
+        }
+        Ok(())
+    }
+
+    fn recv(&mut self, _timeout: Duration) -> Vec<u8> {
+        std::mem::take(&mut self.pending_reply)
+    }
+}
+
+/// Raw mode is meaningless without a TTY, so it's always reported as on.
+impl RawMode for HeadlessTerminal {
+    fn is_enabled(&self) -> std::io::Result<bool> {
+        Ok(true)
+    }
+
+    fn enable(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn disable(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Input events for the event loop, fed through a `HeadlessHandle`.
+pub struct HeadlessInput {
+    events: mpsc::UnboundedReceiver<Event>,
+}
+
+impl futures::Stream for HeadlessInput {
+    type Item = std::io::Result<Event>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.events.poll_recv(cx).map(|event| event.map(Ok))
+    }
+}
+
+/// Drives a headless terminal from the outside: feeds input and reads the
+/// last drawn frame. Cheap to clone.
+#[derive(Clone)]
+pub struct HeadlessHandle {
+    input: mpsc::UnboundedSender<Event>,
+    screen: Arc<Mutex<String>>,
+}
+
+impl HeadlessHandle {
+    /// Feed one input event. Returns false once the event loop has stopped.
+    pub fn send_event(&self, event: Event) -> bool {
+        self.input.send(event).is_ok()
+    }
+
+    /// Feed a key press.
+    pub fn send_key(&self, modifiers: KeyModifiers, code: KeyCode) -> bool {
+        self.send_event(Event::Key(KeyEvent::new(code, modifiers)))
+    }
+
+    /// Feed each character of `text` as a key press.
+    pub fn type_text(&self, text: &str) -> bool {
+        text.chars()
+            .all(|c| self.send_key(KeyModifiers::NONE, KeyCode::Char(c)))
+    }
+
+    /// The last frame the event loop drew, one line per row.
+    pub fn screen_text(&self) -> String {
+        self.screen
+            .lock()
+            .map(|screen| screen.clone())
+            .unwrap_or_default()
+    }
+
+    /// Wait until a drawn frame contains `needle`. Returns false on timeout.
+    pub async fn wait_for_text(&self, needle: &str, timeout: Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            if self.screen_text().contains(needle) {
+                return true;
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(SCREEN_POLL_INTERVAL).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mux::colors::{parse_builtin_osc_color_response, with_raw_mode};
+    use crate::mux::terminal::create_terminal_manager;
+    use std::path::PathBuf;
+
+    const COLORS: TerminalColors = TerminalColors {
+        foreground: Some((0xdd, 0xdd, 0xdd)),
+        background: Some((0x10, 0x20, 0x30)),
+        cursor: None,
+        selection_background: None,
+        selection_foreground: None,
+    };
+
+    #[test]
+    fn answers_color_queries_with_injected_colors() {
+        let (mut terminal, _input, _handle) = HeadlessTerminal::new(80, 24, COLORS);
+
+        terminal.send(b"\x1b]11;?\x1b\\").expect("send");
+        let reply = terminal.recv(Duration::ZERO);
+        assert_eq!(parse_builtin_osc_color_response(&reply), COLORS.background);
+
+        // Unset colors and other sequences go unanswered
+        terminal.send(b"\x1b]12;?\x1b\\").expect("send");
+        assert!(terminal.recv(Duration::ZERO).is_empty());
+        assert_eq!(terminal.written(), b"\x1b]11;?\x1b\\\x1b]12;?\x1b\\");
+
+        // Raw mode is a no-op, so queries run without a TTY
+        assert_eq!(with_raw_mode(&mut terminal, || true), Some(true));
+    }
+
+    #[test]
+    fn headless_mode_injects_probed_colors() {
+        enable_headless_mode(COLORS);
+        let probed = crate::mux::colors::probe_outer_terminal_colors();
+        disable_headless_mode();
+
+        assert_eq!(probed, COLORS);
+        assert_eq!(headless_colors(), None);
+    }
+
+    #[tokio::test]
+    async fn runs_the_event_loop_without_a_tty() {
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        let manager = create_terminal_manager("http://localhost".to_string(), event_tx.clone());
+        let mut app = MuxApp::new("http://localhost".to_string(), event_tx, PathBuf::from("."));
+        app.set_terminal_manager(manager.clone());
+        app.add_sandbox(&uuid::Uuid::new_v4().to_string(), "headless");
+        let pane_id = app.active_pane_id().expect("sandbox workspace has a pane");
+        manager
+            .lock()
+            .await
+            .handle_output(pane_id, b"hello from the pane".to_vec());
+
+        let (backend, input, handle) = HeadlessTerminal::new(100, 30, COLORS);
+        let mut terminal = Terminal::new(backend).expect("terminal");
+
+        let driver = async {
+            let timeout = Duration::from_secs(5);
+            let pane_drawn = handle.wait_for_text("hello from the pane", timeout).await;
+            handle.send_key(KeyModifiers::ALT, KeyCode::Char('p'));
+            let palette_drawn = handle.wait_for_text("Command Palette", timeout).await;
+            // The palette swallows other keys, so close it before quitting
+            handle.send_key(KeyModifiers::NONE, KeyCode::Esc);
+            handle.send_key(KeyModifiers::CONTROL, KeyCode::Char('q'));
+            (pane_drawn, palette_drawn)
+        };
+        let (result, (pane_drawn, palette_drawn)) = tokio::join!(
+            run_headless(&mut terminal, app, event_rx, manager, input),
+            driver
+        );
+
+        result.expect("event loop");
+        assert!(pane_drawn, "pane output never drawn");
+        assert!(palette_drawn, "command palette never drawn");
+        // The cursor style is written straight to the terminal after each frame
+        assert!(terminal.backend().written().ends_with(b" q"));
+    }
+}
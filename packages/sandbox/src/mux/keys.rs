@@ -0,0 +1,212 @@
+//! Encoding keys as the bytes a terminal sends for them.
+//!
+//! Used both for forwarding key events to a pane and for `send-keys`, which
+//! names keys the way tmux does (`C-c`, `M-x`, `Enter`, `Escape`) so scripts
+//! and tests can type into a pane.
+
+use crossterm::event::{KeyCode, KeyModifiers};
+
+/// Convert a key event to terminal input bytes
+pub fn key_to_terminal_input(modifiers: KeyModifiers, code: KeyCode) -> Vec<u8> {
+    match code {
+        KeyCode::Char(c) => {
+            if modifiers.contains(KeyModifiers::CONTROL) {
+                // Ctrl+A = 0x01, Ctrl+B = 0x02, etc.
+                let ctrl_code = (c.to_ascii_lowercase() as u8).saturating_sub(b'a' - 1);
+                if (1..=26).contains(&ctrl_code) {
+                    return vec![ctrl_code];
+                }
+            }
+            let mut buf = [0u8; 4];
+            let s = c.encode_utf8(&mut buf);
+            s.as_bytes().to_vec()
+        }
+        KeyCode::Enter => vec![b'\r'],
+        KeyCode::Backspace => {
+            // Command+Backspace (macOS) / Super+Backspace: delete to beginning of line (Ctrl+U)
+            if modifiers.contains(KeyModifiers::SUPER) {
+                return vec![0x15]; // Ctrl+U
+            }
+            // Option+Backspace (macOS) / Alt+Backspace: delete previous word (Ctrl+W)
+            if modifiers.contains(KeyModifiers::ALT) {
+                return vec![0x17]; // Ctrl+W
+            }
+            vec![0x7f]
+        }
+        KeyCode::Tab => vec![b'\t'],
+        KeyCode::Esc => vec![0x1b],
+        KeyCode::Up => vec![0x1b, b'[', b'A'],
+        KeyCode::Down => vec![0x1b, b'[', b'B'],
+        KeyCode::Right => vec![0x1b, b'[', b'C'],
+        KeyCode::Left => vec![0x1b, b'[', b'D'],
+        KeyCode::Home => vec![0x1b, b'[', b'H'],
+        KeyCode::End => vec![0x1b, b'[', b'F'],
+        KeyCode::PageUp => vec![0x1b, b'[', b'5', b'~'],
+        KeyCode::PageDown => vec![0x1b, b'[', b'6', b'~'],
+        KeyCode::Delete => vec![0x1b, b'[', b'3', b'~'],
+        KeyCode::Insert => vec![0x1b, b'[', b'2', b'~'],
+        KeyCode::F(n) => {
+            // F1-F4 use different sequences than F5+
+            match n {
+                1 => vec![0x1b, b'O', b'P'],
+                2 => vec![0x1b, b'O', b'Q'],
+                3 => vec![0x1b, b'O', b'R'],
+                4 => vec![0x1b, b'O', b'S'],
+                5 => vec![0x1b, b'[', b'1', b'5', b'~'],
+                6 => vec![0x1b, b'[', b'1', b'7', b'~'],
+                7 => vec![0x1b, b'[', b'1', b'8', b'~'],
+                8 => vec![0x1b, b'[', b'1', b'9', b'~'],
+                9 => vec![0x1b, b'[', b'2', b'0', b'~'],
+                10 => vec![0x1b, b'[', b'2', b'1', b'~'],
+                11 => vec![0x1b, b'[', b'2', b'3', b'~'],
+                12 => vec![0x1b, b'[', b'2', b'4', b'~'],
+                _ => vec![],
+            }
+        }
+        _ => vec![],
+    }
+}
+
+/// Flag that makes `send-keys` send its arguments verbatim.
+pub const SEND_KEYS_LITERAL_FLAG: &str = "-l";
+
+/// The bytes `send-keys` writes for `args`.
+///
+/// Each argument is a key name (`C-c`, `M-f`, `Enter`, `Escape`, `Space`,
+/// `BSpace`, `Up`, `PageDown`, `F5`, ...) or, if it isn't one, literal text;
+/// the results are concatenated without separators, as in tmux. With a leading
+/// `-l` nothing is looked up and the remaining arguments are sent joined by
+/// spaces.
+pub fn send_keys_input(args: &[String]) -> Result<Vec<u8>, String> {
+    let (literal, keys) = match args.split_first() {
+        Some((flag, rest)) if flag == SEND_KEYS_LITERAL_FLAG => (true, rest),
+        _ => (false, args),
+    };
+    if keys.is_empty() {
+        return Err("send-keys needs keys to send, e.g. 'send-keys C-c'".to_string());
+    }
+    if literal {
+        return Ok(keys.join(" ").into_bytes());
+    }
+    Ok(keys
+        .iter()
+        .flat_map(|key| match parse_key_name(key) {
+            Some((modifiers, code)) => encode_named_key(modifiers, code),
+            None => key.as_bytes().to_vec(),
+        })
+        .collect())
+}
+
+/// Parse a tmux-style key name: optional `C-`, `M-` and `S-` prefixes, then a
+/// single character or a named key (case-insensitive). `None` if `name` isn't
+/// a key, so it is sent as text.
+fn parse_key_name(name: &str) -> Option<(KeyModifiers, KeyCode)> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = name;
+    loop {
+        let modifier = match rest.get(..2) {
+            Some("C-") => KeyModifiers::CONTROL,
+            Some("M-") => KeyModifiers::ALT,
+            Some("S-") => KeyModifiers::SHIFT,
+            _ => break,
+        };
+        // `C-` alone is the text it spells, not a modifier without a key
+        if rest.len() == 2 {
+            break;
+        }
+        modifiers |= modifier;
+        rest = &rest[2..];
+    }
+
+    let mut chars = rest.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        // A bare character is only a key with a modifier; otherwise it's text
+        return (!modifiers.is_empty()).then_some((modifiers, KeyCode::Char(c)));
+    }
+    let code = match rest.to_ascii_lowercase().as_str() {
+        "enter" => KeyCode::Enter,
+        "escape" | "esc" => KeyCode::Esc,
+        "tab" => KeyCode::Tab,
+        "space" => KeyCode::Char(' '),
+        "bspace" | "backspace" => KeyCode::Backspace,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" | "pgup" | "ppage" => KeyCode::PageUp,
+        "pagedown" | "pgdn" | "npage" => KeyCode::PageDown,
+        "dc" | "delete" => KeyCode::Delete,
+        "ic" | "insert" => KeyCode::Insert,
+        other => {
+            let n = other.strip_prefix('f')?.parse::<u8>().ok()?;
+            if !(1..=12).contains(&n) {
+                return None;
+            }
+            KeyCode::F(n)
+        }
+    };
+    Some((modifiers, code))
+}
+
+/// Parse one key of a chord binding: a key name as `send-keys` takes it, or
+/// a bare character for that key unmodified.
+pub(crate) fn parse_chord_key(name: &str) -> Option<(KeyModifiers, KeyCode)> {
+    parse_key_name(name).or_else(|| {
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some((KeyModifiers::NONE, KeyCode::Char(c))),
+            _ => None,
+        }
+    })
+}
+
+/// Bytes for a named key. Meta is sent as an ESC prefix, the way terminals
+/// encode it, rather than the macOS Option editing shortcuts key events get.
+fn encode_named_key(modifiers: KeyModifiers, code: KeyCode) -> Vec<u8> {
+    let bytes = key_to_terminal_input(modifiers - KeyModifiers::ALT, code);
+    if modifiers.contains(KeyModifiers::ALT) {
+        [&[0x1b][..], &bytes].concat()
+    } else {
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn send(args: &[&str]) -> Result<Vec<u8>, String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        send_keys_input(&args)
+    }
+
+    #[test]
+    fn send_keys_encodes_named_keys() {
+        assert_eq!(send(&["C-c"]), Ok(vec![0x03]));
+        assert_eq!(send(&["Enter"]), Ok(b"\r".to_vec()));
+        assert_eq!(send(&["Escape"]), Ok(vec![0x1b]));
+        assert_eq!(send(&["M-f"]), Ok(b"\x1bf".to_vec()));
+        assert_eq!(send(&["C-M-a"]), Ok(vec![0x1b, 0x01]));
+        assert_eq!(send(&["Up", "F5"]), Ok(b"\x1b[A\x1b[15~".to_vec()));
+        assert_eq!(send(&["enter"]), Ok(b"\r".to_vec()));
+    }
+
+    #[test]
+    fn send_keys_passes_text_through() {
+        assert_eq!(
+            send(&["echo", "Space", "hi", "Enter"]),
+            Ok(b"echo hi\r".to_vec())
+        );
+        assert_eq!(send(&["C-", "F13", "x"]), Ok(b"C-F13x".to_vec()));
+        assert_eq!(send(&["héllo"]), Ok("héllo".as_bytes().to_vec()));
+    }
+
+    #[test]
+    fn send_keys_literal_mode_sends_verbatim() {
+        assert_eq!(send(&["-l", "C-c", "Enter"]), Ok(b"C-c Enter".to_vec()));
+        assert!(send(&["-l"]).is_err());
+        assert!(send(&[]).is_err());
+    }
+}
@@ -1,4 +1,9 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
 use ratatui::layout::Rect;
+
+use crate::mux::colors::TerminalColors;
 use uuid::Uuid;
 
 /// Unique identifier for a sandbox.
@@ -99,6 +104,40 @@ pub enum NavDirection {
     Down,
 }
 
+/// Edge of a tab that a pinned pane keeps when a layout preset is applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlotSpec {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl SlotSpec {
+    /// Fraction of the tab a pinned pane occupies along its edge's axis.
+    pub const RATIO: f32 = 0.3;
+}
+
+/// Arrangement applied to all unpinned panes in a tab.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayoutPreset {
+    /// Panes stacked top to bottom with equal heights.
+    EvenHorizontal,
+    /// Panes side by side with equal widths.
+    EvenVertical,
+    /// Panes in a near-square grid.
+    Tiled,
+}
+
+/// Pane pinning conflicts.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum PinError {
+    #[error("pane not found")]
+    PaneNotFound,
+    #[error("pane {other} is already pinned to the {slot:?} slot")]
+    SlotTaken { slot: SlotSpec, other: PaneId },
+}
+
 /// Content that can be displayed in a pane.
 #[derive(Debug, Clone, Default)]
 pub enum PaneContent {
@@ -117,6 +156,27 @@ pub enum PaneContent {
     },
 }
 
+/// How a pane's process was launched, kept so the pane can be respawned in place.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PaneSpawn {
+    /// Command to run; `None` runs the sandbox's default shell
+    pub command: Option<Vec<String>>,
+    /// Bumped on every respawn so the new process gets its own session
+    pub generation: u32,
+    /// Colors the pane's session renders with instead of the outer terminal's
+    pub theme_override: Option<TerminalColors>,
+    /// Run through a login shell (`$SHELL -l -c`) so the user's profile sets up
+    /// the environment; `None` means on for the default shell, off for a command
+    pub login_shell: Option<bool>,
+}
+
+impl PaneSpawn {
+    /// Whether the pane's process is launched through a login shell.
+    pub fn uses_login_shell(&self) -> bool {
+        self.login_shell.unwrap_or(self.command.is_none())
+    }
+}
+
 /// A single pane in the layout.
 #[derive(Debug, Clone)]
 pub struct Pane {
@@ -124,6 +184,22 @@ pub struct Pane {
     pub content: PaneContent,
     /// The computed area for this pane (set during rendering)
     pub area: Option<Rect>,
+    /// Slot this pane keeps when a layout preset is applied
+    pub pinned_slot: Option<SlotSpec>,
+    /// File that the pane's output is logged to, if any
+    pub log_output: Option<PathBuf>,
+    /// How the pane's process is (re)launched
+    pub spawn: PaneSpawn,
+    /// Whether the pane's process has exited (only kept with `remain_on_exit`)
+    pub exited: bool,
+    /// PTY size (rows, cols) reported to the process whatever the pane's area;
+    /// the content is letterboxed inside the area
+    pub fixed_size: Option<(u16, u16)>,
+    /// Report a `PaneSilence` once the pane prints nothing for this long
+    pub silence_timeout: Option<Duration>,
+    /// Shown in an empty pane in place of the usual hint, e.g. why its
+    /// process couldn't be started
+    pub note: Option<String>,
 }
 
 impl Pane {
@@ -132,6 +208,13 @@ impl Pane {
             id: PaneId::new(),
             content,
             area: None,
+            pinned_slot: None,
+            log_output: None,
+            spawn: PaneSpawn::default(),
+            exited: false,
+            fixed_size: None,
+            silence_timeout: None,
+            note: None,
         }
     }
 
@@ -156,10 +239,76 @@ impl Pane {
     pub fn title(&self) -> String {
         match &self.content {
             PaneContent::Empty => "Empty".to_string(),
+            PaneContent::Terminal { title, .. } if self.exited => format!("{} (exited)", title),
             PaneContent::Terminal { title, .. } => title.clone(),
             PaneContent::Chat { provider, .. } => format!("Chat ({})", provider),
         }
     }
+
+    /// The PTY size (rows, cols) to report to the pane's process: `fixed_size`
+    /// if set, otherwise what fits inside the pane's border. `None` when
+    /// neither is known or the pane has no room.
+    pub fn reported_size(&self) -> Option<(u16, u16)> {
+        if let Some(size) = self.fixed_size.filter(|&(rows, cols)| rows > 0 && cols > 0) {
+            return Some(size);
+        }
+        let area = self.area?;
+        let cols = area.width.saturating_sub(2);
+        let rows = area.height.saturating_sub(2);
+
+        if cols == 0 || rows == 0 {
+            return None;
+        }
+
+        Some((rows, cols))
+    }
+
+    /// Where the pane's content is drawn within `inner`, the area inside its
+    /// border: all of it, or a `fixed_size` box centered in it.
+    pub fn content_area(&self, inner: Rect) -> Rect {
+        match self.fixed_size {
+            Some((rows, cols)) => letterbox(inner, rows, cols),
+            None => inner,
+        }
+    }
+
+    /// Prepare the pane to relaunch its command in the same slot.
+    pub fn respawn(&mut self) {
+        self.spawn.generation = self.spawn.generation.wrapping_add(1);
+        self.exited = false;
+    }
+}
+
+/// A `rows` x `cols` rect centered in `area`, shrunk to fit if `area` is smaller.
+pub fn letterbox(area: Rect, rows: u16, cols: u16) -> Rect {
+    let width = cols.min(area.width);
+    let height = rows.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+/// Split `total` cells at `ratio` with `gap` blank cells between the halves.
+/// Returns the first half's length and the gap actually used: the gap shrinks
+/// when there's no room left for a cell on each side, so it can't make a pane vanish.
+fn split_lengths(total: u16, ratio: f32, gap: u16) -> (u16, u16) {
+    let gap = gap.min(total.saturating_sub(2));
+    let available = total - gap;
+    let first = ((available as f32 * ratio) as u16).min(available);
+    (first, gap)
+}
+
+/// A borrowed view of a split node, as yielded by `LayoutNode::splits`.
+#[derive(Debug, Clone, Copy)]
+pub struct Split<'a> {
+    pub direction: Direction,
+    /// Percentage of space for the first child (0.0 - 1.0)
+    pub ratio: f32,
+    pub first: &'a LayoutNode,
+    pub second: &'a LayoutNode,
 }
 
 /// A node in the layout tree.
@@ -265,6 +414,114 @@ impl LayoutNode {
         }
     }
 
+    /// Every pane with the area it was last laid out at, in order.
+    ///
+    /// Areas come from the last `calculate_areas` call; a pane that hasn't been
+    /// laid out yet reports an empty rect.
+    pub fn leaves(&self) -> impl Iterator<Item = (PaneId, Rect)> + '_ {
+        self.panes()
+            .into_iter()
+            .map(|pane| (pane.id, pane.area.unwrap_or_default()))
+    }
+
+    /// Every split in the tree, parents before their children.
+    pub fn splits(&self) -> impl Iterator<Item = Split<'_>> {
+        let mut splits = Vec::new();
+        self.collect_splits(&mut splits);
+        splits.into_iter()
+    }
+
+    fn collect_splits<'a>(&'a self, splits: &mut Vec<Split<'a>>) {
+        if let LayoutNode::Split {
+            direction,
+            ratio,
+            first,
+            second,
+        } = self
+        {
+            splits.push(Split {
+                direction: *direction,
+                ratio: *ratio,
+                first,
+                second,
+            });
+            first.collect_splits(splits);
+            second.collect_splits(splits);
+        }
+    }
+
+    /// Call `f` on every node with the area it covers, parents before children.
+    ///
+    /// A split covers the bounding box of its children, gap included.
+    pub fn visit(&self, f: &mut impl FnMut(&LayoutNode, Rect)) {
+        f(self, self.area());
+        if let LayoutNode::Split { first, second, .. } = self {
+            first.visit(f);
+            second.visit(f);
+        }
+    }
+
+    /// The area this node was last laid out at.
+    fn area(&self) -> Rect {
+        match self {
+            LayoutNode::Pane(pane) => pane.area.unwrap_or_default(),
+            LayoutNode::Split { first, second, .. } => first.area().union(second.area()),
+        }
+    }
+
+    /// Consume the layout, returning its panes in order.
+    fn into_panes(self, panes: &mut Vec<Pane>) {
+        match self {
+            LayoutNode::Pane(pane) => panes.push(pane),
+            LayoutNode::Split { first, second, .. } => {
+                first.into_panes(panes);
+                second.into_panes(panes);
+            }
+        }
+    }
+
+    /// Arrange `nodes` in a column (`Horizontal`) or row (`Vertical`) with equal
+    /// sizes. Returns `None` if `nodes` is empty.
+    fn even(mut nodes: Vec<LayoutNode>, direction: Direction) -> Option<Self> {
+        if nodes.is_empty() {
+            return None;
+        }
+        let first = nodes.remove(0);
+        if nodes.is_empty() {
+            return Some(first);
+        }
+        let ratio = 1.0 / (nodes.len() + 1) as f32;
+        let second = Self::even(nodes, direction)?;
+        Some(LayoutNode::Split {
+            direction,
+            ratio,
+            first: Box::new(first),
+            second: Box::new(second),
+        })
+    }
+
+    /// Arrange `panes` in a near-square grid, filled row by row.
+    fn tiled(panes: Vec<Pane>) -> Option<Self> {
+        let cols = (panes.len() as f64).sqrt().ceil().max(1.0) as usize;
+        let mut panes = panes.into_iter().map(LayoutNode::Pane).peekable();
+        let mut rows = Vec::new();
+        while panes.peek().is_some() {
+            let row: Vec<LayoutNode> = panes.by_ref().take(cols).collect();
+            rows.extend(Self::even(row, Direction::Vertical));
+        }
+        Self::even(rows, Direction::Horizontal)
+    }
+
+    /// Arrange `panes` according to `preset`, ignoring pins.
+    fn from_preset(panes: Vec<Pane>, preset: LayoutPreset) -> Option<Self> {
+        let direction = match preset {
+            LayoutPreset::EvenHorizontal => Direction::Horizontal,
+            LayoutPreset::EvenVertical => Direction::Vertical,
+            LayoutPreset::Tiled => return Self::tiled(panes),
+        };
+        Self::even(panes.into_iter().map(LayoutNode::Pane).collect(), direction)
+    }
+
     /// Count the total number of panes.
     pub fn pane_count(&self) -> usize {
         match self {
@@ -355,6 +612,12 @@ impl LayoutNode {
 
     /// Calculate areas for all panes given a bounding rect.
     pub fn calculate_areas(&mut self, area: Rect) {
+        self.calculate_areas_with_gap(area, 0);
+    }
+
+    /// Calculate areas for all panes, leaving `gap` blank cells between
+    /// neighboring panes. Panes still tile `area` exactly with the gaps included.
+    pub fn calculate_areas_with_gap(&mut self, area: Rect, gap: u16) {
         match self {
             LayoutNode::Pane(pane) => {
                 pane.area = Some(area);
@@ -367,30 +630,30 @@ impl LayoutNode {
             } => {
                 let (first_area, second_area) = match direction {
                     Direction::Horizontal => {
-                        let split_point = (area.height as f32 * *ratio) as u16;
+                        let (split_point, gap) = split_lengths(area.height, *ratio, gap);
                         let first_area = Rect::new(area.x, area.y, area.width, split_point);
                         let second_area = Rect::new(
                             area.x,
-                            area.y + split_point,
+                            area.y + split_point + gap,
                             area.width,
-                            area.height.saturating_sub(split_point),
+                            area.height.saturating_sub(split_point + gap),
                         );
                         (first_area, second_area)
                     }
                     Direction::Vertical => {
-                        let split_point = (area.width as f32 * *ratio) as u16;
+                        let (split_point, gap) = split_lengths(area.width, *ratio, gap);
                         let first_area = Rect::new(area.x, area.y, split_point, area.height);
                         let second_area = Rect::new(
-                            area.x + split_point,
+                            area.x + split_point + gap,
                             area.y,
-                            area.width.saturating_sub(split_point),
+                            area.width.saturating_sub(split_point + gap),
                             area.height,
                         );
                         (first_area, second_area)
                     }
                 };
-                first.calculate_areas(first_area);
-                second.calculate_areas(second_area);
+                first.calculate_areas_with_gap(first_area, gap);
+                second.calculate_areas_with_gap(second_area, gap);
             }
         }
     }
@@ -671,6 +934,91 @@ impl Tab {
         };
         self.layout.resize_pane(active_id, direction, delta);
     }
+
+    /// Pin a pane to a slot (or unpin it with `None`).
+    /// Fails if another pane in this tab already holds the slot.
+    pub fn pin_pane(&mut self, pane_id: PaneId, slot: Option<SlotSpec>) -> Result<(), PinError> {
+        if let Some(slot) = slot {
+            let taken = self
+                .layout
+                .panes()
+                .into_iter()
+                .find(|pane| pane.id != pane_id && pane.pinned_slot == Some(slot));
+            if let Some(other) = taken {
+                return Err(PinError::SlotTaken {
+                    slot,
+                    other: other.id,
+                });
+            }
+        }
+
+        let pane = self
+            .layout
+            .find_pane_mut(pane_id)
+            .ok_or(PinError::PaneNotFound)?;
+        pane.pinned_slot = slot;
+        Ok(())
+    }
+
+    /// Rearrange the tab's panes according to a layout preset.
+    ///
+    /// Pinned panes are placed first, each taking `SlotSpec::RATIO` of the space
+    /// along its edge (earlier panes outermost), and the remaining panes are
+    /// arranged by the preset in what's left.
+    pub fn apply_preset(&mut self, preset: LayoutPreset) -> Result<(), PinError> {
+        // Validate pins up front so a conflict leaves the layout untouched
+        let mut seen: Vec<(SlotSpec, PaneId)> = Vec::new();
+        for pane in self.layout.panes() {
+            if let Some(slot) = pane.pinned_slot {
+                if let Some(&(_, other)) = seen.iter().find(|(s, _)| *s == slot) {
+                    return Err(PinError::SlotTaken { slot, other });
+                }
+                seen.push((slot, pane.id));
+            }
+        }
+
+        let mut panes = Vec::new();
+        std::mem::replace(&mut self.layout, LayoutNode::empty()).into_panes(&mut panes);
+        let (mut pinned, unpinned): (Vec<Pane>, Vec<Pane>) = panes
+            .into_iter()
+            .partition(|pane| pane.pinned_slot.is_some());
+
+        // If every pane is pinned, the innermost one fills the remaining space
+        let center = match LayoutNode::from_preset(unpinned, preset) {
+            Some(node) => node,
+            None => match pinned.pop() {
+                Some(pane) => LayoutNode::Pane(pane),
+                None => return Ok(()),
+            },
+        };
+
+        self.layout = pinned.into_iter().rev().fold(center, |inner, pane| {
+            let (direction, pinned_first) = match pane.pinned_slot {
+                Some(SlotSpec::Top) => (Direction::Horizontal, true),
+                Some(SlotSpec::Left) => (Direction::Vertical, true),
+                Some(SlotSpec::Bottom) => (Direction::Horizontal, false),
+                Some(SlotSpec::Right) | None => (Direction::Vertical, false),
+            };
+            let pinned = Box::new(LayoutNode::Pane(pane));
+            let inner = Box::new(inner);
+            if pinned_first {
+                LayoutNode::Split {
+                    direction,
+                    ratio: SlotSpec::RATIO,
+                    first: pinned,
+                    second: inner,
+                }
+            } else {
+                LayoutNode::Split {
+                    direction,
+                    ratio: 1.0 - SlotSpec::RATIO,
+                    first: inner,
+                    second: pinned,
+                }
+            }
+        });
+        Ok(())
+    }
 }
 
 /// A workspace for a single sandbox containing all its tabs/splits.
@@ -717,10 +1065,28 @@ impl SandboxWorkspace {
         id
     }
 
+    /// Add a tab built elsewhere (e.g. from a workspace template) and switch to it.
+    pub fn add_tab(&mut self, tab: Tab) -> TabId {
+        let id = tab.id;
+        self.tabs.push(tab);
+        self.active_tab_index = self.tabs.len() - 1;
+        id
+    }
+
     fn tab_index_for_pane(&self, pane_id: PaneId) -> Option<usize> {
         self.tabs.iter().position(|tab| tab.contains_pane(pane_id))
     }
 
+    /// The tab containing a pane.
+    pub fn tab_for_pane(&self, pane_id: PaneId) -> Option<&Tab> {
+        self.tabs.iter().find(|tab| tab.contains_pane(pane_id))
+    }
+
+    /// The tab containing a pane, mutably.
+    pub fn tab_for_pane_mut(&mut self, pane_id: PaneId) -> Option<&mut Tab> {
+        self.tabs.iter_mut().find(|tab| tab.contains_pane(pane_id))
+    }
+
     fn remove_tab_at(&mut self, index: usize) -> Option<Tab> {
         if index >= self.tabs.len() {
             return None;
@@ -837,6 +1203,16 @@ pub enum PaneExitOutcome {
     },
 }
 
+impl PaneExitOutcome {
+    /// Sandbox whose workspace contained the exited pane.
+    pub fn sandbox_id(&self) -> SandboxId {
+        match self {
+            PaneExitOutcome::TabClosed(info) => info.sandbox_id,
+            PaneExitOutcome::PaneRemoved { sandbox_id, .. } => *sandbox_id,
+        }
+    }
+}
+
 /// The workspace containing all tabs.
 /// This is kept for backwards compatibility but now wraps SandboxWorkspace.
 #[derive(Debug)]
@@ -1033,6 +1409,43 @@ impl WorkspaceManager {
         self.workspaces.len()
     }
 
+    /// Find a pane in any sandbox workspace.
+    pub fn find_pane_mut(&mut self, pane_id: PaneId) -> Option<&mut Pane> {
+        self.workspaces
+            .values_mut()
+            .flat_map(|ws| ws.tabs.iter_mut())
+            .find_map(|tab| tab.layout.find_pane_mut(pane_id))
+    }
+
+    /// Panes with a silence timeout, across all sandbox workspaces.
+    pub fn silence_timeouts(&self) -> Vec<(PaneId, Duration)> {
+        self.workspaces
+            .values()
+            .flat_map(|ws| ws.tabs.iter())
+            .flat_map(|tab| tab.layout.panes())
+            .filter_map(|pane| Some((pane.id, pane.silence_timeout?)))
+            .collect()
+    }
+
+    /// Mark a pane's process as exited while leaving the pane in its slot.
+    /// Returns false if no pane matched.
+    pub fn mark_pane_exited(&mut self, pane_id: PaneId) -> bool {
+        let Some(pane) = self.find_pane_mut(pane_id) else {
+            return false;
+        };
+        pane.exited = true;
+        true
+    }
+
+    /// Total number of panes across all sandbox workspaces.
+    pub fn total_pane_count(&self) -> usize {
+        self.workspaces
+            .values()
+            .flat_map(|ws| ws.tabs.iter())
+            .map(|tab| tab.layout.pane_count())
+            .sum()
+    }
+
     /// Check if a sandbox exists.
     pub fn has_sandbox(&self, sandbox_id: SandboxId) -> bool {
         self.workspaces.contains_key(&sandbox_id)
@@ -1059,6 +1472,11 @@ impl WorkspaceManager {
         self.active_workspace_mut().map(|ws| ws.new_tab())
     }
 
+    /// Add a tab to the active workspace and switch to it.
+    pub fn add_tab(&mut self, tab: Tab) -> Option<TabId> {
+        self.active_workspace_mut().map(|ws| ws.add_tab(tab))
+    }
+
     /// Close the active tab in the active workspace.
     pub fn close_active_tab(&mut self) -> bool {
         self.active_workspace_mut()
@@ -1240,6 +1658,16 @@ impl WorkspaceManager {
 mod tests {
     use super::*;
 
+    #[test]
+    fn login_shell_defaults_on_only_for_default_shell() {
+        let mut spawn = PaneSpawn::default();
+        assert!(spawn.uses_login_shell());
+        spawn.command = Some(vec!["top".to_string()]);
+        assert!(!spawn.uses_login_shell());
+        spawn.login_shell = Some(true);
+        assert!(spawn.uses_login_shell());
+    }
+
     #[test]
     fn can_create_workspace() {
         let workspace = Workspace::new();
@@ -1380,4 +1808,184 @@ mod tests {
         assert_eq!(tab.layout.pane_count(), 1);
         assert!(tab.contains_pane(tab.active_pane.expect("active pane should exist")));
     }
+
+    fn tab_with_panes(count: usize) -> (Tab, Vec<PaneId>) {
+        let mut tab = Tab::new("Tab");
+        for _ in 1..count {
+            tab.split(Direction::Vertical, Pane::empty());
+        }
+        let ids = tab.layout.pane_ids();
+        (tab, ids)
+    }
+
+    fn area_of(tab: &Tab, pane_id: PaneId) -> Rect {
+        tab.layout
+            .find_pane(pane_id)
+            .and_then(|pane| pane.area)
+            .expect("pane should have an area")
+    }
+
+    #[test]
+    fn tiled_preset_keeps_pinned_pane_at_bottom() {
+        let (mut tab, ids) = tab_with_panes(5);
+        let log_pane = ids[1];
+        tab.pin_pane(log_pane, Some(SlotSpec::Bottom))
+            .expect("slot is free");
+
+        tab.apply_preset(LayoutPreset::Tiled)
+            .expect("pins don't conflict");
+        let bounds = Rect::new(0, 0, 100, 40);
+        tab.layout.calculate_areas(bounds);
+
+        let log_area = area_of(&tab, log_pane);
+        assert_eq!(log_area.y + log_area.height, bounds.height);
+        assert_eq!(log_area.width, bounds.width);
+        assert_eq!(tab.layout.pane_count(), 5);
+
+        // The other four panes form a 2x2 grid above the pinned pane
+        for &id in ids.iter().filter(|&&id| id != log_pane) {
+            let area = area_of(&tab, id);
+            assert!(area.y + area.height <= log_area.y);
+            assert_eq!(area.width, bounds.width / 2);
+        }
+    }
+
+    #[test]
+    fn conflicting_pins_are_rejected() {
+        let (mut tab, ids) = tab_with_panes(3);
+        tab.pin_pane(ids[0], Some(SlotSpec::Left))
+            .expect("slot is free");
+        assert_eq!(
+            tab.pin_pane(ids[1], Some(SlotSpec::Left)),
+            Err(PinError::SlotTaken {
+                slot: SlotSpec::Left,
+                other: ids[0],
+            })
+        );
+        // Re-pinning the same pane to its own slot is fine
+        assert!(tab.pin_pane(ids[0], Some(SlotSpec::Left)).is_ok());
+
+        // A conflict that bypassed pin_pane leaves the layout untouched
+        if let Some(pane) = tab.layout.find_pane_mut(ids[2]) {
+            pane.pinned_slot = Some(SlotSpec::Left);
+        }
+        let before = tab.layout.pane_ids();
+        assert!(tab.apply_preset(LayoutPreset::EvenHorizontal).is_err());
+        assert_eq!(tab.layout.pane_ids(), before);
+    }
+
+    #[test]
+    fn pane_gap_shrinks_both_panes_and_conserves_total() {
+        let (mut tab, ids) = tab_with_panes(2);
+        tab.apply_preset(LayoutPreset::EvenVertical)
+            .expect("no pins");
+        tab.layout
+            .calculate_areas_with_gap(Rect::new(0, 0, 81, 20), 1);
+
+        let left = area_of(&tab, ids[0]);
+        let right = area_of(&tab, ids[1]);
+        assert_eq!((left.x, left.width), (0, 40));
+        assert_eq!((right.x, right.width), (41, 40));
+        assert_eq!(left.width + 1 + right.width, 81);
+        assert_eq!(left.height, 20);
+    }
+
+    #[test]
+    fn pane_gap_never_makes_panes_vanish() {
+        let (mut tab, ids) = tab_with_panes(2);
+        tab.apply_preset(LayoutPreset::EvenHorizontal)
+            .expect("no pins");
+
+        // Two rows leave no room for a gap
+        tab.layout
+            .calculate_areas_with_gap(Rect::new(0, 0, 10, 2), 3);
+        assert_eq!(area_of(&tab, ids[0]).height, 1);
+        assert_eq!(area_of(&tab, ids[1]).height, 1);
+
+        // Four rows keep one cell per pane and gap the rest
+        tab.layout
+            .calculate_areas_with_gap(Rect::new(0, 0, 10, 4), 3);
+        let top = area_of(&tab, ids[0]);
+        let bottom = area_of(&tab, ids[1]);
+        assert_eq!((top.height, bottom.y, bottom.height), (1, 3, 1));
+    }
+
+    #[test]
+    fn leaves_yield_every_pane_with_its_area() {
+        // [a | [b / c]]
+        let (a, b, c) = (Pane::empty(), Pane::empty(), Pane::empty());
+        let ids = (a.id, b.id, c.id);
+        let mut right = LayoutNode::Pane(b);
+        right.split(Direction::Horizontal, c);
+        let mut layout = LayoutNode::Split {
+            direction: Direction::Vertical,
+            ratio: 0.5,
+            first: Box::new(LayoutNode::Pane(a)),
+            second: Box::new(right),
+        };
+        layout.calculate_areas(Rect::new(0, 0, 80, 20));
+
+        let leaves: Vec<_> = layout.leaves().collect();
+        assert_eq!(
+            leaves,
+            vec![
+                (ids.0, Rect::new(0, 0, 40, 20)),
+                (ids.1, Rect::new(40, 0, 40, 10)),
+                (ids.2, Rect::new(40, 10, 40, 10)),
+            ]
+        );
+
+        let directions: Vec<_> = layout.splits().map(|split| split.direction).collect();
+        assert_eq!(directions, vec![Direction::Vertical, Direction::Horizontal]);
+
+        let mut visited = Vec::new();
+        layout.visit(&mut |node, area| {
+            visited.push((matches!(node, LayoutNode::Split { .. }), area));
+        });
+        assert_eq!(visited.len(), 5);
+        assert_eq!(visited[0], (true, Rect::new(0, 0, 80, 20)));
+        assert_eq!(visited[2], (true, Rect::new(40, 0, 40, 20)));
+    }
+
+    #[test]
+    fn even_preset_splits_space_equally() {
+        let (mut tab, ids) = tab_with_panes(3);
+        tab.apply_preset(LayoutPreset::EvenHorizontal)
+            .expect("no pins");
+        tab.layout.calculate_areas(Rect::new(0, 0, 80, 30));
+
+        for id in ids {
+            assert_eq!(area_of(&tab, id).height, 10);
+        }
+    }
+
+    #[test]
+    fn letterbox_centers_and_clamps() {
+        let area = Rect::new(10, 5, 100, 40);
+        assert_eq!(letterbox(area, 24, 80), Rect::new(20, 13, 80, 24));
+        // Odd margins put the extra cell after the content
+        assert_eq!(letterbox(area, 23, 79), Rect::new(20, 13, 79, 23));
+        // Too big for the area: fill it
+        assert_eq!(letterbox(area, 50, 200), area);
+    }
+
+    #[test]
+    fn fixed_size_is_reported_and_letterboxed() {
+        let mut pane = Pane::terminal(None, "Terminal");
+        assert_eq!(pane.reported_size(), None);
+        pane.area = Some(Rect::new(0, 0, 102, 42));
+        assert_eq!(pane.reported_size(), Some((40, 100)));
+        let inner = Rect::new(1, 1, 100, 40);
+        assert_eq!(pane.content_area(inner), inner);
+
+        pane.fixed_size = Some((24, 80));
+        assert_eq!(pane.reported_size(), Some((24, 80)));
+        assert_eq!(pane.content_area(inner), Rect::new(11, 9, 80, 24));
+
+        // A fixed size is reported even before the first render
+        pane.area = None;
+        assert_eq!(pane.reported_size(), Some((24, 80)));
+        pane.fixed_size = Some((0, 80));
+        assert_eq!(pane.reported_size(), None);
+    }
 }
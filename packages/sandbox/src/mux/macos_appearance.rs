@@ -0,0 +1,125 @@
+//! React to macOS light/dark appearance switches.
+//!
+//! macOS posts `AppleInterfaceThemeChangedNotification` on the distributed
+//! notification center whenever the system appearance flips. Terminals that
+//! follow the system theme change their colors at the same moment, so the
+//! notification is a ready-made replacement for a manual SIGUSR1.
+
+use objc2::rc::Retained;
+use objc2::runtime::NSObject;
+use objc2::{define_class, msg_send, sel, AnyThread, DefinedClass};
+use objc2_foundation::{
+    NSDate, NSDefaultRunLoopMode, NSDistributedNotificationCenter, NSNotification, NSRunLoop,
+    NSString,
+};
+use tokio::sync::mpsc;
+
+use crate::mux::colors::{get_outer_colors, is_nested_dmux, ThemeChangeEvent};
+
+/// Distributed notification posted when the system appearance changes.
+const APPEARANCE_NOTIFICATION: &str = "AppleInterfaceThemeChangedNotification";
+
+/// How long the listener's run loop waits before checking whether the
+/// receiving end has gone away.
+const RUN_LOOP_SLICE_SECS: f64 = 1.0;
+
+define_class!(
+    // SAFETY:
+    // - NSObject has no subclassing requirements.
+    // - `AppearanceObserver` does not implement `Drop`.
+    #[unsafe(super(NSObject))]
+    #[name = "DmuxAppearanceObserver"]
+    #[ivars = mpsc::UnboundedSender<ThemeChangeEvent>]
+    struct AppearanceObserver;
+
+    impl AppearanceObserver {
+        #[unsafe(method(appearanceChanged:))]
+        fn appearance_changed(&self, _notification: &NSNotification) {
+            // Like the SIGUSR1 path, the re-query itself happens in the main
+            // event loop; this only reports that the colors may be stale.
+            let _ = self.ivars().send(ThemeChangeEvent {
+                colors: get_outer_colors(),
+            });
+        }
+    }
+);
+
+impl AppearanceObserver {
+    fn new(tx: mpsc::UnboundedSender<ThemeChangeEvent>) -> Retained<Self> {
+        let this = Self::alloc().set_ivars(tx);
+        unsafe { msg_send![super(this), init] }
+    }
+}
+
+/// Spawn a thread that sends a `ThemeChangeEvent` whenever the macOS system
+/// appearance switches between light and dark.
+///
+/// The observer lives on its own thread with its own run loop, since the
+/// tokio runtime never spins one. The thread exits once `tx`'s receiver is
+/// dropped. Like `spawn_theme_change_listener`, this is a no-op when nested
+/// inside another dmux. Returns whether a listener was spawned.
+pub fn spawn_macos_appearance_listener(tx: mpsc::UnboundedSender<ThemeChangeEvent>) -> bool {
+    if is_nested_dmux() {
+        return false;
+    }
+
+    let spawned = std::thread::Builder::new()
+        .name("macos-appearance".into())
+        .spawn(move || run_appearance_observer(tx));
+    match spawned {
+        Ok(_) => true,
+        Err(e) => {
+            tracing::warn!("Failed to spawn macOS appearance listener: {e}");
+            false
+        }
+    }
+}
+
+/// Register an observer on the current thread and spin its run loop until
+/// the channel closes.
+fn run_appearance_observer(tx: mpsc::UnboundedSender<ThemeChangeEvent>) {
+    let observer = AppearanceObserver::new(tx);
+    let center = NSDistributedNotificationCenter::defaultCenter();
+    let name = NSString::from_str(APPEARANCE_NOTIFICATION);
+    // SAFETY: `appearanceChanged:` takes a single NSNotification argument,
+    // and the observer is removed below before it is released.
+    unsafe {
+        center.addObserver_selector_name_object(
+            &observer,
+            sel!(appearanceChanged:),
+            Some(&name),
+            None,
+        );
+    }
+
+    let run_loop = NSRunLoop::currentRunLoop();
+    while !observer.ivars().is_closed() {
+        let until = NSDate::dateWithTimeIntervalSinceNow(RUN_LOOP_SLICE_SECS);
+        // SAFETY: `NSDefaultRunLoopMode` is an immutable Foundation constant.
+        let ran = unsafe { run_loop.runMode_beforeDate(NSDefaultRunLoopMode, &until) };
+        if !ran {
+            // No input sources attached yet; avoid spinning.
+            std::thread::sleep(std::time::Duration::from_secs_f64(RUN_LOOP_SLICE_SECS));
+        }
+    }
+
+    // SAFETY: the observer was registered with this center above.
+    unsafe { center.removeObserver(&observer) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listener_registers_and_exits_when_channel_closes() {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let observer = AppearanceObserver::new(tx.clone());
+        assert!(!observer.ivars().is_closed());
+
+        let handle = std::thread::spawn(move || run_appearance_observer(tx));
+        drop(rx);
+        drop(observer);
+        assert!(handle.join().is_ok());
+    }
+}
@@ -1,19 +1,42 @@
+pub mod capabilities;
 pub mod character;
+pub mod chords;
+pub mod color_adjust;
 pub mod colors;
 pub mod commands;
+pub mod dirty;
+pub mod embed;
 pub mod events;
+pub mod frame_budget;
+pub mod geometry;
 pub mod grid;
+pub mod headless;
+pub mod keys;
 pub mod layout;
+#[cfg(all(target_os = "macos", feature = "macos"))]
+pub mod macos_appearance;
 pub mod onboard;
+pub mod osc;
+pub mod output;
 pub mod palette;
+pub mod pane_log;
+pub mod pane_pipe;
+pub mod parse;
 pub mod runner;
+pub mod screen;
+pub mod scrollback;
+pub mod sgr_diff;
 pub mod sidebar;
+pub mod silence;
 pub mod state;
+pub mod templates;
 pub mod terminal;
+pub mod theme_import;
 pub mod ui;
 
 pub use colors::{
     get_outer_bg, get_outer_fg, query_outer_terminal_colors, spawn_theme_change_listener,
     TerminalColors, ThemeChangeEvent,
 };
+pub use embed::{Mux, MuxConfig};
 pub use runner::run_mux_tui;
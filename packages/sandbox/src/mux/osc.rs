@@ -0,0 +1,191 @@
+//! Emitters for OSC sequences that carry caller-supplied payloads.
+//!
+//! Payloads are validated before being embedded: a control character such as
+//! ESC or BEL inside a payload would terminate the sequence early and let the
+//! rest of the string be interpreted by the outer terminal.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use thiserror::Error;
+
+use crate::mux::colors::{
+    notify_osc_observers, with_raw_mode, CrosstermRawMode, OscIo, StdioOsc, OSC_QUERY_TIMEOUT,
+};
+
+/// Maximum size of an OSC 52 payload (base64-encoded), in bytes.
+/// Larger sequences are silently dropped or truncated by many terminals.
+pub const OSC52_MAX_PAYLOAD: usize = 100_000;
+
+/// Maximum length of an OSC 8 hyperlink URI, in bytes.
+pub const OSC8_MAX_URI: usize = 2083;
+
+/// Maximum length of the visible text of an OSC 8 hyperlink, in bytes.
+pub const OSC8_MAX_LABEL: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum OscPayloadError {
+    #[error("payload contains control character U+{0:04X}")]
+    ControlCharacter(u32),
+    #[error("payload is {len} bytes, exceeding the {max} byte limit")]
+    TooLong { len: usize, max: usize },
+}
+
+/// Validate a string for embedding in an OSC sequence.
+///
+/// Rejects C0 and C1 control characters (and DEL), and payloads longer than
+/// `max_len` bytes. Over-long payloads are rejected rather than truncated, so a
+/// multi-byte character is never split.
+pub fn sanitize_osc_payload(s: &str, max_len: usize) -> Result<String, OscPayloadError> {
+    if let Some(c) = s.chars().find(|c| c.is_control()) {
+        return Err(OscPayloadError::ControlCharacter(c as u32));
+    }
+    if s.len() > max_len {
+        return Err(OscPayloadError::TooLong {
+            len: s.len(),
+            max: max_len,
+        });
+    }
+    Ok(s.to_string())
+}
+
+/// Build an OSC 52 sequence that copies `text` to the system clipboard.
+///
+/// The text itself may contain newlines and tabs; only the base64-encoded
+/// payload is embedded, and that is what the length limit applies to.
+pub fn osc52_copy(text: &str) -> Result<String, OscPayloadError> {
+    let payload = sanitize_osc_payload(&STANDARD.encode(text), OSC52_MAX_PAYLOAD)?;
+    Ok(format!("\x1b]52;c;{}\x1b\\", payload))
+}
+
+/// OSC 52 query asking the outer terminal for the clipboard contents.
+const OSC52_QUERY: &[u8] = b"\x1b]52;c;?\x1b\\";
+
+/// Read the system clipboard from the outer terminal via an OSC 52 query.
+///
+/// Works over SSH, unlike a local clipboard API. Many terminals disable
+/// clipboard reads for security and either don't answer or answer with an
+/// empty payload; both give `None`. Same requirements as
+/// `query_outer_terminal_colors`: raw mode is enabled if it isn't already.
+pub fn query_clipboard() -> Option<String> {
+    with_raw_mode(&mut CrosstermRawMode, || {
+        query_clipboard_with(&mut StdioOsc)
+    })
+    .flatten()
+}
+
+fn query_clipboard_with<T: OscIo>(io: &mut T) -> Option<String> {
+    io.send(OSC52_QUERY).ok()?;
+    let response = io.recv(OSC_QUERY_TIMEOUT);
+    notify_osc_observers(52, &response);
+    parse_osc52_response(&response)
+}
+
+/// Decode an OSC 52 clipboard reply: OSC 52 ; selection ; base64 ST.
+/// An empty payload (or the `?` of an echoed query) means reads are disabled.
+pub(crate) fn parse_osc52_response(response: &[u8]) -> Option<String> {
+    let start = response.windows(4).position(|w| w == b"]52;")? + 4;
+    let rest = &response[start..];
+    let payload = &rest[rest.iter().position(|&b| b == b';')? + 1..];
+    let len = payload
+        .iter()
+        .take_while(|b| b.is_ascii_alphanumeric() || matches!(b, b'+' | b'/' | b'='))
+        .count();
+    if len == 0 {
+        return None;
+    }
+    let text = String::from_utf8(STANDARD.decode(&payload[..len]).ok()?).ok()?;
+    (!text.is_empty()).then_some(text)
+}
+
+/// Build an OSC 8 hyperlink showing `label` and pointing at `uri`.
+pub fn osc8_hyperlink(uri: &str, label: &str) -> Result<String, OscPayloadError> {
+    let uri = sanitize_osc_payload(uri, OSC8_MAX_URI)?;
+    let label = sanitize_osc_payload(label, OSC8_MAX_LABEL)?;
+    Ok(format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", uri, label))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_rejects_embedded_escape() {
+        assert_eq!(
+            sanitize_osc_payload("https://example.com/\x1b]52;c;ZXZpbA==\x07", 100),
+            Err(OscPayloadError::ControlCharacter(0x1b))
+        );
+        // C1 ST (U+009C) would also end the sequence in 8-bit mode
+        assert_eq!(
+            sanitize_osc_payload("a\u{9c}b", 100),
+            Err(OscPayloadError::ControlCharacter(0x9c))
+        );
+    }
+
+    #[test]
+    fn sanitize_rejects_over_length_payload() {
+        assert_eq!(
+            sanitize_osc_payload(&"a".repeat(11), 10),
+            Err(OscPayloadError::TooLong { len: 11, max: 10 })
+        );
+        // The limit is in bytes, so multi-byte characters count fully
+        assert!(sanitize_osc_payload("日本語", 8).is_err());
+    }
+
+    #[test]
+    fn sanitize_accepts_valid_utf8() {
+        let text = "https://example.com/日本語?q=ü";
+        assert_eq!(sanitize_osc_payload(text, 100).as_deref(), Ok(text));
+    }
+
+    #[test]
+    fn osc52_copy_allows_multiline_text() {
+        assert_eq!(osc52_copy("a\nb").as_deref(), Ok("\x1b]52;c;YQpi\x1b\\"));
+    }
+
+    /// Terminal that answers the clipboard query with a canned reply.
+    struct ClipboardReply(&'static [u8]);
+
+    impl OscIo for ClipboardReply {
+        fn send(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+            assert_eq!(bytes, OSC52_QUERY);
+            Ok(())
+        }
+
+        fn recv(&mut self, _timeout: std::time::Duration) -> Vec<u8> {
+            self.0.to_vec()
+        }
+    }
+
+    #[test]
+    fn query_clipboard_decodes_reply() {
+        let mut term = ClipboardReply(b"\x1b]52;c;aGVsbG8Kd29ybGQ=\x1b\\");
+        assert_eq!(
+            query_clipboard_with(&mut term).as_deref(),
+            Some("hello\nworld")
+        );
+        // BEL-terminated, with the selection reported as primary
+        assert_eq!(
+            parse_osc52_response(b"\x1b]52;p;YQpi\x07").as_deref(),
+            Some("a\nb")
+        );
+    }
+
+    #[test]
+    fn query_clipboard_rejects_disabled_reads() {
+        assert_eq!(query_clipboard_with(&mut ClipboardReply(b"")), None);
+        assert_eq!(
+            query_clipboard_with(&mut ClipboardReply(b"\x1b]52;c;\x1b\\")),
+            None
+        );
+        assert_eq!(parse_osc52_response(b"\x1b]52;c;?\x1b\\"), None);
+        assert_eq!(parse_osc52_response(b"\x1b]52;c;!!!\x07"), None);
+    }
+
+    #[test]
+    fn osc8_hyperlink_rejects_injected_uri() {
+        assert!(osc8_hyperlink("https://example.com\x1b\\evil", "link").is_err());
+        assert_eq!(
+            osc8_hyperlink("https://example.com", "link").as_deref(),
+            Ok("\x1b]8;;https://example.com\x1b\\link\x1b]8;;\x1b\\")
+        );
+    }
+}
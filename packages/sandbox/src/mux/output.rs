@@ -0,0 +1,200 @@
+//! The write side of the outer terminal.
+//!
+//! Frames are drawn through a `TerminalOutput`, which covers the handful of
+//! operations drawing needs beyond writing bytes: cursor moves, cursor
+//! visibility and clearing. `CrosstermOutput` issues them as crossterm
+//! commands; `AnsiOutput` writes the escape sequences itself, for a leaner
+//! path with no crossterm on the write side. Input, raw mode and terminal
+//! queries go through crossterm either way.
+
+use std::io::{self, Write};
+
+use crossterm::{cursor, queue, terminal};
+use ratatui::backend::ClearType;
+
+use crate::settings::RenderBackend;
+
+/// Output operations used to draw a frame.
+pub trait TerminalOutput: Write {
+    /// Move the cursor to column `x`, row `y` (zero-based).
+    fn move_to(&mut self, x: u16, y: u16) -> io::Result<()>;
+
+    fn hide_cursor(&mut self) -> io::Result<()>;
+
+    fn show_cursor(&mut self) -> io::Result<()>;
+
+    /// Clear all or part of the screen.
+    fn clear(&mut self, clear_type: ClearType) -> io::Result<()>;
+}
+
+impl<T: TerminalOutput + ?Sized> TerminalOutput for Box<T> {
+    fn move_to(&mut self, x: u16, y: u16) -> io::Result<()> {
+        (**self).move_to(x, y)
+    }
+
+    fn hide_cursor(&mut self) -> io::Result<()> {
+        (**self).hide_cursor()
+    }
+
+    fn show_cursor(&mut self) -> io::Result<()> {
+        (**self).show_cursor()
+    }
+
+    fn clear(&mut self, clear_type: ClearType) -> io::Result<()> {
+        (**self).clear(clear_type)
+    }
+}
+
+/// The output selected by `backend`, writing to `writer`.
+pub fn terminal_output<W: Write + Send + 'static>(
+    backend: RenderBackend,
+    writer: W,
+) -> Box<dyn TerminalOutput + Send> {
+    match backend {
+        RenderBackend::Crossterm => Box::new(CrosstermOutput::new(writer)),
+        RenderBackend::Ansi => Box::new(AnsiOutput::new(writer)),
+    }
+}
+
+/// Output through crossterm commands (the default).
+pub struct CrosstermOutput<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> CrosstermOutput<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+}
+
+impl<W: Write> Write for CrosstermOutput<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<W: Write> TerminalOutput for CrosstermOutput<W> {
+    fn move_to(&mut self, x: u16, y: u16) -> io::Result<()> {
+        queue!(self.writer, cursor::MoveTo(x, y))
+    }
+
+    fn hide_cursor(&mut self) -> io::Result<()> {
+        queue!(self.writer, cursor::Hide)
+    }
+
+    fn show_cursor(&mut self) -> io::Result<()> {
+        queue!(self.writer, cursor::Show)
+    }
+
+    fn clear(&mut self, clear_type: ClearType) -> io::Result<()> {
+        let clear_type = match clear_type {
+            ClearType::All => terminal::ClearType::All,
+            ClearType::AfterCursor => terminal::ClearType::FromCursorDown,
+            ClearType::BeforeCursor => terminal::ClearType::FromCursorUp,
+            ClearType::CurrentLine => terminal::ClearType::CurrentLine,
+            ClearType::UntilNewLine => terminal::ClearType::UntilNewLine,
+        };
+        queue!(self.writer, terminal::Clear(clear_type))
+    }
+}
+
+/// Output written as plain ANSI escape sequences.
+pub struct AnsiOutput<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> AnsiOutput<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+}
+
+impl<W: Write> Write for AnsiOutput<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<W: Write> TerminalOutput for AnsiOutput<W> {
+    fn move_to(&mut self, x: u16, y: u16) -> io::Result<()> {
+        write!(self.writer, "\x1b[{};{}H", y + 1, x + 1)
+    }
+
+    fn hide_cursor(&mut self) -> io::Result<()> {
+        self.writer.write_all(b"\x1b[?25l")
+    }
+
+    fn show_cursor(&mut self) -> io::Result<()> {
+        self.writer.write_all(b"\x1b[?25h")
+    }
+
+    fn clear(&mut self, clear_type: ClearType) -> io::Result<()> {
+        let sequence: &[u8] = match clear_type {
+            ClearType::All => b"\x1b[2J",
+            ClearType::AfterCursor => b"\x1b[J",
+            ClearType::BeforeCursor => b"\x1b[1J",
+            ClearType::CurrentLine => b"\x1b[2K",
+            ClearType::UntilNewLine => b"\x1b[K",
+        };
+        self.writer.write_all(sequence)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What `ops` write through each backend, as (crossterm, ANSI).
+    fn both(ops: impl Fn(&mut dyn TerminalOutput) -> io::Result<()>) -> (Vec<u8>, Vec<u8>) {
+        let mut crossterm = Vec::new();
+        ops(&mut CrosstermOutput::new(&mut crossterm)).expect("crossterm");
+        let mut ansi = Vec::new();
+        ops(&mut AnsiOutput::new(&mut ansi)).expect("ansi");
+        (crossterm, ansi)
+    }
+
+    #[test]
+    fn cursor_moves_match_across_backends() {
+        let (crossterm, ansi) = both(|out| {
+            out.move_to(0, 0)?;
+            out.move_to(79, 23)?;
+            out.move_to(4, 9)
+        });
+        assert_eq!(crossterm, ansi);
+        assert_eq!(ansi, b"\x1b[1;1H\x1b[24;80H\x1b[10;5H");
+    }
+
+    #[test]
+    fn cursor_visibility_and_clears_match_across_backends() {
+        let (crossterm, ansi) = both(|out| {
+            out.hide_cursor()?;
+            for clear_type in [
+                ClearType::All,
+                ClearType::AfterCursor,
+                ClearType::BeforeCursor,
+                ClearType::CurrentLine,
+                ClearType::UntilNewLine,
+            ] {
+                out.clear(clear_type)?;
+            }
+            out.show_cursor()
+        });
+        assert_eq!(crossterm, ansi);
+    }
+}
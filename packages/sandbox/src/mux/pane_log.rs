@@ -0,0 +1,234 @@
+//! Per-pane output logging.
+//!
+//! A `PaneLog` tees the raw PTY output of a pane to a file, prefixing each
+//! line with a timestamp. Write failures are logged and disable the sink rather
+//! than propagating, so a full disk never takes the pane down with it.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use vte::{Parser, Perform};
+
+/// How a pane's output log is written.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PaneLogOptions {
+    pub path: PathBuf,
+    /// Drop escape sequences and carriage returns, keeping only printable text.
+    pub strip_ansi: bool,
+    /// Once the file would grow past this many bytes it is moved to
+    /// `<path>.1` (replacing any previous one) and a fresh file is started.
+    pub max_bytes: Option<u64>,
+}
+
+impl PaneLogOptions {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            strip_ansi: false,
+            max_bytes: None,
+        }
+    }
+}
+
+/// An open output log for one pane.
+pub struct PaneLog {
+    options: PaneLogOptions,
+    /// `None` once a write has failed.
+    file: Option<File>,
+    /// Bytes in the current file.
+    written: u64,
+    /// Whether the next byte written starts a new line (and needs a timestamp).
+    at_line_start: bool,
+    /// Only used when stripping ANSI; kept across writes so escape sequences
+    /// split between PTY reads are still recognised.
+    parser: Parser,
+}
+
+impl PaneLog {
+    /// Open (or append to) the log file.
+    pub fn open(options: PaneLogOptions) -> io::Result<Self> {
+        let file = open_append(&options.path)?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            options,
+            file: Some(file),
+            written,
+            at_line_start: true,
+            parser: Parser::new(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.options.path
+    }
+
+    /// Whether the sink is still writing (it stops after the first I/O error).
+    pub fn is_active(&self) -> bool {
+        self.file.is_some()
+    }
+
+    /// Append a chunk of PTY output. Errors are logged and disable the sink.
+    pub fn write(&mut self, data: &[u8]) {
+        if self.file.is_none() {
+            return;
+        }
+        if let Err(e) = self.try_write(data) {
+            tracing::warn!(
+                "Pane output log {} failed, disabling it: {}",
+                self.options.path.display(),
+                e
+            );
+            self.file = None;
+        }
+    }
+
+    fn try_write(&mut self, data: &[u8]) -> io::Result<()> {
+        let text = if self.options.strip_ansi {
+            let mut collector = TextCollector::default();
+            self.parser.advance(&mut collector, data);
+            collector.text
+        } else {
+            data.to_vec()
+        };
+        let chunk = self.timestamp_lines(&text);
+        if chunk.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(max) = self.options.max_bytes {
+            if self.written > 0 && self.written + chunk.len() as u64 > max {
+                self.rotate()?;
+            }
+        }
+
+        let Some(file) = self.file.as_mut() else {
+            return Ok(());
+        };
+        file.write_all(&chunk)?;
+        self.written += chunk.len() as u64;
+        Ok(())
+    }
+
+    /// Insert a timestamp at the start of every line in `text`.
+    fn timestamp_lines(&mut self, text: &[u8]) -> Vec<u8> {
+        let stamp = chrono::Local::now()
+            .format("[%Y-%m-%d %H:%M:%S%.3f] ")
+            .to_string();
+        let mut out = Vec::with_capacity(text.len() + stamp.len());
+        for &byte in text {
+            if self.at_line_start {
+                out.extend_from_slice(stamp.as_bytes());
+                self.at_line_start = false;
+            }
+            out.push(byte);
+            if byte == b'\n' {
+                self.at_line_start = true;
+            }
+        }
+        out
+    }
+
+    /// Move the current file to `<path>.1` and start a new one.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file = None;
+        let mut rotated = self.options.path.clone().into_os_string();
+        rotated.push(".1");
+        std::fs::rename(&self.options.path, rotated)?;
+        self.file = Some(open_append(&self.options.path)?);
+        self.written = 0;
+        Ok(())
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// `Perform` that keeps printable text, newlines and tabs, and drops the rest.
+#[derive(Default)]
+struct TextCollector {
+    text: Vec<u8>,
+}
+
+impl Perform for TextCollector {
+    fn print(&mut self, c: char) {
+        let mut buf = [0u8; 4];
+        self.text
+            .extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+    }
+
+    fn execute(&mut self, byte: u8) {
+        if matches!(byte, b'\n' | b'\t') {
+            self.text.push(byte);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Log contents with the timestamp prefix removed from each line.
+    fn read_lines(path: &Path) -> Vec<String> {
+        std::fs::read_to_string(path)
+            .unwrap_or_default()
+            .lines()
+            .map(|line| {
+                assert!(line.starts_with('['), "missing timestamp: {line:?}");
+                line.split_once("] ")
+                    .map_or("", |(_, text)| text)
+                    .to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn output_is_written_to_the_sink() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("pane.log");
+        let mut log = PaneLog::open(PaneLogOptions::new(&path)).expect("open");
+        log.write(b"\x1b[31mred\x1b[0m\r\n");
+        log.write(b"second ");
+        log.write(b"half\n");
+
+        assert!(log.is_active());
+        assert_eq!(read_lines(&path), vec!["\x1b[31mred\x1b[0m", "second half"]);
+    }
+
+    #[test]
+    fn strip_ansi_handles_sequences_split_across_writes() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("pane.log");
+        let mut log = PaneLog::open(PaneLogOptions {
+            strip_ansi: true,
+            ..PaneLogOptions::new(&path)
+        })
+        .expect("open");
+        log.write(b"\x1b[3");
+        log.write(b"1mred\x1b]0;title\x07\r\n");
+
+        assert_eq!(read_lines(&path), vec!["red"]);
+    }
+
+    #[test]
+    fn rotation_triggers_at_the_cap() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("pane.log");
+        let rotated = dir.path().join("pane.log.1");
+        let mut log = PaneLog::open(PaneLogOptions {
+            max_bytes: Some(80),
+            ..PaneLogOptions::new(&path)
+        })
+        .expect("open");
+
+        // Each line is a 26-byte timestamp plus the text
+        log.write(b"first line\n");
+        log.write(b"second line\n");
+        assert!(!rotated.exists());
+
+        log.write(b"third line\n");
+        assert_eq!(read_lines(&rotated), vec!["first line", "second line"]);
+        assert_eq!(read_lines(&path), vec!["third line"]);
+    }
+}
@@ -0,0 +1,120 @@
+//! Piping a pane's output to an external command.
+//!
+//! A `PanePipe` is the `pipe-pane` counterpart to `PaneLog`: the pane's raw PTY
+//! output is written to the stdin of a spawned command as it arrives. When the
+//! command exits or stops reading, piping stops and the failure is logged; the
+//! pane itself carries on.
+
+use std::io::{self, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+/// A running command receiving one pane's output.
+pub struct PanePipe {
+    /// Program name, for log messages.
+    program: String,
+    child: Option<Child>,
+    /// `None` once the command has gone away.
+    stdin: Option<ChildStdin>,
+}
+
+impl PanePipe {
+    /// Spawn `command` with a pipe to its stdin. Its stdout and stderr are
+    /// discarded so it can't scribble over the UI.
+    pub fn spawn(mut command: Command) -> io::Result<Self> {
+        let program = command.get_program().to_string_lossy().into_owned();
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take();
+        Ok(Self {
+            program,
+            child: Some(child),
+            stdin,
+        })
+    }
+
+    /// Whether output is still being piped (it stops once the command dies).
+    pub fn is_active(&self) -> bool {
+        self.stdin.is_some()
+    }
+
+    /// Write a chunk of PTY output to the command. If the command has exited
+    /// or the write fails, piping stops and the reason is logged.
+    pub fn write(&mut self, data: &[u8]) {
+        let Some(stdin) = self.stdin.as_mut() else {
+            return;
+        };
+        if let Some(Ok(Some(status))) = self.child.as_mut().map(Child::try_wait) {
+            tracing::warn!(
+                "Pane pipe command {} exited ({}), no longer piping output",
+                self.program,
+                status
+            );
+            self.stdin = None;
+            return;
+        }
+        if let Err(e) = stdin.write_all(data).and_then(|()| stdin.flush()) {
+            tracing::warn!(
+                "Pane pipe command {} failed, no longer piping output: {}",
+                self.program,
+                e
+            );
+            self.stdin = None;
+        }
+    }
+}
+
+impl Drop for PanePipe {
+    /// Close the command's stdin so it sees EOF, and reap it off-thread so
+    /// stopping a pipe never waits on a slow command.
+    fn drop(&mut self) {
+        self.stdin = None;
+        if let Some(mut child) = self.child.take() {
+            if !matches!(child.try_wait(), Ok(Some(_))) {
+                std::thread::spawn(move || child.wait());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `sh -c 'cat > path'`, which captures its stdin in a file.
+    fn capture_to(path: &std::path::Path) -> Command {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg("cat > \"$1\"").arg("sh").arg(path);
+        command
+    }
+
+    #[test]
+    fn output_is_written_to_the_command_stdin() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("captured");
+        let mut pipe = PanePipe::spawn(capture_to(&path)).expect("spawn");
+        pipe.write(b"\x1b[1mbold\x1b[0m\r\n");
+        pipe.write(b"more");
+        assert!(pipe.is_active());
+
+        let mut child = pipe.child.take().expect("child");
+        drop(pipe);
+        child.wait().expect("wait");
+        assert_eq!(
+            std::fs::read(&path).expect("read"),
+            b"\x1b[1mbold\x1b[0m\r\nmore"
+        );
+    }
+
+    #[test]
+    fn piping_stops_when_the_command_dies() {
+        let mut pipe = PanePipe::spawn(Command::new("true")).expect("spawn");
+        if let Some(child) = pipe.child.as_mut() {
+            child.wait().expect("wait");
+        }
+        pipe.write(b"nobody is listening");
+        assert!(!pipe.is_active());
+    }
+}
@@ -0,0 +1,473 @@
+//! Pure entry points to dmux's escape-sequence parsers.
+//!
+//! Each function takes raw bytes, does no I/O, touches no global state and
+//! returns a structured result, so fuzz targets (see `fuzz/`) can drive them
+//! directly. None of them may panic, whatever the input.
+
+use crate::mux::character::{CharacterStyles, PromptMark};
+use crate::mux::colors::{
+    is_osc_reply_terminated, parse_builtin_osc_color_response, parse_decrqss_cursor_response,
+    CursorStyle, C1_OSC, MAX_OSC_REPLY_LEN,
+};
+use crate::mux::terminal::{apply_sgr_params, parse_osc133_mark, parse_osc_color};
+
+/// Most parameters vte keeps for one sequence; the rest are dropped the same way here.
+const MAX_PARAMS: usize = 32;
+
+/// A color reply from the outer terminal, as read by `parse_any_osc_color`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OscColorResponse {
+    /// OSC code the reply answers (10 fg, 11 bg, 12 cursor, 4 palette), if it named one
+    pub code: Option<u16>,
+    pub rgb: (u8, u8, u8),
+    /// Alpha from an `rgba:` reply
+    pub alpha: Option<u8>,
+}
+
+/// Any OSC color reply dmux knows how to read.
+///
+/// Accepts `ESC ]` or the 8-bit C1 `0x9D` introducer (or none), terminated by
+/// ST, BEL or C1 ST, around a spec in any of these forms: `rgb:`, `rgba:`,
+/// `rgbi:`, the CIE and TekHVC spaces, `#hex`, or bare hex digits. Bytes
+/// before the introducer are skipped.
+pub fn parse_any_osc_color(response: &[u8]) -> Option<OscColorResponse> {
+    let start = response
+        .windows(2)
+        .position(|window| window == b"\x1b]")
+        .map(|pos| pos + 2)
+        .or_else(|| response.iter().position(|&b| b == 0x9d).map(|pos| pos + 1))
+        .unwrap_or(0);
+    let body = &response[start..];
+    let end = body
+        .iter()
+        .position(|&b| matches!(b, 0x07 | 0x1b | 0x9c))
+        .unwrap_or(body.len());
+    // Tolerate whitespace (e.g. a CR/LF) after an unterminated reply
+    let body = std::str::from_utf8(&body[..end]).ok()?.trim_end();
+
+    // `code ; [index ;] spec`; a bare spec has no code
+    let (code, spec) = match body.split_once(';') {
+        Some((code, rest)) => (Some(code.parse().ok()?), rest.rsplit(';').next()?),
+        None => (None, body),
+    };
+
+    let (rgb, alpha) = if let Some(rest) = spec.strip_prefix("rgba:") {
+        let mut channels = rest.split('/');
+        let (r, g, b, a) = match (
+            channels.next(),
+            channels.next(),
+            channels.next(),
+            channels.next(),
+            channels.next(),
+        ) {
+            (Some(r), Some(g), Some(b), Some(a), None) => (r, g, b, a),
+            _ => return None,
+        };
+        let rgb = parse_osc_color(&format!("rgb:{}/{}/{}", r, g, b))?;
+        // Scaled exactly like the color channels
+        let (alpha, _, _) = parse_osc_color(&format!("rgb:{}/{}/{}", a, a, a))?;
+        (rgb, Some(alpha))
+    } else if !spec.is_empty()
+        && spec.len() % 3 == 0
+        && spec.len() <= 12
+        && spec.bytes().all(|b| b.is_ascii_hexdigit())
+    {
+        (parse_osc_color(&format!("#{}", spec))?, None)
+    } else {
+        (parse_osc_color(spec)?, None)
+    };
+
+    Some(OscColorResponse { code, rgb, alpha })
+}
+
+/// Progress of an `OscResponseAccumulator` after a `push`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OscParseState {
+    /// No terminator yet; push more bytes
+    Incomplete,
+    Complete(OscColorResponse),
+    /// The reply was terminated but unreadable, or outgrew `MAX_OSC_REPLY_LEN`
+    Failed,
+}
+
+/// Assembles one OSC color reply from chunks of any size, for readers that
+/// get the outer terminal's bytes piecemeal instead of from `read_osc_reply`.
+///
+/// Bytes before the `ESC ]` or C1 `0x9D` introducer are dropped, so stray
+/// input or a bracketed paste marker ahead of the reply is harmless. The
+/// accumulator resets after every `Complete` or `Failed`; anything after the
+/// terminator in the same chunk is discarded.
+#[derive(Debug, Default)]
+pub struct OscResponseAccumulator {
+    buf: Vec<u8>,
+}
+
+impl OscResponseAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) -> OscParseState {
+        for &byte in bytes {
+            match (self.buf.as_slice(), byte) {
+                ([], 0x1b | C1_OSC) => {}
+                // Noise before the introducer
+                ([], _) => continue,
+                ([0x1b], b']') => {}
+                // A lone ESC that didn't start an OSC (e.g. `ESC [200~`)
+                ([0x1b], _) => {
+                    self.buf.clear();
+                    if !matches!(byte, 0x1b | C1_OSC) {
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            self.buf.push(byte);
+
+            if self.buf.len() > MAX_OSC_REPLY_LEN {
+                self.buf.clear();
+                return OscParseState::Failed;
+            }
+            if self.buf.len() > 2 && is_osc_reply_terminated(&self.buf) {
+                let state = parse_any_osc_color(&self.buf)
+                    .map_or(OscParseState::Failed, OscParseState::Complete);
+                self.buf.clear();
+                return state;
+            }
+        }
+        OscParseState::Incomplete
+    }
+}
+
+/// A complete OSC 10/11/12 reply from the outer terminal, e.g.
+/// `ESC ] 11 ; rgb:1e1e/1e1e/2e2e ESC \`. Registered parse hooks are not consulted.
+pub fn osc_color_reply(bytes: &[u8]) -> Option<(u8, u8, u8)> {
+    parse_builtin_osc_color_response(bytes)
+}
+
+/// The color spec of an OSC color *set* from a pane, e.g. `#ff0000` or
+/// `CIELab:50/10/10`.
+pub fn osc_color_spec(bytes: &[u8]) -> Option<(u8, u8, u8)> {
+    parse_osc_color(std::str::from_utf8(bytes).ok()?)
+}
+
+/// A DECRQSS reply for the cursor style, e.g. `ESC P 1 $ r 2 SP q ESC \`.
+pub fn decrqss_cursor_reply(bytes: &[u8]) -> Option<CursorStyle> {
+    parse_decrqss_cursor_response(bytes)
+}
+
+/// The payload of an OSC 133 mark after `133;`, e.g. `D;0`.
+pub fn osc133_mark(bytes: &[u8]) -> Option<PromptMark> {
+    let params: Vec<&[u8]> = bytes.split(|&b| b == b';').take(MAX_PARAMS).collect();
+    parse_osc133_mark(&params)
+}
+
+/// The parameters of an SGR sequence (between `CSI` and `m`), e.g.
+/// `1;38:2:255:0:0`, applied to the default style.
+///
+/// Parameters are `;`-separated and subparameters `:`-separated. Values
+/// saturate at `u16::MAX` and bytes other than digits are ignored, as in vte.
+pub fn sgr(bytes: &[u8]) -> CharacterStyles {
+    let params: Vec<Vec<u16>> = bytes
+        .split(|&b| b == b';')
+        .take(MAX_PARAMS)
+        .map(|param| param.split(|&b| b == b':').map(parse_param).collect())
+        .collect();
+    let params: Vec<&[u16]> = params.iter().map(Vec::as_slice).collect();
+    apply_sgr_params(CharacterStyles::default(), &params)
+}
+
+fn parse_param(bytes: &[u8]) -> u16 {
+    bytes
+        .iter()
+        .filter(|b| b.is_ascii_digit())
+        .fold(0u16, |value, &digit| {
+            value
+                .saturating_mul(10)
+                .saturating_add(u16::from(digit - b'0'))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::style::{Color, Modifier};
+
+    /// Deterministic xorshift generator, so failures are reproducible.
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, bound: usize) -> usize {
+            (self.next() % bound as u64) as usize
+        }
+    }
+
+    /// Every prefix of `input`, including the empty one.
+    fn truncations(input: &[u8]) -> impl Iterator<Item = &[u8]> {
+        (0..=input.len()).map(move |end| &input[..end])
+    }
+
+    #[test]
+    fn truncated_inputs_never_panic() {
+        for input in truncations(b"\x1b]11;rgb:1e1e/1e1e/2e2e\x1b\\") {
+            let _ = osc_color_reply(input);
+        }
+        for input in truncations(b"\x1b]11;#1e1e2e\x07") {
+            let _ = osc_color_reply(input);
+        }
+        for input in truncations("CIELab:50/10/10".as_bytes()) {
+            let _ = osc_color_spec(input);
+        }
+        for input in truncations(b"\x1bP1$r2 q\x1b\\") {
+            let _ = decrqss_cursor_reply(input);
+        }
+        for input in truncations(b"D;127") {
+            let _ = osc133_mark(input);
+        }
+        for input in truncations(b"1;38;2;255;0;0;48:2:0:1:2:3") {
+            let _ = sgr(input);
+        }
+    }
+
+    #[test]
+    fn oversized_inputs_never_panic() {
+        let digits = "9".repeat(100_000);
+        assert_eq!(
+            osc_color_reply(format!("\x1b]11;rgb:{d}/{d}/{d}\x1b\\", d = digits).as_bytes()),
+            None
+        );
+        assert_eq!(
+            osc_color_spec(format!("rgbi:{d}/{d}/{d}", d = digits).as_bytes()),
+            {
+                // Intensities clamp to 1.0
+                osc_color_spec(b"rgbi:1/1/1")
+            }
+        );
+        assert_eq!(
+            decrqss_cursor_reply(format!("\x1bP1$r{} q\x1b\\", digits).as_bytes()),
+            None
+        );
+        assert_eq!(osc133_mark(format!("D;{}", digits).as_bytes()), {
+            Some(PromptMark::CommandFinished { exit_code: None })
+        });
+        let _ = sgr(";".repeat(100_000).as_bytes());
+        let _ = sgr(":".repeat(100_000).as_bytes());
+        let _ = sgr(digits.as_bytes());
+    }
+
+    #[test]
+    fn parse_any_osc_color_reads_every_format() {
+        let color = |code, rgb| {
+            Some(OscColorResponse {
+                code,
+                rgb,
+                alpha: None,
+            })
+        };
+        assert_eq!(
+            parse_any_osc_color(b"\x1b]11;rgb:1e1e/1e1e/2e2e\x1b\\"),
+            color(Some(11), (0x1e, 0x1e, 0x2e))
+        );
+        assert_eq!(
+            parse_any_osc_color(b"\x1b]10;rgbi:1/1/1\x07"),
+            color(Some(10), (255, 255, 255))
+        );
+        assert_eq!(
+            parse_any_osc_color(b"\x1b]11;#1e1e2e\x1b\\"),
+            color(Some(11), (0x1e, 0x1e, 0x2e))
+        );
+        assert_eq!(
+            parse_any_osc_color(b"\x1b]12;ff8000\x1b\\"),
+            color(Some(12), (0xff, 0x80, 0x00))
+        );
+        assert_eq!(
+            parse_any_osc_color(b"\x9d11;rgb:00/80/ff\x9c"),
+            color(Some(11), (0x00, 0x80, 0xff))
+        );
+        assert_eq!(
+            parse_any_osc_color(b"\x1b]4;1;rgb:cd/00/00\x1b\\"),
+            color(Some(4), (0xcd, 0x00, 0x00))
+        );
+        assert_eq!(
+            parse_any_osc_color(b"#fff"),
+            color(None, (0xf0, 0xf0, 0xf0))
+        );
+        assert_eq!(
+            parse_any_osc_color(b"\x1b]11;rgba:1e1e/1e1e/2e2e/8080\x1b\\"),
+            Some(OscColorResponse {
+                code: Some(11),
+                rgb: (0x1e, 0x1e, 0x2e),
+                alpha: Some(0x80),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_any_osc_color_tolerates_trailing_line_endings() {
+        let expected = OscColorResponse {
+            code: Some(11),
+            rgb: (0x35, 0x37, 0x31),
+            alpha: None,
+        };
+        for reply in [
+            &b"\x1b]11;rgb:3535/3737/3131\x1b\\\r\n"[..],
+            b"\x1b]11;rgb:3535/3737/3131\x07\n",
+            b"\x1b]11;rgb:3535/3737/3131\r\n",
+        ] {
+            assert_eq!(parse_any_osc_color(reply), Some(expected), "{:?}", reply);
+        }
+
+        let mut acc = OscResponseAccumulator::new();
+        assert_eq!(
+            acc.push(b"\x1b]11;rgb:3535/3737/3131\x1b\\\r\n"),
+            OscParseState::Complete(expected)
+        );
+    }
+
+    #[test]
+    fn parse_any_osc_color_rejects_malformed_replies() {
+        for reply in [
+            &b""[..],
+            b"\x1b]",
+            b"\x1b]11;\x1b\\",
+            b"\x1b]11;?\x1b\\",
+            b"\x1b]xx;rgb:00/00/00\x1b\\",
+            b"\x1b]11;rgba:00/00/00\x1b\\",
+            b"\x1b]11;rgba:00/00/00/00/00\x1b\\",
+            b"\x1b]11;1234\x1b\\",
+            b"\x1b]11;rgb:zz/00/00\x1b\\",
+        ] {
+            assert_eq!(parse_any_osc_color(reply), None, "{:?}", reply);
+        }
+    }
+
+    #[test]
+    fn parse_any_osc_color_survives_random_bytes() {
+        const ALPHABET: &[u8] =
+            b"\x1b\x07\x9c\x9d];:/#.-+eE0123456789abcdefABCDEFrgbiaCIELabuvYXZTekHVC\xc3\xa9?";
+        let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..20_000 {
+            let len = rng.below(48);
+            let bytes: Vec<u8> = (0..len)
+                .map(|_| {
+                    if rng.below(4) == 0 {
+                        rng.next() as u8
+                    } else {
+                        ALPHABET[rng.below(ALPHABET.len())]
+                    }
+                })
+                .collect();
+            if let Some(reply) = parse_any_osc_color(&bytes) {
+                assert!(reply.alpha.is_none() || bytes.windows(5).any(|w| w == b"rgba:"));
+            }
+        }
+    }
+
+    #[test]
+    fn parse_any_osc_color_survives_mutated_replies() {
+        let seeds: &[&[u8]] = &[
+            b"\x1b]11;rgb:1e1e/1e1e/2e2e\x1b\\",
+            b"\x1b]11;rgba:1e1e/1e1e/2e2e/ffff\x1b\\",
+            b"\x9d10;rgbi:0.5/0.25/1\x9c",
+            b"\x1b]12;#1e1e2e\x07",
+            b"\x1b]11;CIELab:50/10/10\x1b\\",
+        ];
+        let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+        for _ in 0..20_000 {
+            let mut bytes = seeds[rng.below(seeds.len())].to_vec();
+            for _ in 0..=rng.below(4) {
+                let pos = rng.below(bytes.len() + 1);
+                match rng.below(3) {
+                    0 if pos < bytes.len() => bytes[pos] = rng.next() as u8,
+                    1 if pos < bytes.len() => {
+                        bytes.remove(pos);
+                    }
+                    _ => bytes.insert(pos, rng.next() as u8),
+                }
+            }
+            let _ = parse_any_osc_color(&bytes);
+        }
+    }
+
+    #[test]
+    fn osc_color_spec_rejects_non_ascii_hex() {
+        // Three bytes, but not three characters: slicing by byte would panic
+        assert_eq!(osc_color_spec("#é1".as_bytes()), None);
+        assert_eq!(osc_color_spec(b"#f00"), Some((0xf0, 0, 0)));
+    }
+
+    #[test]
+    fn sgr_handles_truncated_extended_colors() {
+        // 38;2 with its channels missing sets no color; the 2 is then read as dim
+        let styles = sgr(b"1;38;2");
+        assert_eq!(styles.foreground, None);
+        assert_eq!(styles.modifiers, Modifier::BOLD | Modifier::DIM);
+        assert_eq!(sgr(b"38:5"), CharacterStyles::default());
+        assert_eq!(sgr(b"38:5:196").foreground, Some(Color::Indexed(196)));
+        // Values past u8 wrap the way the terminal's own parser does
+        assert_eq!(sgr(b"38;5;65535").foreground, Some(Color::Indexed(255)));
+        assert_eq!(sgr(b""), CharacterStyles::default());
+    }
+
+    fn push_bytewise(acc: &mut OscResponseAccumulator, bytes: &[u8]) -> Vec<OscParseState> {
+        bytes.iter().map(|&b| acc.push(&[b])).collect()
+    }
+
+    #[test]
+    fn accumulator_completes_on_last_byte() {
+        let bg = OscColorResponse {
+            code: Some(11),
+            rgb: (0x35, 0x37, 0x31),
+            alpha: None,
+        };
+        for reply in [
+            &b"\x1b]11;rgb:3535/3737/3131\x1b\\"[..],
+            b"\x1b]11;rgb:3535/3737/3131\x07",
+            b"\x9d11;rgb:3535/3737/3131\x9c",
+        ] {
+            let mut acc = OscResponseAccumulator::new();
+            let states = push_bytewise(&mut acc, reply);
+            let (last, rest) = states.split_last().expect("non-empty reply");
+            assert!(rest.iter().all(|s| *s == OscParseState::Incomplete));
+            assert_eq!(*last, OscParseState::Complete(bg));
+        }
+    }
+
+    #[test]
+    fn accumulator_drops_leading_noise_and_resets() {
+        let mut acc = OscResponseAccumulator::new();
+        let states = push_bytewise(&mut acc, b"junk\x1b[200~\x1b]10;#ffffff\x07");
+        assert_eq!(
+            states.last(),
+            Some(&OscParseState::Complete(OscColorResponse {
+                code: Some(10),
+                rgb: (255, 255, 255),
+                alpha: None,
+            }))
+        );
+
+        // Reusable after a result, and an unreadable reply fails
+        assert_eq!(acc.push(b"\x1b]11;bogus\x07"), OscParseState::Failed);
+        assert!(matches!(
+            acc.push(b"\x1b]11;rgb:00/00/00\x1b\\"),
+            OscParseState::Complete(_)
+        ));
+    }
+
+    #[test]
+    fn accumulator_caps_length() {
+        let mut acc = OscResponseAccumulator::new();
+        assert_eq!(acc.push(b"\x1b]11;"), OscParseState::Incomplete);
+        let filler = vec![b'0'; MAX_OSC_REPLY_LEN];
+        assert_eq!(acc.push(&filler), OscParseState::Failed);
+        assert_eq!(acc.push(b"\x07"), OscParseState::Incomplete);
+    }
+}
@@ -1,92 +1,166 @@
 use anyhow::Result;
 use crossterm::{
-    cursor::SetCursorStyle,
     event::{
-        DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
-        Event, EventStream, KeyCode, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
-        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+        EnableBracketedPaste, EnableMouseCapture, Event, EventStream, KeyCode, KeyEvent,
+        KeyEventKind, KeyModifiers, KeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
     },
     execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    terminal::enable_raw_mode,
 };
 use futures::StreamExt;
-use ratatui::{backend::CrosstermBackend, Terminal};
+use ratatui::Terminal;
 use std::path::PathBuf;
 use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio::time::MissedTickBehavior;
 
-use crate::mux::colors::{query_outer_terminal_colors, spawn_theme_change_listener};
+use crate::mux::capabilities::probe_capabilities;
+use crate::mux::chords::ChordStep;
+use crate::mux::color_adjust::{ColorAdjust, COARSE_STEP, FINE_STEP};
+use crate::mux::colors::{
+    apply_inherited_cursor_style, color_sources, colors_initialized, commit_colors_with_source,
+    merge_outer_palette, notify_osc_observers, osc_reply_code, pasted_osc_reply,
+    query_outer_terminal_theme, set_fallback_background, set_min_color_query_interval,
+    set_reverse_video_override, spawn_theme_change_listener, ColorSource, DeferredColorQuery,
+    TerminalColors,
+};
 use crate::mux::commands::MuxCommand;
+use crate::mux::embed::Mux;
 use crate::mux::events::MuxEvent;
+use crate::mux::geometry::spawn_pixel_size_listener;
+use crate::mux::keys::key_to_terminal_input;
 use crate::mux::layout::{ClosedTabInfo, PaneContent, PaneExitOutcome, SandboxId, TabId};
 use crate::mux::onboard::{
     pull_image_with_progress, run_onboard_check, OnboardEvent, OnboardPhase, OnboardState,
 };
+use crate::mux::output::terminal_output;
+use crate::mux::pane_log::PaneLogOptions;
+use crate::mux::screen::{enter_screen, install_panic_hook, restore_terminal, ScreenMode};
+use crate::mux::sgr_diff::SgrDiffBackend;
 use crate::mux::state::{FocusArea, MuxApp};
-use crate::mux::terminal::{
-    connect_to_sandbox, create_terminal_manager, invalidate_all_render_caches,
-    request_list_sandboxes, send_signal_to_children,
-};
-use crate::mux::ui::ui;
+use crate::mux::terminal::{connect_to_sandbox, create_terminal_manager};
+use crate::mux::theme_import::load_theme_file;
+use crate::settings::{OnLastPaneExit, Settings};
 use crate::sync_files::{detect_sync_files, upload_sync_files_with_list};
 
 /// Run the multiplexer TUI.
 ///
 /// If `workspace_path` is provided, sandboxes created during the session will upload
 /// that directory (defaulting to the current working directory).
-pub async fn run_mux_tui(base_url: String, workspace_path: Option<PathBuf>) -> Result<()> {
+/// `startup_commands` run after those from settings (see `MuxApp::run_startup_commands`).
+pub async fn run_mux_tui(
+    base_url: String,
+    workspace_path: Option<PathBuf>,
+    startup_commands: Vec<String>,
+) -> Result<()> {
+    // Read once so every startup choice comes from the same file contents
+    let settings = Settings::load();
+
     // Query outer terminal colors BEFORE entering alternate screen
     // This allows us to inherit the host terminal's theme
-    let _outer_colors = query_outer_terminal_colors();
+    set_fallback_background(settings.fallback_background);
+    set_min_color_query_interval(Duration::from_millis(settings.min_color_query_interval_ms));
+    let started = std::time::Instant::now();
+    let outer_colors = query_outer_terminal_theme();
+
+    // Probe (and cache) everything else dmux needs to know about the terminal
+    probe_capabilities();
+    set_reverse_video_override(settings.reverse_video);
 
+    let screen_mode = ScreenMode::from_preserve_scrollback(settings.preserve_scrollback);
+    let (_, rows) = crossterm::terminal::size().unwrap_or((80, 24));
     let mut stdout = std::io::stdout();
+    install_panic_hook();
+    enter_screen(&mut stdout, screen_mode, rows)?;
     execute!(
         stdout,
-        EnterAlternateScreen,
         EnableMouseCapture,
         EnableBracketedPaste,
         PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::all())
     )?;
     enable_raw_mode()?;
 
-    let backend = CrosstermBackend::new(stdout);
+    let output = terminal_output(settings.render_backend, stdout);
+    let backend = SgrDiffBackend::new(output);
     let mut terminal = Terminal::new(backend)?;
 
-    let result = run_main_loop(&mut terminal, base_url, workspace_path).await;
+    // Terminals still starting up may not have answered; ask again shortly
+    let deferred_color_query = DeferredColorQuery::schedule(
+        started,
+        Duration::from_millis(settings.color_requery_delay_ms),
+        &outer_colors,
+    );
 
-    // Cleanup must happen in reverse order, and PopKeyboardEnhancementFlags
-    // must be sent BEFORE LeaveAlternateScreen to properly restore terminal state.
-    // Print errors but continue cleanup to ensure all steps run.
-    if let Err(e) = disable_raw_mode() {
-        eprintln!("Warning: failed to disable raw mode: {e}");
-    }
-    if let Err(e) = execute!(
-        terminal.backend_mut(),
-        PopKeyboardEnhancementFlags,
-        DisableBracketedPaste,
-        DisableMouseCapture,
-        LeaveAlternateScreen
-    ) {
+    let result = run_main_loop(
+        &mut terminal,
+        settings,
+        base_url,
+        workspace_path,
+        startup_commands,
+        deferred_color_query,
+    )
+    .await;
+
+    // Shared with the panic hook; a no-op if a panic already restored the terminal.
+    if let Err(e) = restore_terminal(terminal.backend_mut()) {
         eprintln!("Warning: failed to restore terminal state: {e}");
     }
-    if let Err(e) = terminal.show_cursor() {
-        eprintln!("Warning: failed to show cursor: {e}");
-    }
 
     result
 }
 
 async fn run_main_loop<B: ratatui::backend::Backend + std::io::Write>(
     terminal: &mut Terminal<B>,
+    settings: Settings,
     base_url: String,
     workspace_path: Option<PathBuf>,
+    startup_commands: Vec<String>,
+    deferred_color_query: DeferredColorQuery,
 ) -> Result<()> {
     let workspace = workspace_path
         .unwrap_or_else(|| std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")));
     let (event_tx, event_rx) = mpsc::unbounded_channel();
 
-    let mut app = MuxApp::new(base_url.clone(), event_tx.clone(), workspace.clone());
+    let mut app = MuxApp::with_settings(
+        base_url.clone(),
+        event_tx.clone(),
+        workspace.clone(),
+        settings,
+    );
+    app.startup_commands.extend(startup_commands);
+    app.deferred_color_query = deferred_color_query;
+
+    // Match our cursor to the outer terminal's (we're in the alternate screen now)
+    if app.settings.inherit_cursor_style {
+        let _ = apply_inherited_cursor_style();
+    }
+
+    // If the terminal didn't answer, fall back to the theme pinned in settings,
+    // which beats the rough guess from $COLORFGBG
+    let (fg_source, bg_source) = color_sources();
+    let guessed = [fg_source, bg_source].contains(&ColorSource::ColorFgBg);
+    if !colors_initialized() || guessed {
+        if let Some(theme) = &app.settings.theme {
+            commit_colors_with_source(
+                TerminalColors::from_theme_settings(theme),
+                ColorSource::Cached,
+            );
+        }
+    }
+
+    // A theme file is an explicit choice, so it wins over queried colors
+    if let Some(path) = &app.settings.theme_file {
+        match load_theme_file(path) {
+            Ok(theme) => {
+                commit_colors_with_source(theme.colors, ColorSource::ThemeFile);
+                merge_outer_palette(&theme.palette);
+            }
+            Err(e) => {
+                tracing::warn!("{e}");
+                app.set_status(e.to_string());
+            }
+        }
+    }
 
     // Create terminal manager
     let terminal_manager = create_terminal_manager(base_url.clone(), event_tx.clone());
@@ -125,8 +199,13 @@ async fn run_main_loop<B: ratatui::backend::Backend + std::io::Write>(
 
     // Spawn theme change signal listener (SIGUSR1 on Unix)
     let (theme_tx, mut theme_rx) = mpsc::unbounded_channel();
+    #[cfg(all(target_os = "macos", feature = "macos"))]
+    crate::mux::macos_appearance::spawn_macos_appearance_listener(theme_tx.clone());
     spawn_theme_change_listener(theme_tx);
 
+    // Drop the cached pixel size whenever the window is resized (SIGWINCH on Unix)
+    spawn_pixel_size_listener();
+
     // Forward theme change events to the main event channel
     let theme_event_tx = event_tx.clone();
     tokio::spawn(async move {
@@ -158,17 +237,29 @@ async fn run_main_loop<B: ratatui::backend::Backend + std::io::Write>(
         }
     });
 
-    run_app(terminal, app, event_rx, terminal_manager).await
+    run_app(
+        terminal,
+        app,
+        event_rx,
+        terminal_manager,
+        EventStream::new(),
+    )
+    .await
 }
 
-async fn run_app<B: ratatui::backend::Backend + std::io::Write>(
+/// Run the event loop until the user quits, reading input events from `reader`.
+pub(crate) async fn run_app<B, S>(
     terminal: &mut Terminal<B>,
-    mut app: MuxApp<'_>,
-    mut event_rx: mpsc::UnboundedReceiver<MuxEvent>,
+    app: MuxApp<'_>,
+    event_rx: mpsc::UnboundedReceiver<MuxEvent>,
     terminal_manager: crate::mux::terminal::SharedTerminalManager,
-) -> Result<()> {
-    let mut reader = EventStream::new();
-    let mut redraw_needed = true;
+    mut reader: S,
+) -> Result<()>
+where
+    B: ratatui::backend::Backend + std::io::Write,
+    S: futures::Stream<Item = std::io::Result<Event>> + Unpin,
+{
+    let mut mux = Mux::from_parts(app, event_rx, terminal_manager);
     let mut status_tick = tokio::time::interval(Duration::from_millis(33));
     let mut render_tick = tokio::time::interval(Duration::from_millis(8));
     render_tick.set_missed_tick_behavior(MissedTickBehavior::Skip);
@@ -176,178 +267,22 @@ async fn run_app<B: ratatui::backend::Backend + std::io::Write>(
     loop {
         tokio::select! {
             _ = status_tick.tick() => {
-                let had_status = app.status_message.is_some();
-                app.clear_expired_status();
-                if had_status && app.status_message.is_none() {
-                    redraw_needed = true;
+                if mux.tick(std::time::Instant::now()) {
+                    break;
                 }
             }
-            _ = render_tick.tick(), if redraw_needed => {
-                terminal.draw(|f| ui(f, &mut app))?;
-                // Apply cursor style based on terminal's cursor blink mode
-                let cursor_style = if app.cursor_blink {
-                    SetCursorStyle::BlinkingBlock
-                } else {
-                    SetCursorStyle::SteadyBlock
-                };
-                let _ = execute!(terminal.backend_mut(), cursor_style);
-                sync_terminal_sizes(&app, &terminal_manager);
-                // Keep redrawing if we have a blinking colored cursor (we manage the blink ourselves)
-                redraw_needed = app.cursor_blink && app.cursor_color.is_some();
+            _ = render_tick.tick(), if mux.needs_redraw() => {
+                mux.draw(terminal)?;
             }
-            Some(event) = event_rx.recv() => {
-                match &event {
-                    MuxEvent::ConnectToSandbox { sandbox_id } => {
-                        app.pending_connects.push_back(sandbox_id.clone());
-                        try_consume_pending_connection(&mut app, &terminal_manager);
-                    }
-                    MuxEvent::CreateSandboxWithWorkspace {
-                        workspace_path,
-                        tab_id,
-                    } => {
-                        let event_tx = app.event_tx.clone();
-                        let base_url = app.base_url.clone();
-                        let workspace_path = workspace_path.clone();
-                        let tab_id_value =
-                            tab_id.clone().unwrap_or_else(|| TabId::new().to_string());
-                        tokio::spawn(async move {
-                            if let Err(error) = create_sandbox_with_workspace(
-                                base_url,
-                                workspace_path,
-                                Some(tab_id_value),
-                                event_tx.clone(),
-                            )
-                            .await
-                            {
-                                let _ = event_tx.send(MuxEvent::Error(format!(
-                                    "Failed to create sandbox: {}",
-                                    error
-                                )));
-                            }
-                        });
-                    }
-                    MuxEvent::ConnectActivePaneToSandbox => {
-                        // Use selected sandbox, or fall back to first item in pending queue
-                        let target = app
-                            .selected_sandbox_id_string()
-                            .or_else(|| app.pending_connects.front().cloned());
-                        if let Some(sandbox_id) = target {
-                            app.pending_connects.push_back(sandbox_id);
-                            try_consume_pending_connection(&mut app, &terminal_manager);
-                        }
-                    }
-                    MuxEvent::StatusMessage { message } if message.contains("Refreshing sandboxes") => {
-                        // Request sandbox list via WebSocket - server responds with SandboxList
-                        let manager = terminal_manager.clone();
-                        let event_tx = app.event_tx.clone();
-                        tokio::spawn(async move {
-                            if let Err(e) = request_list_sandboxes(manager).await {
-                                let _ = event_tx.send(MuxEvent::Error(format!(
-                                    "Failed to refresh sandboxes: {}",
-                                    e
-                                )));
-                            }
-                        });
-                        app.set_status(message.clone());
-                    }
-                    MuxEvent::TerminalExited { pane_id, sandbox_id } => {
-                        handle_terminal_exit_for_pane(
-                            &mut app,
-                            &terminal_manager,
-                            *pane_id,
-                            sandbox_id,
-                        );
-                    }
-                    MuxEvent::ThemeChanged { colors: _ } => {
-                        // Theme change signal received - re-query colors from outer terminal
-                        // VSCode terminal doesn't respond to OSC 10/11 while in alternate screen,
-                        // so we need to leave alt screen, query, then re-enter.
-
-                        // Leave alternate screen and disable raw mode for clean OSC query
-                        let _ = execute!(terminal.backend_mut(), LeaveAlternateScreen);
-                        let _ = disable_raw_mode();
-
-                        // Small delay to let terminal settle
-                        std::thread::sleep(std::time::Duration::from_millis(50));
-
-                        // Query colors now that we're in normal screen mode
-                        let new_colors = crate::mux::colors::query_outer_terminal_colors();
-
-                        // Re-enable raw mode and re-enter alternate screen
-                        let _ = enable_raw_mode();
-                        let _ = execute!(terminal.backend_mut(), EnterAlternateScreen);
-
-                        // Force full terminal redraw
-                        let _ = terminal.clear();
-
-                        app.set_status(format!(
-                            "Theme updated: bg={:?}",
-                            new_colors.background.map(|(r, g, b)| format!("#{:02x}{:02x}{:02x}", r, g, b))
-                        ));
-
-                        // Invalidate all render caches so terminal buffers re-render with new colors
-                        invalidate_all_render_caches(terminal_manager.clone()).await;
-
-                        // Send SIGWINCH to trigger re-render in TUI apps
-                        // Note: We don't send SIGUSR1 because most apps don't handle it
-                        // and the default action would kill them
-                        let manager_clone = terminal_manager.clone();
-                        tokio::spawn(async move {
-                            let _ = send_signal_to_children(manager_clone, libc::SIGWINCH).await;
-                        });
-                    }
-                    MuxEvent::Onboard(onboard_event) => {
-                        let event_tx_for_handler = app.event_tx.clone();
-                        handle_onboard_event(&mut app, onboard_event.clone(), &event_tx_for_handler);
-                    }
-                    MuxEvent::SendTerminalInput { pane_id, input } => {
-                        if let Ok(mut manager) = terminal_manager.try_lock() {
-                            if !manager.send_input(*pane_id, input.clone()) {
-                                tracing::warn!("Failed to send terminal input to pane {:?}", pane_id);
-                            }
-                        }
-                    }
-                    MuxEvent::ExecInSandbox { sandbox_id, command } => {
-                        // Execute command in sandbox silently via exec API
-                        let base_url = app.base_url.clone();
-                        let sandbox_id = sandbox_id.clone();
-                        let command = command.clone();
-                        tokio::spawn(async move {
-                            let client = reqwest::Client::new();
-                            let url = format!(
-                                "{}/sandboxes/{}/exec",
-                                base_url.trim_end_matches('/'),
-                                sandbox_id
-                            );
-                            let body = crate::models::ExecRequest {
-                                command,
-                                workdir: None,
-                                env: Vec::new(),
-                            };
-                            if let Err(e) = client.post(&url).json(&body).send().await {
-                                tracing::warn!("Failed to exec in sandbox: {}", e);
-                            }
-                        });
-                    }
-                    _ => {}
+            Some(event) = mux.next_event() => {
+                if mux.handle_event(event).await {
+                    break;
                 }
-                app.handle_event(event);
-                try_consume_pending_connection(&mut app, &terminal_manager);
-                redraw_needed = true;
             }
             Some(Ok(event)) = reader.next() => {
-                // Skip focus events - we don't forward them to inner ptys because:
-                // 1. Bash and most shells don't handle focus tracking and just echo ^[[I
-                // 2. Querying colors on focus is problematic (responses leak or cause flicker)
-                // Colors are queried at startup before entering alt screen.
-                if matches!(event, Event::FocusGained | Event::FocusLost) {
-                    continue;
-                }
-
-                if handle_input(&mut app, event, &terminal_manager) {
+                if mux.handle_input(event) {
                     break;
                 }
-                redraw_needed = true;
             }
         }
     }
@@ -362,7 +297,7 @@ fn fallback_terminal_size() -> (u16, u16) {
 
 /// Process all pending sandbox connections from the queue.
 /// This connects terminals for ALL user-created sandboxes, not just the selected one.
-fn try_consume_pending_connection(
+pub(crate) fn try_consume_pending_connection(
     app: &mut MuxApp<'_>,
     terminal_manager: &crate::mux::terminal::SharedTerminalManager,
 ) {
@@ -390,8 +325,6 @@ fn connect_sandbox_terminal(
     terminal_manager: &crate::mux::terminal::SharedTerminalManager,
     sandbox_id: &str,
 ) {
-    use crate::mux::layout::{PaneContent, SandboxId};
-
     // Parse the sandbox ID
     let Ok(sandbox_uuid) = uuid::Uuid::parse_str(sandbox_id) else {
         return;
@@ -405,22 +338,36 @@ fn connect_sandbox_terminal(
         .and_then(|ws| ws.active_tab())
         .and_then(|tab| tab.active_pane);
 
-    let Some(pane_id) = pane_id else {
+    if let Some(pane_id) = pane_id {
+        connect_sandbox_pane(app, terminal_manager, sandbox_id, pane_id);
+    }
+}
+
+/// Connect a pane in any tab of a sandbox's workspace to the sandbox's terminal.
+pub(crate) fn connect_sandbox_pane(
+    app: &mut MuxApp<'_>,
+    terminal_manager: &crate::mux::terminal::SharedTerminalManager,
+    sandbox_id: &str,
+    pane_id: crate::mux::layout::PaneId,
+) {
+    let Ok(sandbox_uuid) = uuid::Uuid::parse_str(sandbox_id) else {
         return;
     };
+    let sandbox_layout_id = SandboxId::from_uuid(sandbox_uuid);
 
     // Update the pane's sandbox_id in the workspace
-    if let Some(ws) = app.workspace_manager.get_workspace_mut(sandbox_layout_id) {
-        if let Some(tab) = ws.active_tab_mut() {
-            if let Some(pane) = tab.layout.find_pane_mut(pane_id) {
-                if let PaneContent::Terminal {
-                    sandbox_id: pane_sandbox,
-                    ..
-                } = &mut pane.content
-                {
-                    *pane_sandbox = Some(sandbox_id.to_string());
-                }
-            }
+    if let Some(pane) = app
+        .workspace_manager
+        .get_workspace_mut(sandbox_layout_id)
+        .and_then(|ws| ws.tab_for_pane_mut(pane_id))
+        .and_then(|tab| tab.layout.find_pane_mut(pane_id))
+    {
+        if let PaneContent::Terminal {
+            sandbox_id: pane_sandbox,
+            ..
+        } = &mut pane.content
+        {
+            *pane_sandbox = Some(sandbox_id.to_string());
         }
     }
 
@@ -434,28 +381,47 @@ fn connect_sandbox_terminal(
         return;
     }
 
-    // Get dimensions for the pane
-    let (rows, cols) = app
+    let tab = app
         .workspace_manager
         .get_workspace(sandbox_layout_id)
-        .and_then(|ws| ws.active_tab())
-        .and_then(|tab| tab.layout.find_pane(pane_id))
-        .and_then(pane_content_dimensions)
+        .and_then(|ws| ws.tab_for_pane(pane_id));
+    let pane = tab.and_then(|tab| tab.layout.find_pane(pane_id));
+
+    // Get dimensions for the pane
+    let (rows, cols) = pane
+        .and_then(crate::mux::layout::Pane::reported_size)
         .unwrap_or_else(fallback_terminal_size);
 
+    let output_log = pane
+        .and_then(|pane| pane.log_output.clone())
+        .map(|path| PaneLogOptions {
+            strip_ansi: app.settings.pane_log.strip_ansi,
+            max_bytes: app.settings.pane_log.max_bytes,
+            ..PaneLogOptions::new(path)
+        });
+
+    let spawn = pane.map(|pane| pane.spawn.clone()).unwrap_or_default();
+    let detach_others = app.settings.detach_other_clients;
+
     // Spawn terminal connection
     let manager = terminal_manager.clone();
     let event_tx = app.event_tx.clone();
     let sandbox_id_owned = sandbox_id.to_string();
-    let tab_id = app
-        .workspace_manager
-        .get_workspace(sandbox_layout_id)
-        .and_then(|ws| ws.active_tab())
-        .map(|tab| tab.id);
+    let tab_id = tab.map(|tab| tab.id);
 
     tokio::spawn(async move {
-        if let Err(e) =
-            connect_to_sandbox(manager, pane_id, sandbox_id_owned, tab_id, cols, rows).await
+        if let Err(e) = connect_to_sandbox(
+            manager,
+            pane_id,
+            sandbox_id_owned,
+            tab_id,
+            cols,
+            rows,
+            spawn,
+            output_log,
+            detach_others,
+        )
+        .await
         {
             let _ = event_tx.send(MuxEvent::Error(format!(
                 "Failed to connect to sandbox: {}",
@@ -465,19 +431,7 @@ fn connect_sandbox_terminal(
     });
 }
 
-fn pane_content_dimensions(pane: &crate::mux::layout::Pane) -> Option<(u16, u16)> {
-    let area = pane.area?;
-    let cols = area.width.saturating_sub(2);
-    let rows = area.height.saturating_sub(2);
-
-    if cols == 0 || rows == 0 {
-        return None;
-    }
-
-    Some((rows, cols))
-}
-
-fn sync_terminal_sizes(
+pub(crate) fn sync_terminal_sizes(
     app: &MuxApp<'_>,
     terminal_manager: &crate::mux::terminal::SharedTerminalManager,
 ) {
@@ -490,7 +444,7 @@ fn sync_terminal_sizes(
         if !matches!(pane.content, PaneContent::Terminal { .. }) {
             continue;
         }
-        if let Some((rows, cols)) = pane_content_dimensions(pane) {
+        if let Some((rows, cols)) = pane.reported_size() {
             targets.push((pane.id, rows, cols));
         }
     }
@@ -506,15 +460,25 @@ fn sync_terminal_sizes(
     }
 }
 
-fn handle_terminal_exit_for_pane(
+/// Handle a terminal exit for a pane. Returns true if the app should quit.
+pub(crate) fn handle_terminal_exit_for_pane(
     app: &mut MuxApp<'_>,
     terminal_manager: &crate::mux::terminal::SharedTerminalManager,
     pane_id: crate::mux::layout::PaneId,
     sandbox_id: &str,
-) {
+) -> bool {
+    if app.settings.remain_on_exit && app.workspace_manager.mark_pane_exited(pane_id) {
+        app.set_status("Terminal exited; run Respawn Pane to restart it");
+        return false;
+    }
+
     let mut pane_ids_to_cleanup = vec![pane_id];
 
-    match app.workspace_manager.handle_pane_exit(pane_id) {
+    let outcome = app.workspace_manager.handle_pane_exit(pane_id);
+    let last_pane_action = app.last_pane_exit_action(outcome.as_ref());
+    let outcome_sandbox = outcome.as_ref().map(PaneExitOutcome::sandbox_id);
+
+    match outcome {
         Some(PaneExitOutcome::TabClosed(info)) => {
             let ClosedTabInfo {
                 sandbox_id: info_sandbox,
@@ -563,10 +527,49 @@ fn handle_terminal_exit_for_pane(
             guard.remove_pane_state(id);
         }
     }
+
+    match (last_pane_action, outcome_sandbox) {
+        (Some(OnLastPaneExit::Quit), _) => true,
+        (Some(OnLastPaneExit::Respawn), Some(sandbox)) => {
+            if app.respawn_last_pane(sandbox) {
+                app.set_status("Last terminal exited; respawned shell");
+                try_consume_pending_connection(app, terminal_manager);
+            }
+            false
+        }
+        _ => false,
+    }
+}
+
+/// Drop a pane's old session and output, then reconnect it with its recorded command.
+/// The pane keeps its slot; the bumped generation gives it a fresh session.
+pub(crate) fn respawn_pane_terminal(
+    app: &mut MuxApp<'_>,
+    terminal_manager: &crate::mux::terminal::SharedTerminalManager,
+    pane_id: crate::mux::layout::PaneId,
+) {
+    let sandbox_id = app
+        .workspace_manager
+        .find_pane_mut(pane_id)
+        .and_then(|pane| match &pane.content {
+            PaneContent::Terminal { sandbox_id, .. } => sandbox_id.clone(),
+            _ => None,
+        })
+        .or_else(|| app.selected_sandbox_id_string());
+    let Some(sandbox_id) = sandbox_id else {
+        return;
+    };
+
+    if let Ok(mut guard) = terminal_manager.try_lock() {
+        guard.remove_pane_state(pane_id);
+    }
+    app.last_terminal_views.remove(&pane_id);
+    app.pending_connects.push_back(sandbox_id);
+    try_consume_pending_connection(app, terminal_manager);
 }
 
 /// Handle input events. Returns true if the app should quit.
-fn handle_input(
+pub(crate) fn handle_input(
     app: &mut MuxApp<'_>,
     event: Event,
     terminal_manager: &crate::mux::terminal::SharedTerminalManager,
@@ -596,6 +599,23 @@ fn handle_input(
                 }
             }
 
+            // Handle color adjustment overlay
+            if app.color_adjust.is_some() {
+                match key.code {
+                    KeyCode::Enter => app.close_color_adjust(true, false),
+                    KeyCode::Char('e') => app.close_color_adjust(true, true),
+                    KeyCode::Esc => app.close_color_adjust(false, false),
+                    KeyCode::Tab => app.update_color_adjust(ColorAdjust::next_channel),
+                    KeyCode::BackTab => app.update_color_adjust(ColorAdjust::prev_channel),
+                    KeyCode::Right => app.update_color_adjust(|a| a.adjust(FINE_STEP)),
+                    KeyCode::Left => app.update_color_adjust(|a| a.adjust(-FINE_STEP)),
+                    KeyCode::Up => app.update_color_adjust(|a| a.adjust(COARSE_STEP)),
+                    KeyCode::Down => app.update_color_adjust(|a| a.adjust(-COARSE_STEP)),
+                    _ => {}
+                }
+                return false;
+            }
+
             // Handle notifications overlay
             if app.notifications.is_open && app.focus == FocusArea::Notifications {
                 match key.code {
@@ -773,132 +793,14 @@ fn handle_input(
                 }
             }
 
-            // Check for command keybindings first
-            if let Some(cmd) = MuxCommand::from_key(key.modifiers, key.code) {
-                if cmd == MuxCommand::Quit {
-                    return true;
-                }
-                // Handle DeleteSandbox specially - needs to remove from sidebar immediately
-                // and spawn async deletion task (same as Backspace in sidebar)
-                if cmd == MuxCommand::DeleteSandbox {
-                    if let Some((sandbox_id, sandbox_name)) = remove_selected_sandbox(app) {
-                        let base_url = app.base_url.clone();
-                        let event_tx = app.event_tx.clone();
-
-                        app.set_status(format!("Deleting sandbox: {}", sandbox_name));
-
-                        tokio::spawn(async move {
-                            delete_sidebar_sandbox(base_url, sandbox_id, event_tx).await;
-                        });
-                    }
-                    return false;
-                }
-                app.execute_command(cmd);
-                return false;
+            // Keys that start a chord are held until it completes or can't
+            let pressed = (key.modifiers, key.code);
+            if !app.send_next_key && app.key_chords.wants(pressed) {
+                let steps = app.key_chords.feed(pressed, std::time::Instant::now());
+                return run_chord_steps(app, steps, terminal_manager);
             }
 
-            // Handle focus-specific inputs
-            match app.focus {
-                FocusArea::Sidebar => {
-                    match key.code {
-                        KeyCode::Up | KeyCode::Char('k') => {
-                            app.sidebar.select_previous();
-                            // Auto-switch workspace on selection change
-                            select_sidebar_sandbox(app);
-                        }
-                        KeyCode::Down | KeyCode::Char('j') => {
-                            app.sidebar.select_next();
-                            // Auto-switch workspace on selection change
-                            select_sidebar_sandbox(app);
-                        }
-                        KeyCode::Backspace => {
-                            if let Some((sandbox_id, sandbox_name)) = remove_selected_sandbox(app) {
-                                let base_url = app.base_url.clone();
-                                let event_tx = app.event_tx.clone();
-
-                                app.set_status(format!("Deleting sandbox: {}", sandbox_name));
-
-                                tokio::spawn(async move {
-                                    delete_sidebar_sandbox(base_url, sandbox_id, event_tx).await;
-                                });
-                            }
-                        }
-                        KeyCode::Enter | KeyCode::Esc | KeyCode::Tab => {
-                            // Just focus the main area
-                            app.focus = FocusArea::MainArea;
-                        }
-                        _ => {}
-                    }
-                }
-                FocusArea::MainArea => {
-                    // Check if we should forward input to the terminal
-                    let should_forward = if let Some(pane_id) = app.active_pane_id() {
-                        let guard = terminal_manager.try_lock();
-                        guard.map(|g| g.is_connected(pane_id)).unwrap_or(false)
-                    } else {
-                        false
-                    };
-
-                    if should_forward {
-                        // Forward input to terminal
-                        if let Some(pane_id) = app.active_pane_id() {
-                            let input = key_to_terminal_input(key.modifiers, key.code);
-                            if !input.is_empty() {
-                                if let Ok(mut guard) = terminal_manager.try_lock() {
-                                    guard.send_input(pane_id, input);
-                                }
-                            }
-                        }
-                    } else {
-                        // Handle vim-style navigation when not connected
-                        match key.code {
-                            KeyCode::Char('h')
-                                if !key.modifiers.contains(KeyModifiers::CONTROL) =>
-                            {
-                                if let Some(tab) = app.active_tab_mut() {
-                                    tab.navigate(crate::mux::layout::NavDirection::Left);
-                                }
-                            }
-                            KeyCode::Char('j')
-                                if !key.modifiers.contains(KeyModifiers::CONTROL) =>
-                            {
-                                if let Some(tab) = app.active_tab_mut() {
-                                    tab.navigate(crate::mux::layout::NavDirection::Down);
-                                }
-                            }
-                            KeyCode::Char('k')
-                                if !key.modifiers.contains(KeyModifiers::CONTROL) =>
-                            {
-                                if let Some(tab) = app.active_tab_mut() {
-                                    tab.navigate(crate::mux::layout::NavDirection::Up);
-                                }
-                            }
-                            KeyCode::Char('l')
-                                if !key.modifiers.contains(KeyModifiers::CONTROL) =>
-                            {
-                                if let Some(tab) = app.active_tab_mut() {
-                                    tab.navigate(crate::mux::layout::NavDirection::Right);
-                                }
-                            }
-                            KeyCode::Tab => {
-                                if app.sidebar.visible {
-                                    app.focus = FocusArea::Sidebar;
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
-                }
-                FocusArea::CommandPalette => {
-                    // Already handled above
-                }
-                FocusArea::Notifications => {
-                    // Notifications overlay is handled before focus-specific input.
-                }
-                FocusArea::Onboard => {
-                    // Onboard overlay is handled before focus-specific input.
-                }
-            }
+            return handle_key_press(app, key, terminal_manager);
         }
         Event::Mouse(mouse_event) => {
             // Handle mouse events (scrolling, clicking)
@@ -908,16 +810,25 @@ fn handle_input(
             if let Some(pane_id) = app.active_pane_id() {
                 if app.focus == FocusArea::MainArea {
                     // Get the pane's area to check if mouse is inside and compute relative coords
-                    let pane_area = app
+                    let content_area = app
                         .active_tab()
-                        .and_then(|tab| tab.layout.find_pane(pane_id).and_then(|p| p.area));
+                        .and_then(|tab| tab.layout.find_pane(pane_id))
+                        .and_then(|pane| {
+                            // Account for border (1 cell), and letterboxing
+                            let area = pane.area?;
+                            Some(pane.content_area(ratatui::layout::Rect {
+                                x: area.x.saturating_add(1),
+                                y: area.y.saturating_add(1),
+                                width: area.width.saturating_sub(2),
+                                height: area.height.saturating_sub(2),
+                            }))
+                        });
 
-                    if let Some(area) = pane_area {
-                        // Account for border (1 cell)
-                        let inner_x = area.x.saturating_add(1);
-                        let inner_y = area.y.saturating_add(1);
-                        let inner_w = area.width.saturating_sub(2);
-                        let inner_h = area.height.saturating_sub(2);
+                    if let Some(content) = content_area {
+                        let inner_x = content.x;
+                        let inner_y = content.y;
+                        let inner_w = content.width;
+                        let inner_h = content.height;
 
                         // Check if mouse is inside pane content area
                         if mouse_event.column >= inner_x
@@ -1010,6 +921,26 @@ fn handle_input(
                                     }
                                 }
                             }
+
+                            // Track drag selections for copy_on_select
+                            let cell = (rel_row_0, rel_col_0);
+                            match mouse_event.kind {
+                                MouseEventKind::Down(MouseButton::Left) => {
+                                    let now = std::time::Instant::now();
+                                    if app.register_click(pane_id, cell, now) {
+                                        app.select_word(pane_id, cell);
+                                    } else {
+                                        app.begin_mouse_selection(pane_id, cell);
+                                    }
+                                }
+                                MouseEventKind::Drag(MouseButton::Left) => {
+                                    app.extend_mouse_selection(cell);
+                                }
+                                MouseEventKind::Up(MouseButton::Left) => {
+                                    app.finish_mouse_selection(cell);
+                                }
+                                _ => {}
+                            }
                         }
                     }
                 }
@@ -1039,6 +970,14 @@ fn handle_input(
             }
         }
         Event::Paste(text) => {
+            // A late OSC reply delivered as a paste is for dmux, not the pane
+            if let Some(reply) = pasted_osc_reply(&text) {
+                if let Some(code) = osc_reply_code(reply).and_then(|code| u8::try_from(code).ok()) {
+                    notify_osc_observers(code, reply);
+                }
+                tracing::debug!("Dropped an OSC reply delivered as a paste");
+                return false;
+            }
             // Forward paste to active terminal
             if let Some(pane_id) = app.active_pane_id() {
                 if let Ok(mut guard) = terminal_manager.try_lock() {
@@ -1052,6 +991,177 @@ fn handle_input(
     false
 }
 
+/// Handle a key press that reached the bindings and the focused area.
+/// Returns whether to quit.
+fn handle_key_press(
+    app: &mut MuxApp<'_>,
+    key: KeyEvent,
+    terminal_manager: &crate::mux::terminal::SharedTerminalManager,
+) -> bool {
+    // A key after Send Next Key skips the bindings, e.g. to reach a nested dmux
+    if app.send_next_key {
+        app.send_next_key = false;
+        app.status_message = None;
+        if let Some(pane_id) = app.active_pane_id() {
+            let input = key_to_terminal_input(key.modifiers, key.code);
+            if let Ok(mut guard) = terminal_manager.try_lock() {
+                if !input.is_empty() && guard.is_connected(pane_id) {
+                    guard.send_input(pane_id, input);
+                }
+            }
+        }
+        return false;
+    }
+
+    // Check for command keybindings first
+    if let Some(cmd) = app.command_for_key(key.modifiers, key.code) {
+        if cmd == MuxCommand::Quit {
+            return true;
+        }
+        // Handle DeleteSandbox specially - needs to remove from sidebar immediately
+        // and spawn async deletion task (same as Backspace in sidebar)
+        if cmd == MuxCommand::DeleteSandbox {
+            if let Some((sandbox_id, sandbox_name)) = remove_selected_sandbox(app) {
+                let base_url = app.base_url.clone();
+                let event_tx = app.event_tx.clone();
+
+                app.set_status(format!("Deleting sandbox: {}", sandbox_name));
+
+                tokio::spawn(async move {
+                    delete_sidebar_sandbox(base_url, sandbox_id, event_tx).await;
+                });
+            }
+            return false;
+        }
+        app.execute_command(cmd);
+        return false;
+    }
+
+    // Handle focus-specific inputs
+    match app.focus {
+        FocusArea::Sidebar => {
+            match key.code {
+                KeyCode::Up | KeyCode::Char('k') => {
+                    app.sidebar.select_previous();
+                    // Auto-switch workspace on selection change
+                    select_sidebar_sandbox(app);
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    app.sidebar.select_next();
+                    // Auto-switch workspace on selection change
+                    select_sidebar_sandbox(app);
+                }
+                KeyCode::Backspace => {
+                    if let Some((sandbox_id, sandbox_name)) = remove_selected_sandbox(app) {
+                        let base_url = app.base_url.clone();
+                        let event_tx = app.event_tx.clone();
+
+                        app.set_status(format!("Deleting sandbox: {}", sandbox_name));
+
+                        tokio::spawn(async move {
+                            delete_sidebar_sandbox(base_url, sandbox_id, event_tx).await;
+                        });
+                    }
+                }
+                KeyCode::Enter | KeyCode::Esc | KeyCode::Tab => {
+                    // Just focus the main area
+                    app.focus = FocusArea::MainArea;
+                }
+                _ => {}
+            }
+        }
+        FocusArea::MainArea => {
+            // A pane in copy mode takes keys for its cursor instead of its process
+            if app.handle_copy_mode_key(key.code) {
+                return false;
+            }
+
+            // Check if we should forward input to the terminal
+            let should_forward = if let Some(pane_id) = app.active_pane_id() {
+                let guard = terminal_manager.try_lock();
+                guard.map(|g| g.is_connected(pane_id)).unwrap_or(false)
+            } else {
+                false
+            };
+
+            if should_forward {
+                // Forward input to terminal
+                if let Some(pane_id) = app.active_pane_id() {
+                    let input = key_to_terminal_input(key.modifiers, key.code);
+                    if !input.is_empty() {
+                        if let Ok(mut guard) = terminal_manager.try_lock() {
+                            guard.send_input(pane_id, input);
+                        }
+                    }
+                }
+            } else {
+                // Handle vim-style navigation when not connected
+                match key.code {
+                    KeyCode::Char('h') if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        if let Some(tab) = app.active_tab_mut() {
+                            tab.navigate(crate::mux::layout::NavDirection::Left);
+                        }
+                    }
+                    KeyCode::Char('j') if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        if let Some(tab) = app.active_tab_mut() {
+                            tab.navigate(crate::mux::layout::NavDirection::Down);
+                        }
+                    }
+                    KeyCode::Char('k') if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        if let Some(tab) = app.active_tab_mut() {
+                            tab.navigate(crate::mux::layout::NavDirection::Up);
+                        }
+                    }
+                    KeyCode::Char('l') if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        if let Some(tab) = app.active_tab_mut() {
+                            tab.navigate(crate::mux::layout::NavDirection::Right);
+                        }
+                    }
+                    KeyCode::Tab if app.sidebar.visible => {
+                        app.focus = FocusArea::Sidebar;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        FocusArea::CommandPalette => {
+            // Already handled above
+        }
+        FocusArea::Notifications => {
+            // Notifications overlay is handled before focus-specific input.
+        }
+        FocusArea::Onboard => {
+            // Onboard overlay is handled before focus-specific input.
+        }
+    }
+    false
+}
+
+/// Act on resolved chord keys: run the commands of completed chords and
+/// handle keys that weren't part of one as usual. Returns whether to quit.
+pub(crate) fn run_chord_steps(
+    app: &mut MuxApp<'_>,
+    steps: Vec<ChordStep>,
+    terminal_manager: &crate::mux::terminal::SharedTerminalManager,
+) -> bool {
+    for step in steps {
+        match step {
+            ChordStep::Matched(invocation) => {
+                if invocation.command == MuxCommand::Quit {
+                    return true;
+                }
+                app.dispatch_command(invocation);
+            }
+            ChordStep::Unmatched((modifiers, code)) => {
+                if handle_key_press(app, KeyEvent::new(code, modifiers), terminal_manager) {
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
 /// Select the currently highlighted sandbox in the sidebar and switch to its workspace.
 fn select_sidebar_sandbox(app: &mut MuxApp<'_>) {
     if let Some(sandbox) = app.sidebar.selected_sandbox() {
@@ -1154,68 +1264,7 @@ fn remove_selected_sandbox(app: &mut MuxApp<'_>) -> Option<(String, String)> {
     Some((sandbox_id, sandbox_name))
 }
 
-/// Convert a key event to terminal input bytes
-fn key_to_terminal_input(modifiers: KeyModifiers, code: KeyCode) -> Vec<u8> {
-    match code {
-        KeyCode::Char(c) => {
-            if modifiers.contains(KeyModifiers::CONTROL) {
-                // Ctrl+A = 0x01, Ctrl+B = 0x02, etc.
-                let ctrl_code = (c.to_ascii_lowercase() as u8).saturating_sub(b'a' - 1);
-                if (1..=26).contains(&ctrl_code) {
-                    return vec![ctrl_code];
-                }
-            }
-            let mut buf = [0u8; 4];
-            let s = c.encode_utf8(&mut buf);
-            s.as_bytes().to_vec()
-        }
-        KeyCode::Enter => vec![b'\r'],
-        KeyCode::Backspace => {
-            // Command+Backspace (macOS) / Super+Backspace: delete to beginning of line (Ctrl+U)
-            if modifiers.contains(KeyModifiers::SUPER) {
-                return vec![0x15]; // Ctrl+U
-            }
-            // Option+Backspace (macOS) / Alt+Backspace: delete previous word (Ctrl+W)
-            if modifiers.contains(KeyModifiers::ALT) {
-                return vec![0x17]; // Ctrl+W
-            }
-            vec![0x7f]
-        }
-        KeyCode::Tab => vec![b'\t'],
-        KeyCode::Esc => vec![0x1b],
-        KeyCode::Up => vec![0x1b, b'[', b'A'],
-        KeyCode::Down => vec![0x1b, b'[', b'B'],
-        KeyCode::Right => vec![0x1b, b'[', b'C'],
-        KeyCode::Left => vec![0x1b, b'[', b'D'],
-        KeyCode::Home => vec![0x1b, b'[', b'H'],
-        KeyCode::End => vec![0x1b, b'[', b'F'],
-        KeyCode::PageUp => vec![0x1b, b'[', b'5', b'~'],
-        KeyCode::PageDown => vec![0x1b, b'[', b'6', b'~'],
-        KeyCode::Delete => vec![0x1b, b'[', b'3', b'~'],
-        KeyCode::Insert => vec![0x1b, b'[', b'2', b'~'],
-        KeyCode::F(n) => {
-            // F1-F4 use different sequences than F5+
-            match n {
-                1 => vec![0x1b, b'O', b'P'],
-                2 => vec![0x1b, b'O', b'Q'],
-                3 => vec![0x1b, b'O', b'R'],
-                4 => vec![0x1b, b'O', b'S'],
-                5 => vec![0x1b, b'[', b'1', b'5', b'~'],
-                6 => vec![0x1b, b'[', b'1', b'7', b'~'],
-                7 => vec![0x1b, b'[', b'1', b'8', b'~'],
-                8 => vec![0x1b, b'[', b'1', b'9', b'~'],
-                9 => vec![0x1b, b'[', b'2', b'0', b'~'],
-                10 => vec![0x1b, b'[', b'2', b'1', b'~'],
-                11 => vec![0x1b, b'[', b'2', b'3', b'~'],
-                12 => vec![0x1b, b'[', b'2', b'4', b'~'],
-                _ => vec![],
-            }
-        }
-        _ => vec![],
-    }
-}
-
-async fn create_sandbox_with_workspace(
+pub(crate) async fn create_sandbox_with_workspace(
     base_url: String,
     workspace_path: PathBuf,
     tab_id: Option<String>,
@@ -1525,7 +1574,7 @@ async fn spawn_pull_with_mux_events(
 }
 
 /// Handle onboarding events.
-fn handle_onboard_event(
+pub(crate) fn handle_onboard_event(
     app: &mut MuxApp<'_>,
     event: OnboardEvent,
     event_tx: &mpsc::UnboundedSender<MuxEvent>,
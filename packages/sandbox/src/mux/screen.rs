@@ -0,0 +1,198 @@
+//! Entering and leaving dmux's full-screen display.
+//!
+//! By default dmux draws on the alternate screen, which hides the outer
+//! terminal's scrollback until exit. The soft mode instead pushes the visible
+//! content into the scrollback and draws on the normal screen, so the user's
+//! history stays reachable and is left intact when dmux exits.
+
+use std::io::Write;
+use std::sync::{Once, RwLock};
+
+use crossterm::event::{DisableBracketedPaste, DisableMouseCapture, PopKeyboardEnhancementFlags};
+
+/// Enable the alternate screen buffer (saving the cursor).
+const ENTER_ALT_SCREEN: &[u8] = b"\x1b[?1049h";
+/// Return to the normal screen buffer (restoring the cursor).
+const LEAVE_ALT_SCREEN: &[u8] = b"\x1b[?1049l";
+/// Move home and clear the visible screen.
+const HOME_AND_CLEAR: &[u8] = b"\x1b[H\x1b[2J";
+/// Reset colors and attributes, then show the cursor.
+const RESET_STYLE_AND_SHOW_CURSOR: &[u8] = b"\x1b[0m\x1b[?25h";
+
+/// The mode dmux is currently drawing in, if it has entered one.
+static ACTIVE_MODE: RwLock<Option<ScreenMode>> = RwLock::new(None);
+
+/// How dmux takes over the terminal.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ScreenMode {
+    /// The alternate screen buffer; the outer scrollback is hidden until exit.
+    #[default]
+    Alternate,
+    /// Clear and home on the normal screen, leaving the outer scrollback intact.
+    Soft,
+}
+
+impl ScreenMode {
+    /// The mode selected by the `preserve_scrollback` setting.
+    pub fn from_preserve_scrollback(preserve_scrollback: bool) -> Self {
+        if preserve_scrollback {
+            ScreenMode::Soft
+        } else {
+            ScreenMode::Alternate
+        }
+    }
+
+    /// Bytes that take over a terminal with `rows` visible rows.
+    ///
+    /// Soft mode first scrolls every visible row into the scrollback (content
+    /// can't be below the cursor, so `rows` newlines are always enough), then
+    /// clears the now-blank screen.
+    pub fn enter_sequence(self, rows: u16) -> Vec<u8> {
+        match self {
+            ScreenMode::Alternate => ENTER_ALT_SCREEN.to_vec(),
+            ScreenMode::Soft => [b"\r\n".repeat(rows as usize).as_slice(), HOME_AND_CLEAR].concat(),
+        }
+    }
+
+    /// Bytes that hand the terminal back.
+    /// Soft mode clears dmux's last frame so the shell prompt starts on a clean screen.
+    pub fn exit_sequence(self) -> Vec<u8> {
+        match self {
+            ScreenMode::Alternate => LEAVE_ALT_SCREEN.to_vec(),
+            ScreenMode::Soft => HOME_AND_CLEAR.to_vec(),
+        }
+    }
+}
+
+/// Take over the terminal in `mode` and remember it for `leave_screen`.
+///
+/// Startup color and capability queries must run before this, while the
+/// terminal is still showing the user's own screen.
+pub fn enter_screen<W: Write>(out: &mut W, mode: ScreenMode, rows: u16) -> std::io::Result<()> {
+    out.write_all(&mode.enter_sequence(rows))?;
+    out.flush()?;
+    if let Ok(mut active) = ACTIVE_MODE.write() {
+        *active = Some(mode);
+    }
+    Ok(())
+}
+
+/// Undo `enter_screen`. Does nothing if no screen mode is active.
+pub fn leave_screen<W: Write>(out: &mut W) -> std::io::Result<()> {
+    let mode = ACTIVE_MODE
+        .write()
+        .ok()
+        .and_then(|mut active| active.take());
+    let Some(mode) = mode else {
+        return Ok(());
+    };
+    out.write_all(&mode.exit_sequence())?;
+    out.flush()
+}
+
+/// Hand the whole terminal back: raw mode, input modes, screen and colors.
+///
+/// This is the one cleanup routine for every way out of dmux (normal exit
+/// and the panic hook), so it only acts for the first caller after
+/// `enter_screen`; later calls find nothing active and do nothing.
+pub fn restore_terminal<W: Write>(out: &mut W) -> std::io::Result<()> {
+    let mode = ACTIVE_MODE
+        .write()
+        .ok()
+        .and_then(|mut active| active.take());
+    match mode {
+        Some(mode) => write_restore(out, mode),
+        None => Ok(()),
+    }
+}
+
+/// Undo raw mode and everything `run_mux_tui` enabled for `mode`.
+///
+/// Keyboard enhancement flags must be popped before leaving the screen.
+/// Every step runs even if an earlier one fails; the first error is returned.
+fn write_restore<W: Write>(out: &mut W, mode: ScreenMode) -> std::io::Result<()> {
+    let raw = crossterm::terminal::disable_raw_mode();
+    let modes = crossterm::execute!(
+        out,
+        PopKeyboardEnhancementFlags,
+        DisableBracketedPaste,
+        DisableMouseCapture
+    );
+    let screen = out
+        .write_all(&mode.exit_sequence())
+        .and_then(|()| out.write_all(RESET_STYLE_AND_SHOW_CURSOR))
+        .and_then(|()| out.flush());
+    raw.and(modes).and(screen)
+}
+
+/// Install a panic hook that restores the terminal before the default hook
+/// prints the panic message and backtrace, which are unreadable in raw mode
+/// on the alternate screen. Safe to call more than once.
+pub fn install_panic_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let _ = restore_terminal(&mut std::io::stdout());
+            default_hook(info);
+        }));
+    });
+}
+
+/// The screen mode dmux entered, if it is currently full-screen.
+pub fn active_screen_mode() -> Option<ScreenMode> {
+    ACTIVE_MODE.read().ok().and_then(|active| *active)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alternate_mode_uses_the_alt_screen_buffer() {
+        let mode = ScreenMode::Alternate;
+        assert_eq!(mode.enter_sequence(24), b"\x1b[?1049h");
+        assert_eq!(mode.exit_sequence(), b"\x1b[?1049l");
+    }
+
+    #[test]
+    fn soft_mode_never_touches_the_alt_screen_buffer() {
+        let mode = ScreenMode::Soft;
+        assert_eq!(mode.enter_sequence(2), b"\r\n\r\n\x1b[H\x1b[2J");
+        assert_eq!(mode.exit_sequence(), b"\x1b[H\x1b[2J");
+        for sequence in [mode.enter_sequence(24), mode.exit_sequence()] {
+            assert!(!sequence.windows(5).any(|w| w == b"?1049"));
+        }
+    }
+
+    #[test]
+    fn leave_screen_undoes_the_entered_mode() {
+        let mut out = Vec::new();
+        enter_screen(&mut out, ScreenMode::Soft, 1).expect("write");
+        assert_eq!(active_screen_mode(), Some(ScreenMode::Soft));
+
+        out.clear();
+        leave_screen(&mut out).expect("write");
+        assert_eq!(out, ScreenMode::Soft.exit_sequence());
+        assert_eq!(active_screen_mode(), None);
+
+        // A second leave has nothing to undo
+        out.clear();
+        leave_screen(&mut out).expect("write");
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn panic_cleanup_leaves_the_screen_and_resets_colors() {
+        let mut out = Vec::new();
+        write_restore(&mut out, ScreenMode::Alternate).expect("restore");
+        let exit = ScreenMode::Alternate.exit_sequence();
+        let leave_at = out
+            .windows(exit.len())
+            .position(|w| w == exit.as_slice())
+            .expect("left the alt screen");
+        assert!(out.ends_with(RESET_STYLE_AND_SHOW_CURSOR));
+        // Bracketed paste is turned off before the screen is handed back
+        assert!(out[..leave_at].windows(8).any(|w| w == b"\x1b[?2004l"));
+    }
+}
@@ -0,0 +1,416 @@
+//! Scrollback buffer for rows that have scrolled above the viewport.
+//!
+//! Rows keep their `is_canonical` flag from the grid, so runs of soft-wrapped
+//! rows can be joined back into logical lines and re-split when the terminal
+//! width changes.
+//!
+//! Markers anchor to a row by its absolute line number (rows ever pushed), so
+//! they keep pointing at the same row as older rows are evicted.
+//!
+//! Rows carrying an OSC 133 prompt-start mark double as implicit markers for
+//! prompt-to-prompt navigation.
+
+use std::collections::VecDeque;
+
+use super::character::{PromptMark, Row, TerminalCharacter};
+
+/// Default maximum number of lines to keep in scrollback.
+pub const MAX_SCROLLBACK_LINES: usize = 10_000;
+
+/// Identifier of a scrollback marker.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MarkerId(u64);
+
+/// A bookmark on a scrollback row.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Marker {
+    pub id: MarkerId,
+    pub label: Option<String>,
+    /// Absolute line number of the marked row.
+    line: u64,
+}
+
+/// Direction for prompt-to-prompt navigation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PromptDirection {
+    /// Towards older output.
+    Previous,
+    /// Towards newer output.
+    Next,
+}
+
+/// Bounded buffer of scrollback rows, oldest first.
+#[derive(Clone, Debug)]
+pub struct Scrollback {
+    rows: VecDeque<Row>,
+    max_lines: usize,
+    /// Number of rows dropped from the front so far.
+    evicted: u64,
+    markers: Vec<Marker>,
+    next_marker_id: u64,
+}
+
+impl Default for Scrollback {
+    fn default() -> Self {
+        Self::new(MAX_SCROLLBACK_LINES)
+    }
+}
+
+impl Scrollback {
+    /// Create an empty scrollback holding at most `max_lines` rows.
+    pub fn new(max_lines: usize) -> Self {
+        Self {
+            rows: VecDeque::new(),
+            max_lines,
+            evicted: 0,
+            markers: Vec::new(),
+            next_marker_id: 0,
+        }
+    }
+
+    /// Number of rows in the scrollback.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Check if the scrollback is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// Get the row at the given index (0 = oldest).
+    #[inline]
+    pub fn get(&self, index: usize) -> Option<&Row> {
+        self.rows.get(index)
+    }
+
+    /// Iterate over rows, oldest first.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Row> + ExactSizeIterator {
+        self.rows.iter()
+    }
+
+    /// Append a row, dropping the oldest row if the buffer is full.
+    pub fn push_back(&mut self, row: Row) {
+        if self.rows.len() >= self.max_lines {
+            self.rows.pop_front();
+            self.evict(1);
+        }
+        self.rows.push_back(row);
+    }
+
+    /// Remove all rows (and with them all markers).
+    pub fn clear(&mut self) {
+        self.evicted += self.rows.len() as u64;
+        self.rows.clear();
+        self.markers.clear();
+    }
+
+    /// Record that `count` rows were dropped from the front, dropping their markers.
+    fn evict(&mut self, count: usize) {
+        self.evicted += count as u64;
+        let evicted = self.evicted;
+        self.markers.retain(|marker| marker.line >= evicted);
+    }
+
+    /// Drop a marker on the current bottom row.
+    /// If the scrollback is empty, the marker lands on the next row pushed.
+    pub fn add_marker(&mut self, label: Option<String>) -> MarkerId {
+        let id = MarkerId(self.next_marker_id);
+        self.next_marker_id += 1;
+        self.markers.push(Marker {
+            id,
+            label,
+            line: self.evicted + self.rows.len().saturating_sub(1) as u64,
+        });
+        id
+    }
+
+    /// Markers still in the buffer, oldest first.
+    pub fn list_markers(&self) -> &[Marker] {
+        &self.markers
+    }
+
+    /// Current row index (0 = oldest) of a marker, if it hasn't been evicted.
+    pub fn marker_index(&self, id: MarkerId) -> Option<usize> {
+        let marker = self.markers.iter().find(|marker| marker.id == id)?;
+        usize::try_from(marker.line - self.evicted)
+            .ok()
+            .filter(|&index| index < self.rows.len())
+    }
+
+    /// Scroll offset that puts a marker's row at the top of the view,
+    /// in the same units as `TerminalBuffer::scroll_offset`.
+    pub fn jump_to_marker(&self, id: MarkerId) -> Option<usize> {
+        let index = self.marker_index(id)?;
+        Some(self.rows.len().saturating_sub(index))
+    }
+
+    /// Scroll offset that puts the nearest prompt in `direction` at the top
+    /// of the view, starting from the current `offset`. Offsets are in the
+    /// same units as `jump_to_marker`.
+    pub fn jump_to_prompt(&self, offset: usize, direction: PromptDirection) -> Option<usize> {
+        let len = self.rows.len();
+        let top = len.saturating_sub(offset);
+        let is_prompt = |(_, row): &(usize, &Row)| row.prompt_mark == Some(PromptMark::PromptStart);
+        let index = match direction {
+            PromptDirection::Previous => {
+                self.rows.iter().enumerate().take(top).rev().find(is_prompt)
+            }
+            PromptDirection::Next => self.rows.iter().enumerate().skip(top + 1).find(is_prompt),
+        }?
+        .0;
+        Some(len - index)
+    }
+
+    /// Re-wrap all rows to `new_width`.
+    ///
+    /// Soft-wrapped rows (those with `is_canonical == false`) are joined onto the
+    /// row before them to rebuild each logical line, trailing blank padding is
+    /// trimmed, and the line is split again at the new width. Hard newlines
+    /// (canonical rows) are preserved as line boundaries.
+    pub fn reflow(&mut self, new_width: usize) {
+        if new_width == 0 || self.rows.is_empty() {
+            return;
+        }
+
+        let (lines, line_of_row) = self.logical_lines();
+        let mut reflowed = VecDeque::with_capacity(self.rows.len());
+        let mut line_starts = Vec::with_capacity(lines.len());
+        for line in lines {
+            line_starts.push(reflowed.len());
+            for mut row in line.split_to_rows_of_length(new_width) {
+                row.fill_to_width(new_width);
+                reflowed.push_back(row);
+            }
+        }
+
+        // Move markers to the first row of their logical line
+        let old_len = self.rows.len();
+        for marker in &mut self.markers {
+            let index = (marker.line - self.evicted) as usize;
+            let new_index = match line_of_row.get(index) {
+                Some(&line) => line_starts[line],
+                None => reflowed.len() + (index - old_len),
+            };
+            marker.line = self.evicted + new_index as u64;
+        }
+
+        let overflow = reflowed.len().saturating_sub(self.max_lines);
+        reflowed.drain(..overflow);
+        self.rows = reflowed;
+        self.evict(overflow);
+    }
+
+    /// Join soft-wrapped rows into logical lines with trailing padding removed.
+    /// Also returns, for each row, the index of the logical line it belongs to.
+    fn logical_lines(&self) -> (Vec<Row>, Vec<usize>) {
+        let mut lines: Vec<Row> = Vec::new();
+        let mut line_of_row = Vec::with_capacity(self.rows.len());
+        for row in &self.rows {
+            let continues_previous = !row.is_canonical && !lines.is_empty();
+            match lines.last_mut() {
+                Some(line) if continues_previous => {
+                    line.columns.extend(row.columns.iter().cloned())
+                }
+                _ => {
+                    let mut line = row.clone();
+                    line.is_canonical = true;
+                    lines.push(line);
+                }
+            }
+            line_of_row.push(lines.len() - 1);
+        }
+
+        let blank = TerminalCharacter::default();
+        for line in &mut lines {
+            while line.columns.back() == Some(&blank) {
+                line.columns.pop_back();
+            }
+        }
+        (lines, line_of_row)
+    }
+}
+
+impl std::ops::Index<usize> for Scrollback {
+    type Output = Row;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.rows[index]
+    }
+}
+
+impl<'a> IntoIterator for &'a Scrollback {
+    type Item = &'a Row;
+    type IntoIter = std::collections::vec_deque::Iter<'a, Row>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.rows.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mux::character::SharedStyles;
+
+    fn row_from(text: &str, width: usize, is_canonical: bool) -> Row {
+        let mut row = Row::with_capacity(width);
+        for c in text.chars() {
+            row.columns
+                .push_back(TerminalCharacter::new(c, SharedStyles::Default));
+        }
+        row.fill_to_width(width);
+        row.is_canonical = is_canonical;
+        row
+    }
+
+    fn logical_text(scrollback: &Scrollback) -> Vec<String> {
+        scrollback
+            .logical_lines()
+            .0
+            .iter()
+            .map(|line| line.as_string())
+            .collect()
+    }
+
+    #[test]
+    fn push_back_respects_max_lines() {
+        let mut scrollback = Scrollback::new(2);
+        scrollback.push_back(row_from("a", 4, true));
+        scrollback.push_back(row_from("b", 4, true));
+        scrollback.push_back(row_from("c", 4, true));
+        assert_eq!(scrollback.len(), 2);
+        assert_eq!(scrollback[0].columns[0].character, 'b');
+    }
+
+    #[test]
+    fn reflow_narrow_then_wide_recovers_logical_lines() {
+        let mut scrollback = Scrollback::default();
+        // "abcdefghij" soft-wrapped at width 6, followed by a hard newline
+        scrollback.push_back(row_from("abcdef", 6, true));
+        scrollback.push_back(row_from("ghij", 6, false));
+        scrollback.push_back(row_from("xyz", 6, true));
+        let original = logical_text(&scrollback);
+        assert_eq!(original, vec!["abcdefghij", "xyz"]);
+
+        scrollback.reflow(3);
+        assert_eq!(scrollback.len(), 5);
+        assert!(scrollback[0].is_canonical);
+        assert!(!scrollback[1].is_canonical);
+        assert!(scrollback[4].is_canonical);
+        assert_eq!(logical_text(&scrollback), original);
+
+        scrollback.reflow(20);
+        assert_eq!(scrollback.len(), 2);
+        assert_eq!(logical_text(&scrollback), original);
+        assert!(scrollback.iter().all(|row| row.len() == 20));
+    }
+
+    #[test]
+    fn reflow_preserves_hard_newlines() {
+        let mut scrollback = Scrollback::default();
+        scrollback.push_back(row_from("one", 10, true));
+        scrollback.push_back(row_from("", 10, true));
+        scrollback.push_back(row_from("two", 10, true));
+
+        scrollback.reflow(40);
+        assert_eq!(logical_text(&scrollback), vec!["one", "", "two"]);
+    }
+
+    #[test]
+    fn reflow_keeps_wide_chars_intact() {
+        let mut scrollback = Scrollback::default();
+        let mut row = Row::with_capacity(4);
+        row.add_character_at(TerminalCharacter::new('中', SharedStyles::Default), 0);
+        row.add_character_at(TerminalCharacter::new('文', SharedStyles::Default), 2);
+        scrollback.push_back(row);
+
+        scrollback.reflow(3);
+        assert_eq!(scrollback.len(), 2);
+        assert_eq!(scrollback[0].columns[0].character, '中');
+        assert!(scrollback[0].columns[1].wide_spacer);
+        assert_eq!(scrollback[1].columns[0].character, '文');
+    }
+
+    #[test]
+    fn marker_stays_anchored_after_pushes_until_evicted() {
+        let mut scrollback = Scrollback::new(4);
+        scrollback.push_back(row_from("a", 4, true));
+        scrollback.push_back(row_from("b", 4, true));
+        let id = scrollback.add_marker(Some("before build".to_string()));
+        assert_eq!(scrollback.marker_index(id), Some(1));
+        assert_eq!(scrollback.jump_to_marker(id), Some(1));
+
+        scrollback.push_back(row_from("c", 4, true));
+        scrollback.push_back(row_from("d", 4, true));
+        assert_eq!(scrollback.jump_to_marker(id), Some(3));
+
+        // "a" is evicted; the marker moves down with "b"
+        scrollback.push_back(row_from("e", 4, true));
+        let index = scrollback.marker_index(id).expect("still in buffer");
+        assert_eq!(scrollback[index].columns[0].character, 'b');
+        assert_eq!(scrollback.list_markers().len(), 1);
+        assert_eq!(
+            scrollback.list_markers()[0].label.as_deref(),
+            Some("before build")
+        );
+
+        // Evicting "b" drops the marker
+        scrollback.push_back(row_from("f", 4, true));
+        assert_eq!(scrollback.marker_index(id), None);
+        assert!(scrollback.list_markers().is_empty());
+    }
+
+    #[test]
+    fn jump_to_prompt_walks_between_prompt_rows() {
+        let mut scrollback = Scrollback::default();
+        for (text, mark) in [
+            ("$ make", Some(PromptMark::PromptStart)),
+            ("building", Some(PromptMark::OutputStart)),
+            ("$ ls", Some(PromptMark::PromptStart)),
+            ("a b c", Some(PromptMark::OutputStart)),
+            ("done", None),
+        ] {
+            let mut row = row_from(text, 10, true);
+            row.prompt_mark = mark;
+            scrollback.push_back(row);
+        }
+
+        // From the live view, walk back through both prompts and no further
+        let prev = scrollback.jump_to_prompt(0, PromptDirection::Previous);
+        assert_eq!(prev, Some(3));
+        let prev = scrollback.jump_to_prompt(3, PromptDirection::Previous);
+        assert_eq!(prev, Some(5));
+        assert_eq!(
+            scrollback.jump_to_prompt(5, PromptDirection::Previous),
+            None
+        );
+
+        // And forward again; there is no prompt after the last one
+        assert_eq!(scrollback.jump_to_prompt(5, PromptDirection::Next), Some(3));
+        assert_eq!(scrollback.jump_to_prompt(3, PromptDirection::Next), None);
+
+        // Marks survive reflow on the first row of their line
+        scrollback.reflow(3);
+        let offset = scrollback
+            .jump_to_prompt(0, PromptDirection::Previous)
+            .expect("prompt row");
+        let row = &scrollback[scrollback.len() - offset];
+        assert_eq!(row.as_string().trim_end(), "$ l");
+    }
+
+    #[test]
+    fn marker_follows_its_line_through_reflow() {
+        let mut scrollback = Scrollback::default();
+        scrollback.push_back(row_from("abcdef", 6, true));
+        scrollback.push_back(row_from("ghij", 6, false));
+        scrollback.push_back(row_from("xyz", 6, true));
+        let id = scrollback.add_marker(None);
+
+        scrollback.reflow(3);
+        let index = scrollback.marker_index(id).expect("still in buffer");
+        assert_eq!(scrollback[index].columns[0].character, 'x');
+    }
+}
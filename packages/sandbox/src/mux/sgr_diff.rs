@@ -0,0 +1,509 @@
+//! Minimal SGR output for the frame diff.
+//!
+//! Each frame, ratatui hands the backend only the cells that changed. Writing
+//! them with a full reset plus every attribute per cell bloats the output, so
+//! `write_cells` tracks the attributes currently applied on the outer terminal
+//! and emits just the difference when moving to the next cell: `\x1b[1m` to
+//! turn on bold, `\x1b[32m` to change the foreground. A reset is used instead
+//! only when it is strictly shorter than the delta.
+//!
+//! On terminals that can't blink, blinking cells are drawn in reverse video
+//! instead (or bold, if already reversed), so they still stand out.
+
+use std::io::{self, Write};
+
+use ratatui::backend::{Backend, ClearType, WindowSize};
+use ratatui::buffer::Cell;
+use ratatui::layout::{Position, Size};
+use ratatui::style::{Color, Modifier};
+
+use crate::mux::capabilities::terminal_capabilities;
+use crate::mux::output::TerminalOutput;
+
+/// The graphic attributes in effect on the terminal, as far as SGR goes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SgrState {
+    pub fg: Color,
+    pub bg: Color,
+    pub underline_color: Color,
+    pub modifier: Modifier,
+}
+
+/// Modifiers with their SGR "on" codes, in the order they are emitted.
+const MODIFIER_ON: [(Modifier, u8); 9] = [
+    (Modifier::BOLD, 1),
+    (Modifier::DIM, 2),
+    (Modifier::ITALIC, 3),
+    (Modifier::UNDERLINED, 4),
+    (Modifier::SLOW_BLINK, 5),
+    (Modifier::RAPID_BLINK, 6),
+    (Modifier::REVERSED, 7),
+    (Modifier::HIDDEN, 8),
+    (Modifier::CROSSED_OUT, 9),
+];
+
+/// SGR "off" codes and the modifiers each clears. 22 and 25 clear two at once.
+const MODIFIER_OFF: [(Modifier, u8); 7] = [
+    (Modifier::BOLD.union(Modifier::DIM), 22),
+    (Modifier::ITALIC, 23),
+    (Modifier::UNDERLINED, 24),
+    (Modifier::SLOW_BLINK.union(Modifier::RAPID_BLINK), 25),
+    (Modifier::REVERSED, 27),
+    (Modifier::HIDDEN, 28),
+    (Modifier::CROSSED_OUT, 29),
+];
+
+impl SgrState {
+    /// The attributes a cell is drawn with.
+    pub fn of(cell: &Cell) -> Self {
+        Self {
+            fg: cell.fg,
+            bg: cell.bg,
+            underline_color: cell.underline_color,
+            modifier: cell.modifier,
+        }
+    }
+
+    /// These attributes with blinking replaced by reverse video, or by bold
+    /// where the cell is already reversed, for terminals that can't blink.
+    pub fn without_blink(self) -> Self {
+        let blink = Modifier::SLOW_BLINK | Modifier::RAPID_BLINK;
+        if !self.modifier.intersects(blink) {
+            return self;
+        }
+        let accent = if self.modifier.contains(Modifier::REVERSED) {
+            Modifier::BOLD
+        } else {
+            Modifier::REVERSED
+        };
+        Self {
+            modifier: self.modifier.difference(blink) | accent,
+            ..self
+        }
+    }
+
+    /// The SGR sequence that takes the terminal from these attributes to
+    /// `target`: the minimal delta, or a reset and full re-set if that is
+    /// strictly shorter. Empty when nothing changes.
+    pub fn transition(&self, target: &SgrState) -> String {
+        if self == target {
+            return String::new();
+        }
+        let delta = sgr(&self.delta_params(target));
+        let mut reset_params = vec!["0".to_string()];
+        reset_params.extend(SgrState::default().delta_params(target));
+        let reset = sgr(&reset_params);
+        if reset.len() < delta.len() {
+            reset
+        } else {
+            delta
+        }
+    }
+
+    /// Parameters changing only what differs between these attributes and `target`.
+    fn delta_params(&self, target: &SgrState) -> Vec<String> {
+        let mut params = Vec::new();
+        let removed = self.modifier.difference(target.modifier);
+        let mut added = target.modifier.difference(self.modifier);
+        for (cleared, code) in MODIFIER_OFF {
+            if removed.intersects(cleared) {
+                params.push(code.to_string());
+                // The shared off code also cleared any of its pair that stays on
+                added |= target.modifier.intersection(cleared);
+            }
+        }
+        for (modifier, code) in MODIFIER_ON {
+            if added.contains(modifier) {
+                params.push(code.to_string());
+            }
+        }
+        if self.fg != target.fg {
+            params.push(color_param(target.fg, 30, 38, 39));
+        }
+        if self.bg != target.bg {
+            params.push(color_param(target.bg, 40, 48, 49));
+        }
+        if self.underline_color != target.underline_color {
+            params.push(underline_color_param(target.underline_color));
+        }
+        params
+    }
+}
+
+/// `CSI params m`.
+fn sgr(params: &[String]) -> String {
+    format!("\x1b[{}m", params.join(";"))
+}
+
+/// Index of a named color in the 16-color palette.
+fn ansi_index(color: Color) -> Option<u8> {
+    let index = match color {
+        Color::Black => 0,
+        Color::Red => 1,
+        Color::Green => 2,
+        Color::Yellow => 3,
+        Color::Blue => 4,
+        Color::Magenta => 5,
+        Color::Cyan => 6,
+        Color::Gray => 7,
+        Color::DarkGray => 8,
+        Color::LightRed => 9,
+        Color::LightGreen => 10,
+        Color::LightYellow => 11,
+        Color::LightBlue => 12,
+        Color::LightMagenta => 13,
+        Color::LightCyan => 14,
+        Color::White => 15,
+        _ => return None,
+    };
+    Some(index)
+}
+
+/// The SGR parameter selecting `color` as fg or bg: `base` (30/40) plus the
+/// index for the first eight named colors, `base + 60` for the bright ones,
+/// `extended` (38/48) for indexed and RGB, `default` (39/49) for `Reset`.
+fn color_param(color: Color, base: u8, extended: u8, default: u8) -> String {
+    match color {
+        Color::Reset => default.to_string(),
+        Color::Indexed(index) => format!("{};5;{}", extended, index),
+        Color::Rgb(r, g, b) => format!("{};2;{};{};{}", extended, r, g, b),
+        named => match ansi_index(named) {
+            Some(index @ 0..=7) => (base + index).to_string(),
+            Some(index) => (base + 60 + index - 8).to_string(),
+            None => default.to_string(),
+        },
+    }
+}
+
+/// The SGR 58/59 parameter for an underline color; named colors go by index.
+fn underline_color_param(color: Color) -> String {
+    match color {
+        Color::Reset => "59".to_string(),
+        Color::Rgb(r, g, b) => format!("58;2;{};{};{}", r, g, b),
+        Color::Indexed(index) => format!("58;5;{}", index),
+        named => match ansi_index(named) {
+            Some(index) => format!("58;5;{}", index),
+            None => "59".to_string(),
+        },
+    }
+}
+
+/// Write changed cells, moving the cursor only where a cell doesn't follow
+/// the previous one and changing attributes only by their SGR delta. Unless
+/// `blink` is supported, blinking cells get `SgrState::without_blink`.
+///
+/// The terminal is assumed to start with default attributes, and is left
+/// with them, so other output between frames isn't affected.
+pub fn write_cells<'a, O, I>(out: &mut O, cells: I, blink: bool) -> io::Result<()>
+where
+    O: TerminalOutput + ?Sized,
+    I: Iterator<Item = (u16, u16, &'a Cell)>,
+{
+    let mut applied = SgrState::default();
+    let mut last: Option<(u16, u16)> = None;
+    for (x, y, cell) in cells {
+        if !matches!(last, Some((last_x, last_y)) if x == last_x + 1 && y == last_y) {
+            out.move_to(x, y)?;
+        }
+        last = Some((x, y));
+        let target = if blink {
+            SgrState::of(cell)
+        } else {
+            SgrState::of(cell).without_blink()
+        };
+        out.write_all(applied.transition(&target).as_bytes())?;
+        applied = target;
+        out.write_all(cell.symbol().as_bytes())?;
+    }
+    if applied != SgrState::default() {
+        out.write_all(b"\x1b[0m")?;
+    }
+    Ok(())
+}
+
+/// A ratatui backend drawing cells through `write_cells` to a
+/// `TerminalOutput`. Sizes and the cursor position are read via crossterm
+/// whichever output is used.
+pub struct SgrDiffBackend<O: TerminalOutput> {
+    out: O,
+    /// Whether the outer terminal blinks text (see `TerminalCapabilities::blink`)
+    blink: bool,
+}
+
+impl<O: TerminalOutput> SgrDiffBackend<O> {
+    pub fn new(out: O) -> Self {
+        Self {
+            out,
+            blink: terminal_capabilities().blink,
+        }
+    }
+
+    /// The output cells are drawn to.
+    pub fn output_mut(&mut self) -> &mut O {
+        &mut self.out
+    }
+}
+
+impl<O: TerminalOutput> Backend for SgrDiffBackend<O> {
+    fn draw<'a, I>(&mut self, content: I) -> io::Result<()>
+    where
+        I: Iterator<Item = (u16, u16, &'a Cell)>,
+    {
+        write_cells(&mut self.out, content, self.blink)
+    }
+
+    fn hide_cursor(&mut self) -> io::Result<()> {
+        self.out.hide_cursor()?;
+        self.out.flush()
+    }
+
+    fn show_cursor(&mut self) -> io::Result<()> {
+        self.out.show_cursor()?;
+        self.out.flush()
+    }
+
+    fn get_cursor_position(&mut self) -> io::Result<Position> {
+        crossterm::cursor::position().map(|(x, y)| Position { x, y })
+    }
+
+    fn set_cursor_position<P: Into<Position>>(&mut self, position: P) -> io::Result<()> {
+        let Position { x, y } = position.into();
+        self.out.move_to(x, y)?;
+        self.out.flush()
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.clear_region(ClearType::All)
+    }
+
+    fn clear_region(&mut self, clear_type: ClearType) -> io::Result<()> {
+        self.out.clear(clear_type)?;
+        self.out.flush()
+    }
+
+    fn append_lines(&mut self, n: u16) -> io::Result<()> {
+        for _ in 0..n {
+            self.out.write_all(b"\n")?;
+        }
+        self.out.flush()
+    }
+
+    fn size(&self) -> io::Result<Size> {
+        let (width, height) = crossterm::terminal::size()?;
+        Ok(Size { width, height })
+    }
+
+    fn window_size(&mut self) -> io::Result<WindowSize> {
+        let crossterm::terminal::WindowSize {
+            columns,
+            rows,
+            width,
+            height,
+        } = crossterm::terminal::window_size()?;
+        Ok(WindowSize {
+            columns_rows: Size {
+                width: columns,
+                height: rows,
+            },
+            pixels: Size { width, height },
+        })
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+impl<O: TerminalOutput> Write for SgrDiffBackend<O> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.out.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mux::output::{AnsiOutput, CrosstermOutput};
+    use ratatui::style::Style;
+
+    fn cell(symbol: &str, style: Style) -> Cell {
+        let mut cell = Cell::default();
+        cell.set_symbol(symbol).set_style(style);
+        cell
+    }
+
+    /// Output for `cells` drawn left to right from the origin.
+    fn draw(cells: &[Cell]) -> Vec<u8> {
+        draw_with_blink(cells, true)
+    }
+
+    fn draw_with_blink(cells: &[Cell], blink: bool) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_cells(
+            &mut AnsiOutput::new(&mut out),
+            cells
+                .iter()
+                .enumerate()
+                .map(|(x, cell)| (x as u16, 0, cell)),
+            blink,
+        )
+        .expect("write");
+        out
+    }
+
+    /// What a renderer re-sending every attribute for every cell would write.
+    fn naive_len(cells: &[Cell]) -> usize {
+        let reset_and_set: usize = cells
+            .iter()
+            .map(|cell| SgrState::default().transition(&SgrState::of(cell)).len() + 4)
+            .sum();
+        "\x1b[1;1H".len() + reset_and_set + cells.len()
+    }
+
+    fn sgr_count(out: &[u8]) -> usize {
+        String::from_utf8_lossy(out).matches('m').count()
+    }
+
+    #[test]
+    fn a_run_of_same_attribute_cells_sets_them_once() {
+        let style = Style::default().fg(Color::Red).add_modifier(Modifier::BOLD);
+        let cells: Vec<Cell> = (0..10).map(|_| cell("x", style)).collect();
+        let out = draw(&cells);
+
+        assert_eq!(out, b"\x1b[1;1H\x1b[1;31mxxxxxxxxxx\x1b[0m");
+        assert_eq!(sgr_count(&out), 2);
+        assert!(out.len() < naive_len(&cells) / 2);
+    }
+
+    #[test]
+    fn alternating_attributes_emit_only_the_delta() {
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        let red = bold.fg(Color::Red);
+        let green = bold.fg(Color::Green);
+        let cells: Vec<Cell> = (0..10)
+            .map(|i| cell("x", if i % 2 == 0 { red } else { green }))
+            .collect();
+        let out = draw(&cells);
+
+        // Bold stays on; only the foreground changes between cells
+        let text = String::from_utf8_lossy(&out);
+        assert!(
+            text.starts_with("\x1b[1;1H\x1b[1;31mx\x1b[32mx\x1b[31mx"),
+            "{text:?}"
+        );
+        assert_eq!(text.matches("\x1b[0m").count(), 1);
+        assert_eq!(sgr_count(&out), 11);
+        assert!(out.len() < naive_len(&cells));
+
+        // Same-attribute output is cheaper still
+        let same: Vec<Cell> = (0..10).map(|_| cell("x", red)).collect();
+        assert!(draw(&same).len() < out.len());
+    }
+
+    #[test]
+    fn reset_is_used_only_when_strictly_cheaper() {
+        let busy = SgrState {
+            fg: Color::Rgb(255, 0, 0),
+            modifier: Modifier::BOLD | Modifier::ITALIC | Modifier::UNDERLINED,
+            ..SgrState::default()
+        };
+        assert_eq!(busy.transition(&SgrState::default()), "\x1b[0m");
+
+        // Dropping bold but keeping a color: "22" beats "0;31"
+        let bold_red = SgrState {
+            fg: Color::Red,
+            modifier: Modifier::BOLD,
+            ..SgrState::default()
+        };
+        let red = SgrState {
+            fg: Color::Red,
+            ..SgrState::default()
+        };
+        assert_eq!(bold_red.transition(&red), "\x1b[22m");
+    }
+
+    #[test]
+    fn clearing_bold_keeps_dim() {
+        let both = SgrState {
+            modifier: Modifier::BOLD | Modifier::DIM,
+            ..SgrState::default()
+        };
+        let dim = SgrState {
+            modifier: Modifier::DIM,
+            ..SgrState::default()
+        };
+        // "22" also clears dim, so the delta has to set it again...
+        assert_eq!(both.delta_params(&dim), ["22", "2"]);
+        // ...which makes a reset the shorter way there
+        assert_eq!(both.transition(&dim), "\x1b[0;2m");
+    }
+
+    #[test]
+    fn colors_map_to_their_sgr_parameters() {
+        let target = SgrState {
+            fg: Color::LightBlue,
+            bg: Color::Indexed(236),
+            underline_color: Color::Rgb(1, 2, 3),
+            ..SgrState::default()
+        };
+        assert_eq!(
+            SgrState::default().transition(&target),
+            "\x1b[94;48;5;236;58;2;1;2;3m"
+        );
+    }
+
+    #[test]
+    fn cursor_moves_only_between_non_adjacent_cells() {
+        let a = cell("a", Style::default());
+        let b = cell("b", Style::default());
+        let cells = [(0, 0, &a), (1, 0, &b), (5, 2, &a)];
+        let mut ansi = Vec::new();
+        write_cells(&mut AnsiOutput::new(&mut ansi), cells.into_iter(), true).expect("write");
+        assert_eq!(ansi, b"\x1b[1;1Hab\x1b[3;6Ha");
+
+        let mut crossterm = Vec::new();
+        write_cells(
+            &mut CrosstermOutput::new(&mut crossterm),
+            cells.into_iter(),
+            true,
+        )
+        .expect("write");
+        assert_eq!(crossterm, ansi);
+    }
+
+    #[test]
+    fn blink_is_emitted_where_supported() {
+        let blinking = cell("!", Style::default().add_modifier(Modifier::SLOW_BLINK));
+        assert_eq!(
+            draw_with_blink(&[blinking], true),
+            b"\x1b[1;1H\x1b[5m!\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn unsupported_blink_falls_back_to_reverse_video() {
+        let blinking = cell(
+            "!",
+            Style::default()
+                .fg(Color::Red)
+                .add_modifier(Modifier::SLOW_BLINK),
+        );
+        let out = draw_with_blink(&[blinking], false);
+        assert_eq!(out, b"\x1b[1;1H\x1b[7;31m!\x1b[0m");
+        assert!(!String::from_utf8_lossy(&out).contains("[5"));
+
+        // Already reversed: bold marks it instead
+        let reversed = cell(
+            "!",
+            Style::default().add_modifier(Modifier::RAPID_BLINK | Modifier::REVERSED),
+        );
+        assert_eq!(
+            draw_with_blink(&[reversed], false),
+            b"\x1b[1;1H\x1b[1;7m!\x1b[0m"
+        );
+    }
+}
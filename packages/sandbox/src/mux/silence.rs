@@ -0,0 +1,111 @@
+//! Notice when a pane stops producing output.
+//!
+//! The inverse of an activity alert: a pane with a silence timeout reports
+//! once when it has printed nothing for that long (say, a build finished),
+//! and re-arms on its next output. Callers pass the current time, so the
+//! timing can be tested without sleeping.
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use crate::mux::layout::PaneId;
+
+/// Last-output times for panes, and which of them already reported silence.
+#[derive(Debug, Default)]
+pub struct SilenceMonitor {
+    last_output: HashMap<PaneId, Instant>,
+    reported: HashSet<PaneId>,
+}
+
+impl SilenceMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Restart `pane_id`'s silence timer.
+    pub fn record_output(&mut self, pane_id: PaneId, now: Instant) {
+        self.last_output.insert(pane_id, now);
+        self.reported.remove(&pane_id);
+    }
+
+    /// Stop tracking a pane (it closed, or its monitor was turned off).
+    pub fn forget(&mut self, pane_id: PaneId) {
+        self.last_output.remove(&pane_id);
+        self.reported.remove(&pane_id);
+    }
+
+    /// Panes that have gone silent for their timeout since their last output.
+    ///
+    /// Each silence is reported once. A pane that has never produced output
+    /// has nothing to finish, so it is not reported.
+    pub fn poll(
+        &mut self,
+        now: Instant,
+        timeouts: impl IntoIterator<Item = (PaneId, Duration)>,
+    ) -> Vec<PaneId> {
+        let mut silent = Vec::new();
+        for (pane_id, timeout) in timeouts {
+            let Some(&last) = self.last_output.get(&pane_id) else {
+                continue;
+            };
+            if now.saturating_duration_since(last) >= timeout && self.reported.insert(pane_id) {
+                silent.push(pane_id);
+            }
+        }
+        silent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[test]
+    fn silence_fires_once_after_the_timeout() {
+        let mut monitor = SilenceMonitor::new();
+        let pane = PaneId::new();
+        let start = Instant::now();
+        monitor.record_output(pane, start);
+
+        assert!(monitor
+            .poll(start + Duration::from_secs(9), [(pane, TIMEOUT)])
+            .is_empty());
+        assert_eq!(monitor.poll(start + TIMEOUT, [(pane, TIMEOUT)]), vec![pane]);
+        assert!(monitor
+            .poll(start + TIMEOUT * 3, [(pane, TIMEOUT)])
+            .is_empty());
+    }
+
+    #[test]
+    fn new_output_cancels_and_rearms_the_timer() {
+        let mut monitor = SilenceMonitor::new();
+        let pane = PaneId::new();
+        let start = Instant::now();
+        monitor.record_output(pane, start);
+        monitor.record_output(pane, start + Duration::from_secs(8));
+
+        assert!(monitor.poll(start + TIMEOUT, [(pane, TIMEOUT)]).is_empty());
+        let silent_at = start + Duration::from_secs(18);
+        assert_eq!(monitor.poll(silent_at, [(pane, TIMEOUT)]), vec![pane]);
+
+        // Output after a reported silence arms it again
+        monitor.record_output(pane, silent_at);
+        assert_eq!(
+            monitor.poll(silent_at + TIMEOUT, [(pane, TIMEOUT)]),
+            vec![pane]
+        );
+    }
+
+    #[test]
+    fn panes_without_output_or_timeout_are_not_reported() {
+        let mut monitor = SilenceMonitor::new();
+        let quiet = PaneId::new();
+        let unmonitored = PaneId::new();
+        let start = Instant::now();
+        monitor.record_output(unmonitored, start);
+
+        assert!(monitor.poll(start + TIMEOUT, [(quiet, TIMEOUT)]).is_empty());
+    }
+}
@@ -1,18 +1,31 @@
 use std::collections::HashSet;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use chrono::{DateTime, Utc};
 use tokio::sync::mpsc;
 
 use crate::models::{NotificationLevel, SandboxNetwork, SandboxStatus, SandboxSummary};
-use crate::mux::commands::MuxCommand;
+use crate::mux::chords::{ChordMatcher, ChordTrie};
+use crate::mux::color_adjust::ColorAdjust;
+use crate::mux::colors::{
+    dmux_nesting_depth, export_theme_config, get_outer_colors, outer_bg_state, pop_color_override,
+    push_color_override, DeferredColorQuery, TerminalColors, HIGH_VISIBILITY_COLORS,
+};
+use crate::mux::commands::{nested_command_for_key, parse_command, CommandInvocation, MuxCommand};
+use crate::mux::dirty::DirtyPanes;
 use crate::mux::events::MuxEvent;
-use crate::mux::layout::{Direction, NavDirection, Pane, PaneId, SandboxId, WorkspaceManager};
+use crate::mux::frame_budget::FrameBudget;
+use crate::mux::layout::{
+    Direction, NavDirection, Pane, PaneContent, PaneExitOutcome, PaneId, SandboxId,
+    WorkspaceManager,
+};
 use crate::mux::onboard::OnboardState;
 use crate::mux::palette::CommandPalette;
 use crate::mux::sidebar::Sidebar;
-use crate::mux::terminal::{SharedTerminalManager, TerminalRenderView};
-use crate::settings::{EditorChoice, Settings};
+use crate::mux::silence::SilenceMonitor;
+use crate::mux::templates;
+use crate::mux::terminal::{CopyModeState, CopyMotion, SharedTerminalManager, TerminalRenderView};
+use crate::settings::{EditorChoice, NestedKeys, OnLastPaneExit, Settings, WorkspaceTemplate};
 use uuid::Uuid;
 
 /// Result of ensuring SSH config is set up for sandboxes.
@@ -303,6 +316,19 @@ impl NotificationsState {
     }
 }
 
+/// Longest gap between two clicks on the same cell that makes a double-click.
+pub const DOUBLE_CLICK_INTERVAL: std::time::Duration = std::time::Duration::from_millis(400);
+
+/// An in-progress mouse drag selection, in pane content cells (row, col).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MouseSelection {
+    pub pane_id: PaneId,
+    pub anchor: (usize, usize),
+    pub head: (usize, usize),
+    /// A double-clicked word, which dragging doesn't change
+    pub by_word: bool,
+}
+
 /// The main application state for the multiplexer.
 pub struct MuxApp<'a> {
     // Core state - WorkspaceManager holds all sandbox workspaces
@@ -348,6 +374,15 @@ pub struct MuxApp<'a> {
     pub last_terminal_views:
         std::collections::HashMap<PaneId, crate::mux::terminal::TerminalRenderView>,
 
+    /// Panes with output since the last frame, drawn together on the next render tick
+    pub dirty_panes: DirtyPanes,
+    /// Panes being redrawn in the frame under way (taken from `dirty_panes`)
+    pub frame_damage: DirtyPanes,
+    /// Frame render times and the quality level they call for
+    pub frame_budget: FrameBudget,
+    /// Retry of a startup color query the outer terminal didn't answer
+    pub deferred_color_query: DeferredColorQuery,
+
     /// Cursor blink state for the active terminal pane (set during render)
     pub cursor_blink: bool,
     /// Cursor color for the active terminal pane (set during render)
@@ -369,6 +404,35 @@ pub struct MuxApp<'a> {
 
     /// Persistent settings (editor choice, etc.)
     pub settings: Settings,
+
+    /// Live pane waiting for a repeated Respawn Pane to confirm restarting it
+    pub pending_respawn: Option<PaneId>,
+
+    /// Whether the high-visibility color override is pushed
+    pub high_visibility: bool,
+
+    /// Color adjustment overlay, while open (its preview is the top color override)
+    pub color_adjust: Option<ColorAdjust>,
+
+    /// Command lines still to run at startup (see `run_startup_commands`)
+    pub startup_commands: std::collections::VecDeque<String>,
+
+    /// How many dmux instances this one runs inside (0 when not nested)
+    pub nesting_depth: u32,
+
+    /// Whether the next key goes straight to the active pane (`SendNextKey`)
+    pub send_next_key: bool,
+
+    /// Multi-key bindings from settings, and the keys of one under way
+    pub key_chords: ChordMatcher,
+
+    /// Mouse drag selection in progress, if any
+    pub mouse_selection: Option<MouseSelection>,
+    /// Where and when the left button was last pressed, to spot double-clicks
+    last_click: Option<(PaneId, (usize, usize), std::time::Instant)>,
+
+    /// When each pane last produced output, for silence monitoring
+    pub silence_monitor: SilenceMonitor,
 }
 
 impl<'a> MuxApp<'a> {
@@ -376,10 +440,25 @@ impl<'a> MuxApp<'a> {
         base_url: String,
         event_tx: mpsc::UnboundedSender<MuxEvent>,
         workspace_path: PathBuf,
+    ) -> Self {
+        Self::with_settings(base_url, event_tx, workspace_path, Settings::load())
+    }
+
+    /// An app using `settings` already loaded, so startup reads the settings
+    /// file only once.
+    pub fn with_settings(
+        base_url: String,
+        event_tx: mpsc::UnboundedSender<MuxEvent>,
+        workspace_path: PathBuf,
+        settings: Settings,
     ) -> Self {
         // Pre-generate SSH key for sandbox access so it's included in initial syncs
         // This ensures VS Code SSH works without additional setup
         let _ = ensure_ssh_config_for_sandboxes(&base_url);
+        let key_chords = ChordMatcher::new(
+            ChordTrie::from_settings(&settings.key_chords),
+            std::time::Duration::from_millis(settings.chord_timeout_ms),
+        );
 
         Self {
             workspace_manager: WorkspaceManager::new(),
@@ -399,13 +478,28 @@ impl<'a> MuxApp<'a> {
             pending_connects: std::collections::VecDeque::new(),
             needs_initial_sandbox: false,
             last_terminal_views: std::collections::HashMap::new(),
+            dirty_panes: DirtyPanes::default(),
+            // Frames drawn outside the render loop are always full
+            frame_damage: DirtyPanes::everything(),
+            frame_budget: FrameBudget::default(),
+            deferred_color_query: DeferredColorQuery::default(),
             cursor_blink: true,
             cursor_color: None,
             onboard: None,
             delta_enabled_sandboxes: HashSet::new(),
             pending_creation_tab_ids: HashSet::new(),
             most_recent_creation_tab_id: None,
-            settings: Settings::load(),
+            startup_commands: settings.startup_commands.iter().cloned().collect(),
+            settings,
+            pending_respawn: None,
+            high_visibility: false,
+            color_adjust: None,
+            nesting_depth: dmux_nesting_depth(),
+            send_next_key: false,
+            key_chords,
+            mouse_selection: None,
+            last_click: None,
+            silence_monitor: SilenceMonitor::new(),
         }
     }
 
@@ -441,6 +535,11 @@ impl<'a> MuxApp<'a> {
 
     /// Set the terminal manager
     pub fn set_terminal_manager(&mut self, manager: SharedTerminalManager) {
+        // Not yet shared with the connection tasks, so never contended
+        if let Ok(mut guard) = manager.try_lock() {
+            guard.set_restore_scroll_on_reattach(self.settings.restore_scroll_on_reattach);
+            guard.set_sync_theme_across_sessions(self.settings.sync_theme_across_sessions);
+        }
         self.terminal_manager = Some(manager);
     }
 
@@ -453,6 +552,31 @@ impl<'a> MuxApp<'a> {
         Some(buffer.render_view(height))
     }
 
+    /// The theme override of the pane's attached session, if any.
+    pub fn session_theme_override(&self, pane_id: PaneId) -> Option<TerminalColors> {
+        let manager = self.terminal_manager.as_ref()?;
+        manager.try_lock().ok()?.session_theme_override(pane_id)
+    }
+
+    /// The colors a pane renders with (see `TerminalManager::effective_colors`),
+    /// or the outer terminal's if the manager is busy.
+    pub fn session_colors(&self, pane_id: PaneId) -> TerminalColors {
+        self.terminal_manager
+            .as_ref()
+            .and_then(|manager| manager.try_lock().ok())
+            .map(|manager| manager.effective_colors(pane_id))
+            .unwrap_or_else(get_outer_colors)
+    }
+
+    /// Whether to leave empty cells unpainted for a translucent terminal:
+    /// the `transparent_background` setting, or a guess from the terminal's
+    /// reported background if unset.
+    pub fn transparent_background(&self) -> bool {
+        self.settings
+            .transparent_background
+            .unwrap_or_else(|| outer_bg_state().likely_transparent())
+    }
+
     /// Get the active pane ID from the active workspace.
     pub fn active_pane_id(&self) -> Option<PaneId> {
         self.workspace_manager
@@ -481,6 +605,113 @@ impl<'a> MuxApp<'a> {
             .is_some_and(|id| self.delta_enabled_sandboxes.contains(&id))
     }
 
+    /// Decide what to do after a pane exit has been applied to the layout.
+    /// Returns the configured behavior only when the exit removed the last remaining pane.
+    /// An exit that matched no pane (e.g. a late exit for a pane already removed when
+    /// several panes exited at once) never triggers the behavior a second time.
+    pub fn last_pane_exit_action(
+        &self,
+        outcome: Option<&PaneExitOutcome>,
+    ) -> Option<OnLastPaneExit> {
+        outcome?;
+        if self.workspace_manager.total_pane_count() > 0 {
+            return None;
+        }
+        Some(self.settings.on_last_pane_exit)
+    }
+
+    /// Open a fresh tab in the given sandbox and queue its pane to connect to the default shell.
+    pub fn respawn_last_pane(&mut self, sandbox_id: SandboxId) -> bool {
+        let Some(ws) = self.workspace_manager.get_workspace_mut(sandbox_id) else {
+            return false;
+        };
+        ws.new_tab();
+        self.workspace_manager.select_sandbox(sandbox_id);
+        self.focus = FocusArea::MainArea;
+        self.pending_connects.push_back(sandbox_id.to_string());
+        true
+    }
+
+    /// Push or pop the high-contrast presentation colors, then have the runner
+    /// redraw and let inner apps re-query the new colors.
+    pub fn toggle_high_visibility(&mut self) {
+        if self.high_visibility {
+            pop_color_override();
+        } else {
+            push_color_override(HIGH_VISIBILITY_COLORS);
+        }
+        self.high_visibility = !self.high_visibility;
+        self.refresh_theme_colors();
+    }
+
+    /// Open the color adjustment overlay on the current colors. Its sliders
+    /// are previewed as a color override for as long as it is open.
+    pub fn open_color_adjust(&mut self) {
+        if self.color_adjust.is_some() {
+            return;
+        }
+        let adjust = ColorAdjust::new(get_outer_colors());
+        push_color_override(adjust.colors());
+        self.color_adjust = Some(adjust);
+        self.refresh_theme_colors();
+    }
+
+    /// Change the open color adjustment and redraw with the new preview.
+    pub fn update_color_adjust(&mut self, change: impl FnOnce(&mut ColorAdjust)) {
+        let Some(adjust) = self.color_adjust.as_mut() else {
+            return;
+        };
+        change(adjust);
+        let colors = adjust.colors();
+        pop_color_override();
+        push_color_override(colors);
+        self.refresh_theme_colors();
+    }
+
+    /// Close the color adjustment overlay. Confirming keeps the previewed
+    /// colors as an override (and with `export`, copies them as a settings
+    /// snippet); cancelling drops it and restores the colors it opened with.
+    pub fn close_color_adjust(&mut self, confirm: bool, export: bool) {
+        if self.color_adjust.take().is_none() {
+            return;
+        }
+        if !confirm {
+            pop_color_override();
+            self.refresh_theme_colors();
+            self.set_status("Color adjustment cancelled");
+        } else if export {
+            self.execute_command(MuxCommand::CopyThemeConfig);
+        } else {
+            self.set_status("Applied adjusted colors; Copy Theme Config exports them");
+        }
+    }
+
+    /// Have the runner redraw with the current outer colors.
+    fn refresh_theme_colors(&self) {
+        let _ = self.event_tx.send(MuxEvent::ThemeColorsRefreshed {
+            colors: get_outer_colors(),
+        });
+    }
+
+    /// Relaunch a pane's recorded command in the same slot.
+    /// A pane whose process exited restarts at once; a live pane only restarts when
+    /// the request is repeated, so a stray command can't kill a running process.
+    pub fn request_respawn_pane(&mut self, pane_id: PaneId) -> bool {
+        let confirmed = self.pending_respawn.take() == Some(pane_id);
+        let Some(pane) = self.workspace_manager.find_pane_mut(pane_id) else {
+            return false;
+        };
+        if !pane.exited && !confirmed {
+            self.pending_respawn = Some(pane_id);
+            self.set_status("Pane is still running; respawn again to restart it");
+            return false;
+        }
+        pane.respawn();
+        let _ = self.event_tx.send(MuxEvent::RespawnPane { pane_id });
+        self.set_status("Respawned pane");
+        true
+    }
+
     /// Clear expired status messages.
     pub fn clear_expired_status(&mut self) {
         if let Some((_, time)) = &self.status_message {
@@ -611,8 +842,404 @@ impl<'a> MuxApp<'a> {
         self.set_status("Opened notification");
     }
 
+    /// The command bound to a key, taking nesting into account (see `nested_command_for_key`).
+    pub fn command_for_key(
+        &self,
+        modifiers: crossterm::event::KeyModifiers,
+        keycode: crossterm::event::KeyCode,
+    ) -> Option<MuxCommand> {
+        let remap = self.settings.nested_keys == NestedKeys::Remap;
+        nested_command_for_key(modifiers, keycode, self.nesting_depth, remap)
+    }
+
+    /// Start a mouse selection at a content cell of `pane_id`.
+    pub fn begin_mouse_selection(&mut self, pane_id: PaneId, at: (usize, usize)) {
+        self.mouse_selection = Some(MouseSelection {
+            pane_id,
+            anchor: at,
+            head: at,
+            by_word: false,
+        });
+    }
+
+    /// Record a left-button press at `now`, returning whether it completes a
+    /// double-click (a second press on the same cell within
+    /// `DOUBLE_CLICK_INTERVAL`).
+    pub fn register_click(
+        &mut self,
+        pane_id: PaneId,
+        at: (usize, usize),
+        now: std::time::Instant,
+    ) -> bool {
+        let double = self.last_click.is_some_and(|(last_pane, last_at, when)| {
+            last_pane == pane_id
+                && last_at == at
+                && now.saturating_duration_since(when) <= DOUBLE_CLICK_INTERVAL
+        });
+        // A third click starts over rather than making another double-click
+        self.last_click = (!double).then_some((pane_id, at, now));
+        double
+    }
+
+    /// Select the word under a content cell of `pane_id`, as delimited by
+    /// the `word_separators` setting (see `TerminalBuffer::word_bounds`).
+    pub fn select_word(&mut self, pane_id: PaneId, at: (usize, usize)) {
+        let separators = &self.settings.word_separators;
+        let bounds = self.terminal_manager.as_ref().and_then(|manager| {
+            let guard = manager.try_lock().ok()?;
+            Some(guard.get_buffer(pane_id)?.word_bounds(at, separators))
+        });
+        let (anchor, head) = bounds.unwrap_or((at, at));
+        self.mouse_selection = Some(MouseSelection {
+            pane_id,
+            anchor,
+            head,
+            by_word: true,
+        });
+    }
+
+    /// Move the end of the mouse selection in progress.
+    pub fn extend_mouse_selection(&mut self, at: (usize, usize)) {
+        if let Some(selection) = self.mouse_selection.as_mut().filter(|s| !s.by_word) {
+            selection.head = at;
+        }
+    }
+
+    /// Complete the mouse selection in progress at `at`.
+    ///
+    /// With `copy_on_select` enabled, the selected text (extracted the same
+    /// way as copy mode, see `TerminalBuffer::selection_text`) is sent to the
+    /// clipboard. A click without a drag selects nothing, but a double-click
+    /// selects a word. Returns whether a copy was requested.
+    pub fn finish_mouse_selection(&mut self, at: (usize, usize)) -> bool {
+        let Some(mut selection) = self.mouse_selection.take() else {
+            return false;
+        };
+        if !selection.by_word {
+            selection.head = at;
+        }
+        let empty = selection.anchor == selection.head && !selection.by_word;
+        if !self.settings.copy_on_select || empty {
+            return false;
+        }
+
+        let text = self.terminal_manager.as_ref().and_then(|manager| {
+            let guard = manager.try_lock().ok()?;
+            let buffer = guard.get_buffer(selection.pane_id)?;
+            Some(buffer.selection_text(selection.anchor, selection.head))
+        });
+        match text {
+            Some(text) if !text.is_empty() => {
+                let _ = self.event_tx.send(MuxEvent::CopyToClipboard { text });
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Put the active pane in copy mode.
+    fn enter_copy_mode(&mut self) {
+        let Some(pane_id) = self.active_pane_id() else {
+            return;
+        };
+        let entered = self
+            .terminal_manager
+            .as_ref()
+            .and_then(|manager| manager.try_lock().ok())
+            .and_then(|mut guard| {
+                guard.get_buffer_mut(pane_id)?.enter_copy_mode();
+                Some(())
+            })
+            .is_some();
+        if entered {
+            self.dirty_panes.mark(pane_id);
+            self.set_status("Copy mode: h/j/k/l/w/b move, v selects, y copies, q leaves");
+        }
+    }
+
+    /// The copy mode state of a pane, or `None` if it isn't in copy mode.
+    pub fn copy_mode(&self, pane_id: PaneId) -> Option<CopyModeState> {
+        let manager = self.terminal_manager.as_ref()?;
+        manager.try_lock().ok()?.get_buffer(pane_id)?.copy_mode()
+    }
+
+    /// Handle a key for the active pane in copy mode: `h`/`l` or Left/Right
+    /// move a glyph, `j`/`k` or Down/Up a row, `w`/`b` a word as delimited by
+    /// the `word_separators` setting, `v` or Space starts or drops a selection, `y` or Enter copies it to the clipboard and leaves, and `q`
+    /// or Esc leaves. Returns false, leaving the key to the pane, if the
+    /// active pane isn't in copy mode.
+    pub fn handle_copy_mode_key(&mut self, key: crossterm::event::KeyCode) -> bool {
+        use crossterm::event::KeyCode;
+
+        let Some(pane_id) = self.active_pane_id() else {
+            return false;
+        };
+        let Some(manager) = self.terminal_manager.clone() else {
+            return false;
+        };
+        let Ok(mut guard) = manager.try_lock() else {
+            return false;
+        };
+        let Some(buffer) = guard.get_buffer_mut(pane_id) else {
+            return false;
+        };
+        if buffer.copy_mode().is_none() {
+            return false;
+        }
+
+        let separators = &self.settings.word_separators;
+        let mut copied = None;
+        match key {
+            KeyCode::Char('h') | KeyCode::Left => {
+                buffer.move_copy_cursor(CopyMotion::Left, separators)
+            }
+            KeyCode::Char('l') | KeyCode::Right => {
+                buffer.move_copy_cursor(CopyMotion::Right, separators)
+            }
+            KeyCode::Char('k') | KeyCode::Up => buffer.move_copy_cursor(CopyMotion::Up, separators),
+            KeyCode::Char('j') | KeyCode::Down => {
+                buffer.move_copy_cursor(CopyMotion::Down, separators)
+            }
+            KeyCode::Char('w') => buffer.move_copy_cursor(CopyMotion::NextWord, separators),
+            KeyCode::Char('b') => buffer.move_copy_cursor(CopyMotion::PrevWord, separators),
+            KeyCode::Char('v') | KeyCode::Char(' ') => buffer.toggle_copy_selection(),
+            KeyCode::Char('y') | KeyCode::Enter => {
+                copied = buffer.copy_selection_text();
+                buffer.exit_copy_mode();
+            }
+            KeyCode::Char('q') | KeyCode::Esc => buffer.exit_copy_mode(),
+            _ => {}
+        }
+        drop(guard);
+
+        self.dirty_panes.mark(pane_id);
+        if let Some(text) = copied.filter(|text| !text.is_empty()) {
+            let _ = self.event_tx.send(MuxEvent::CopyToClipboard { text });
+            self.set_status("Copied selection");
+        }
+        true
+    }
+
+    /// Report monitored panes that have been silent for their timeout as of `now`.
+    pub fn check_pane_silence(&mut self, now: std::time::Instant) {
+        let timeouts = self.workspace_manager.silence_timeouts();
+        for pane_id in self.silence_monitor.poll(now, timeouts) {
+            let _ = self.event_tx.send(MuxEvent::PaneSilence(pane_id));
+        }
+    }
+
+    /// Run queued startup commands once there is a tab to run them in.
+    ///
+    /// New panes connect to the sandbox through the active pane, so after a
+    /// command opens one the rest wait until it has been handed its sandbox.
+    /// Invalid lines are logged, reported in the status bar and skipped.
+    /// Returns whether any command ran.
+    pub fn run_startup_commands(&mut self) -> bool {
+        let mut ran = false;
+        while !self.startup_commands.is_empty() {
+            let awaiting_connect = match self.active_pane_id().and_then(|id| {
+                self.active_tab()
+                    .and_then(|tab| tab.layout.find_pane(id))
+                    .map(|pane| &pane.content)
+            }) {
+                Some(PaneContent::Terminal { sandbox_id, .. }) => sandbox_id.is_none(),
+                Some(_) => false,
+                None => true,
+            };
+            if awaiting_connect {
+                break;
+            }
+            let Some(line) = self.startup_commands.pop_front() else {
+                break;
+            };
+            match parse_command(&line) {
+                Ok(invocation) => self.dispatch_command(invocation),
+                Err(e) => {
+                    tracing::warn!("Skipping startup command {:?}: {}", line, e);
+                    self.set_status(format!("Startup command '{}': {}", line, e));
+                }
+            }
+            ran = true;
+        }
+        ran
+    }
+
+    /// Workspace templates from settings and the templates directory.
+    pub fn workspace_templates(&self) -> Vec<WorkspaceTemplate> {
+        templates::load_templates(
+            &self.settings.workspace_templates,
+            Settings::templates_dir().as_deref(),
+        )
+    }
+
+    /// Spawn the workspace template called `name` (see `spawn_workspace`).
+    pub fn spawn_workspace_named(&mut self, name: &str) {
+        match self
+            .workspace_templates()
+            .into_iter()
+            .find(|template| template.name == name)
+        {
+            Some(template) => self.spawn_workspace(&template, Path::new(templates::WORKSPACE_DIR)),
+            None => self.set_status(format!("No workspace template named '{}'", name)),
+        }
+    }
+
+    /// Open a tab laid out from `template` in the selected sandbox and start
+    /// each pane's command, with relative `cwd`s under `base_dir`. Panes whose
+    /// command can't be run are left empty with a note; the rest still start.
+    pub fn spawn_workspace(&mut self, template: &WorkspaceTemplate, base_dir: &Path) {
+        let Some(sandbox_id) = self.selected_sandbox_id_string() else {
+            self.set_status("No sandbox selected");
+            return;
+        };
+        let tab = templates::build_tab(template, base_dir);
+        let total = tab.layout.pane_count();
+        let terminals: Vec<PaneId> = tab
+            .layout
+            .panes()
+            .into_iter()
+            .filter(|pane| matches!(pane.content, PaneContent::Terminal { .. }))
+            .map(|pane| pane.id)
+            .collect();
+        if self.workspace_manager.add_tab(tab).is_none() {
+            self.set_status("No sandbox selected");
+            return;
+        }
+        let failed = total - terminals.len();
+        for pane_id in terminals {
+            let _ = self.event_tx.send(MuxEvent::ConnectPaneToSandbox {
+                sandbox_id: sandbox_id.clone(),
+                pane_id,
+            });
+        }
+        self.set_status(match failed {
+            0 => format!("Spawned workspace '{}'", template.name),
+            _ => format!(
+                "Spawned workspace '{}'; {} of {} panes couldn't start",
+                template.name, failed, total
+            ),
+        });
+    }
+
+    /// Leave a pane whose process couldn't be started empty, with the error
+    /// in its place.
+    pub fn mark_pane_spawn_failed(&mut self, pane_id: PaneId, message: &str) {
+        if let Some(pane) = self.workspace_manager.find_pane_mut(pane_id) {
+            pane.content = PaneContent::Empty;
+            pane.note = Some(message.to_string());
+        }
+        self.last_terminal_views.remove(&pane_id);
+        self.dirty_panes.mark(pane_id);
+        self.set_status(format!("Pane couldn't start: {}", message));
+    }
+
+    /// Type keys into the active pane, as `send-keys` arguments.
+    pub fn send_keys_to_active_pane(&mut self, args: &[String]) {
+        let result: Result<(), String> = (|| {
+            let pane_id = self.active_pane_id().ok_or("No active pane")?;
+            let manager = self
+                .terminal_manager
+                .as_ref()
+                .ok_or("Terminal manager not available")?;
+            let mut guard = manager
+                .try_lock()
+                .map_err(|_| "Could not access terminal")?;
+            guard.send_keys(pane_id, &args.join(" "))
+        })();
+        if let Err(e) = result {
+            self.set_status(e);
+        }
+    }
+
+    /// Pipe the active pane's output to `args` run through `sh -c`, or with
+    /// no args stop an existing pipe.
+    pub fn pipe_active_pane(&mut self, args: &[String]) {
+        let result: Result<String, String> = (|| {
+            let pane_id = self.active_pane_id().ok_or("No active pane")?;
+            let manager = self
+                .terminal_manager
+                .as_ref()
+                .ok_or("Terminal manager not available")?;
+            let mut guard = manager
+                .try_lock()
+                .map_err(|_| "Could not access terminal")?;
+            if args.is_empty() {
+                if !guard.is_output_piped(pane_id) {
+                    return Err("Usage: pipe-pane COMMAND (run again without one to stop)".into());
+                }
+                guard
+                    .set_output_pipe(pane_id, None)
+                    .map_err(|e| e.to_string())?;
+                return Ok("Stopped piping pane output".to_string());
+            }
+            let line = args.join(" ");
+            let mut command = std::process::Command::new("sh");
+            command.arg("-c").arg(&line);
+            guard
+                .set_output_pipe(pane_id, Some(command))
+                .map_err(|e| format!("Failed to start '{}': {}", line, e))?;
+            Ok(format!("Piping pane output to '{}'", line))
+        })();
+        match result {
+            Ok(status) | Err(status) => self.set_status(status),
+        }
+    }
+
+    /// Reset the active pane's terminal state (`Settings::pane_reset` picks a
+    /// soft or full reset) and redraw it from scratch.
+    pub fn reset_active_pane(&mut self) {
+        let mode = self.settings.pane_reset;
+        let reset = self.active_pane_id().filter(|&pane_id| {
+            self.terminal_manager
+                .as_ref()
+                .and_then(|manager| manager.try_lock().ok())
+                .is_some_and(|mut guard| guard.reset_pane(pane_id, mode))
+        });
+        match reset {
+            Some(pane_id) => {
+                self.last_terminal_views.remove(&pane_id);
+                self.dirty_panes.mark(pane_id);
+                self.set_status("Reset pane");
+            }
+            None => self.set_status("No terminal to reset"),
+        }
+    }
+
+    /// Execute a parsed command line, running its program in the pane it opens.
+    pub fn dispatch_command(&mut self, invocation: CommandInvocation) {
+        if invocation.command == MuxCommand::SendKeys && !invocation.args.is_empty() {
+            self.send_keys_to_active_pane(&invocation.args);
+            return;
+        }
+        if invocation.command == MuxCommand::PipePane {
+            self.pipe_active_pane(&invocation.args);
+            return;
+        }
+        if invocation.command == MuxCommand::SpawnWorkspace && !invocation.args.is_empty() {
+            self.spawn_workspace_named(&invocation.args.join(" "));
+            return;
+        }
+        let before = self.active_pane_id();
+        self.execute_command(invocation.command);
+        if invocation.args.is_empty() {
+            return;
+        }
+        let Some(pane_id) = self.active_pane_id().filter(|&id| Some(id) != before) else {
+            return;
+        };
+        if let Some(pane) = self
+            .active_tab_mut()
+            .and_then(|tab| tab.layout.find_pane_mut(pane_id))
+        {
+            pane.spawn.command = Some(invocation.args);
+        }
+    }
+
     /// Execute a command.
     pub fn execute_command(&mut self, cmd: MuxCommand) {
+        if cmd != MuxCommand::RespawnPane {
+            self.pending_respawn = None;
+        }
         match cmd {
             // Navigation
             MuxCommand::FocusLeft => {
@@ -701,6 +1328,40 @@ impl<'a> MuxApp<'a> {
                     }
                 }
             }
+            MuxCommand::RespawnPane => {
+                if let Some(pane_id) = self.active_pane_id() {
+                    self.request_respawn_pane(pane_id);
+                }
+            }
+            MuxCommand::ResetPane => self.reset_active_pane(),
+            MuxCommand::SendKeys => {
+                self.set_status("Usage: send-keys [-l] KEY... (e.g. send-keys C-c)");
+            }
+            MuxCommand::PipePane => self.pipe_active_pane(&[]),
+            MuxCommand::MonitorSilence => {
+                if let Some(pane_id) = self.active_pane_id() {
+                    let timeout =
+                        std::time::Duration::from_secs(self.settings.silence_timeout_secs);
+                    let monitoring = self.workspace_manager.find_pane_mut(pane_id).map(|pane| {
+                        pane.silence_timeout = match pane.silence_timeout {
+                            Some(_) => None,
+                            None => Some(timeout),
+                        };
+                        pane.silence_timeout.is_some()
+                    });
+                    match monitoring {
+                        Some(true) => self.set_status(format!(
+                            "Notifying after {}s of silence",
+                            timeout.as_secs()
+                        )),
+                        Some(false) => {
+                            self.silence_monitor.forget(pane_id);
+                            self.set_status("Silence monitor off");
+                        }
+                        None => {}
+                    }
+                }
+            }
             MuxCommand::ToggleZoom => {
                 if let Some(tab) = self.active_tab() {
                     if self.zoomed_pane.is_some() {
@@ -763,6 +1424,21 @@ impl<'a> MuxApp<'a> {
             MuxCommand::MoveTabRight => {
                 self.workspace_manager.move_tab_right();
             }
+            MuxCommand::SpawnWorkspace => {
+                let names: Vec<String> = self
+                    .workspace_templates()
+                    .into_iter()
+                    .map(|template| template.name)
+                    .collect();
+                if names.is_empty() {
+                    self.set_status("Usage: spawn-workspace NAME (no templates configured)");
+                } else {
+                    self.set_status(format!(
+                        "Usage: spawn-workspace NAME ({})",
+                        names.join(", ")
+                    ));
+                }
+            }
 
             // Sidebar - Ctrl+S toggles focus between sidebar and main area
             MuxCommand::ToggleSidebar => {
@@ -967,6 +1643,36 @@ impl<'a> MuxApp<'a> {
                     }
                 }
             }
+            MuxCommand::CopyThemeConfig => {
+                let snippet = export_theme_config();
+                match arboard::Clipboard::new() {
+                    Ok(mut clipboard) => match clipboard.set_text(&snippet) {
+                        Ok(()) => self.set_status("Copied theme config to clipboard"),
+                        Err(e) => self.set_status(format!("Failed to copy: {}", e)),
+                    },
+                    Err(e) => self.set_status(format!("Clipboard not available: {}", e)),
+                }
+            }
+            MuxCommand::RefreshThemeColors => {
+                // The runner leaves the alternate screen to re-query, same as on SIGUSR1
+                let _ = self.event_tx.send(MuxEvent::ThemeChanged {
+                    colors: get_outer_colors(),
+                });
+                self.set_status("Refreshing theme colors...");
+            }
+            MuxCommand::ToggleHighVisibility => {
+                self.toggle_high_visibility();
+            }
+            MuxCommand::AdjustColors => {
+                self.open_color_adjust();
+            }
+            MuxCommand::SendNextKey => {
+                self.send_next_key = true;
+                self.set_status("Next key goes to the pane");
+            }
+            MuxCommand::CopyMode => {
+                self.enter_copy_mode();
+            }
             MuxCommand::OpenWith => {
                 // This normally opens a submenu in the palette, but if executed directly:
                 self.set_status("Use command palette to choose an editor");
@@ -1305,8 +2011,20 @@ impl<'a> MuxApp<'a> {
                 };
                 self.set_status(format!("Sandbox {}: {}", sandbox_id, state));
             }
-            MuxEvent::TerminalOutput { .. } => {
-                // TODO: Forward to appropriate pane
+            MuxEvent::TerminalOutput { pane_id } => {
+                self.silence_monitor
+                    .record_output(pane_id, std::time::Instant::now());
+                self.dirty_panes.mark(pane_id);
+            }
+            MuxEvent::PaneSilence(pane_id) => {
+                self.record_notification(
+                    "Pane went silent".to_string(),
+                    NotificationLevel::Info,
+                    None,
+                    None,
+                    Some(pane_id.to_string()),
+                );
+                self.set_status("Pane went silent");
             }
             MuxEvent::Error(msg) => {
                 self.set_status(format!("Error: {}", msg));
@@ -1328,13 +2046,16 @@ impl<'a> MuxApp<'a> {
                 // The runner handles pending_connect for terminal connection
                 self.set_status(format!("Connecting to sandbox: {}", sandbox_id));
             }
-            MuxEvent::ConnectActivePaneToSandbox => {
+            MuxEvent::ConnectActivePaneToSandbox | MuxEvent::ConnectPaneToSandbox { .. } => {
                 // This is handled in the runner, just acknowledge here
             }
-            MuxEvent::TerminalExited { .. } => {
+            MuxEvent::PaneSpawnFailed { pane_id, message } => {
+                self.mark_pane_spawn_failed(pane_id, &message);
+            }
+            MuxEvent::TerminalExited { .. } | MuxEvent::RespawnPane { .. } => {
                 // Cleanup is handled in the runner where terminal state is available
             }
-            MuxEvent::ThemeChanged { .. } => {
+            MuxEvent::ThemeChanged { .. } | MuxEvent::ThemeColorsRefreshed { .. } => {
                 // Theme change is handled in the runner
             }
             MuxEvent::Onboard(_) => {
@@ -1346,6 +2067,9 @@ impl<'a> MuxApp<'a> {
             MuxEvent::ExecInSandbox { .. } => {
                 // Exec requests are handled in the runner
             }
+            MuxEvent::CopyToClipboard { .. } => {
+                // Clipboard writes go through the runner's backend
+            }
         }
     }
 
@@ -1554,6 +2278,154 @@ mod tests {
             .is_none());
     }
 
+    fn app_with_single_pane(mode: OnLastPaneExit) -> (MuxApp<'static>, SandboxId, PaneId) {
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut app = MuxApp::new("http://localhost".to_string(), tx, PathBuf::from("."));
+        app.settings.on_last_pane_exit = mode;
+        let sandbox_id = SandboxId::new();
+        app.workspace_manager.add_sandbox(sandbox_id, "Test");
+        let pane_id = app.active_pane_id().expect("pane should exist");
+        (app, sandbox_id, pane_id)
+    }
+
+    #[test]
+    fn last_pane_exit_quit() {
+        let (mut app, _, pane_id) = app_with_single_pane(OnLastPaneExit::Quit);
+        let outcome = app.workspace_manager.handle_pane_exit(pane_id);
+        assert_eq!(
+            app.last_pane_exit_action(outcome.as_ref()),
+            Some(OnLastPaneExit::Quit)
+        );
+    }
+
+    #[test]
+    fn last_pane_exit_keep_empty() {
+        let (mut app, _, pane_id) = app_with_single_pane(OnLastPaneExit::KeepEmpty);
+        let outcome = app.workspace_manager.handle_pane_exit(pane_id);
+        assert_eq!(
+            app.last_pane_exit_action(outcome.as_ref()),
+            Some(OnLastPaneExit::KeepEmpty)
+        );
+        assert_eq!(app.workspace_manager.total_pane_count(), 0);
+    }
+
+    #[test]
+    fn last_pane_exit_respawn_opens_new_pane() {
+        let (mut app, sandbox_id, pane_id) = app_with_single_pane(OnLastPaneExit::Respawn);
+        let outcome = app.workspace_manager.handle_pane_exit(pane_id);
+        assert_eq!(
+            app.last_pane_exit_action(outcome.as_ref()),
+            Some(OnLastPaneExit::Respawn)
+        );
+
+        assert!(app.respawn_last_pane(sandbox_id));
+        assert_eq!(app.workspace_manager.total_pane_count(), 1);
+        assert_ne!(app.active_pane_id(), Some(pane_id));
+        assert_eq!(app.pending_connects.back(), Some(&sandbox_id.to_string()));
+    }
+
+    #[test]
+    fn non_last_pane_exit_takes_no_action() {
+        let (mut app, _, first_pane) = app_with_single_pane(OnLastPaneExit::Quit);
+        if let Some(tab) = app.active_tab_mut() {
+            tab.split(Direction::Vertical, Pane::terminal(None, "Second"));
+        }
+        let outcome = app.workspace_manager.handle_pane_exit(first_pane);
+        assert_eq!(app.last_pane_exit_action(outcome.as_ref()), None);
+    }
+
+    #[test]
+    fn near_simultaneous_exits_decide_once() {
+        let (mut app, sandbox_id, first_pane) = app_with_single_pane(OnLastPaneExit::Respawn);
+        let second_pane = {
+            let tab = app.active_tab_mut().expect("tab should exist");
+            tab.split(Direction::Vertical, Pane::terminal(None, "Second"));
+            tab.active_pane.expect("split pane should be active")
+        };
+
+        let outcome = app.workspace_manager.handle_pane_exit(first_pane);
+        assert_eq!(app.last_pane_exit_action(outcome.as_ref()), None);
+
+        let outcome = app.workspace_manager.handle_pane_exit(second_pane);
+        assert_eq!(
+            app.last_pane_exit_action(outcome.as_ref()),
+            Some(OnLastPaneExit::Respawn)
+        );
+        assert!(app.respawn_last_pane(sandbox_id));
+
+        // A duplicate exit for a pane that is already gone must not respawn again.
+        let outcome = app.workspace_manager.handle_pane_exit(second_pane);
+        assert_eq!(app.last_pane_exit_action(outcome.as_ref()), None);
+        assert_eq!(app.workspace_manager.total_pane_count(), 1);
+    }
+
+    #[test]
+    fn respawning_dead_pane_relaunches_command_in_same_slot() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut app = MuxApp::new("http://localhost".to_string(), tx, PathBuf::from("."));
+        app.settings.remain_on_exit = true;
+        app.workspace_manager.add_sandbox(SandboxId::new(), "Test");
+        let first_pane = app.active_pane_id().expect("pane should exist");
+        let dev_server = {
+            let tab = app.active_tab_mut().expect("tab should exist");
+            tab.split(Direction::Vertical, Pane::terminal(None, "Dev server"));
+            let pane_id = tab.active_pane.expect("split pane should be active");
+            let pane = tab.layout.find_pane_mut(pane_id).expect("pane");
+            pane.spawn.command = Some(vec![
+                "npm".to_string(),
+                "run".to_string(),
+                "dev".to_string(),
+            ]);
+            pane_id
+        };
+        let slots = app.active_tab().expect("tab").layout.pane_ids();
+
+        assert!(app.workspace_manager.mark_pane_exited(dev_server));
+        assert!(app.request_respawn_pane(dev_server));
+
+        let tab = app.active_tab().expect("tab");
+        assert_eq!(tab.layout.pane_ids(), slots);
+        assert_eq!(tab.layout.pane_ids(), vec![first_pane, dev_server]);
+        let pane = tab.layout.find_pane(dev_server).expect("pane");
+        assert!(!pane.exited);
+        assert_eq!(pane.spawn.generation, 1);
+        assert_eq!(
+            pane.spawn.command.as_deref(),
+            Some(["npm".to_string(), "run".to_string(), "dev".to_string()].as_slice())
+        );
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(MuxEvent::RespawnPane { pane_id }) if pane_id == dev_server
+        ));
+    }
+
+    #[test]
+    fn respawning_live_pane_requires_confirmation() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut app = MuxApp::new("http://localhost".to_string(), tx, PathBuf::from("."));
+        app.workspace_manager.add_sandbox(SandboxId::new(), "Test");
+        let pane_id = app.active_pane_id().expect("pane should exist");
+
+        app.execute_command(MuxCommand::RespawnPane);
+        assert!(rx.try_recv().is_err());
+
+        // Any other command in between cancels the pending confirmation
+        app.execute_command(MuxCommand::FocusMainArea);
+        app.execute_command(MuxCommand::RespawnPane);
+        assert!(rx.try_recv().is_err());
+
+        app.execute_command(MuxCommand::RespawnPane);
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(MuxEvent::RespawnPane { pane_id: respawned }) if respawned == pane_id
+        ));
+        let pane = app
+            .active_tab()
+            .and_then(|tab| tab.layout.find_pane(pane_id))
+            .expect("pane keeps its slot");
+        assert_eq!(pane.spawn.generation, 1);
+    }
+
     fn sample_sandbox(name: &str) -> SandboxSummary {
         SandboxSummary {
             id: Uuid::new_v4(),
@@ -1572,4 +2444,381 @@ mod tests {
             correlation_id: None,
         }
     }
+
+    /// Stand in for the runner handing the active pane its sandbox.
+    fn connect_active_pane(app: &mut MuxApp<'_>) {
+        let pane_id = app.active_pane_id().expect("pane should exist");
+        let tab = app.active_tab_mut().expect("tab should exist");
+        if let Some(PaneContent::Terminal { sandbox_id, .. }) = tab
+            .layout
+            .find_pane_mut(pane_id)
+            .map(|pane| &mut pane.content)
+        {
+            *sandbox_id = Some("sandbox".to_string());
+        }
+    }
+
+    #[test]
+    fn startup_commands_build_layout_one_pane_at_a_time() {
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut app = MuxApp::new("http://localhost".to_string(), tx, PathBuf::from("."));
+        app.startup_commands = [
+            "split-horizontal top",
+            "split-vertical",
+            "not-a-command",
+            "focus-left",
+        ]
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+
+        // Nothing runs before there is a tab
+        assert!(!app.run_startup_commands());
+        app.workspace_manager.add_sandbox(SandboxId::new(), "Test");
+        let first_pane = app.active_pane_id().expect("pane should exist");
+        // ...or while the first pane waits for its sandbox
+        assert!(!app.run_startup_commands());
+
+        connect_active_pane(&mut app);
+        assert!(app.run_startup_commands());
+        let top = app.active_pane_id().expect("split pane should be active");
+        assert_ne!(top, first_pane);
+        assert_eq!(app.startup_commands.len(), 3);
+
+        connect_active_pane(&mut app);
+        assert!(app.run_startup_commands());
+        let third = app.active_pane_id().expect("split pane should be active");
+
+        connect_active_pane(&mut app);
+        assert!(app.run_startup_commands());
+        assert!(app.startup_commands.is_empty());
+
+        let tab = app.active_tab().expect("tab");
+        assert_eq!(tab.layout.pane_ids(), vec![first_pane, top, third]);
+        let directions: Vec<Direction> = tab.layout.splits().map(|s| s.direction).collect();
+        assert_eq!(directions, vec![Direction::Horizontal, Direction::Vertical]);
+        let command = |id| {
+            tab.layout
+                .find_pane(id)
+                .and_then(|p| p.spawn.command.clone())
+        };
+        assert_eq!(command(first_pane), None);
+        assert_eq!(command(top), Some(vec!["top".to_string()]));
+        assert_eq!(command(third), None);
+        // The bad line was reported, and the rest still ran
+        let (status, _) = app.status_message.as_ref().expect("status");
+        assert!(status.contains("not-a-command"), "{status}");
+    }
+
+    #[test]
+    fn spawn_workspace_opens_a_tab_and_connects_each_pane() {
+        use crate::settings::{PaneTemplate, TemplateLayout};
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut app = MuxApp::new("http://localhost".to_string(), tx, PathBuf::from("."));
+        let sandbox_id = SandboxId::new();
+        app.workspace_manager.add_sandbox(sandbox_id, "Test");
+        let pane = |command: &str| PaneTemplate {
+            command: Some(command.to_string()),
+            ..PaneTemplate::default()
+        };
+        let template = WorkspaceTemplate {
+            name: "dev".to_string(),
+            layout: TemplateLayout::EvenVertical,
+            panes: vec![pane("cargo test"), pane("\"oops"), pane("git status")],
+        };
+
+        app.spawn_workspace(&template, Path::new("/workspace"));
+
+        let workspace = app.workspace_manager.active_workspace().expect("workspace");
+        assert_eq!(workspace.tabs.len(), 2);
+        let tab = app.active_tab().expect("tab");
+        assert_eq!(tab.name, "dev");
+        let ids = tab.layout.pane_ids();
+        assert_eq!(ids.len(), 3);
+        let commands: Vec<Option<Vec<String>>> = ids
+            .iter()
+            .map(|&id| {
+                tab.layout
+                    .find_pane(id)
+                    .and_then(|p| p.spawn.command.clone())
+            })
+            .collect();
+        assert_eq!(
+            commands,
+            [
+                Some(vec!["cargo".to_string(), "test".to_string()]),
+                None,
+                Some(vec!["git".to_string(), "status".to_string()]),
+            ]
+        );
+        let failed = tab.layout.find_pane(ids[1]).expect("pane");
+        assert!(matches!(failed.content, PaneContent::Empty));
+        assert!(failed.note.is_some());
+
+        // Only the panes that can start are connected
+        let mut connected = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            if let MuxEvent::ConnectPaneToSandbox {
+                sandbox_id: id,
+                pane_id,
+            } = event
+            {
+                assert_eq!(id, sandbox_id.to_string());
+                connected.push(pane_id);
+            }
+        }
+        assert_eq!(connected, [ids[0], ids[2]]);
+        let (status, _) = app.status_message.as_ref().expect("status");
+        assert!(status.contains("1 of 3"), "{status}");
+
+        // A pane whose process fails to spawn is emptied the same way
+        app.mark_pane_spawn_failed(ids[0], "Failed to spawn PTY: not found");
+        let pane = app
+            .active_tab()
+            .and_then(|tab| tab.layout.find_pane(ids[0]))
+            .expect("pane");
+        assert!(matches!(pane.content, PaneContent::Empty));
+        assert_eq!(pane.note.as_deref(), Some("Failed to spawn PTY: not found"));
+    }
+
+    #[test]
+    fn nested_key_bindings_follow_settings() {
+        use crossterm::event::{KeyCode, KeyModifiers};
+
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let mut app = MuxApp::new("http://localhost".to_string(), tx, PathBuf::from("."));
+        let ctrl_alt = KeyModifiers::CONTROL | KeyModifiers::ALT;
+        let split = KeyCode::Char('d');
+
+        app.nesting_depth = 0;
+        app.settings.nested_keys = NestedKeys::Remap;
+        assert_eq!(
+            app.command_for_key(KeyModifiers::ALT, split),
+            Some(MuxCommand::SplitVertical)
+        );
+        assert_eq!(
+            app.command_for_key(ctrl_alt, split),
+            Some(MuxCommand::SplitVertical)
+        );
+
+        app.nesting_depth = 1;
+        assert_eq!(app.command_for_key(KeyModifiers::ALT, split), None);
+        assert_eq!(
+            app.command_for_key(ctrl_alt, split),
+            Some(MuxCommand::SplitVertical)
+        );
+
+        app.settings.nested_keys = NestedKeys::DoublePrefix;
+        assert_eq!(
+            app.command_for_key(KeyModifiers::ALT, split),
+            Some(MuxCommand::SplitVertical)
+        );
+
+        app.execute_command(MuxCommand::SendNextKey);
+        assert!(app.send_next_key);
+    }
+
+    #[test]
+    fn output_from_two_panes_dirties_one_frame() {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let mut app = MuxApp::new("http://localhost".to_string(), tx, PathBuf::from("."));
+        let (first, second) = (PaneId::new(), PaneId::new());
+
+        app.handle_event(MuxEvent::TerminalOutput { pane_id: first });
+        app.handle_event(MuxEvent::TerminalOutput { pane_id: second });
+
+        let frame = app.dirty_panes.take();
+        assert!(frame.contains(first) && frame.contains(second));
+        assert!(!frame.contains(PaneId::new()));
+        assert!(app.dirty_panes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reset_pane_uses_configured_reset() {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let mut app = MuxApp::new(
+            "http://localhost".to_string(),
+            tx.clone(),
+            PathBuf::from("."),
+        );
+        let manager =
+            crate::mux::terminal::create_terminal_manager("http://localhost".to_string(), tx);
+        app.set_terminal_manager(manager.clone());
+        app.add_sandbox(&Uuid::new_v4().to_string(), "reset");
+        let pane_id = app.active_pane_id().expect("sandbox workspace has a pane");
+        let text = |app: &MuxApp| -> String {
+            let view = app.get_terminal_view(pane_id, 4).expect("view");
+            view.lines[0]
+                .spans
+                .iter()
+                .map(|s| s.content.as_ref())
+                .collect()
+        };
+
+        // Line drawing maps 'q' to a horizontal line until the soft reset
+        manager
+            .lock()
+            .await
+            .handle_output(pane_id, b"\x1b(0q".to_vec());
+        app.execute_command(MuxCommand::ResetPane);
+        manager.lock().await.handle_output(pane_id, b"q".to_vec());
+        assert!(text(&app).starts_with("─q"));
+
+        app.settings.pane_reset = crate::settings::PaneReset::Full;
+        app.execute_command(MuxCommand::ResetPane);
+        assert!(text(&app).trim().is_empty());
+        assert!(app.dirty_panes.contains(pane_id));
+    }
+
+    async fn app_with_selected_text(
+        copy_on_select: bool,
+    ) -> (MuxApp<'static>, mpsc::UnboundedReceiver<MuxEvent>, PaneId) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut app = MuxApp::new(
+            "http://localhost".to_string(),
+            tx.clone(),
+            PathBuf::from("."),
+        );
+        app.settings.copy_on_select = copy_on_select;
+        let manager =
+            crate::mux::terminal::create_terminal_manager("http://localhost".to_string(), tx);
+        let pane_id = PaneId::new();
+        manager
+            .lock()
+            .await
+            .handle_output(pane_id, b"first line\r\nsecond line".to_vec());
+        app.set_terminal_manager(manager);
+        (app, rx, pane_id)
+    }
+
+    #[tokio::test]
+    async fn mouse_selection_copies_when_enabled() {
+        let (mut app, mut rx, pane_id) = app_with_selected_text(true).await;
+
+        app.begin_mouse_selection(pane_id, (0, 6));
+        app.extend_mouse_selection((1, 2));
+        assert!(app.finish_mouse_selection((1, 5)));
+        assert!(app.mouse_selection.is_none());
+
+        match rx.try_recv() {
+            Ok(MuxEvent::CopyToClipboard { text }) => assert_eq!(text, "line\nsecond"),
+            other => panic!("expected CopyToClipboard, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn double_click_selects_a_word() {
+        let (mut app, mut rx, pane_id) = app_with_selected_text(true).await;
+        let now = std::time::Instant::now();
+
+        assert!(!app.register_click(pane_id, (1, 2), now));
+        assert!(app.register_click(pane_id, (1, 2), now + DOUBLE_CLICK_INTERVAL));
+        // A third click, a click elsewhere, or a slow second click is single
+        assert!(!app.register_click(pane_id, (1, 2), now + DOUBLE_CLICK_INTERVAL));
+        assert!(!app.register_click(pane_id, (1, 3), now + DOUBLE_CLICK_INTERVAL));
+        assert!(!app.register_click(pane_id, (1, 3), now + DOUBLE_CLICK_INTERVAL * 3));
+
+        app.select_word(pane_id, (1, 2));
+        // Dragging after a double-click keeps the word
+        app.extend_mouse_selection((1, 9));
+        assert!(app.finish_mouse_selection((1, 9)));
+        match rx.try_recv() {
+            Ok(MuxEvent::CopyToClipboard { text }) => assert_eq!(text, "second"),
+            other => panic!("expected CopyToClipboard, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn copy_mode_keys_select_and_copy() {
+        use crossterm::event::KeyCode;
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut app = MuxApp::new(
+            "http://localhost".to_string(),
+            tx.clone(),
+            PathBuf::from("."),
+        );
+        let manager =
+            crate::mux::terminal::create_terminal_manager("http://localhost".to_string(), tx);
+        app.set_terminal_manager(manager.clone());
+        app.add_sandbox(&Uuid::new_v4().to_string(), "copy");
+        let pane_id = app.active_pane_id().expect("sandbox workspace has a pane");
+        manager
+            .lock()
+            .await
+            .handle_output(pane_id, b"first line\r\nsecond".to_vec());
+        while rx.try_recv().is_ok() {}
+
+        // Outside copy mode keys go to the pane
+        assert!(!app.handle_copy_mode_key(KeyCode::Char('k')));
+        app.execute_command(MuxCommand::CopyMode);
+        assert_eq!(
+            app.copy_mode(pane_id).map(|state| state.cursor),
+            Some((1, 6))
+        );
+
+        // From past "second" up to the start of "line"
+        for key in [
+            KeyCode::Char('v'),
+            KeyCode::Char('k'),
+            KeyCode::Char('l'),
+            KeyCode::Char('h'),
+        ] {
+            assert!(app.handle_copy_mode_key(key));
+        }
+        assert!(app.handle_copy_mode_key(KeyCode::Char('y')));
+        assert!(app.copy_mode(pane_id).is_none());
+        match rx.try_recv() {
+            Ok(MuxEvent::CopyToClipboard { text }) => assert_eq!(text, "line\nsecond"),
+            other => panic!("expected CopyToClipboard, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn copy_mode_word_motion_follows_word_separators() {
+        use crossterm::event::KeyCode;
+
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let mut app = MuxApp::new(
+            "http://localhost".to_string(),
+            tx.clone(),
+            PathBuf::from("."),
+        );
+        let manager =
+            crate::mux::terminal::create_terminal_manager("http://localhost".to_string(), tx);
+        app.set_terminal_manager(manager.clone());
+        app.add_sandbox(&Uuid::new_v4().to_string(), "words");
+        let pane_id = app.active_pane_id().expect("sandbox workspace has a pane");
+        manager
+            .lock()
+            .await
+            .handle_output(pane_id, b"cd /usr/local".to_vec());
+        let cursor = |app: &MuxApp| app.copy_mode(pane_id).expect("copy mode").cursor;
+
+        app.settings.word_separators = String::new();
+        app.execute_command(MuxCommand::CopyMode);
+        app.handle_copy_mode_key(KeyCode::Char('b'));
+        assert_eq!(cursor(&app), (0, 3));
+        app.handle_copy_mode_key(KeyCode::Char('q'));
+
+        // With '/' ending words, the path is split into its parts
+        app.settings.word_separators = "/".to_string();
+        app.execute_command(MuxCommand::CopyMode);
+        app.handle_copy_mode_key(KeyCode::Char('b'));
+        assert_eq!(cursor(&app), (0, 8));
+        app.handle_copy_mode_key(KeyCode::Char('b'));
+        assert_eq!(cursor(&app), (0, 4));
+        app.handle_copy_mode_key(KeyCode::Char('w'));
+        assert_eq!(cursor(&app), (0, 8));
+    }
+
+    #[tokio::test]
+    async fn mouse_selection_does_not_copy_when_disabled() {
+        let (mut app, mut rx, pane_id) = app_with_selected_text(false).await;
+
+        app.begin_mouse_selection(pane_id, (0, 0));
+        assert!(!app.finish_mouse_selection((1, 5)));
+        assert!(rx.try_recv().is_err());
+    }
 }
@@ -0,0 +1,308 @@
+//! Workspace templates: a tab of panes laid out by a preset, each running its
+//! own command in its own directory, so a workspace can be opened the same
+//! way every time.
+//!
+//! Templates come from the `workspace_templates` setting and from JSON files
+//! in `Settings::templates_dir`. A pane whose command can't be run is left
+//! empty with a note saying why, and the rest of the workspace still opens.
+
+use std::path::Path;
+
+use crate::mux::layout::{Direction, LayoutNode, LayoutPreset, Pane, Tab};
+use crate::settings::{PaneTemplate, TemplateLayout, WorkspaceTemplate};
+
+/// Where relative template `cwd`s resolve: the sandbox's workspace.
+pub const WORKSPACE_DIR: &str = "/workspace";
+
+impl From<TemplateLayout> for LayoutPreset {
+    fn from(layout: TemplateLayout) -> Self {
+        match layout {
+            TemplateLayout::EvenHorizontal => LayoutPreset::EvenHorizontal,
+            TemplateLayout::EvenVertical => LayoutPreset::EvenVertical,
+            TemplateLayout::Tiled => LayoutPreset::Tiled,
+        }
+    }
+}
+
+/// The templates from settings, followed by those in the `*.json` files in
+/// `dir` in file name order. Files that can't be read are logged and skipped.
+pub fn load_templates(
+    configured: &[WorkspaceTemplate],
+    dir: Option<&Path>,
+) -> Vec<WorkspaceTemplate> {
+    let mut templates = configured.to_vec();
+    let Some(entries) = dir.and_then(|dir| std::fs::read_dir(dir).ok()) else {
+        return templates;
+    };
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+    for path in paths {
+        match load_template_file(&path) {
+            Ok(template) => templates.push(template),
+            Err(e) => tracing::warn!("Skipping workspace template {:?}: {}", path, e),
+        }
+    }
+    templates
+}
+
+/// Read a template file. A template without a name is named after the file.
+pub fn load_template_file(path: &Path) -> Result<WorkspaceTemplate, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut template: WorkspaceTemplate =
+        serde_json::from_str(&contents).map_err(|e| e.to_string())?;
+    if template.name.is_empty() {
+        if let Some(stem) = path.file_stem() {
+            template.name = stem.to_string_lossy().into_owned();
+        }
+    }
+    Ok(template)
+}
+
+/// Split a command line into words as a shell would: whitespace separates
+/// words, quotes group them, and a backslash outside single quotes escapes
+/// the next character.
+pub fn split_command_line(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote: Option<char> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('\''), c) => word.get_or_insert_with(String::new).push(c),
+            (_, '\\') => {
+                let escaped = chars.next().unwrap_or('\\');
+                word.get_or_insert_with(String::new).push(escaped);
+            }
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (_, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        return Err("unterminated quote".to_string());
+    }
+    words.extend(word);
+    if words.is_empty() {
+        return Err("empty command".to_string());
+    }
+    Ok(words)
+}
+
+/// The program a template pane runs, started in its `cwd` (relative to
+/// `base_dir`) when it has one. `None` runs the sandbox's default shell.
+pub fn pane_command(pane: &PaneTemplate, base_dir: &Path) -> Result<Option<Vec<String>>, String> {
+    let command = pane
+        .command
+        .as_deref()
+        .map(split_command_line)
+        .transpose()?;
+    let Some(cwd) = &pane.cwd else {
+        return Ok(command);
+    };
+    let dir = base_dir.join(cwd);
+    let dir = dir.to_str().ok_or("cwd is not valid UTF-8")?;
+    // The directory and program go in as positional arguments, so they need
+    // no quoting
+    let script = match command {
+        Some(_) => "cd \"$0\" && exec \"$@\"",
+        None => "cd \"$0\" && exec \"${SHELL:-sh}\" -l",
+    };
+    let mut argv = vec!["sh".to_string(), "-c".to_string(), script.to_string()];
+    argv.push(dir.to_string());
+    argv.extend(command.unwrap_or_default());
+    Ok(Some(argv))
+}
+
+/// A pane for one template slot, or an empty pane with a note if its command
+/// can't be run.
+fn template_pane(slot: &PaneTemplate, base_dir: &Path) -> Pane {
+    match pane_command(slot, base_dir) {
+        Ok(command) => {
+            let title = slot
+                .title
+                .clone()
+                .or_else(|| slot.command.clone())
+                .unwrap_or_else(|| "Terminal".to_string());
+            let mut pane = Pane::terminal(None, title);
+            pane.spawn.command = command;
+            pane
+        }
+        Err(e) => {
+            let mut pane = Pane::empty();
+            let command = slot.command.as_deref().unwrap_or_default();
+            pane.note = Some(format!("Couldn't run '{}': {}", command, e));
+            pane
+        }
+    }
+}
+
+/// The tab a template describes: one pane per slot, in the template's layout,
+/// with the first pane active. The panes are not connected yet. A template
+/// without panes gets a single default shell.
+pub fn build_tab(template: &WorkspaceTemplate, base_dir: &Path) -> Tab {
+    let mut tab = Tab::new(template.name.clone());
+    let mut panes = template
+        .panes
+        .iter()
+        .map(|slot| template_pane(slot, base_dir));
+    let Some(first) = panes.next() else {
+        return tab;
+    };
+    let first_id = first.id;
+    tab.layout = LayoutNode::Pane(first);
+    tab.active_pane = Some(first_id);
+    for pane in panes {
+        tab.split(Direction::Vertical, pane);
+    }
+    // Template panes aren't pinned, so the preset can't conflict
+    let _ = tab.apply_preset(template.layout.into());
+    tab.active_pane = Some(first_id);
+    tab
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mux::layout::PaneContent;
+    use std::path::PathBuf;
+
+    fn slot(command: Option<&str>, cwd: Option<&str>) -> PaneTemplate {
+        PaneTemplate {
+            command: command.map(str::to_string),
+            cwd: cwd.map(PathBuf::from),
+            title: None,
+        }
+    }
+
+    fn words(words: &[&str]) -> Vec<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn template_builds_one_pane_per_slot_with_its_command() {
+        let template = WorkspaceTemplate {
+            name: "dev".to_string(),
+            layout: TemplateLayout::Tiled,
+            panes: vec![
+                slot(Some("cargo watch -x 'test --lib'"), None),
+                slot(Some("npm run dev"), Some("web")),
+                slot(None, None),
+            ],
+        };
+        let tab = build_tab(&template, Path::new("/workspace"));
+
+        assert_eq!(tab.name, "dev");
+        assert_eq!(tab.layout.pane_count(), 3);
+        let ids = tab.layout.pane_ids();
+        assert_eq!(tab.active_pane, ids.first().copied());
+        let panes: Vec<&Pane> = ids
+            .iter()
+            .map(|&id| tab.layout.find_pane(id).expect("pane"))
+            .collect();
+        assert_eq!(
+            panes[0].spawn.command,
+            Some(words(&["cargo", "watch", "-x", "test --lib"]))
+        );
+        assert_eq!(
+            panes[1].spawn.command,
+            Some(words(&[
+                "sh",
+                "-c",
+                "cd \"$0\" && exec \"$@\"",
+                "/workspace/web",
+                "npm",
+                "run",
+                "dev"
+            ]))
+        );
+        assert_eq!(panes[2].spawn.command, None);
+        assert_eq!(panes[1].title(), "npm run dev");
+        assert!(panes.iter().all(|pane| matches!(
+            pane.content,
+            PaneContent::Terminal {
+                sandbox_id: None,
+                ..
+            }
+        )));
+    }
+
+    #[test]
+    fn failed_command_leaves_an_empty_pane_with_a_note() {
+        let template = WorkspaceTemplate {
+            name: "broken".to_string(),
+            layout: TemplateLayout::EvenVertical,
+            panes: vec![slot(Some("htop"), None), slot(Some("echo 'oops"), None)],
+        };
+        let tab = build_tab(&template, Path::new("/workspace"));
+
+        assert_eq!(tab.layout.pane_count(), 2);
+        let ids = tab.layout.pane_ids();
+        let ok = tab.layout.find_pane(ids[0]).expect("pane");
+        assert_eq!(ok.spawn.command, Some(words(&["htop"])));
+        let failed = tab.layout.find_pane(ids[1]).expect("pane");
+        assert!(matches!(failed.content, PaneContent::Empty));
+        assert_eq!(
+            failed.note.as_deref(),
+            Some("Couldn't run 'echo 'oops': unterminated quote")
+        );
+
+        // No panes opens a plain shell
+        let empty = WorkspaceTemplate::default();
+        assert_eq!(
+            build_tab(&empty, Path::new("/workspace"))
+                .layout
+                .pane_count(),
+            1
+        );
+    }
+
+    #[test]
+    fn command_lines_split_like_a_shell() {
+        assert_eq!(
+            split_command_line(r#"git log  --format="%h %s" 'a b'\ c"#),
+            Ok(words(&["git", "log", "--format=%h %s", "a b c"]))
+        );
+        assert_eq!(split_command_line("echo ''"), Ok(words(&["echo", ""])));
+        assert_eq!(
+            split_command_line(r#"'it\'s'"#),
+            Err("unterminated quote".to_string())
+        );
+        assert_eq!(split_command_line("   "), Err("empty command".to_string()));
+    }
+
+    #[test]
+    fn template_files_load_after_settings() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        std::fs::write(
+            dir.path().join("web.json"),
+            r#"{"layout":"even_vertical","panes":[{"command":"npm run dev"}]}"#,
+        )
+        .expect("write template");
+        std::fs::write(dir.path().join("bad.json"), "not json").expect("write template");
+        std::fs::write(dir.path().join("notes.txt"), "{}").expect("write file");
+        let configured = vec![WorkspaceTemplate {
+            name: "dev".to_string(),
+            ..WorkspaceTemplate::default()
+        }];
+
+        let templates = load_templates(&configured, Some(dir.path()));
+        let names: Vec<&str> = templates.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["dev", "web"]);
+        assert_eq!(templates[1].layout, TemplateLayout::EvenVertical);
+        assert_eq!(templates[1].panes, [slot(Some("npm run dev"), None)]);
+
+        // A missing directory leaves just the configured ones
+        let missing = dir.path().join("missing");
+        assert_eq!(
+            load_templates(&configured, Some(missing.as_path())),
+            configured
+        );
+    }
+}
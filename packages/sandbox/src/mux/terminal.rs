@@ -1,6 +1,7 @@
 use futures::{SinkExt, StreamExt};
 use ratatui::style::{Color, Modifier, Style};
 use std::collections::HashMap;
+use std::process::Command;
 use std::sync::Arc;
 use tokio::sync::mpsc;
 use tokio::sync::Mutex;
@@ -8,11 +9,20 @@ use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
 use vte::{Params, Parser, Perform};
 
 use crate::models::{MuxClientMessage, MuxServerMessage, PtySessionId};
-use crate::mux::character::{CharacterStyles, Row, TerminalCharacter};
-use crate::mux::colors::{get_outer_bg, get_outer_fg};
+use crate::mux::capabilities::terminal_capabilities;
+use crate::mux::character::{CharacterStyles, PromptMark, Row, TerminalCharacter, UnderlineStyle};
+use crate::mux::colors::{
+    get_outer_bg, get_outer_colors, get_outer_fg, nesting_child_env, TerminalColors,
+    ThemeChangeEvent,
+};
 use crate::mux::events::MuxEvent;
 use crate::mux::grid::Grid;
-use crate::mux::layout::{PaneId, TabId};
+use crate::mux::keys::send_keys_input;
+use crate::mux::layout::{PaneId, PaneSpawn, TabId};
+use crate::mux::pane_log::{PaneLog, PaneLogOptions};
+use crate::mux::pane_pipe::PanePipe;
+use crate::mux::scrollback::{MarkerId, PromptDirection};
+use crate::settings::PaneReset;
 
 /// A single cell in the terminal grid (legacy compatibility type).
 /// This is used for backward compatibility with existing tests and APIs.
@@ -396,6 +406,25 @@ fn tekhvc_to_rgb(h: f64, v: f64, c: f64) -> (u8, u8, u8) {
     linear_rgb_to_u8(r, g, b)
 }
 
+/// Parse the parameters after `133` of an OSC 133 shell-integration mark.
+/// Accepts `A`, `B`, `C` and `D[;exit_code]`, ignoring any trailing
+/// `key=value` options.
+pub(crate) fn parse_osc133_mark(params: &[&[u8]]) -> Option<PromptMark> {
+    let mark = match *params.first()? {
+        b"A" => PromptMark::PromptStart,
+        b"B" => PromptMark::CommandStart,
+        b"C" => PromptMark::OutputStart,
+        b"D" => PromptMark::CommandFinished {
+            exit_code: params
+                .get(1)
+                .and_then(|code| std::str::from_utf8(code).ok())
+                .and_then(|code| code.parse().ok()),
+        },
+        _ => return None,
+    };
+    Some(mark)
+}
+
 /// Parse an OSC color specification and return RGB values.
 /// Supports formats:
 /// - `rgb:RRRR/GGGG/BBBB` (X11 format, 16-bit per channel)
@@ -406,7 +435,7 @@ fn tekhvc_to_rgb(h: f64, v: f64, c: f64) -> (u8, u8, u8) {
 /// - `TekHVC:H/V/C`
 /// - `#RRGGBB` (6-digit hex)
 /// - `#RGB` (3-digit hex)
-fn parse_osc_color(s: &str) -> Option<(u8, u8, u8)> {
+pub(crate) fn parse_osc_color(s: &str) -> Option<(u8, u8, u8)> {
     let s = s.trim();
 
     if let Some(rest) = s.strip_prefix("rgb:") {
@@ -436,7 +465,8 @@ fn parse_osc_color(s: &str) -> Option<(u8, u8, u8)> {
                 scale(b, parts[2].len()),
             ));
         }
-    } else if let Some(rest) = s.strip_prefix('#') {
+    } else if let Some(rest) = s.strip_prefix('#').filter(|rest| rest.is_ascii()) {
+        // Lengths below are in bytes, so only ASCII can be sliced safely
         match rest.len() {
             // #RGB -> 4-bit per channel, store high nibble
             3 => {
@@ -799,6 +829,19 @@ struct AlternateScreen {
 }
 
 impl VirtualTerminal {
+    /// Reply to DA1, listing only what the emulator supports:
+    /// 64 = VT420 (left/right margins)
+    /// 17 = terminal state interrogation (DECRQSS, DECRQM)
+    /// 22 = ANSI color
+    /// 28 = rectangular editing (DECFRA, DECERA)
+    const DA1_RESPONSE: &[u8] = b"\x1b[?64;17;22;28c";
+
+    /// Reply to DA2, as xterm version 354+:
+    /// 41 = xterm terminal type
+    /// 354 = version number
+    /// 0 = ROM cartridge registration number (always 0)
+    const DA2_RESPONSE: &[u8] = b"\x1b[>41;354;0c";
+
     pub fn new(rows: usize, cols: usize) -> Self {
         // Initialize default tab stops every 8 columns
         let tab_stops: Vec<usize> = (0..cols).filter(|&c| c % 8 == 0 && c > 0).collect();
@@ -1127,6 +1170,8 @@ impl VirtualTerminal {
             self.pending_wrap = false;
             self.internal_grid.cursor_col = 0;
             self.newline();
+            self.internal_grid
+                .set_row_canonical(self.internal_grid.cursor_row, false);
         }
 
         // Apply line drawing character set if active
@@ -1160,6 +1205,8 @@ impl VirtualTerminal {
                 );
                 self.internal_grid.cursor_col = 0;
                 self.newline();
+                self.internal_grid
+                    .set_row_canonical(self.internal_grid.cursor_row, false);
             } else {
                 // Can't fit, don't print
                 return;
@@ -1249,8 +1296,10 @@ impl VirtualTerminal {
         }
     }
 
-    /// Repeat the last printed character n times
+    /// Repeat the last printed character n times, with the current attributes.
+    /// Capped at one screenful: further repeats would only overwrite it again.
     fn repeat_char(&mut self, n: usize) {
+        let n = n.min(self.internal_grid.rows * self.internal_grid.cols);
         if let Some(c) = self.last_printed_char {
             for _ in 0..n {
                 // Temporarily disable line drawing since character is already translated
@@ -1429,7 +1478,10 @@ impl VirtualTerminal {
             params.push("3".to_string());
         }
         if styles.modifiers.contains(Modifier::UNDERLINED) {
-            params.push("4".to_string());
+            match styles.underline_style {
+                UnderlineStyle::Single => params.push("4".to_string()),
+                style => params.push(format!("4:{}", style.to_sgr())),
+            }
         }
         if styles.modifiers.contains(Modifier::SLOW_BLINK) {
             params.push("5".to_string());
@@ -1454,6 +1506,17 @@ impl VirtualTerminal {
             self.color_to_sgr_params(color, 40, 100, 48, &mut params);
         }
 
+        // Underline color, which has no short forms
+        match styles.underline_color {
+            Some(Color::Rgb(r, g, b)) => params.push(format!("58;2;{};{};{}", r, g, b)),
+            Some(Color::Reset) | None => {}
+            Some(color) => {
+                if let Some(index) = ansi_color_index(color) {
+                    params.push(format!("58;5;{}", index));
+                }
+            }
+        }
+
         params.join(";")
     }
 
@@ -1512,146 +1575,186 @@ impl VirtualTerminal {
         self.internal_grid.scroll_view_up(n)
     }
 
-    /// Parse SGR (Select Graphic Rendition) parameters
-    /// Handles both semicolon-separated (38;2;r;g;b) and colon-separated (38:2:r:g:b) formats
+    /// Apply SGR (Select Graphic Rendition) parameters to the current style
     fn apply_sgr(&mut self, params: &Params) {
-        // Collect params, preserving subparameters for extended color handling
         let raw_params: Vec<&[u16]> = params.iter().collect();
+        let styles = apply_sgr_params(self.internal_grid.current_styles, &raw_params);
+        self.internal_grid.set_current_styles(styles);
+    }
+}
 
-        if raw_params.is_empty() {
-            self.internal_grid
-                .set_current_styles(CharacterStyles::default());
-            return;
-        }
-
-        let mut styles = self.internal_grid.current_styles;
-        let mut i = 0;
-        while i < raw_params.len() {
-            let param = raw_params[i];
-            let code = param[0];
-
-            match code {
-                0 => styles = CharacterStyles::default(),
-                1 => styles = styles.add_modifier(Modifier::BOLD),
-                2 => styles = styles.add_modifier(Modifier::DIM),
-                3 => styles = styles.add_modifier(Modifier::ITALIC),
-                4 => styles = styles.add_modifier(Modifier::UNDERLINED),
-                5 | 6 => styles = styles.add_modifier(Modifier::SLOW_BLINK),
-                7 => styles = styles.add_modifier(Modifier::REVERSED),
-                8 => styles = styles.add_modifier(Modifier::HIDDEN),
-                9 => styles = styles.add_modifier(Modifier::CROSSED_OUT),
-                22 => styles = styles.remove_modifier(Modifier::BOLD | Modifier::DIM),
-                23 => styles = styles.remove_modifier(Modifier::ITALIC),
-                24 => styles = styles.remove_modifier(Modifier::UNDERLINED),
-                25 => styles = styles.remove_modifier(Modifier::SLOW_BLINK),
-                27 => styles = styles.remove_modifier(Modifier::REVERSED),
-                28 => styles = styles.remove_modifier(Modifier::HIDDEN),
-                29 => styles = styles.remove_modifier(Modifier::CROSSED_OUT),
-                // Foreground colors
-                30 => styles = styles.fg(Color::Black),
-                31 => styles = styles.fg(Color::Red),
-                32 => styles = styles.fg(Color::Green),
-                33 => styles = styles.fg(Color::Yellow),
-                34 => styles = styles.fg(Color::Blue),
-                35 => styles = styles.fg(Color::Magenta),
-                36 => styles = styles.fg(Color::Cyan),
-                37 => styles = styles.fg(Color::Gray),
-                38 => {
-                    // Extended foreground color
-                    // Check for colon-separated subparameters first (38:2:r:g:b or 38:5:n)
-                    if param.len() >= 3 && param[1] == 5 {
-                        // 256 color mode with subparameters: 38:5:n
-                        styles = styles.fg(Color::Indexed(param[2] as u8));
-                    } else if param.len() >= 5 && param[1] == 2 {
-                        // RGB color mode with subparameters: 38:2:r:g:b
-                        // Note: Some terminals use 38:2:colorspace:r:g:b (6 params)
-                        let (r, g, b) = if param.len() >= 6 {
-                            // 38:2:colorspace:r:g:b format
-                            (param[3] as u8, param[4] as u8, param[5] as u8)
-                        } else {
-                            // 38:2:r:g:b format
-                            (param[2] as u8, param[3] as u8, param[4] as u8)
-                        };
-                        styles = styles.fg(Color::Rgb(r, g, b));
-                    } else if i + 2 < raw_params.len() && raw_params[i + 1][0] == 5 {
-                        // Semicolon-separated 256 color: 38;5;n
-                        styles = styles.fg(Color::Indexed(raw_params[i + 2][0] as u8));
-                        i += 2;
-                    } else if i + 4 < raw_params.len() && raw_params[i + 1][0] == 2 {
-                        // Semicolon-separated RGB: 38;2;r;g;b
-                        styles = styles.fg(Color::Rgb(
-                            raw_params[i + 2][0] as u8,
-                            raw_params[i + 3][0] as u8,
-                            raw_params[i + 4][0] as u8,
-                        ));
-                        i += 4;
-                    }
+/// Apply SGR (Select Graphic Rendition) parameters to `styles`.
+/// Handles both semicolon-separated (38;2;r;g;b) and colon-separated (38:2:r:g:b) formats.
+/// Each entry of `raw_params` is a parameter with its colon-separated subparameters.
+///
+/// Pure and total: malformed or truncated parameter lists are ignored, never panic.
+pub(crate) fn apply_sgr_params(styles: CharacterStyles, raw_params: &[&[u16]]) -> CharacterStyles {
+    if raw_params.is_empty() {
+        return CharacterStyles::default();
+    }
+
+    // Subparameter slices are never empty coming from vte, but may be from other callers
+    let first = |param: &[u16]| param.first().copied().unwrap_or(0);
+    let mut styles = styles;
+    let mut i = 0;
+    while i < raw_params.len() {
+        let param = raw_params[i];
+        let code = first(param);
+
+        match code {
+            0 => styles = CharacterStyles::default(),
+            1 => styles = styles.add_modifier(Modifier::BOLD),
+            2 => styles = styles.add_modifier(Modifier::DIM),
+            3 => styles = styles.add_modifier(Modifier::ITALIC),
+            // `4:n` picks the underline's shape; `4:0` turns it off
+            4 => match param.get(1).copied() {
+                None => {
+                    styles = styles.add_modifier(Modifier::UNDERLINED);
+                    styles.underline_style = UnderlineStyle::Single;
                 }
-                39 => styles.foreground = None,
-                // Background colors
-                40 => styles = styles.bg(Color::Black),
-                41 => styles = styles.bg(Color::Red),
-                42 => styles = styles.bg(Color::Green),
-                43 => styles = styles.bg(Color::Yellow),
-                44 => styles = styles.bg(Color::Blue),
-                45 => styles = styles.bg(Color::Magenta),
-                46 => styles = styles.bg(Color::Cyan),
-                47 => styles = styles.bg(Color::Gray),
-                48 => {
-                    // Extended background color
-                    // Check for colon-separated subparameters first (48:2:r:g:b or 48:5:n)
-                    if param.len() >= 3 && param[1] == 5 {
-                        // 256 color mode with subparameters: 48:5:n
-                        styles = styles.bg(Color::Indexed(param[2] as u8));
-                    } else if param.len() >= 5 && param[1] == 2 {
-                        // RGB color mode with subparameters: 48:2:r:g:b
-                        // Note: Some terminals use 48:2:colorspace:r:g:b (6 params)
-                        let (r, g, b) = if param.len() >= 6 {
-                            // 48:2:colorspace:r:g:b format
-                            (param[3] as u8, param[4] as u8, param[5] as u8)
-                        } else {
-                            // 48:2:r:g:b format
-                            (param[2] as u8, param[3] as u8, param[4] as u8)
-                        };
-                        styles = styles.bg(Color::Rgb(r, g, b));
-                    } else if i + 2 < raw_params.len() && raw_params[i + 1][0] == 5 {
-                        // Semicolon-separated 256 color: 48;5;n
-                        styles = styles.bg(Color::Indexed(raw_params[i + 2][0] as u8));
-                        i += 2;
-                    } else if i + 4 < raw_params.len() && raw_params[i + 1][0] == 2 {
-                        // Semicolon-separated RGB: 48;2;r;g;b
-                        styles = styles.bg(Color::Rgb(
-                            raw_params[i + 2][0] as u8,
-                            raw_params[i + 3][0] as u8,
-                            raw_params[i + 4][0] as u8,
-                        ));
-                        i += 4;
+                Some(0) => {
+                    styles = styles.remove_modifier(Modifier::UNDERLINED);
+                    styles.underline_style = UnderlineStyle::Single;
+                }
+                Some(n) => {
+                    if let Some(style) = UnderlineStyle::from_sgr(n) {
+                        styles = styles.add_modifier(Modifier::UNDERLINED);
+                        styles.underline_style = style;
                     }
                 }
-                49 => styles.background = None,
-                // Bright foreground colors
-                90 => styles = styles.fg(Color::DarkGray),
-                91 => styles = styles.fg(Color::LightRed),
-                92 => styles = styles.fg(Color::LightGreen),
-                93 => styles = styles.fg(Color::LightYellow),
-                94 => styles = styles.fg(Color::LightBlue),
-                95 => styles = styles.fg(Color::LightMagenta),
-                96 => styles = styles.fg(Color::LightCyan),
-                97 => styles = styles.fg(Color::Indexed(15)), // Bright white
-                // Bright background colors
-                100 => styles = styles.bg(Color::DarkGray),
-                101 => styles = styles.bg(Color::LightRed),
-                102 => styles = styles.bg(Color::LightGreen),
-                103 => styles = styles.bg(Color::LightYellow),
-                104 => styles = styles.bg(Color::LightBlue),
-                105 => styles = styles.bg(Color::LightMagenta),
-                106 => styles = styles.bg(Color::LightCyan),
-                107 => styles = styles.bg(Color::Indexed(15)), // Bright white
-                _ => {}
+            },
+            5 | 6 => styles = styles.add_modifier(Modifier::SLOW_BLINK),
+            7 => styles = styles.add_modifier(Modifier::REVERSED),
+            8 => styles = styles.add_modifier(Modifier::HIDDEN),
+            9 => styles = styles.add_modifier(Modifier::CROSSED_OUT),
+            22 => styles = styles.remove_modifier(Modifier::BOLD | Modifier::DIM),
+            23 => styles = styles.remove_modifier(Modifier::ITALIC),
+            24 => {
+                styles = styles.remove_modifier(Modifier::UNDERLINED);
+                styles.underline_style = UnderlineStyle::Single;
             }
-            i += 1;
+            25 => styles = styles.remove_modifier(Modifier::SLOW_BLINK),
+            27 => styles = styles.remove_modifier(Modifier::REVERSED),
+            28 => styles = styles.remove_modifier(Modifier::HIDDEN),
+            29 => styles = styles.remove_modifier(Modifier::CROSSED_OUT),
+            // Foreground colors
+            30 => styles = styles.fg(Color::Black),
+            31 => styles = styles.fg(Color::Red),
+            32 => styles = styles.fg(Color::Green),
+            33 => styles = styles.fg(Color::Yellow),
+            34 => styles = styles.fg(Color::Blue),
+            35 => styles = styles.fg(Color::Magenta),
+            36 => styles = styles.fg(Color::Cyan),
+            37 => styles = styles.fg(Color::Gray),
+            38 => {
+                let (color, consumed) = extended_color(raw_params, i);
+                if let Some(color) = color {
+                    styles = styles.fg(color);
+                }
+                i += consumed;
+            }
+            39 => styles.foreground = None,
+            // Background colors
+            40 => styles = styles.bg(Color::Black),
+            41 => styles = styles.bg(Color::Red),
+            42 => styles = styles.bg(Color::Green),
+            43 => styles = styles.bg(Color::Yellow),
+            44 => styles = styles.bg(Color::Blue),
+            45 => styles = styles.bg(Color::Magenta),
+            46 => styles = styles.bg(Color::Cyan),
+            47 => styles = styles.bg(Color::Gray),
+            48 => {
+                let (color, consumed) = extended_color(raw_params, i);
+                if let Some(color) = color {
+                    styles = styles.bg(color);
+                }
+                i += consumed;
+            }
+            49 => styles.background = None,
+            58 => {
+                let (color, consumed) = extended_color(raw_params, i);
+                if color.is_some() {
+                    styles.underline_color = color;
+                }
+                i += consumed;
+            }
+            59 => styles.underline_color = None,
+            // Bright foreground colors
+            90 => styles = styles.fg(Color::DarkGray),
+            91 => styles = styles.fg(Color::LightRed),
+            92 => styles = styles.fg(Color::LightGreen),
+            93 => styles = styles.fg(Color::LightYellow),
+            94 => styles = styles.fg(Color::LightBlue),
+            95 => styles = styles.fg(Color::LightMagenta),
+            96 => styles = styles.fg(Color::LightCyan),
+            97 => styles = styles.fg(Color::Indexed(15)), // Bright white
+            // Bright background colors
+            100 => styles = styles.bg(Color::DarkGray),
+            101 => styles = styles.bg(Color::LightRed),
+            102 => styles = styles.bg(Color::LightGreen),
+            103 => styles = styles.bg(Color::LightYellow),
+            104 => styles = styles.bg(Color::LightBlue),
+            105 => styles = styles.bg(Color::LightMagenta),
+            106 => styles = styles.bg(Color::LightCyan),
+            107 => styles = styles.bg(Color::Indexed(15)), // Bright white
+            _ => {}
         }
-        self.internal_grid.set_current_styles(styles);
+        i += 1;
+    }
+    styles
+}
+
+/// The palette index of a named or indexed color.
+fn ansi_color_index(color: Color) -> Option<u8> {
+    Some(match color {
+        Color::Black => 0,
+        Color::Red => 1,
+        Color::Green => 2,
+        Color::Yellow => 3,
+        Color::Blue => 4,
+        Color::Magenta => 5,
+        Color::Cyan => 6,
+        Color::White | Color::Gray => 7,
+        Color::DarkGray => 8,
+        Color::LightRed => 9,
+        Color::LightGreen => 10,
+        Color::LightYellow => 11,
+        Color::LightBlue => 12,
+        Color::LightMagenta => 13,
+        Color::LightCyan => 14,
+        Color::Indexed(n) => n,
+        Color::Rgb(..) | Color::Reset => return None,
+    })
+}
+
+/// Read the extended color (`38`, `48` or `58`) at `raw_params[i]`, given
+/// either as subparameters (`38:5:n`, `38:2:r:g:b`, `38:2:colorspace:r:g:b`)
+/// or as the following parameters (`38;5;n`, `38;2;r;g;b`). Returns the
+/// color and how many following parameters it used.
+fn extended_color(raw_params: &[&[u16]], i: usize) -> (Option<Color>, usize) {
+    let first = |param: &[u16]| param.first().copied().unwrap_or(0);
+    let param = raw_params[i];
+    if param.len() >= 3 && param[1] == 5 {
+        (Some(Color::Indexed(param[2] as u8)), 0)
+    } else if param.len() >= 5 && param[1] == 2 {
+        // Some terminals include a colorspace id before the channels
+        let (r, g, b) = if param.len() >= 6 {
+            (param[3] as u8, param[4] as u8, param[5] as u8)
+        } else {
+            (param[2] as u8, param[3] as u8, param[4] as u8)
+        };
+        (Some(Color::Rgb(r, g, b)), 0)
+    } else if i + 2 < raw_params.len() && first(raw_params[i + 1]) == 5 {
+        (Some(Color::Indexed(first(raw_params[i + 2]) as u8)), 2)
+    } else if i + 4 < raw_params.len() && first(raw_params[i + 1]) == 2 {
+        let color = Color::Rgb(
+            first(raw_params[i + 2]) as u8,
+            first(raw_params[i + 3]) as u8,
+            first(raw_params[i + 4]) as u8,
+        );
+        (Some(color), 4)
+    } else {
+        (None, 0)
     }
 }
 
@@ -1709,6 +1812,8 @@ impl Perform for VirtualTerminal {
             0x0A..=0x0C => {
                 self.newline();
                 self.carriage_return();
+                self.internal_grid
+                    .set_row_canonical(self.internal_grid.cursor_row, true);
             }
             // Carriage return
             0x0D => {
@@ -2015,6 +2120,14 @@ impl Perform for VirtualTerminal {
                         }
                     }
                 }
+                // OSC 133 - Shell integration prompt marks
+                // Format: OSC 133 ; A|B|C ST or OSC 133 ; D [; exit_code] ST
+                "133" => {
+                    if let Some(mark) = parse_osc133_mark(&params[1..]) {
+                        let row = self.internal_grid.cursor_row;
+                        self.internal_grid.set_row_prompt_mark(row, mark);
+                    }
+                }
                 _ => {}
             }
         }
@@ -2201,33 +2314,17 @@ impl Perform for VirtualTerminal {
                     _ => {}
                 }
             }
-            // Device Attributes (DA1 and DA2)
-            'c' => {
+            // Device Attributes (DA1 and DA2). dmux owns the PTY, so it answers
+            // for the terminal it emulates rather than passing the host's through.
+            // Only the request forms (no parameter or 0) are answered; like
+            // xterm, a nonzero parameter such as CSI 1 c gets no reply
+            'c' if matches!(params_vec.as_slice(), [] | [0]) => {
                 if intermediates.is_empty() {
                     // Primary Device Attributes (DA1): CSI c or CSI 0 c
-                    // Respond as xterm-compatible VT420 with capabilities:
-                    // 64 = VT420
-                    // 1 = 132 columns
-                    // 2 = printer port
-                    // 6 = selective erase
-                    // 9 = national replacement character sets
-                    // 15 = technical character set
-                    // 16 = locator port (DEC)
-                    // 17 = terminal state interrogation
-                    // 18 = user windows
-                    // 21 = horizontal scrolling
-                    // 22 = ANSI color
-                    // 28 = rectangular editing
-                    // 29 = ANSI text locator
-                    self.pending_responses
-                        .push(b"\x1b[?64;1;2;6;9;15;16;17;18;21;22;28;29c".to_vec());
+                    self.pending_responses.push(Self::DA1_RESPONSE.to_vec());
                 } else if intermediates == [b'>'] {
                     // Secondary Device Attributes (DA2): CSI > c
-                    // Respond as xterm version 314+:
-                    // 41 = xterm terminal type
-                    // 354 = version number (xterm 354+)
-                    // 0 = ROM cartridge registration number (always 0)
-                    self.pending_responses.push(b"\x1b[>41;354;0c".to_vec());
+                    self.pending_responses.push(Self::DA2_RESPONSE.to_vec());
                 }
             }
             // Set scroll region
@@ -2927,11 +3024,22 @@ struct TerminalSession {
     session_id: PtySessionId,
     /// Sandbox ID this session is connected to
     sandbox_id: String,
+    /// Colors this session renders with in place of the outer terminal's
+    theme_override: Option<TerminalColors>,
+    /// The outer terminal's colors as this session last took them up (see
+    /// `TerminalManager::apply_theme_change`)
+    colors: TerminalColors,
 }
 
 /// Convert PaneId to a session ID string for the multiplexed protocol.
-fn pane_id_to_session_id(pane_id: PaneId) -> PtySessionId {
-    pane_id.to_string()
+/// A respawned pane gets a fresh session so late output from its previous
+/// process can't land in the new one.
+fn pane_id_to_session_id(pane_id: PaneId, generation: u32) -> PtySessionId {
+    if generation == 0 {
+        pane_id.to_string()
+    } else {
+        format!("{}-{}", pane_id, generation)
+    }
 }
 
 /// Sender for the multiplexed WebSocket connection.
@@ -2995,6 +3103,43 @@ impl RenderCache {
     }
 }
 
+/// Whether `c` is part of a word for word motion and selection: neither
+/// whitespace nor one of `separators`.
+pub fn is_word_char(c: char, separators: &str) -> bool {
+    !c.is_whitespace() && !separators.contains(c)
+}
+
+/// Where a pane's view was when its session detached, restored on reattach.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PaneViewState {
+    /// Lines scrolled back from the bottom (0 = live view)
+    pub scroll_offset: usize,
+    /// Copy mode's cursor and selection, if the pane was in copy mode
+    pub copy_mode: Option<CopyModeState>,
+}
+
+/// A pane's keyboard copy mode, in visible cells (row, col).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CopyModeState {
+    /// Where the copy-mode cursor is, always on a glyph's leading half
+    pub cursor: (usize, usize),
+    /// The other end of the selection, once one has been started
+    pub anchor: Option<(usize, usize)>,
+}
+
+/// A copy-mode cursor movement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyMotion {
+    Left,
+    Right,
+    Up,
+    Down,
+    /// To the start of the next word (see `next_word`)
+    NextWord,
+    /// To the start of this or the previous word (see `prev_word`)
+    PrevWord,
+}
+
 /// Terminal output buffer for rendering - now using VirtualTerminal
 pub struct TerminalBuffer {
     pub terminal: VirtualTerminal,
@@ -3002,6 +3147,11 @@ pub struct TerminalBuffer {
     render_cache: Option<RenderCache>,
     generation: u64,
     scroll_offset: usize,
+    /// View to restore with the next output, replayed on reattach
+    pending_view: Option<PaneViewState>,
+    copy_mode: Option<CopyModeState>,
+    output_log: Option<PaneLog>,
+    output_pipe: Option<PanePipe>,
 }
 
 impl std::fmt::Debug for TerminalBuffer {
@@ -3027,6 +3177,10 @@ impl TerminalBuffer {
             render_cache: None,
             generation: 0,
             scroll_offset: 0,
+            pending_view: None,
+            copy_mode: None,
+            output_log: None,
+            output_pipe: None,
         }
     }
 
@@ -3037,6 +3191,10 @@ impl TerminalBuffer {
             render_cache: None,
             generation: 0,
             scroll_offset: 0,
+            pending_view: None,
+            copy_mode: None,
+            output_log: None,
+            output_pipe: None,
         }
     }
 
@@ -3046,20 +3204,88 @@ impl TerminalBuffer {
         self.generation = self.generation.wrapping_add(1);
     }
 
+    /// Tee all further output to `log`, or stop logging with `None`.
+    pub fn set_output_log(&mut self, log: Option<PaneLog>) {
+        self.output_log = log;
+    }
+
+    /// Pipe all further output to `command`'s stdin, replacing any earlier pipe.
+    pub fn pipe_to(&mut self, command: Command) -> std::io::Result<()> {
+        self.output_pipe = Some(PanePipe::spawn(command)?);
+        Ok(())
+    }
+
+    /// Stop piping output, closing the command's stdin.
+    pub fn stop_pipe(&mut self) {
+        self.output_pipe = None;
+    }
+
+    /// Whether output is being piped to a command that is still running.
+    pub fn is_piped(&self) -> bool {
+        self.output_pipe.as_ref().is_some_and(PanePipe::is_active)
+    }
+
     /// Process raw terminal data
     pub fn process(&mut self, data: &[u8]) {
+        if let Some(log) = &mut self.output_log {
+            log.write(data);
+        }
+        if let Some(pipe) = &mut self.output_pipe {
+            pipe.write(data);
+        }
         self.parser.advance(&mut self.terminal, data);
         // Reset scroll position when alternate screen is entered/exited
         if self.terminal.alt_screen_toggled {
             self.terminal.alt_screen_toggled = false;
             self.scroll_offset = 0;
+            self.pending_view = None;
+            self.copy_mode = None;
+        }
+        if let Some(view) = self.pending_view.take() {
+            self.restore_view_state(view);
         }
         self.mark_dirty();
     }
 
+    /// The view to save when the session detaches.
+    pub fn view_state(&self) -> PaneViewState {
+        PaneViewState {
+            scroll_offset: self.scroll_offset,
+            copy_mode: self.copy_mode,
+        }
+    }
+
+    /// Return to a saved view, clamping an offset past the scrollback and
+    /// copy-mode positions past the screen.
+    pub fn restore_view_state(&mut self, view: PaneViewState) {
+        self.scroll_offset = view.scroll_offset.min(self.terminal.scrollback_len());
+        self.copy_mode = view.copy_mode.map(|state| self.clamp_copy_mode(state));
+        self.mark_dirty();
+    }
+
+    /// Restore `view` once the next output has been processed, unless the
+    /// user scrolls first.
+    pub fn restore_view_after_output(&mut self, view: PaneViewState) {
+        self.pending_view = Some(view);
+    }
+
+    /// Reset the terminal state a misbehaving program left behind (stuck
+    /// attributes, a line-drawing charset, odd modes) by running `mode`'s
+    /// sequence through the emulator. A half-received escape sequence is
+    /// dropped too. Unlike output, the reset isn't logged or piped.
+    pub fn reset(&mut self, mode: PaneReset) {
+        self.parser = Parser::new();
+        self.parser.advance(&mut self.terminal, mode.sequence());
+        self.scroll_offset = 0;
+        self.pending_view = None;
+        self.copy_mode = None;
+        self.mark_dirty();
+    }
+
     /// Resize the terminal
     pub fn resize(&mut self, rows: usize, cols: usize) {
         self.terminal.resize(rows, cols);
+        self.copy_mode = self.copy_mode.map(|state| self.clamp_copy_mode(state));
         self.mark_dirty();
     }
 
@@ -3067,18 +3293,21 @@ impl TerminalBuffer {
     pub fn scroll_up(&mut self, n: usize) {
         let max_scroll = self.terminal.scrollback_len();
         self.scroll_offset = (self.scroll_offset + n).min(max_scroll);
+        self.pending_view = None;
         self.mark_dirty();
     }
 
     /// Scroll view down
     pub fn scroll_down(&mut self, n: usize) {
         self.scroll_offset = self.scroll_offset.saturating_sub(n);
+        self.pending_view = None;
         self.mark_dirty();
     }
 
     /// Scroll to bottom
     pub fn scroll_to_bottom(&mut self) {
         self.scroll_offset = 0;
+        self.pending_view = None;
         self.mark_dirty();
     }
 
@@ -3087,6 +3316,39 @@ impl TerminalBuffer {
         self.scroll_offset
     }
 
+    /// Drop a scrollback marker on the current bottom scrollback row.
+    pub fn add_marker(&mut self, label: Option<String>) -> MarkerId {
+        self.terminal.internal_grid.lines_above.add_marker(label)
+    }
+
+    /// Scroll so a marker's row is at the top of the view.
+    /// Returns false if the marker has been evicted.
+    pub fn jump_to_marker(&mut self, id: MarkerId) -> bool {
+        let Some(offset) = self.terminal.internal_grid.lines_above.jump_to_marker(id) else {
+            return false;
+        };
+        self.scroll_offset = offset;
+        self.pending_view = None;
+        self.mark_dirty();
+        true
+    }
+
+    /// Scroll to the previous or next shell prompt recorded via OSC 133.
+    /// Moving past the newest prompt returns to the live view.
+    /// Returns false if there is nowhere to go.
+    pub fn jump_to_prompt(&mut self, direction: PromptDirection) -> bool {
+        let scrollback = &self.terminal.internal_grid.lines_above;
+        let offset = match scrollback.jump_to_prompt(self.scroll_offset, direction) {
+            Some(offset) => offset,
+            None if direction == PromptDirection::Next && self.scroll_offset > 0 => 0,
+            None => return false,
+        };
+        self.scroll_offset = offset;
+        self.pending_view = None;
+        self.mark_dirty();
+        true
+    }
+
     /// Clear the terminal
     pub fn clear(&mut self) {
         let rows = self.terminal.rows();
@@ -3094,6 +3356,8 @@ impl TerminalBuffer {
         self.terminal = VirtualTerminal::new(rows, cols);
         self.parser = Parser::new();
         self.scroll_offset = 0;
+        self.pending_view = None;
+        self.copy_mode = None;
         self.mark_dirty();
     }
 
@@ -3123,9 +3387,10 @@ impl TerminalBuffer {
         false
     }
 
-    /// Get cursor position (row, col) - returns None if scrolled away from bottom
+    /// Get cursor position (row, col) - returns None if scrolled away from
+    /// bottom or in copy mode
     pub fn cursor_position(&self) -> Option<(u16, u16)> {
-        if self.scroll_offset == 0 && self.terminal.cursor_visible {
+        if self.scroll_offset == 0 && self.terminal.cursor_visible && self.copy_mode.is_none() {
             Some((
                 self.terminal.cursor_row() as u16,
                 self.terminal.cursor_col() as u16,
@@ -3137,7 +3402,7 @@ impl TerminalBuffer {
 
     /// Check if cursor is visible
     pub fn cursor_visible(&self) -> bool {
-        self.terminal.cursor_visible && self.scroll_offset == 0
+        self.terminal.cursor_visible && self.scroll_offset == 0 && self.copy_mode.is_none()
     }
 
     /// Check if mouse tracking is enabled
@@ -3178,6 +3443,289 @@ impl TerminalBuffer {
         lines.join("\n")
     }
 
+    /// Snap a visible cell (row, col) on the trailing half of a double-width
+    /// glyph back to its leading half, so a cursor never rests mid-glyph.
+    pub fn snap_to_glyph(&self, (row, col): (usize, usize)) -> (usize, usize) {
+        let on_spacer = self
+            .terminal
+            .visible_lines(self.terminal.rows(), self.scroll_offset)
+            .get(row)
+            .and_then(|line| line.columns.get(col))
+            .is_some_and(|cell| cell.wide_spacer);
+        if on_spacer && col > 0 {
+            (row, col - 1)
+        } else {
+            (row, col)
+        }
+    }
+
+    /// The glyph right of a visible cell, skipping both columns of a
+    /// double-width glyph. Stays put at the last column.
+    pub fn glyph_right(&self, at: (usize, usize)) -> (usize, usize) {
+        let (row, col) = self.snap_to_glyph(at);
+        let width = if self.snap_to_glyph((row, col + 1)).1 == col {
+            2
+        } else {
+            1
+        };
+        if col + width < self.terminal.cols() {
+            (row, col + width)
+        } else {
+            (row, col)
+        }
+    }
+
+    /// The glyph left of a visible cell, landing on the leading half of a
+    /// double-width glyph. Stays put at column 0.
+    pub fn glyph_left(&self, at: (usize, usize)) -> (usize, usize) {
+        let (row, col) = self.snap_to_glyph(at);
+        self.snap_to_glyph((row, col.saturating_sub(1)))
+    }
+
+    /// The character shown at a visible cell, the leading glyph's for the
+    /// trailing half of a double-width one, and blank past the end of a row.
+    fn visible_char(&self, at: (usize, usize)) -> char {
+        let (row, col) = self.snap_to_glyph(at);
+        self.terminal
+            .visible_lines(self.terminal.rows(), self.scroll_offset)
+            .get(row)
+            .and_then(|line| line.columns.get(col))
+            .map_or(' ', |cell| cell.character)
+    }
+
+    /// The first and last visible cells of the word under `at` on its row,
+    /// where a word is a run of characters that are neither whitespace nor in
+    /// `separators`. Off a word, that's just the glyph at `at`.
+    pub fn word_bounds(
+        &self,
+        at: (usize, usize),
+        separators: &str,
+    ) -> ((usize, usize), (usize, usize)) {
+        let (row, col) = self.snap_to_glyph(at);
+        let in_word = |col| is_word_char(self.visible_char((row, col)), separators);
+        if !in_word(col) {
+            return ((row, col), self.glyph_end((row, col)));
+        }
+        let mut start = col;
+        while start > 0 && in_word(start - 1) {
+            start -= 1;
+        }
+        let mut end = col;
+        while end + 1 < self.terminal.cols() && in_word(end + 1) {
+            end += 1;
+        }
+        (self.snap_to_glyph((row, start)), (row, end))
+    }
+
+    /// The start of the next word after `at`, continuing onto later rows
+    /// (see `word_bounds`). Stays put if there is none.
+    pub fn next_word(&self, at: (usize, usize), separators: &str) -> (usize, usize) {
+        let cols = self.terminal.cols();
+        let cells = self.terminal.rows() * cols;
+        let at = self.snap_to_glyph(at);
+        let in_word = |i: usize| is_word_char(self.visible_char((i / cols, i % cols)), separators);
+        let mut i = at.0 * cols + at.1;
+        while i < cells && in_word(i) {
+            i += 1;
+        }
+        while i < cells && !in_word(i) {
+            i += 1;
+        }
+        if i < cells {
+            (i / cols, i % cols)
+        } else {
+            at
+        }
+    }
+
+    /// The start of the word before `at`, or of the word `at` is inside,
+    /// continuing onto earlier rows (see `word_bounds`). Stays put if there
+    /// is none.
+    pub fn prev_word(&self, at: (usize, usize), separators: &str) -> (usize, usize) {
+        let cols = self.terminal.cols();
+        let at = self.snap_to_glyph(at);
+        let in_word = |i: usize| is_word_char(self.visible_char((i / cols, i % cols)), separators);
+        let mut i = at.0 * cols + at.1;
+        while i > 0 && !in_word(i - 1) {
+            i -= 1;
+        }
+        if i == 0 {
+            return at;
+        }
+        while i > 0 && in_word(i - 1) {
+            i -= 1;
+        }
+        self.snap_to_glyph((i / cols, i % cols))
+    }
+
+    /// The last cell of the glyph at a visible cell: its trailing half if
+    /// double-width.
+    fn glyph_end(&self, at: (usize, usize)) -> (usize, usize) {
+        let (row, col) = self.snap_to_glyph(at);
+        if col + 1 < self.terminal.cols() && self.snap_to_glyph((row, col + 1)).1 == col {
+            (row, col + 1)
+        } else {
+            (row, col)
+        }
+    }
+
+    /// Extract the text of a stream selection between two visible cells
+    /// (row, col), 0-indexed and inclusive, in either order.
+    ///
+    /// Shared by mouse selection and copy mode so both copy the same text:
+    /// an endpoint on either half of a double-width glyph takes in the whole
+    /// glyph, trailing blanks are trimmed per row, and soft-wrapped rows are
+    /// joined without a newline.
+    pub fn selection_text(&self, start: (usize, usize), end: (usize, usize)) -> String {
+        let (start, end) = if start <= end {
+            (start, end)
+        } else {
+            (end, start)
+        };
+        // The end needs no snapping: a glyph is kept by its leading cell and
+        // its spacer is never copied
+        let start = self.snap_to_glyph(start);
+        let rows = self
+            .terminal
+            .visible_lines(self.terminal.rows(), self.scroll_offset);
+
+        let mut text = String::new();
+        for (row_idx, row) in rows.iter().enumerate().take(end.0 + 1).skip(start.0) {
+            if row_idx > start.0 && row.is_canonical {
+                text.push('\n');
+            }
+            let first_col = if row_idx == start.0 { start.1 } else { 0 };
+            let last_col = if row_idx == end.0 { end.1 } else { usize::MAX };
+            let line: String = row
+                .columns
+                .iter()
+                .enumerate()
+                .filter(|(col, c)| *col >= first_col && *col <= last_col && !c.wide_spacer)
+                .map(|(_, c)| c.character)
+                .collect();
+            if row_idx == end.0 || rows.get(row_idx + 1).is_none_or(|next| next.is_canonical) {
+                text.push_str(line.trim_end());
+            } else {
+                text.push_str(&line);
+            }
+        }
+        text
+    }
+
+    /// Enter copy mode with its cursor on the terminal's, or on the top left
+    /// cell when scrolled back. Does nothing if already in copy mode.
+    pub fn enter_copy_mode(&mut self) {
+        if self.copy_mode.is_some() {
+            return;
+        }
+        let cursor = if self.scroll_offset == 0 {
+            (self.terminal.cursor_row(), self.terminal.cursor_col())
+        } else {
+            (0, 0)
+        };
+        self.copy_mode = Some(self.clamp_copy_mode(CopyModeState {
+            cursor,
+            anchor: None,
+        }));
+        self.mark_dirty();
+    }
+
+    /// Leave copy mode, dropping any selection.
+    pub fn exit_copy_mode(&mut self) {
+        if self.copy_mode.take().is_some() {
+            self.mark_dirty();
+        }
+    }
+
+    /// The copy mode state, or `None` outside copy mode.
+    pub fn copy_mode(&self) -> Option<CopyModeState> {
+        self.copy_mode
+    }
+
+    /// Keep a copy-mode cursor and anchor on the screen and off the trailing
+    /// half of a double-width glyph.
+    fn clamp_copy_mode(&self, state: CopyModeState) -> CopyModeState {
+        let clamp = |(row, col): (usize, usize)| {
+            self.snap_to_glyph((
+                row.min(self.terminal.rows().saturating_sub(1)),
+                col.min(self.terminal.cols().saturating_sub(1)),
+            ))
+        };
+        CopyModeState {
+            cursor: clamp(state.cursor),
+            anchor: state.anchor.map(clamp),
+        }
+    }
+
+    /// Move the copy-mode cursor a glyph, a row or a word, with words ended by
+    /// whitespace and `separators`. Moving up from the top row or down from
+    /// the bottom one scrolls the view instead, taking the selection's anchor
+    /// along with the text under it while that's in view.
+    pub fn move_copy_cursor(&mut self, motion: CopyMotion, separators: &str) {
+        let Some(mut state) = self.copy_mode else {
+            return;
+        };
+        let (row, col) = state.cursor;
+        let last_row = self.terminal.rows().saturating_sub(1);
+        let cursor = match motion {
+            CopyMotion::Left => self.glyph_left(state.cursor),
+            CopyMotion::Right => self.glyph_right(state.cursor),
+            CopyMotion::NextWord => self.next_word(state.cursor, separators),
+            CopyMotion::PrevWord => self.prev_word(state.cursor, separators),
+            CopyMotion::Up if row > 0 => (row - 1, col),
+            CopyMotion::Down if row < last_row => (row + 1, col),
+            CopyMotion::Up => {
+                if self.scroll_offset < self.terminal.scrollback_len() {
+                    self.scroll_offset += 1;
+                    state.anchor = state.anchor.map(|(row, col)| (row + 1, col));
+                }
+                state.cursor
+            }
+            CopyMotion::Down => {
+                if self.scroll_offset > 0 {
+                    self.scroll_offset -= 1;
+                    state.anchor = state.anchor.map(|(row, col)| (row.saturating_sub(1), col));
+                }
+                state.cursor
+            }
+        };
+        state.cursor = cursor;
+        self.copy_mode = Some(self.clamp_copy_mode(state));
+        self.pending_view = None;
+        self.mark_dirty();
+    }
+
+    /// Start a copy-mode selection at the cursor, or drop the one under way.
+    pub fn toggle_copy_selection(&mut self) {
+        if let Some(state) = &mut self.copy_mode {
+            state.anchor = match state.anchor {
+                Some(_) => None,
+                None => Some(state.cursor),
+            };
+            self.mark_dirty();
+        }
+    }
+
+    /// The first and last cells of the copy-mode selection, in order, with
+    /// double-width glyphs at either end taken in whole.
+    pub fn copy_selection_bounds(&self) -> Option<((usize, usize), (usize, usize))> {
+        let state = self.copy_mode?;
+        let anchor = state.anchor?;
+        let (start, end) = if anchor <= state.cursor {
+            (anchor, state.cursor)
+        } else {
+            (state.cursor, anchor)
+        };
+        Some((self.snap_to_glyph(start), self.glyph_end(end)))
+    }
+
+    /// The text of the copy-mode selection (see `selection_text`), or `None`
+    /// without one.
+    pub fn copy_selection_text(&self) -> Option<String> {
+        let (start, end) = self.copy_selection_bounds()?;
+        Some(self.selection_text(start, end))
+    }
+
     /// Try to extract a URL at the given row and column (0-indexed).
     pub fn url_at_position(&self, row: usize, col: usize) -> Option<String> {
         if self.scroll_offset != 0 {
@@ -3348,6 +3896,13 @@ pub struct TerminalManager {
     mux_sender: Option<MuxConnectionSender>,
     /// Flag indicating if connection is being established
     connecting: bool,
+    /// Views of panes whose sessions detached, restored when they reattach
+    saved_views: HashMap<PaneId, PaneViewState>,
+    /// Whether views are saved on detach (`restore_scroll_on_reattach`)
+    restore_views: bool,
+    /// Whether theme changes reach sessions out of view
+    /// (`sync_theme_across_sessions`)
+    sync_theme: bool,
 }
 
 impl TerminalManager {
@@ -3361,9 +3916,40 @@ impl TerminalManager {
             event_tx,
             mux_sender: None,
             connecting: false,
+            saved_views: HashMap::new(),
+            restore_views: true,
+            sync_theme: true,
         }
     }
 
+    /// Save panes' scroll positions when their sessions detach, so reattaching
+    /// returns to them, or always reattach at the bottom.
+    pub fn set_restore_scroll_on_reattach(&mut self, enabled: bool) {
+        self.restore_views = enabled;
+        if !enabled {
+            self.saved_views.clear();
+        }
+    }
+
+    /// Remember where `pane_id` was scrolled to and its copy mode, if it was
+    /// scrolled back or in copy mode.
+    fn save_view(&mut self, pane_id: PaneId) {
+        let view = self.buffers.get(&pane_id).map(TerminalBuffer::view_state);
+        match view {
+            Some(view) if self.restore_views && view != PaneViewState::default() => {
+                self.saved_views.insert(pane_id, view);
+            }
+            _ => {
+                self.saved_views.remove(&pane_id);
+            }
+        }
+    }
+
+    /// The view `pane_id` returns to when its session reattaches.
+    pub fn saved_view(&self, pane_id: PaneId) -> Option<PaneViewState> {
+        self.saved_views.get(&pane_id).copied()
+    }
+
     /// Check if the multiplexed connection is established.
     pub fn is_mux_connected(&self) -> bool {
         self.mux_sender.is_some()
@@ -3389,6 +3975,10 @@ impl TerminalManager {
     pub fn clear_mux_connection(&mut self) {
         self.mux_sender = None;
         self.connecting = false;
+        let attached: Vec<PaneId> = self.sessions.keys().copied().collect();
+        for pane_id in attached {
+            self.save_view(pane_id);
+        }
         // Clear all sessions since they're now invalid
         self.sessions.clear();
         self.session_to_pane.clear();
@@ -3422,6 +4012,18 @@ impl TerminalManager {
         self.sessions.contains_key(&pane_id)
     }
 
+    /// Type keys into a pane, like tmux `send-keys`: `keys` is whitespace
+    /// separated key names or text, or `-l` and text to send verbatim (see
+    /// `send_keys_input`).
+    pub fn send_keys(&mut self, pane_id: PaneId, keys: &str) -> Result<(), String> {
+        let args: Vec<String> = keys.split_whitespace().map(str::to_string).collect();
+        let input = send_keys_input(&args)?;
+        if !self.send_input(pane_id, input) {
+            return Err("Pane is not connected".to_string());
+        }
+        Ok(())
+    }
+
     /// Send input to a terminal session via the multiplexed connection.
     /// Also scrolls to bottom so the user sees where they're typing.
     pub fn send_input(&mut self, pane_id: PaneId, data: Vec<u8>) -> bool {
@@ -3485,6 +4087,7 @@ impl TerminalManager {
     /// Disconnect a terminal session
     pub fn disconnect(&mut self, pane_id: PaneId) {
         if let Some(session) = self.sessions.remove(&pane_id) {
+            self.save_view(pane_id);
             self.session_to_pane.remove(&session.session_id);
             // Send detach message to server
             if let Some(sender) = &self.mux_sender {
@@ -3509,6 +4112,7 @@ impl TerminalManager {
         }
         self.last_sizes.remove(&pane_id);
         self.buffers.remove(&pane_id);
+        self.saved_views.remove(&pane_id);
     }
 
     /// Clear a terminal buffer
@@ -3557,27 +4161,138 @@ impl TerminalManager {
         self.last_sizes.insert(pane_id, (rows as u16, cols as u16));
     }
 
-    /// Register a new session for a pane (called after receiving Attached message)
+    /// Start logging a pane's output to a file, or stop with `None`.
+    pub fn set_output_log(
+        &mut self,
+        pane_id: PaneId,
+        options: Option<PaneLogOptions>,
+    ) -> std::io::Result<()> {
+        let log = options.map(PaneLog::open).transpose()?;
+        self.buffers.entry(pane_id).or_default().set_output_log(log);
+        Ok(())
+    }
+
+    /// Reset a pane's terminal state (see `TerminalBuffer::reset`).
+    /// Returns false if the pane has no terminal.
+    pub fn reset_pane(&mut self, pane_id: PaneId, mode: PaneReset) -> bool {
+        let Some(buffer) = self.buffers.get_mut(&pane_id) else {
+            return false;
+        };
+        buffer.reset(mode);
+        true
+    }
+
+    /// Pipe a pane's output to `command`, like tmux `pipe-pane`, or stop with `None`.
+    pub fn set_output_pipe(
+        &mut self,
+        pane_id: PaneId,
+        command: Option<Command>,
+    ) -> std::io::Result<()> {
+        let buffer = self.buffers.entry(pane_id).or_default();
+        match command {
+            Some(command) => buffer.pipe_to(command),
+            None => {
+                buffer.stop_pipe();
+                Ok(())
+            }
+        }
+    }
+
+    /// Whether a pane's output is being piped to a running command.
+    pub fn is_output_piped(&self, pane_id: PaneId) -> bool {
+        self.buffers
+            .get(&pane_id)
+            .is_some_and(TerminalBuffer::is_piped)
+    }
+
+    /// Register a new session for a pane (called after receiving Attached message).
+    /// `theme_override` applies for as long as the session stays attached.
     pub fn register_session(
         &mut self,
         pane_id: PaneId,
         session_id: PtySessionId,
         sandbox_id: String,
+        theme_override: Option<TerminalColors>,
     ) {
         self.sessions.insert(
             pane_id,
             TerminalSession {
                 session_id: session_id.clone(),
                 sandbox_id,
+                theme_override,
+                colors: get_outer_colors(),
             },
         );
         self.session_to_pane.insert(session_id, pane_id);
+        // The view comes back with the output the server replays on attach
+        if let Some(view) = self.saved_views.remove(&pane_id) {
+            if let Some(buffer) = self.buffers.get_mut(&pane_id) {
+                buffer.restore_view_after_output(view);
+            }
+        }
+    }
+
+    /// Theme an attached session independently of the outer terminal, or
+    /// return it to inheriting with `None`. Returns whether the pane has a session.
+    pub fn set_session_theme_override(
+        &mut self,
+        pane_id: PaneId,
+        theme_override: Option<TerminalColors>,
+    ) -> bool {
+        match self.sessions.get_mut(&pane_id) {
+            Some(session) => {
+                session.theme_override = theme_override;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The theme override of the pane's attached session, if any.
+    pub fn session_theme_override(&self, pane_id: PaneId) -> Option<TerminalColors> {
+        self.sessions.get(&pane_id)?.theme_override
+    }
+
+    /// The colors a pane renders with: its session's override layered over the
+    /// outer terminal's colors as the session last took them up. Detached
+    /// panes inherit the outer colors.
+    pub fn effective_colors(&self, pane_id: PaneId) -> TerminalColors {
+        let Some(session) = self.sessions.get(&pane_id) else {
+            return get_outer_colors();
+        };
+        match session.theme_override {
+            Some(theme) => theme.layered_over(session.colors),
+            None => session.colors,
+        }
+    }
+
+    /// Propagate theme changes to every session's colors, or only to the
+    /// session in view when they happen (`sync_theme_across_sessions`).
+    pub fn set_sync_theme_across_sessions(&mut self, enabled: bool) {
+        self.sync_theme = enabled;
+    }
+
+    /// Take up the outer terminal's new colors in the session of `in_view`
+    /// (the pane on screen) and, when syncing themes, in every other session
+    /// too, so they're right when switched to. Sessions with a theme override
+    /// keep their colors.
+    pub fn apply_theme_change(&mut self, event: &ThemeChangeEvent, in_view: Option<PaneId>) {
+        for (pane_id, session) in &mut self.sessions {
+            if session.theme_override.is_some() {
+                continue;
+            }
+            if self.sync_theme || in_view == Some(*pane_id) {
+                session.colors = event.colors;
+            }
+        }
+        self.invalidate_all_render_caches();
     }
 
     /// Handle session exit (called when Exited message received)
     pub fn handle_session_exit(&mut self, session_id: &PtySessionId) -> Option<(PaneId, String)> {
         if let Some(&pane_id) = self.session_to_pane.get(session_id) {
             if let Some(session) = self.sessions.remove(&pane_id) {
+                self.save_view(pane_id);
                 self.session_to_pane.remove(session_id);
                 return Some((pane_id, session.sandbox_id));
             }
@@ -3703,6 +4418,19 @@ pub async fn establish_mux_connection(manager: SharedTerminalManager) -> anyhow:
                                         });
                                     }
                                 }
+                                MuxServerMessage::Detached { session_id, message } => {
+                                    let _ = event_tx_clone.send(MuxEvent::StatusMessage { message });
+                                    let exit_info = {
+                                        let mut mgr = manager_clone.lock().await;
+                                        mgr.handle_session_exit(&session_id)
+                                    };
+                                    if let Some((pane_id, sandbox_id)) = exit_info {
+                                        let _ = event_tx_clone.send(MuxEvent::TerminalExited {
+                                            pane_id,
+                                            sandbox_id,
+                                        });
+                                    }
+                                }
                                 MuxServerMessage::Exited { session_id, .. } => {
                                     let exit_info = {
                                         let mut mgr = manager_clone.lock().await;
@@ -3716,9 +4444,23 @@ pub async fn establish_mux_connection(manager: SharedTerminalManager) -> anyhow:
                                     }
                                 }
                                 MuxServerMessage::Error { session_id, message } => {
-                                    let _ = event_tx_clone.send(MuxEvent::Error(format!(
-                                        "Session {:?}: {}", session_id, message
-                                    )));
+                                    // A session's error means its process never started
+                                    let exit_info = match &session_id {
+                                        Some(session_id) => {
+                                            let mut mgr = manager_clone.lock().await;
+                                            mgr.handle_session_exit(session_id)
+                                        }
+                                        None => None,
+                                    };
+                                    let event = match exit_info {
+                                        Some((pane_id, _)) => {
+                                            MuxEvent::PaneSpawnFailed { pane_id, message }
+                                        }
+                                        None => MuxEvent::Error(format!(
+                                            "Session {:?}: {}", session_id, message
+                                        )),
+                                    };
+                                    let _ = event_tx_clone.send(event);
                                 }
                                 MuxServerMessage::Pong { .. } => {
                                     // Keepalive response, ignore
@@ -3807,6 +4549,7 @@ pub async fn establish_mux_connection(manager: SharedTerminalManager) -> anyhow:
 }
 
 /// Connect a pane to a sandbox terminal via the multiplexed connection.
+#[allow(clippy::too_many_arguments)]
 pub async fn connect_to_sandbox(
     manager: SharedTerminalManager,
     pane_id: PaneId,
@@ -3814,11 +4557,14 @@ pub async fn connect_to_sandbox(
     tab_id: Option<TabId>,
     cols: u16,
     rows: u16,
+    spawn: PaneSpawn,
+    output_log: Option<PaneLogOptions>,
+    detach_others: bool,
 ) -> anyhow::Result<()> {
     // Ensure the multiplexed connection is established
     establish_mux_connection(manager.clone()).await?;
 
-    let session_id = pane_id_to_session_id(pane_id);
+    let session_id = pane_id_to_session_id(pane_id, spawn.generation);
     let tab_id_string = tab_id.map(|id| id.to_string());
     let pane_id_string = pane_id.to_string();
 
@@ -3829,20 +4575,40 @@ pub async fn connect_to_sandbox(
         // Initialize buffer with correct size
         mgr.init_buffer(pane_id, rows as usize, cols as usize);
 
+        // A log that can't be opened shouldn't stop the pane from connecting
+        if let Some(options) = output_log {
+            let path = options.path.clone();
+            if let Err(e) = mgr.set_output_log(pane_id, Some(options)) {
+                tracing::warn!("Failed to open pane output log {}: {}", path.display(), e);
+            }
+        }
+
         // Register the session (optimistically - server will confirm)
-        mgr.register_session(pane_id, session_id.clone(), sandbox_id.clone());
+        mgr.register_session(
+            pane_id,
+            session_id.clone(),
+            sandbox_id.clone(),
+            spawn.theme_override,
+        );
 
         // Send attach message
         if let Some(sender) = mgr.get_mux_sender() {
+            let login_shell = spawn.uses_login_shell();
+            // Nested dmux instances started in the pane detect nesting from these
+            let mut env = terminal_capabilities().child_env();
+            env.extend(nesting_child_env());
             sender.send(MuxClientMessage::Attach {
                 session_id,
                 sandbox_id: sandbox_id.clone(),
                 cols,
                 rows,
-                command: None,
+                command: spawn.command,
                 tty: true,
                 tab_id: tab_id_string,
                 pane_id: Some(pane_id_string),
+                env,
+                attach_detach_others: detach_others,
+                login_shell,
             });
         } else {
             return Err(anyhow::anyhow!("Mux connection not established"));
@@ -3987,6 +4753,35 @@ mod tests {
         assert_eq!(grid[1][0].c, 'L');
     }
 
+    #[test]
+    fn scrollback_reflows_soft_wrapped_lines_on_resize() {
+        let mut term = VirtualTerminal::new(2, 5);
+        // "abcdefgh" soft-wraps at width 5; the trailing newlines push it into scrollback
+        term.process(
+            b"abcdefgh
+xy
+
+
+",
+        );
+        let scrollback: Vec<String> = term
+            .scrollback_snapshot()
+            .iter()
+            .map(|row| row.iter().map(|c| c.c).collect::<String>())
+            .collect();
+        assert_eq!(scrollback[0], "abcde");
+        assert_eq!(scrollback[1].trim_end(), "fgh");
+
+        term.resize(2, 10);
+        let scrollback: Vec<String> = term
+            .scrollback_snapshot()
+            .iter()
+            .map(|row| row.iter().map(|c| c.c).collect::<String>())
+            .collect();
+        assert_eq!(scrollback[0].trim_end(), "abcdefgh");
+        assert_eq!(scrollback[1].trim_end(), "xy");
+    }
+
     #[test]
     fn virtual_terminal_handles_cursor_movement() {
         let mut term = VirtualTerminal::new(24, 80);
@@ -3996,6 +4791,73 @@ mod tests {
         assert_eq!(grid[1][0].c, 'W');
     }
 
+    #[test]
+    fn rep_repeats_the_last_glyph() {
+        let mut term = VirtualTerminal::new(2, 10);
+        term.process(b"ab\x1b[3b");
+        let row: String = term.grid_snapshot()[0].iter().map(|c| c.c).collect();
+        assert_eq!(row.trim_end(), "abbbb");
+
+        // Without a count, REP repeats once
+        term.process(b"\x1b[bx\x1b[0b");
+        let row: String = term.grid_snapshot()[0].iter().map(|c| c.c).collect();
+        assert_eq!(row.trim_end(), "abbbbbxx");
+    }
+
+    #[test]
+    fn rep_uses_current_attributes_and_wraps() {
+        let mut term = VirtualTerminal::new(3, 4);
+        term.process(b"a\x1b[31m\x1b[5b");
+        let grid = term.grid_snapshot();
+        assert_eq!(grid[0][0].style.fg, None);
+        for cell in &grid[0][1..4] {
+            assert_eq!(cell.c, 'a');
+            assert_eq!(cell.style.fg, Some(Color::Red));
+        }
+        // The last two repeats wrap onto the next row
+        assert_eq!(grid[1][0].c, 'a');
+        assert_eq!(grid[1][1].c, 'a');
+        assert_eq!(grid[1][2].c, ' ');
+    }
+
+    #[test]
+    fn rep_after_cursor_addressing_repeats_at_the_new_position() {
+        let mut term = VirtualTerminal::new(3, 10);
+        term.process(b"x\x1b[2;4H-\x1b[2b\x1b[3;1H\x1b[2b");
+        let grid = term.grid_snapshot();
+        let row: String = grid[1].iter().map(|c| c.c).collect();
+        assert_eq!(row.trim_end(), "   ---");
+        // CUP doesn't reset the repeated glyph
+        let row: String = grid[2].iter().map(|c| c.c).collect();
+        assert_eq!(row.trim_end(), "--");
+        assert_eq!(grid[0][0].c, 'x');
+    }
+
+    #[test]
+    fn rep_without_a_previous_glyph_is_ignored_and_huge_counts_are_capped() {
+        let mut term = VirtualTerminal::new(2, 3);
+        term.process(b"\x1b[5b");
+        assert!(term.grid_snapshot()[0].iter().all(|c| c.c == ' '));
+
+        // One screenful of repeats after the printed 'z' scrolls a single row off
+        term.process(b"z\x1b[65535b");
+        let grid = term.grid_snapshot();
+        let text: Vec<String> = grid
+            .iter()
+            .map(|row| row.iter().map(|c| c.c).collect())
+            .collect();
+        assert_eq!(text, ["zzz", "z  "]);
+        assert_eq!(term.scrollback_len(), 1);
+    }
+
+    #[test]
+    fn rep_repeats_translated_line_drawing_glyphs() {
+        let mut term = VirtualTerminal::new(1, 10);
+        term.process(b"\x1b(0q\x1b[2b");
+        let row: String = term.grid_snapshot()[0].iter().map(|c| c.c).collect();
+        assert_eq!(row.trim_end(), "───");
+    }
+
     #[test]
     fn virtual_terminal_handles_colors() {
         let mut term = VirtualTerminal::new(24, 80);
@@ -4072,8 +4934,8 @@ mod tests {
 
         let responses = term.drain_responses();
         assert_eq!(responses.len(), 1);
-        // Should respond as xterm-compatible VT420 with capabilities
-        assert_eq!(responses[0], b"\x1b[?64;1;2;6;9;15;16;17;18;21;22;28;29c");
+        // Should respond as a VT420 with the emulated capabilities
+        assert_eq!(responses[0], VirtualTerminal::DA1_RESPONSE);
     }
 
     #[test]
@@ -4084,7 +4946,7 @@ mod tests {
 
         let responses = term.drain_responses();
         assert_eq!(responses.len(), 1);
-        assert_eq!(responses[0], b"\x1b[?64;1;2;6;9;15;16;17;18;21;22;28;29c");
+        assert_eq!(responses[0], VirtualTerminal::DA1_RESPONSE);
     }
 
     #[test]
@@ -4099,6 +4961,15 @@ mod tests {
         assert_eq!(responses[0], b"\x1b[>41;354;0c");
     }
 
+    #[test]
+    fn virtual_terminal_ignores_other_device_attribute_requests() {
+        let mut term = VirtualTerminal::new(24, 80);
+        // DA1 with a nonzero parameter, and DA3 (CSI = c), get no reply
+        term.process(b"\x1b[1c\x1b[=c\x1b[>1c");
+
+        assert!(term.drain_responses().is_empty());
+    }
+
     #[test]
     fn virtual_terminal_responds_to_window_size_query() {
         let mut term = VirtualTerminal::new(24, 80);
@@ -4479,6 +5350,48 @@ mod tests {
         assert_eq!(term.color_palette()[235], None);
     }
 
+    #[test]
+    fn osc133_marks_prompt_command_and_output_rows() {
+        let mut term = VirtualTerminal::new(5, 20);
+        term.process(b"\x1b]133;A\x07$ \x1b]133;B\x07ls\r\n");
+        term.process(b"\x1b]133;C\x1b\\file\r\n");
+        term.process(b"\x1b]133;D;2\x1b\\");
+        let marks: Vec<_> = term
+            .internal_grid
+            .viewport
+            .iter()
+            .map(|row| row.prompt_mark)
+            .collect();
+        // A and B share the prompt row; the prompt start takes precedence
+        assert_eq!(marks[0], Some(PromptMark::PromptStart));
+        assert_eq!(marks[1], Some(PromptMark::OutputStart));
+        assert_eq!(
+            marks[2],
+            Some(PromptMark::CommandFinished { exit_code: Some(2) })
+        );
+        assert_eq!(marks[3], None);
+    }
+
+    #[test]
+    fn parse_osc133_variants() {
+        assert_eq!(
+            parse_osc133_mark(&[b"A", b"aid=123"]),
+            Some(PromptMark::PromptStart)
+        );
+        assert_eq!(parse_osc133_mark(&[b"B"]), Some(PromptMark::CommandStart));
+        assert_eq!(parse_osc133_mark(&[b"C"]), Some(PromptMark::OutputStart));
+        assert_eq!(
+            parse_osc133_mark(&[b"D"]),
+            Some(PromptMark::CommandFinished { exit_code: None })
+        );
+        assert_eq!(
+            parse_osc133_mark(&[b"D", b"0"]),
+            Some(PromptMark::CommandFinished { exit_code: Some(0) })
+        );
+        assert_eq!(parse_osc133_mark(&[b"Z"]), None);
+        assert_eq!(parse_osc133_mark(&[]), None);
+    }
+
     #[test]
     fn parse_osc_color_formats() {
         use super::parse_osc_color;
@@ -5485,6 +6398,44 @@ mod tests {
         assert_eq!(response, "\x1bP1$r0;4m\x1b\\");
     }
 
+    #[test]
+    fn sgr_styled_underline() {
+        let mut term = VirtualTerminal::new(24, 80);
+        term.process(b"\x1b[0m\x1b[4:3m"); // curly
+        let styles = term.internal_grid.current_styles;
+        assert_eq!(styles.underline_style, UnderlineStyle::Curly);
+        assert!(styles.modifiers.contains(Modifier::UNDERLINED));
+        assert_eq!(get_decrqss_sgr_response(&mut term), "\x1bP1$r0;4:3m\x1b\\");
+
+        // Plain 4 goes back to a single underline, 4:0 removes it
+        term.process(b"\x1b[4m");
+        assert_eq!(get_decrqss_sgr_response(&mut term), "\x1bP1$r0;4m\x1b\\");
+        term.process(b"\x1b[4:5m\x1b[4:0m");
+        assert_eq!(get_decrqss_sgr_response(&mut term), "\x1bP1$r0m\x1b\\");
+    }
+
+    #[test]
+    fn sgr_underline_color() {
+        let mut term = VirtualTerminal::new(24, 80);
+        // Parameters after the color are still applied
+        term.process(b"\x1b[0m\x1b[4;58;5;196;31m");
+        let styles = term.internal_grid.current_styles;
+        assert_eq!(styles.underline_color, Some(Color::Indexed(196)));
+        assert_eq!(styles.foreground, Some(Color::Red));
+        assert_eq!(
+            get_decrqss_sgr_response(&mut term),
+            "\x1bP1$r0;4;31;58;5;196m\x1b\\"
+        );
+
+        term.process(b"\x1b[58:2::10:20:30m");
+        assert_eq!(
+            term.internal_grid.current_styles.underline_color,
+            Some(Color::Rgb(10, 20, 30))
+        );
+        term.process(b"\x1b[59m");
+        assert_eq!(term.internal_grid.current_styles.underline_color, None);
+    }
+
     #[test]
     fn sgr_blink() {
         let mut term = VirtualTerminal::new(24, 80);
@@ -6047,4 +6998,402 @@ mod tests {
         assert_eq!(term.cursor_style, 6);
         assert!(!term.cursor_blink);
     }
+
+    #[test]
+    fn copy_cursor_steps_over_wide_glyphs() {
+        let mut buffer = TerminalBuffer::with_size(4, 10);
+        buffer.process("a中b".as_bytes());
+
+        // Right from 'a' lands on the CJK glyph, then skips its spacer
+        assert_eq!(buffer.glyph_right((0, 0)), (0, 1));
+        assert_eq!(buffer.glyph_right((0, 1)), (0, 3));
+        // Landing on the trailing half snaps to the leading half
+        assert_eq!(buffer.snap_to_glyph((0, 2)), (0, 1));
+        assert_eq!(buffer.glyph_right((0, 2)), (0, 3));
+        assert_eq!(buffer.glyph_left((0, 3)), (0, 1));
+        assert_eq!(buffer.glyph_left((0, 1)), (0, 0));
+        assert_eq!(buffer.glyph_left((0, 0)), (0, 0));
+        assert_eq!(buffer.glyph_right((0, 9)), (0, 9));
+    }
+
+    #[test]
+    fn copy_mode_moves_over_and_selects_wide_glyphs() {
+        let mut buffer = TerminalBuffer::with_size(4, 10);
+        buffer.process("a中b".as_bytes());
+        buffer.enter_copy_mode();
+        assert_eq!(buffer.cursor_position(), None);
+        let cursor = |buffer: &TerminalBuffer| buffer.copy_mode().expect("copy mode").cursor;
+
+        // Entering starts on the terminal's cursor, just past the 'b'
+        assert_eq!(cursor(&buffer), (0, 4));
+        buffer.move_copy_cursor(CopyMotion::Left, "");
+        assert_eq!(cursor(&buffer), (0, 3));
+        buffer.move_copy_cursor(CopyMotion::Left, "");
+        assert_eq!(cursor(&buffer), (0, 1));
+
+        // Selecting from the glyph takes in both of its columns
+        buffer.toggle_copy_selection();
+        assert_eq!(buffer.copy_selection_bounds(), Some(((0, 1), (0, 2))));
+        assert_eq!(buffer.copy_selection_text().as_deref(), Some("中"));
+        buffer.move_copy_cursor(CopyMotion::Right, "");
+        assert_eq!(cursor(&buffer), (0, 3));
+        assert_eq!(buffer.copy_selection_text().as_deref(), Some("中b"));
+
+        // A row down onto a glyph's trailing half lands on its leading half
+        buffer.process("\r\nxx中".as_bytes());
+        buffer.toggle_copy_selection();
+        buffer.move_copy_cursor(CopyMotion::Down, "");
+        assert_eq!(cursor(&buffer), (1, 2));
+        assert_eq!(buffer.copy_selection_text(), None);
+
+        buffer.exit_copy_mode();
+        assert!(buffer.copy_mode().is_none());
+        assert!(buffer.cursor_position().is_some());
+    }
+
+    #[test]
+    fn copy_cursor_scrolls_past_the_top_row() {
+        let mut buffer = TerminalBuffer::with_size(2, 10);
+        buffer.process(b"aaaaa\r\nbbbbb\r\nccccc");
+        buffer.enter_copy_mode();
+        for _ in 0..5 {
+            buffer.move_copy_cursor(CopyMotion::Left, "");
+        }
+        buffer.move_copy_cursor(CopyMotion::Up, "");
+        buffer.toggle_copy_selection();
+
+        buffer.move_copy_cursor(CopyMotion::Up, "");
+        assert_eq!(buffer.scroll_offset(), 1);
+        // The anchor stays at the start of "bbbbb" as the view scrolls
+        assert_eq!(buffer.copy_selection_text().as_deref(), Some("aaaaa\nb"));
+        // Nothing further back to scroll to
+        buffer.move_copy_cursor(CopyMotion::Up, "");
+        assert_eq!(buffer.scroll_offset(), 1);
+
+        buffer.move_copy_cursor(CopyMotion::Down, "");
+        buffer.move_copy_cursor(CopyMotion::Down, "");
+        assert_eq!(buffer.scroll_offset(), 0);
+        assert_eq!(buffer.copy_selection_text().as_deref(), Some("bbbbb\nc"));
+    }
+
+    #[test]
+    fn word_bounds_follow_separators() {
+        let mut buffer = TerminalBuffer::with_size(2, 30);
+        buffer.process(b"ls /usr/local-bin main.rs");
+        let word = |separators: &str| {
+            let (start, end) = buffer.word_bounds((0, 12), separators);
+            buffer.selection_text(start, end)
+        };
+
+        assert_eq!(word(""), "/usr/local-bin");
+        assert_eq!(word("/"), "local-bin");
+        assert_eq!(word(crate::settings::DEFAULT_WORD_SEPARATORS), "local");
+        // Off a word, just the glyph under the cursor
+        assert_eq!(buffer.word_bounds((0, 2), "/"), ((0, 2), (0, 2)));
+        assert_eq!(buffer.word_bounds((0, 3), "/"), ((0, 3), (0, 3)));
+    }
+
+    #[test]
+    fn word_motion_follows_separators() {
+        let mut buffer = TerminalBuffer::with_size(2, 20);
+        buffer.process(b"cd a/b c\r\nnext");
+
+        assert_eq!(buffer.next_word((0, 0), ""), (0, 3));
+        assert_eq!(buffer.next_word((0, 3), ""), (0, 7));
+        assert_eq!(buffer.next_word((0, 3), "/"), (0, 5));
+        // Motion carries on to the next row, and stops at the last word
+        assert_eq!(buffer.next_word((0, 7), ""), (1, 0));
+        assert_eq!(buffer.next_word((1, 0), ""), (1, 0));
+
+        assert_eq!(buffer.prev_word((1, 0), ""), (0, 7));
+        assert_eq!(buffer.prev_word((0, 6), "/"), (0, 5));
+        assert_eq!(buffer.prev_word((0, 6), ""), (0, 3));
+        assert_eq!(buffer.prev_word((0, 0), ""), (0, 0));
+    }
+
+    #[test]
+    fn selection_endpoints_take_in_whole_wide_glyphs() {
+        let mut buffer = TerminalBuffer::with_size(4, 10);
+        buffer.process("a中b".as_bytes());
+
+        // Either half of the glyph selects all of it
+        assert_eq!(buffer.selection_text((0, 2), (0, 2)), "中");
+        assert_eq!(buffer.selection_text((0, 1), (0, 1)), "中");
+        assert_eq!(buffer.selection_text((0, 2), (0, 3)), "中b");
+        assert_eq!(buffer.selection_text((0, 0), (0, 2)), "a中");
+        assert_eq!(buffer.selection_text((0, 3), (0, 2)), "中b");
+    }
+
+    #[test]
+    fn soft_pane_reset_clears_tracked_state_and_keeps_screen() {
+        let mut buffer = TerminalBuffer::with_size(4, 10);
+        // Stuck bold red, the line-drawing charset, and a cut-off sequence
+        buffer.process(b"ok\x1b[1;31m\x1b(0\x0e\x1b[3");
+        buffer.reset(PaneReset::Soft);
+
+        let styles = buffer.terminal.internal_grid.current_styles;
+        assert!(!styles.modifiers.contains(Modifier::BOLD));
+        assert_eq!(styles.foreground, None);
+        assert_eq!(buffer.terminal.charset_index, 0);
+        assert!(!buffer.terminal.g0_charset_line_drawing);
+
+        buffer.process(b"q");
+        let grid = buffer.terminal.legacy_grid();
+        assert_eq!((grid[0][0].c, grid[0][1].c, grid[0][2].c), ('o', 'k', 'q'));
+    }
+
+    #[test]
+    fn full_pane_reset_clears_screen() {
+        let mut buffer = TerminalBuffer::with_size(4, 10);
+        buffer.process(b"ok\x1b[1;31m\x1b(0");
+        buffer.reset(PaneReset::Full);
+
+        assert!(!buffer.terminal.g0_charset_line_drawing);
+        assert_eq!(
+            buffer.terminal.internal_grid.current_styles,
+            CharacterStyles::default()
+        );
+        assert_eq!(buffer.terminal.legacy_grid()[0][0].c, ' ');
+        assert_eq!(buffer.cursor_position(), Some((0, 0)));
+    }
+
+    #[test]
+    fn reset_pane_only_resets_known_panes() {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let mut manager = TerminalManager::new("http://localhost".to_string(), tx);
+        let pane_id = PaneId::new();
+        assert!(!manager.reset_pane(pane_id, PaneReset::Soft));
+
+        manager.handle_output(pane_id, b"\x1b(0".to_vec());
+        assert!(manager.reset_pane(pane_id, PaneReset::Soft));
+        let buffer = manager.get_buffer_mut(pane_id).expect("buffer");
+        assert!(!buffer.terminal.g0_charset_line_drawing);
+    }
+
+    #[test]
+    fn device_queries_are_answered_into_the_pty() {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let mut manager = TerminalManager::new("http://localhost".to_string(), tx);
+        let (msg_tx, mut msg_rx) = mpsc::unbounded_channel();
+        manager.set_mux_sender(MuxConnectionSender { tx: msg_tx });
+        let pane_id = PaneId::new();
+        let session_id: PtySessionId = "session-1".to_string();
+        manager.register_session(pane_id, session_id.clone(), "sandbox".to_string(), None);
+
+        // A child asks for DA1, DA2 and a cursor position report in one write
+        manager.handle_output_by_session(&session_id, b"ab\x1b[c\x1b[>c\x1b[6n".to_vec());
+
+        let mut replies = Vec::new();
+        while let Ok(msg) = msg_rx.try_recv() {
+            match msg {
+                MuxClientMessage::Input {
+                    session_id: to,
+                    data,
+                } => {
+                    assert_eq!(to, session_id);
+                    replies.push(data);
+                }
+                other => panic!("unexpected message {:?}", other),
+            }
+        }
+        assert_eq!(
+            replies,
+            [
+                VirtualTerminal::DA1_RESPONSE.to_vec(),
+                VirtualTerminal::DA2_RESPONSE.to_vec(),
+                b"\x1b[1;3R".to_vec()
+            ]
+        );
+        // The requests themselves never reach the screen
+        let buffer = manager.get_buffer_mut(pane_id).expect("buffer");
+        let row = &buffer.terminal.legacy_grid()[0];
+        let first_line: String = (0..buffer.terminal.cols()).map(|c| row[c].c).collect();
+        assert_eq!(first_line.trim_end(), "ab");
+    }
+
+    fn numbered_lines(count: usize) -> Vec<u8> {
+        (0..count)
+            .map(|i| format!("line {}\r\n", i))
+            .collect::<String>()
+            .into_bytes()
+    }
+
+    fn attach(manager: &mut TerminalManager, pane_id: PaneId, session_id: &str) {
+        manager.init_buffer(pane_id, 5, 20);
+        manager.register_session(pane_id, session_id.to_string(), "sandbox".to_string(), None);
+    }
+
+    #[test]
+    fn scroll_position_is_restored_on_reattach() {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let mut manager = TerminalManager::new("http://localhost".to_string(), tx);
+        let pane_id = PaneId::new();
+        attach(&mut manager, pane_id, "first");
+        manager.handle_output(pane_id, numbered_lines(30));
+        manager
+            .get_buffer_mut(pane_id)
+            .expect("buffer")
+            .scroll_up(10);
+
+        manager.disconnect(pane_id);
+        assert_eq!(
+            manager.saved_view(pane_id),
+            Some(PaneViewState {
+                scroll_offset: 10,
+                copy_mode: None
+            })
+        );
+
+        // Reattaching starts a fresh buffer that the server replays into
+        attach(&mut manager, pane_id, "second");
+        assert_eq!(manager.saved_view(pane_id), None);
+        manager.handle_output(pane_id, numbered_lines(30));
+        let buffer = manager.get_buffer_mut(pane_id).expect("buffer");
+        assert_eq!(buffer.scroll_offset(), 10);
+
+        // Only the first output after reattaching moves the view
+        buffer.process(b"more\r\n");
+        assert_eq!(buffer.scroll_offset(), 10);
+
+        // With the option off, reattaching returns to the bottom
+        manager.set_restore_scroll_on_reattach(false);
+        manager.disconnect(pane_id);
+        assert_eq!(manager.saved_view(pane_id), None);
+    }
+
+    #[test]
+    fn copy_mode_is_restored_on_reattach() {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let mut manager = TerminalManager::new("http://localhost".to_string(), tx);
+        let pane_id = PaneId::new();
+        attach(&mut manager, pane_id, "first");
+        manager.handle_output(pane_id, numbered_lines(30));
+        let buffer = manager.get_buffer_mut(pane_id).expect("buffer");
+        buffer.scroll_up(10);
+        buffer.enter_copy_mode();
+        buffer.move_copy_cursor(CopyMotion::Down, "");
+        buffer.toggle_copy_selection();
+        buffer.move_copy_cursor(CopyMotion::Right, "");
+        let view = buffer.view_state();
+        let selected = buffer.copy_selection_text();
+        assert!(view.copy_mode.is_some_and(|state| state.anchor.is_some()));
+
+        manager.disconnect(pane_id);
+        assert_eq!(manager.saved_view(pane_id), Some(view));
+
+        attach(&mut manager, pane_id, "second");
+        manager.handle_output(pane_id, numbered_lines(30));
+        let buffer = manager.get_buffer_mut(pane_id).expect("buffer");
+        assert_eq!(buffer.view_state(), view);
+        assert_eq!(buffer.copy_selection_text(), selected);
+    }
+
+    #[test]
+    fn saved_copy_mode_is_clamped_to_the_screen() {
+        let mut buffer = TerminalBuffer::with_size(3, 8);
+        buffer.process("ab中".as_bytes());
+        // Saved from a bigger pane, with the anchor now on a glyph's trailing half
+        buffer.restore_view_state(PaneViewState {
+            scroll_offset: 0,
+            copy_mode: Some(CopyModeState {
+                cursor: (10, 30),
+                anchor: Some((0, 3)),
+            }),
+        });
+        assert_eq!(
+            buffer.copy_mode(),
+            Some(CopyModeState {
+                cursor: (2, 7),
+                anchor: Some((0, 2)),
+            })
+        );
+    }
+
+    #[test]
+    fn saved_scroll_offset_is_clamped_on_reattach() {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let mut manager = TerminalManager::new("http://localhost".to_string(), tx);
+        let pane_id = PaneId::new();
+        attach(&mut manager, pane_id, "first");
+        manager.handle_output(pane_id, numbered_lines(30));
+        manager
+            .get_buffer_mut(pane_id)
+            .expect("buffer")
+            .scroll_up(20);
+        manager.disconnect(pane_id);
+
+        // Less scrollback comes back than there was
+        attach(&mut manager, pane_id, "second");
+        manager.handle_output(pane_id, numbered_lines(8));
+        let buffer = manager.get_buffer_mut(pane_id).expect("buffer");
+        let max_scroll = buffer.terminal.scrollback_len();
+        assert!(max_scroll > 0 && max_scroll < 20);
+        assert_eq!(buffer.scroll_offset(), max_scroll);
+    }
+
+    #[test]
+    fn theme_changes_reach_sessions_out_of_view_when_synced() {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let mut manager = TerminalManager::new("http://localhost".to_string(), tx);
+        let theme = |bg| TerminalColors {
+            foreground: Some((200, 200, 200)),
+            background: Some(bg),
+            cursor: None,
+            selection_background: None,
+            selection_foreground: None,
+        };
+        let (dark, light, prod) = (
+            theme((10, 10, 10)),
+            theme((250, 250, 250)),
+            theme((80, 0, 0)),
+        );
+        let (in_view, out_of_view, themed) = (PaneId::new(), PaneId::new(), PaneId::new());
+        for (i, pane_id) in [in_view, out_of_view, themed].into_iter().enumerate() {
+            let theme_override = (pane_id == themed).then_some(prod);
+            manager.register_session(pane_id, format!("s{}", i), "sandbox".into(), theme_override);
+        }
+
+        let change = |colors| ThemeChangeEvent { colors };
+        manager.apply_theme_change(&change(dark), Some(in_view));
+        assert_eq!(manager.effective_colors(in_view), dark);
+        assert_eq!(manager.effective_colors(out_of_view), dark);
+        // An explicit theme isn't touched
+        assert_eq!(manager.effective_colors(themed).background, prod.background);
+
+        // Unsynced, only the session in view follows the outer terminal
+        manager.set_sync_theme_across_sessions(false);
+        manager.apply_theme_change(&change(light), Some(in_view));
+        assert_eq!(manager.effective_colors(in_view), light);
+        assert_eq!(manager.effective_colors(out_of_view), dark);
+    }
+
+    #[test]
+    fn session_theme_override_lasts_until_detach() {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let mut manager = TerminalManager::new("http://localhost".to_string(), tx);
+        let pane_id = PaneId::new();
+        let prod = TerminalColors {
+            foreground: Some((255, 230, 230)),
+            background: Some((80, 0, 0)),
+            cursor: None,
+            selection_background: None,
+            selection_foreground: None,
+        };
+
+        // Nothing attached yet, so there's nothing to theme
+        assert!(!manager.set_session_theme_override(pane_id, Some(prod)));
+        assert_eq!(manager.effective_colors(pane_id), get_outer_colors());
+
+        let session_id = pane_id_to_session_id(pane_id, 0);
+        manager.register_session(pane_id, session_id, "sandbox".to_string(), Some(prod));
+        let effective = manager.effective_colors(pane_id);
+        assert_eq!(effective.background, prod.background);
+        assert_eq!(effective.foreground, prod.foreground);
+        // Unset override colors are still inherited
+        assert_eq!(effective.cursor, get_outer_colors().cursor);
+
+        manager.disconnect(pane_id);
+        assert_eq!(manager.session_theme_override(pane_id), None);
+        assert_eq!(manager.effective_colors(pane_id), get_outer_colors());
+    }
 }
@@ -0,0 +1,259 @@
+//! Import color schemes from other terminals' theme files.
+//!
+//! Lets dmux adopt a user's existing theme without querying the outer
+//! terminal: X resources (`*foreground: #c5c8c6`, `*color1: ...`) and iTerm2
+//! `.itermcolors` property lists are read into the default colors plus the
+//! 16-color ANSI palette.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::mux::colors::{
+    parse_builtin_osc_color_response, parse_hex_color, AnsiPalette, TerminalColors,
+};
+
+/// Colors read from a theme file. Entries the file doesn't set are `None`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportedTheme {
+    pub colors: TerminalColors,
+    pub palette: AnsiPalette,
+}
+
+impl ImportedTheme {
+    /// Record a color under its X resource name (`foreground`, `color4`, ...).
+    fn set(&mut self, name: &str, color: (u8, u8, u8)) {
+        match name {
+            "foreground" => self.colors.foreground = Some(color),
+            "background" => self.colors.background = Some(color),
+            "cursorColor" => self.colors.cursor = Some(color),
+            _ => {
+                if let Some(entry) = name
+                    .strip_prefix("color")
+                    .and_then(|idx| idx.parse::<usize>().ok())
+                    .and_then(|idx| self.palette.get_mut(idx))
+                {
+                    *entry = Some(color);
+                }
+            }
+        }
+    }
+}
+
+/// Why a `theme_file` couldn't be used.
+#[derive(Debug, Error)]
+pub enum ThemeImportError {
+    #[error("failed to read theme file {path}: {source}")]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("theme file {0} sets no colors")]
+    NoColors(PathBuf),
+}
+
+/// Read a theme file, picking the format from its extension: `.itermcolors`
+/// is an iTerm2 plist, anything else is treated as X resources.
+pub fn load_theme_file(path: &Path) -> Result<ImportedTheme, ThemeImportError> {
+    let contents = std::fs::read_to_string(path).map_err(|source| ThemeImportError::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let theme = if path.extension().is_some_and(|ext| ext == "itermcolors") {
+        import_theme_itermcolors(&contents)
+    } else {
+        import_theme_xresources(&contents)
+    };
+    if theme == ImportedTheme::default() {
+        return Err(ThemeImportError::NoColors(path.to_path_buf()));
+    }
+    Ok(theme)
+}
+
+/// Parse X resources such as `*foreground: #c5c8c6` or `URxvt.color1: rgb:cc/66/66`.
+///
+/// Only the last component of each resource name counts, so `*background`,
+/// `*.background` and `XTerm*background` are the same. Simple `#define NAME
+/// value` macros, common in base16 themes, are substituted. `!` comments and
+/// unparseable values are skipped.
+pub fn import_theme_xresources(contents: &str) -> ImportedTheme {
+    let mut defines: HashMap<&str, &str> = HashMap::new();
+    let mut theme = ImportedTheme::default();
+
+    for line in contents.lines().map(str::trim) {
+        if let Some(define) = line.strip_prefix("#define") {
+            let mut parts = define.split_whitespace();
+            if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+                defines.insert(name, value);
+            }
+            continue;
+        }
+        if line.starts_with('!') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let name = key.trim().rsplit(['*', '.']).next().unwrap_or_default();
+        let value = value.trim();
+        let value = defines.get(value).copied().unwrap_or(value);
+        if let Some(color) = parse_resource_color(value) {
+            theme.set(name, color);
+        }
+    }
+    theme
+}
+
+/// A color resource value: `#rrggbb` or `rgb:r/g/b`.
+fn parse_resource_color(value: &str) -> Option<(u8, u8, u8)> {
+    if value.starts_with("rgb:") {
+        parse_builtin_osc_color_response(value.as_bytes())
+    } else {
+        parse_hex_color(value)
+    }
+}
+
+/// Parse an iTerm2 `.itermcolors` plist.
+///
+/// Each top-level key (`Ansi 0 Color` ... `Ansi 15 Color`, `Foreground
+/// Color`, `Background Color`, `Cursor Color`) maps to a dict of `Red`,
+/// `Green` and `Blue Component` floats in 0.0-1.0. Colors missing a component
+/// are skipped.
+pub fn import_theme_itermcolors(contents: &str) -> ImportedTheme {
+    let mut theme = ImportedTheme::default();
+    let mut depth = 0usize;
+    let mut color_key: Option<&str> = None;
+    let mut component_key: Option<&str> = None;
+    let mut rgb: [Option<u8>; 3] = [None; 3];
+
+    let mut rest = contents;
+    while let Some(start) = rest.find('<') {
+        let Some(end) = rest[start..].find('>') else {
+            break;
+        };
+        let tag = &rest[start + 1..start + end];
+        rest = &rest[start + end + 1..];
+
+        match tag {
+            "dict" => {
+                depth += 1;
+                rgb = [None; 3];
+            }
+            "/dict" => {
+                if depth == 2 {
+                    if let (Some(key), [Some(r), Some(g), Some(b)]) = (color_key, rgb) {
+                        if let Some(name) = iterm_resource_name(key) {
+                            theme.set(&name, (r, g, b));
+                        }
+                    }
+                }
+                depth = depth.saturating_sub(1);
+            }
+            "key" | "real" | "integer" => {
+                let Some(close) = rest.find('<') else {
+                    break;
+                };
+                let text = rest[..close].trim();
+                match (tag, depth) {
+                    ("key", 1) => color_key = Some(text),
+                    ("key", _) => component_key = Some(text),
+                    (_, 2) => {
+                        let slot = match component_key {
+                            Some("Red Component") => Some(0),
+                            Some("Green Component") => Some(1),
+                            Some("Blue Component") => Some(2),
+                            _ => None,
+                        };
+                        if let (Some(slot), Ok(value)) = (slot, text.parse::<f64>()) {
+                            rgb[slot] = Some((value.clamp(0.0, 1.0) * 255.0).round() as u8);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    theme
+}
+
+/// The X resource name for an iTerm2 color key.
+fn iterm_resource_name(key: &str) -> Option<String> {
+    match key {
+        "Foreground Color" => Some("foreground".to_string()),
+        "Background Color" => Some("background".to_string()),
+        "Cursor Color" => Some("cursorColor".to_string()),
+        _ => {
+            let idx = key.strip_prefix("Ansi ")?.strip_suffix(" Color")?;
+            Some(format!("color{}", idx.parse::<u8>().ok()?))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xresources_snippet_sets_colors_and_palette() {
+        let snippet = "\
+! base16 style
+#define base08 #cc6666
+*foreground: #c5c8c6
+*.background:   #1d1f21
+XTerm*cursorColor: rgb:ff/ff/ff
+*color1: base08
+URxvt.color15: #ffffff
+*color16: #000000
+*scrollBar: false
+";
+        let theme = import_theme_xresources(snippet);
+        assert_eq!(theme.colors.foreground, Some((0xc5, 0xc8, 0xc6)));
+        assert_eq!(theme.colors.background, Some((0x1d, 0x1f, 0x21)));
+        assert_eq!(theme.colors.cursor, Some((0xff, 0xff, 0xff)));
+        assert_eq!(theme.palette[1], Some((0xcc, 0x66, 0x66)));
+        assert_eq!(theme.palette[15], Some((0xff, 0xff, 0xff)));
+        assert_eq!(theme.palette[0], None);
+    }
+
+    #[test]
+    fn itermcolors_plist_sets_colors_and_palette() {
+        let plist = r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+	<key>Ansi 2 Color</key>
+	<dict>
+		<key>Blue Component</key>
+		<real>0.0</real>
+		<key>Color Space</key>
+		<string>sRGB</string>
+		<key>Green Component</key>
+		<real>1</real>
+		<key>Red Component</key>
+		<real>0.5</real>
+	</dict>
+	<key>Background Color</key>
+	<dict>
+		<key>Blue Component</key>
+		<real>0.0</real>
+		<key>Green Component</key>
+		<real>0.0</real>
+		<key>Red Component</key>
+		<real>0.0</real>
+	</dict>
+	<key>Foreground Color</key>
+	<dict>
+		<key>Red Component</key>
+		<real>1.0</real>
+	</dict>
+</dict>
+</plist>
+"#;
+        let theme = import_theme_itermcolors(plist);
+        assert_eq!(theme.palette[2], Some((128, 255, 0)));
+        assert_eq!(theme.colors.background, Some((0, 0, 0)));
+        // Missing components make the color unusable
+        assert_eq!(theme.colors.foreground, None);
+    }
+}
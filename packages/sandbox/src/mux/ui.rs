@@ -3,23 +3,42 @@ use ratatui::{
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Clear, Paragraph, Tabs},
+    widgets::{Block, Borders, Clear, Paragraph, Tabs, Wrap},
     Frame,
 };
 use std::time::{SystemTime, UNIX_EPOCH};
+use unicode_width::UnicodeWidthChar;
 
+use crate::mux::capabilities::terminal_capabilities;
+use crate::mux::color_adjust::{Channel, ColorAdjust, ColorTarget};
+use crate::mux::colors::{get_outer_ansi256, get_outer_bg, rgb_to_ansi256};
 use crate::mux::commands::MuxCommand;
+use crate::mux::frame_budget::RenderQuality;
 use crate::mux::layout::LayoutNode;
 use crate::mux::onboard::OnboardPhase;
 use crate::mux::palette::PaletteItem;
 use crate::mux::sidebar::Sidebar;
 use crate::mux::state::{FocusArea, MuxApp};
-use crate::settings::EditorChoice;
+use crate::settings::{EditorChoice, StatusSegment};
+
+/// Smallest terminal (cols, rows) the normal layout is drawn at. Below it
+/// panes can't be tiled, so only the focused pane is drawn (see `render_too_small`).
+pub(crate) const MIN_TILING_SIZE: (u16, u16) = (24, 8);
+
+/// Whether `area` is below `MIN_TILING_SIZE` in either dimension.
+pub(crate) fn is_too_small(area: Rect) -> bool {
+    area.width < MIN_TILING_SIZE.0 || area.height < MIN_TILING_SIZE.1
+}
 
 /// Main UI rendering function.
 pub fn ui(f: &mut Frame, app: &mut MuxApp) {
     let area = f.area();
 
+    if is_too_small(area) {
+        render_too_small(f, app, area);
+        return;
+    }
+
     // Main layout: sidebar | main area
     let main_chunks = if app.sidebar.visible {
         Layout::default()
@@ -42,18 +61,12 @@ pub fn ui(f: &mut Frame, app: &mut MuxApp) {
     };
 
     // Main area layout: tabs | workspace | status
-    let workspace_chunks = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([
-            Constraint::Length(1), // Tab bar
-            Constraint::Min(1),    // Workspace
-            Constraint::Length(1), // Status bar
-        ])
-        .split(main_area);
+    let status_rows = u16::try_from(app.settings.status_rows.len()).unwrap_or(u16::MAX);
+    let (tab_bar_area, workspace_area, status_area) = compute_rects(main_area, status_rows);
 
-    render_tab_bar(f, app, workspace_chunks[0]);
-    render_workspace(f, app, workspace_chunks[1]);
-    render_status_bar(f, app, workspace_chunks[2]);
+    render_tab_bar(f, app, tab_bar_area);
+    render_workspace(f, app, workspace_area);
+    render_status_bar(f, app, status_area);
 
     // Render overlays
     if app.command_palette.is_visible() {
@@ -72,6 +85,10 @@ pub fn ui(f: &mut Frame, app: &mut MuxApp) {
         render_rename_dialog(f, app);
     }
 
+    if let Some(adjust) = &app.color_adjust {
+        render_color_adjust_overlay(f, adjust);
+    }
+
     // Onboard overlay (highest priority - blocks other interactions during setup)
     if let Some(onboard) = &app.onboard {
         if onboard.is_visible {
@@ -207,9 +224,10 @@ fn render_workspace(f: &mut Frame, app: &mut MuxApp, area: Rect) {
     // First, calculate areas (needs mutable borrow)
     // Capture zoomed_pane first to avoid borrow issues
     let zoomed_pane = app.zoomed_pane;
+    let pane_gap = app.settings.pane_gap;
 
     if let Some(tab) = app.active_tab_mut() {
-        tab.layout.calculate_areas(area);
+        tab.layout.calculate_areas_with_gap(area, pane_gap);
         if let Some(zoomed_id) = zoomed_pane {
             if let Some(pane) = tab.layout.find_pane_mut(zoomed_id) {
                 pane.area = Some(area);
@@ -243,10 +261,114 @@ fn render_workspace(f: &mut Frame, app: &mut MuxApp, area: Rect) {
         }
     }
 
+    // Gaps between panes show the outer terminal's background
+    if pane_gap > 0 {
+        f.render_widget(
+            Block::default()
+                .style(Style::default().bg(outer_bg_color(app.transparent_background()))),
+            area,
+        );
+    }
+
     // Render all panes
     render_layout_node(f, &layout, active_pane_id, is_main_focused, app);
 }
 
+/// Fallback for terminals below `MIN_TILING_SIZE`: the focused pane fills all
+/// but the last row, which shows a "terminal too small" hint. The normal
+/// layout, and any open overlay, comes back as soon as the terminal is enlarged.
+fn render_too_small(f: &mut Frame, app: &mut MuxApp, area: Rect) {
+    let hint_height = area.height.min(1);
+    let pane_area = Rect {
+        height: area.height - hint_height,
+        ..area
+    };
+    let hint_area = Rect {
+        y: area.y + pane_area.height,
+        height: hint_height,
+        ..area
+    };
+
+    let zoomed_pane = app.zoomed_pane;
+    let focused = app.active_tab_mut().and_then(|tab| {
+        let pane_id = zoomed_pane
+            .filter(|&id| tab.layout.find_pane(id).is_some())
+            .or(tab.active_pane)?;
+        let pane = tab.layout.find_pane_mut(pane_id)?;
+        pane.area = Some(pane_area);
+        Some(pane.clone())
+    });
+    if let Some(pane) = focused {
+        if !pane_area.is_empty() {
+            let is_main_focused = app.focus == FocusArea::MainArea;
+            render_pane(f, &pane, pane_area, true, is_main_focused, app);
+        }
+    }
+
+    let hint = Paragraph::new(Line::styled(
+        format!("terminal too small ({}x{})", area.width, area.height),
+        Style::default().fg(Color::Yellow),
+    ));
+    f.render_widget(hint, hint_area);
+}
+
+/// The outer terminal's background: exact on truecolor terminals, otherwise
+/// the nearest 256-color index so the UI still tracks the theme. With a
+/// `transparent` background nothing is painted, so it shows through.
+fn outer_bg_color(transparent: bool) -> Color {
+    if transparent {
+        return Color::Reset;
+    }
+    if terminal_capabilities().truecolor {
+        let (r, g, b) = get_outer_bg();
+        return Color::Rgb(r, g, b);
+    }
+    match get_outer_ansi256().background {
+        Some(index) => Color::Indexed(index),
+        None => Color::Reset,
+    }
+}
+
+/// An RGB color as truecolor, or its nearest 256-color index without truecolor.
+fn rgb_color((r, g, b): (u8, u8, u8)) -> Color {
+    if terminal_capabilities().truecolor {
+        Color::Rgb(r, g, b)
+    } else {
+        Color::Indexed(rgb_to_ansi256((r, g, b)))
+    }
+}
+
+/// Highlight the stream selection between two content cells (row, col) of a
+/// pane drawn at `area`, in either order, with (fg, bg) `colors`.
+fn paint_selection(
+    buf: &mut ratatui::buffer::Buffer,
+    area: Rect,
+    anchor: (usize, usize),
+    head: (usize, usize),
+    (fg, bg): ((u8, u8, u8), (u8, u8, u8)),
+) {
+    if area.is_empty() {
+        return;
+    }
+    let (start, end) = if anchor <= head {
+        (anchor, head)
+    } else {
+        (head, anchor)
+    };
+    let style = Style::default().fg(rgb_color(fg)).bg(rgb_color(bg));
+    let rows = end.0.min((area.height as usize).saturating_sub(1));
+    for row in start.0..=rows {
+        let first = if row == start.0 { start.1 } else { 0 };
+        let last = if row == end.0 { end.1 } else { usize::MAX };
+        let last = last.min((area.width as usize).saturating_sub(1));
+        for col in first..=last {
+            if let Some(cell) = buf.cell_mut((area.x + col as u16, area.y + row as u16)) {
+                cell.set_style(style);
+            }
+        }
+    }
+}
+
 /// Recursively render layout nodes.
 fn render_layout_node(
     f: &mut Frame,
@@ -306,15 +428,50 @@ fn render_pane(
     // Render pane content
     match &pane.content {
         crate::mux::layout::PaneContent::Empty => {
-            let text = Paragraph::new("Empty pane\n\nUse Alt+- or Alt+\\ to split")
-                .style(Style::default().fg(Color::DarkGray))
-                .alignment(Alignment::Center);
-            f.render_widget(text, inner_area);
+            let text = match &pane.note {
+                Some(note) => Paragraph::new(note.as_str())
+                    .style(Style::default().fg(Color::Red))
+                    .wrap(Wrap { trim: false }),
+                None => Paragraph::new("Empty pane\n\nUse Alt+- or Alt+\\ to split")
+                    .style(Style::default().fg(Color::DarkGray)),
+            };
+            f.render_widget(text.alignment(Alignment::Center), inner_area);
         }
         crate::mux::layout::PaneContent::Terminal { sandbox_id, .. } => {
+            // A fixed-size pane is letterboxed, the margins in the inherited background
+            let inner_area = if pane.fixed_size.is_some() {
+                f.render_widget(
+                    Block::default()
+                        .style(Style::default().bg(outer_bg_color(app.transparent_background()))),
+                    inner_area,
+                );
+                pane.content_area(inner_area)
+            } else {
+                inner_area
+            };
+
+            // A themed session paints its own background under the output
+            let themed_bg = app
+                .session_theme_override(pane.id)
+                .and_then(|theme| theme.background);
+            if let Some((r, g, b)) = themed_bg {
+                f.render_widget(
+                    Block::default().style(Style::default().bg(Color::Rgb(r, g, b))),
+                    inner_area,
+                );
+            }
+
             // Check if we have terminal output to display
             let height = inner_area.height as usize;
-            let view = app.get_terminal_view(pane.id, height);
+            // A pane without output since the last frame repaints its last view
+            // rather than re-reading and re-diffing its terminal buffer
+            let stale = !app.frame_damage.contains(pane.id)
+                && app.last_terminal_views.contains_key(&pane.id);
+            let view = if stale {
+                None
+            } else {
+                app.get_terminal_view(pane.id, height)
+            };
             let previous = app.last_terminal_views.get(&pane.id).cloned();
             let render_view = match (view, previous.clone()) {
                 (Some(v), _) if v.has_content => Some(v),
@@ -338,9 +495,11 @@ fn render_pane(
                     let visible_rows = height.min(view.lines.len());
 
                     let changed = view.changed_lines.as_ref();
+                    let styled_underlines = terminal_capabilities().styled_underlines;
 
                     for row in 0..visible_rows {
-                        let row_changed = previous.is_none() || changed.binary_search(&row).is_ok();
+                        let row_changed =
+                            stale || previous.is_none() || changed.binary_search(&row).is_ok();
                         if !row_changed {
                             continue;
                         }
@@ -351,6 +510,21 @@ fn render_pane(
                         let y = inner_area.y + row as u16;
 
                         for span in &line.spans {
+                            let plain;
+                            let span = if !styled_underlines && span.style.underline_color.is_some()
+                            {
+                                // Terminals without SGR 58 misread it as other attributes
+                                plain = Span::styled(
+                                    span.content.clone(),
+                                    Style {
+                                        underline_color: None,
+                                        ..span.style
+                                    },
+                                );
+                                &plain
+                            } else {
+                                span
+                            };
                             let (next_x, _) = buf.set_span(
                                 x,
                                 y,
@@ -392,6 +566,38 @@ fn render_pane(
 
                     app.last_terminal_views.insert(pane.id, view.clone());
 
+                    if let Some(selection) = app.mouse_selection.filter(|s| s.pane_id == pane.id) {
+                        let colors = app.session_colors(pane.id);
+                        paint_selection(
+                            f.buffer_mut(),
+                            inner_area,
+                            selection.anchor,
+                            selection.head,
+                            colors.selection_colors(),
+                        );
+                    }
+
+                    if let Some(copy_mode) = app.copy_mode(pane.id) {
+                        let (fg, bg) = app.session_colors(pane.id).selection_colors();
+                        if let Some(anchor) = copy_mode.anchor {
+                            paint_selection(
+                                f.buffer_mut(),
+                                inner_area,
+                                anchor,
+                                copy_mode.cursor,
+                                (fg, bg),
+                            );
+                        }
+                        // The copy-mode cursor, in the selection colors swapped
+                        paint_selection(
+                            f.buffer_mut(),
+                            inner_area,
+                            copy_mode.cursor,
+                            copy_mode.cursor,
+                            (bg, fg),
+                        );
+                    }
+
                     // Set cursor position only if:
                     // 1. Pane is active
                     // 2. Main area is focused (not sidebar or command palette)
@@ -484,116 +690,243 @@ fn render_pane(
     }
 }
 
-/// Render the status bar.
+/// Render the status bar: one line per configured `StatusRow`, each with its
+/// left, center and right segments.
 fn render_status_bar(f: &mut Frame, app: &mut MuxApp, area: Rect) {
     app.clear_expired_status();
 
-    let mut spans = Vec::new();
+    let rows = app.settings.status_rows.clone();
+    for (index, row) in rows.iter().enumerate() {
+        let Some(y) = u16::try_from(index)
+            .ok()
+            .map(|offset| area.y + offset)
+            .filter(|&y| y < area.bottom())
+        else {
+            break;
+        };
+        let row_area = Rect::new(area.x, y, area.width, 1);
+        let line = compose_status_row(
+            status_segments_line(app, &row.left),
+            status_segments_line(app, &row.center),
+            status_segments_line(app, &row.right),
+            usize::from(area.width),
+        );
+        f.render_widget(Paragraph::new(line), row_area);
+    }
+}
 
-    // Mode indicator
-    let mode = match app.focus {
-        FocusArea::Sidebar => "SIDEBAR",
-        FocusArea::MainArea => "NORMAL",
-        FocusArea::CommandPalette => "COMMAND",
-        FocusArea::Notifications => "NOTIFS",
-        FocusArea::Onboard => "SETUP",
+/// One status row `width` columns wide: `left` at the start, `right` at the
+/// end and `center` centered as far as it fits between them, a space from
+/// each. A row too narrow for all three cuts the right segment first, then
+/// the center, so the left one stays whole as long as it fits.
+fn compose_status_row(
+    left: Line<'static>,
+    center: Line<'static>,
+    right: Line<'static>,
+    width: usize,
+) -> Line<'static> {
+    let left = truncate_line(left, width);
+    let left_end = left.width();
+    let start_min = if left_end > 0 { left_end + 1 } else { 0 };
+    let right = truncate_line(right, width.saturating_sub(start_min));
+    let right_start = width - right.width();
+    let end_max = if right.width() > 0 {
+        right_start.saturating_sub(1)
+    } else {
+        width
     };
-    spans.push(Span::styled(
-        format!(" {} ", mode),
-        Style::default()
-            .fg(Color::Black)
-            .bg(Color::Cyan)
-            .add_modifier(Modifier::BOLD),
-    ));
-    spans.push(Span::raw(" "));
-
-    // Debug build indicator (only in debug builds)
-    #[cfg(debug_assertions)]
-    {
-        spans.push(Span::styled(
-            "[debug build]",
-            Style::default()
-                .fg(Color::Black)
-                .bg(Color::Yellow)
-                .add_modifier(Modifier::BOLD),
-        ));
-        spans.push(Span::raw(" "));
+    let center = truncate_line(center, end_max.saturating_sub(start_min));
+
+    let mut spans = left.spans;
+    let mut column = left_end;
+    if center.width() > 0 {
+        let center_start = (width.saturating_sub(center.width()) / 2)
+            .min(end_max - center.width())
+            .max(start_min);
+        spans.push(Span::raw(" ".repeat(center_start - column)));
+        column = center_start + center.width();
+        spans.extend(center.spans);
     }
-
-    // Active sandbox info
-    if let Some(workspace) = app.workspace_manager.active_workspace() {
-        spans.push(Span::styled(
-            format!("[{}] ", workspace.name),
-            Style::default().fg(Color::Green),
-        ));
+    if right.width() > 0 {
+        spans.push(Span::raw(" ".repeat(right_start - column)));
+        spans.extend(right.spans);
     }
+    Line::from(spans)
+}
 
-    // Pane info
-    if let Some(tab) = app.active_tab() {
-        let pane_count = tab.layout.pane_count();
-        spans.push(Span::styled(
-            format!(
-                "{} pane{}",
-                pane_count,
-                if pane_count == 1 { "" } else { "s" }
-            ),
-            Style::default().fg(Color::DarkGray),
-        ));
-        spans.push(Span::raw(" │ "));
+/// `line` cut to at most `width` columns.
+fn truncate_line(line: Line<'static>, width: usize) -> Line<'static> {
+    if line.width() <= width {
+        return line;
     }
-
-    // Tab info
-    if let Some(workspace) = app.workspace_manager.active_workspace() {
-        spans.push(Span::styled(
-            format!(
-                "Tab {}/{}",
-                workspace.active_tab_index + 1,
-                workspace.tabs.len()
-            ),
-            Style::default().fg(Color::DarkGray),
-        ));
-    } else {
-        spans.push(Span::styled(
-            "No sandbox",
-            Style::default().fg(Color::DarkGray),
-        ));
+    let mut remaining = width;
+    let mut spans = Vec::new();
+    for span in line.spans {
+        let mut text = String::new();
+        for c in span.content.chars() {
+            let char_width = c.width().unwrap_or(0);
+            if char_width > remaining {
+                remaining = 0;
+                break;
+            }
+            remaining -= char_width;
+            text.push(c);
+        }
+        spans.push(Span::styled(text, span.style));
+        if remaining == 0 {
+            break;
+        }
     }
+    Line::from(spans)
+}
 
-    // Status message
-    if let Some((msg, _)) = &app.status_message {
-        spans.push(Span::raw(" │ "));
-        spans.push(Span::styled(
-            msg.clone(),
-            Style::default().fg(Color::Yellow),
-        ));
+/// The spans of `segments`, separated by ` │ ` except after the mode,
+/// indicators and workspace, which are followed by a space. Segments with
+/// nothing to show are left out.
+fn status_segments_line(app: &MuxApp, segments: &[StatusSegment]) -> Line<'static> {
+    let mut spans: Vec<Span<'static>> = Vec::new();
+    let mut separator = None;
+    for segment in segments {
+        let segment_spans = status_segment_spans(app, segment);
+        if segment_spans.is_empty() {
+            continue;
+        }
+        if let Some(separator) = separator {
+            spans.push(Span::raw(separator));
+        }
+        spans.extend(segment_spans);
+        separator = Some(match segment {
+            StatusSegment::Mode | StatusSegment::Indicators | StatusSegment::Workspace => " ",
+            _ => " │ ",
+        });
     }
+    Line::from(spans)
+}
 
-    // Unread notifications
-    let unread = app.notifications.unread_count();
-    spans.push(Span::raw(" │ "));
-    spans.push(Span::styled(
-        format!("Notifs: {}", unread),
-        if unread > 0 {
+/// What one status bar segment shows, or nothing.
+fn status_segment_spans(app: &MuxApp, segment: &StatusSegment) -> Vec<Span<'static>> {
+    let badge = |text: String, bg: Color| {
+        Span::styled(
+            text,
             Style::default()
-                .fg(Color::Yellow)
-                .add_modifier(Modifier::BOLD)
-        } else {
-            Style::default().fg(Color::DarkGray)
+                .fg(Color::Black)
+                .bg(bg)
+                .add_modifier(Modifier::BOLD),
+        )
+    };
+    let dim = |text: String| Span::styled(text, Style::default().fg(Color::DarkGray));
+
+    match segment {
+        StatusSegment::Mode => {
+            let mode = match app.focus {
+                FocusArea::Sidebar => "SIDEBAR",
+                FocusArea::MainArea => "NORMAL",
+                FocusArea::CommandPalette => "COMMAND",
+                FocusArea::Notifications => "NOTIFS",
+                FocusArea::Onboard => "SETUP",
+            };
+            vec![badge(format!(" {} ", mode), Color::Cyan)]
+        }
+        StatusSegment::Indicators => {
+            let mut badges = Vec::new();
+            // Debug build indicator (only in debug builds)
+            if cfg!(debug_assertions) {
+                badges.push(badge("[debug build]".to_string(), Color::Yellow));
+            }
+            // Nesting indicator, so it's clear which dmux the keys go to
+            if app.nesting_depth > 0 {
+                badges.push(badge(
+                    format!("[nested {}]", app.nesting_depth),
+                    Color::Magenta,
+                ));
+            }
+            // Render quality, when load has lowered it
+            let quality = app.frame_budget.quality();
+            if quality != RenderQuality::Full {
+                badges.push(Span::styled(
+                    format!("[render: {}]", quality.label()),
+                    Style::default().fg(Color::Black).bg(Color::Yellow),
+                ));
+            }
+            let mut spans = Vec::new();
+            for (index, badge) in badges.into_iter().enumerate() {
+                if index > 0 {
+                    spans.push(Span::raw(" "));
+                }
+                spans.push(badge);
+            }
+            spans
+        }
+        StatusSegment::Workspace => app
+            .workspace_manager
+            .active_workspace()
+            .map(|workspace| {
+                Span::styled(
+                    format!("[{}]", workspace.name),
+                    Style::default().fg(Color::Green),
+                )
+            })
+            .into_iter()
+            .collect(),
+        StatusSegment::Panes => app
+            .active_tab()
+            .map(|tab| {
+                let pane_count = tab.layout.pane_count();
+                dim(format!(
+                    "{} pane{}",
+                    pane_count,
+                    if pane_count == 1 { "" } else { "s" }
+                ))
+            })
+            .into_iter()
+            .collect(),
+        StatusSegment::Tab => match app.workspace_manager.active_workspace() {
+            Some(workspace) => vec![dim(format!(
+                "Tab {}/{}",
+                workspace.active_tab_index + 1,
+                workspace.tabs.len()
+            ))],
+            None => vec![dim("No sandbox".to_string())],
         },
-    ));
-
-    // Right side: keybinding hints
-    let hints = " Alt+P: Commands │ Alt+Shift+N: Notifications │ Ctrl+S: Sidebar │ Ctrl+Q: Quit ";
-    let hints_width = hints.len() as u16;
-    let left_width: u16 = spans.iter().map(|s| s.content.len() as u16).sum();
-    let padding = area.width.saturating_sub(left_width + hints_width);
-
-    spans.push(Span::raw(" ".repeat(padding as usize)));
-    spans.push(Span::styled(hints, Style::default().fg(Color::DarkGray)));
+        StatusSegment::Message => app
+            .status_message
+            .as_ref()
+            .map(|(msg, _)| Span::styled(msg.clone(), Style::default().fg(Color::Yellow)))
+            .into_iter()
+            .collect(),
+        StatusSegment::Notifications => {
+            let unread = app.notifications.unread_count();
+            vec![Span::styled(
+                format!("Notifs: {}", unread),
+                if unread > 0 {
+                    Style::default()
+                        .fg(Color::Yellow)
+                        .add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default().fg(Color::DarkGray)
+                },
+            )]
+        }
+        StatusSegment::Hints => vec![dim(
+            " Alt+P: Commands │ Alt+Shift+N: Notifications │ Ctrl+S: Sidebar │ Ctrl+Q: Quit "
+                .to_string(),
+        )],
+        StatusSegment::Text(text) => vec![Span::raw(text.clone())],
+    }
+}
 
-    let line = Line::from(spans);
-    let paragraph = Paragraph::new(line);
-    f.render_widget(paragraph, area);
+/// Split the main area into the tab bar, the workspace, and a status bar of
+/// `status_rows` rows at the bottom, so panes never overlap the status.
+pub(crate) fn compute_rects(area: Rect, status_rows: u16) -> (Rect, Rect, Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(1),           // Tab bar
+            Constraint::Min(1),              // Workspace
+            Constraint::Length(status_rows), // Status bar
+        ])
+        .split(area);
+    (chunks[0], chunks[1], chunks[2])
 }
 
 /// Render the command palette overlay.
@@ -636,7 +969,12 @@ fn render_command_palette(f: &mut Frame, app: &mut MuxApp) {
     f.render_widget(search_prefix, Rect::new(search_area.x, search_area.y, 2, 1));
     f.render_widget(
         app.command_palette.search_input(),
-        Rect::new(search_area.x + 2, search_area.y, search_area.width - 2, 1),
+        Rect::new(
+            search_area.x + 2,
+            search_area.y,
+            search_area.width.saturating_sub(2),
+            1,
+        ),
     );
 
     // Commands list
@@ -767,7 +1105,7 @@ fn render_command_palette(f: &mut Frame, app: &mut MuxApp) {
     // Help text at bottom - changes based on whether we're in a submenu
     let help_area = Rect::new(
         inner_area.x,
-        inner_area.y + inner_area.height - 1,
+        inner_area.y + inner_area.height.saturating_sub(1),
         inner_area.width,
         1,
     );
@@ -1120,7 +1458,7 @@ fn render_rename_dialog(f: &mut Frame, app: &MuxApp) {
 
     let help_area = Rect::new(
         inner_area.x,
-        inner_area.y + inner_area.height - 1,
+        inner_area.y + inner_area.height.saturating_sub(1),
         inner_area.width,
         1,
     );
@@ -1131,6 +1469,68 @@ fn render_rename_dialog(f: &mut Frame, app: &MuxApp) {
     f.render_widget(help, help_area);
 }
 
+/// Render the color adjustment sliders: a swatch and RGB values per color,
+/// with the focused channel highlighted.
+fn render_color_adjust_overlay(f: &mut Frame, adjust: &ColorAdjust) {
+    let area = f.area();
+
+    let dialog_width = 50u16.min(area.width.saturating_sub(4));
+    let dialog_height = 8u16;
+
+    let x = (area.width.saturating_sub(dialog_width)) / 2;
+    let y = (area.height.saturating_sub(dialog_height)) / 2;
+
+    let dialog_area = Rect::new(x, y, dialog_width, dialog_height);
+    f.render_widget(Clear, dialog_area);
+
+    let block = Block::default()
+        .title(" Adjust Colors ")
+        .title_style(
+            Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD),
+        )
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan));
+
+    let inner_area = block.inner(dialog_area);
+    f.render_widget(block, dialog_area);
+
+    let selected = adjust.selected();
+    let mut lines: Vec<Line> = ColorTarget::ALL
+        .iter()
+        .map(|&target| {
+            let mut spans = vec![
+                Span::raw(format!("{:<11}", target.label())),
+                Span::styled("  ", Style::default().bg(rgb_color(adjust.color(target)))),
+            ];
+            for channel in Channel::ALL {
+                let style = if (target, channel) == selected {
+                    Style::default()
+                        .fg(Color::Cyan)
+                        .add_modifier(Modifier::BOLD | Modifier::REVERSED)
+                } else {
+                    Style::default()
+                };
+                spans.push(Span::raw(" "));
+                spans.push(Span::styled(
+                    format!("{} {:>3}", channel.label(), adjust.value(target, channel)),
+                    style,
+                ));
+            }
+            Line::from(spans)
+        })
+        .collect();
+    lines.push(Line::raw(""));
+    for help in [
+        "←→: ±1 │ ↑↓: ±16 │ Tab: next channel",
+        "Enter: apply │ e: apply + export │ Esc: cancel",
+    ] {
+        lines.push(Line::styled(help, Style::default().fg(Color::DarkGray)));
+    }
+    f.render_widget(Paragraph::new(lines), inner_area);
+}
+
 /// Render the onboarding overlay for Docker image setup.
 fn render_onboard_overlay(f: &mut Frame, app: &MuxApp) {
     let Some(onboard) = &app.onboard else {
@@ -1344,3 +1744,160 @@ fn render_onboard_overlay(f: &mut Frame, app: &MuxApp) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::StatusRow;
+    use ratatui::{backend::TestBackend, Terminal};
+    use std::path::PathBuf;
+    use tokio::sync::mpsc;
+
+    fn app_with_split_tab() -> MuxApp<'static> {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let mut app = MuxApp::new("http://localhost".to_string(), tx, PathBuf::from("."));
+        app.workspace_manager
+            .add_sandbox(crate::mux::layout::SandboxId::new(), "Test");
+        app.execute_command(MuxCommand::SplitVertical);
+        app.execute_command(MuxCommand::SplitHorizontal);
+        app
+    }
+
+    fn draw(app: &mut MuxApp, cols: u16, rows: u16) -> ratatui::buffer::Buffer {
+        let mut terminal = Terminal::new(TestBackend::new(cols, rows)).expect("terminal");
+        terminal.draw(|f| ui(f, app)).expect("draw");
+        terminal.backend().buffer().clone()
+    }
+
+    fn buffer_text(buffer: &ratatui::buffer::Buffer) -> String {
+        buffer.content().iter().map(|cell| cell.symbol()).collect()
+    }
+
+    #[test]
+    fn tiny_terminals_draw_without_panicking() {
+        let mut app = app_with_split_tab();
+        for (cols, rows) in [(0, 0), (1, 1), (1, 0), (0, 1), (10, 3), (3, 10)] {
+            draw(&mut app, cols, rows);
+        }
+
+        // Overlays must fit too
+        app.show_help = true;
+        app.notifications.is_open = true;
+        app.renaming_tab = true;
+        app.command_palette.open();
+        for (cols, rows) in [(1, 1), (10, 3), MIN_TILING_SIZE, (30, 9)] {
+            draw(&mut app, cols, rows);
+        }
+    }
+
+    #[test]
+    fn too_small_shows_only_the_focused_pane_until_enlarged() {
+        let mut app = app_with_split_tab();
+        let (cols, rows) = MIN_TILING_SIZE;
+        let focused = app.active_pane_id().expect("active pane");
+
+        let small = draw(&mut app, cols - 1, rows);
+        assert!(buffer_text(&small).contains("terminal too small"));
+        let tab = app.active_tab().expect("tab");
+        let area = tab.layout.find_pane(focused).and_then(|p| p.area);
+        assert_eq!(area, Some(Rect::new(0, 0, cols - 1, rows - 1)));
+
+        assert!(buffer_text(&draw(&mut app, cols, rows - 1)).contains("terminal too small"));
+
+        // At the threshold the normal layout (tab bar, status bar) is back
+        let normal = draw(&mut app, cols, rows);
+        assert!(!buffer_text(&normal).contains("terminal too small"));
+        let tab = app.active_tab().expect("tab");
+        let areas: Vec<Rect> = tab.layout.panes().iter().filter_map(|p| p.area).collect();
+        assert_eq!(areas.len(), 3);
+        assert!(areas.iter().all(|a| a.y >= 1 && a.bottom() < rows));
+    }
+
+    #[test]
+    fn compute_rects_reserves_every_status_row() {
+        let (tab_bar, workspace, status) = compute_rects(Rect::new(0, 0, 80, 24), 2);
+        assert_eq!(tab_bar, Rect::new(0, 0, 80, 1));
+        assert_eq!(workspace, Rect::new(0, 1, 80, 21));
+        assert_eq!(status, Rect::new(0, 22, 80, 2));
+    }
+
+    #[test]
+    fn status_rows_render_aligned_segments_below_the_panes() {
+        let mut app = app_with_split_tab();
+        app.sidebar.visible = false;
+        app.settings.status_rows.push(StatusRow {
+            left: vec![StatusSegment::Text("left-text".to_string())],
+            center: vec![StatusSegment::Text("center-text".to_string())],
+            right: vec![StatusSegment::Text("right-text".to_string())],
+        });
+
+        let (cols, rows) = (80, 24);
+        let buffer = draw(&mut app, cols, rows);
+        let row_text = |y: u16| -> String {
+            (0..cols)
+                .map(|x| buffer.cell((x, y)).map_or(" ", |cell| cell.symbol()))
+                .collect()
+        };
+
+        assert!(row_text(rows - 2).contains("NORMAL"));
+        let last = row_text(rows - 1);
+        assert!(last.starts_with("left-text"));
+        assert!(last.trim_end().ends_with("right-text"));
+        let center = last.find("center-text").expect("center segment");
+        assert!(center > 20 && center < 40);
+
+        let tab = app.active_tab().expect("tab");
+        let areas: Vec<Rect> = tab.layout.panes().iter().filter_map(|p| p.area).collect();
+        assert_eq!(areas.len(), 3);
+        assert!(areas.iter().all(|a| a.bottom() <= rows - 2));
+    }
+
+    #[test]
+    fn narrow_status_rows_cut_the_right_then_the_center() {
+        let text = |line: Line<'static>| -> String {
+            line.spans
+                .iter()
+                .map(|span| span.content.as_ref())
+                .collect()
+        };
+        let row = |left: &str, center: &str, right: &str, width| {
+            text(compose_status_row(
+                Line::from(left.to_string()),
+                Line::from(center.to_string()),
+                Line::from(right.to_string()),
+                width,
+            ))
+        };
+
+        assert_eq!(row("left", "mid", "right", 21), "left     mid    right");
+        assert_eq!(
+            row("left", "centered-text", "right", 20),
+            "left centered- right"
+        );
+        assert_eq!(row("left", "", "0123456789abcdef", 12), "left 0123456");
+        assert_eq!(row("left", "mid", "right", 5), "left");
+        assert_eq!(row("a long left side", "mid", "right", 6), "a long");
+    }
+
+    #[test]
+    fn transparent_background_leaves_gaps_unpainted() {
+        let mut app = app_with_split_tab();
+        app.settings.pane_gap = 1;
+        app.settings.transparent_background = Some(true);
+
+        let painted = |buffer: &ratatui::buffer::Buffer| {
+            buffer
+                .content()
+                .iter()
+                .any(|cell| matches!(cell.bg, Color::Rgb(..) | Color::Indexed(_)))
+        };
+        assert!(!painted(&draw(&mut app, 60, 20)));
+        assert_eq!(outer_bg_color(true), Color::Reset);
+
+        // Opaque, the gaps get the inherited background wherever it's known
+        app.settings.transparent_background = Some(false);
+        if outer_bg_color(false) != Color::Reset {
+            assert!(painted(&draw(&mut app, 60, 20)));
+        }
+    }
+}
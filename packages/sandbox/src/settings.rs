@@ -11,6 +11,11 @@ use std::path::PathBuf;
 
 const APP_NAME: &str = "cmux";
 const SETTINGS_FILE: &str = "settings.json";
+const TEMPLATES_DIR: &str = "templates";
+
+/// Default `word_separators`: ASCII punctuation other than `_`, so words are
+/// identifiers and path or URL components.
+pub const DEFAULT_WORD_SEPARATORS: &str = r#"`~!@#$%^&*()-=+[{]}\|;:'",.<>/?"#;
 
 /// Default editor choice for opening sandboxes via SSH.
 #[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
@@ -127,12 +132,373 @@ impl EditorChoice {
     }
 }
 
+/// What dmux does when the process in the last remaining pane exits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnLastPaneExit {
+    /// Exit dmux.
+    Quit,
+    /// Leave the window open with no panes.
+    #[default]
+    KeepEmpty,
+    /// Open a fresh pane running the default shell.
+    Respawn,
+}
+
+/// How Reset Pane resets a pane's terminal state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaneReset {
+    /// DECSTR: reset attributes, charsets and modes, keeping the screen.
+    #[default]
+    Soft,
+    /// RIS: reset everything and clear the screen.
+    Full,
+}
+
+impl PaneReset {
+    /// The escape sequence that performs this reset.
+    pub fn sequence(self) -> &'static [u8] {
+        match self {
+            PaneReset::Soft => b"\x1b[!p",
+            PaneReset::Full => b"\x1bc",
+        }
+    }
+}
+
+/// How frames are written to the outer terminal (see `mux::output`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RenderBackend {
+    /// Through crossterm's commands.
+    #[default]
+    Crossterm,
+    /// As plain ANSI escape sequences.
+    Ansi,
+}
+
+/// The background assumed when the outer terminal doesn't report its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FallbackBackground {
+    /// Near black or off-white, whichever suits the foreground's theme mode.
+    #[default]
+    Derived,
+    /// The dark gray of ghostty's default theme.
+    Ghostty,
+}
+
+/// How a dmux running inside another dmux takes its key bindings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NestedKeys {
+    /// Add Ctrl to every Alt binding (Alt+d becomes Ctrl+Alt+d).
+    #[default]
+    Remap,
+    /// Keep the bindings; reach them through the outer dmux's Send Next Key.
+    DoublePrefix,
+}
+
+/// Light or dark theme mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemeMode {
+    Light,
+    Dark,
+}
+
+/// Theme colors pinned in the settings file, as `#rrggbb` hex strings.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct ThemeSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub foreground: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub background: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<ThemeMode>,
+}
+
+/// What a status bar segment shows.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatusSegment {
+    /// The focused area, e.g. `NORMAL`.
+    Mode,
+    /// Debug build, nesting and render quality badges, when they apply.
+    Indicators,
+    /// The active sandbox's name.
+    Workspace,
+    /// How many panes the active tab has.
+    Panes,
+    /// The active tab's position.
+    Tab,
+    /// The transient status message, if any.
+    Message,
+    /// Unread notification count.
+    Notifications,
+    /// Key binding hints.
+    Hints,
+    /// Fixed text.
+    Text(String),
+}
+
+/// One row of the status bar: segments aligned left, centered and right.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct StatusRow {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub left: Vec<StatusSegment>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub center: Vec<StatusSegment>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub right: Vec<StatusSegment>,
+}
+
+/// A multi-key binding: `keys`, pressed in turn, run `command`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyChord {
+    /// Space-separated key names as `send-keys` takes them, e.g. `M-g s`.
+    pub keys: String,
+    /// Command line to run, as in `startup_commands`.
+    pub command: String,
+}
+
+/// Arrangement of a `WorkspaceTemplate`'s panes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TemplateLayout {
+    /// Stacked top to bottom.
+    #[default]
+    EvenHorizontal,
+    /// Side by side.
+    EvenVertical,
+    /// In a near-square grid.
+    Tiled,
+}
+
+/// One pane of a `WorkspaceTemplate`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaneTemplate {
+    /// Command line to run, split like a shell would (quotes group words);
+    /// unset runs the sandbox's default shell.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    /// Directory to run it in, relative to the workspace.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<PathBuf>,
+    /// Pane title; defaults to the command.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+/// A tab of panes laid out by a preset, opened with `spawn-workspace NAME`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkspaceTemplate {
+    /// Name to spawn it by; a template file's defaults to the file name.
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub layout: TemplateLayout,
+    /// Panes in layout order.
+    #[serde(default)]
+    pub panes: Vec<PaneTemplate>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// The single status row dmux has always shown.
+fn default_status_rows() -> Vec<StatusRow> {
+    vec![StatusRow {
+        left: vec![
+            StatusSegment::Mode,
+            StatusSegment::Indicators,
+            StatusSegment::Workspace,
+            StatusSegment::Panes,
+            StatusSegment::Tab,
+            StatusSegment::Message,
+            StatusSegment::Notifications,
+        ],
+        center: Vec::new(),
+        right: vec![StatusSegment::Hints],
+    }]
+}
+
+fn default_silence_timeout_secs() -> u64 {
+    30
+}
+
+fn default_color_requery_delay_ms() -> u64 {
+    500
+}
+
+fn default_min_color_query_interval_ms() -> u64 {
+    250
+}
+
+fn default_word_separators() -> String {
+    DEFAULT_WORD_SEPARATORS.to_string()
+}
+
+fn default_chord_timeout_ms() -> u64 {
+    1000
+}
+
 /// Persistent settings for the application.
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Settings {
     /// Default editor for opening sandboxes.
     #[serde(default)]
     pub default_editor: EditorChoice,
+    /// Behavior when the last pane's process exits.
+    #[serde(default)]
+    pub on_last_pane_exit: OnLastPaneExit,
+    /// Keep a pane in its slot after its process exits, so it can be respawned.
+    #[serde(default)]
+    pub remain_on_exit: bool,
+    /// Return panes to where they were scrolled when their sessions reattach,
+    /// instead of to the bottom.
+    #[serde(default = "default_true")]
+    pub restore_scroll_on_reattach: bool,
+    /// Detach every other client attached to a session when a pane attaches
+    /// to it, like `tmux attach -d`. Those clients are told why they were detached.
+    #[serde(default)]
+    pub detach_other_clients: bool,
+    /// Match dmux's cursor shape to the outer terminal's.
+    #[serde(default = "default_true")]
+    pub inherit_cursor_style: bool,
+    /// Pinned theme colors (see `export_theme_config`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theme: Option<ThemeSettings>,
+    /// How pane output logs are written.
+    #[serde(default)]
+    pub pane_log: PaneLogSettings,
+    /// Blank cells left between neighboring panes.
+    #[serde(default)]
+    pub pane_gap: u16,
+    /// Rows of the status bar, top to bottom.
+    #[serde(default = "default_status_rows")]
+    pub status_rows: Vec<StatusRow>,
+    /// Draw on the normal screen instead of the alternate screen, so the outer
+    /// terminal's scrollback stays reachable.
+    #[serde(default)]
+    pub preserve_scrollback: bool,
+    /// How frames are written to the terminal; read at startup.
+    #[serde(default)]
+    pub render_backend: RenderBackend,
+    /// Commands run once the first tab opens, e.g. `split-horizontal top`
+    /// (see `parse_command`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub startup_commands: Vec<String>,
+    /// Multi-key bindings, e.g. `M-g s` for a git status pane.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub key_chords: Vec<KeyChord>,
+    /// Milliseconds to wait for the rest of a key chord once it's started.
+    #[serde(default = "default_chord_timeout_ms")]
+    pub chord_timeout_ms: u64,
+    /// Templates for `spawn-workspace`, besides the files in `templates_dir`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub workspace_templates: Vec<WorkspaceTemplate>,
+    /// Key bindings used when running inside another dmux.
+    #[serde(default)]
+    pub nested_keys: NestedKeys,
+    /// Copy the text of a completed mouse selection to the clipboard (OSC 52).
+    #[serde(default)]
+    pub copy_on_select: bool,
+    /// Characters that end a word, besides whitespace, for word motion and
+    /// double-click selection.
+    #[serde(default = "default_word_separators")]
+    pub word_separators: String,
+    /// What Reset Pane sends through a pane's terminal.
+    #[serde(default)]
+    pub pane_reset: PaneReset,
+    /// Seconds without output before a pane monitored for silence reports it.
+    #[serde(default = "default_silence_timeout_secs")]
+    pub silence_timeout_secs: u64,
+    /// Theme to adopt instead of the outer terminal's colors: an X resources
+    /// file or an iTerm2 `.itermcolors` plist.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub theme_file: Option<PathBuf>,
+    /// Hand theme changes of the outer terminal to every session, not just
+    /// the one in view, so sessions are themed right when switched to.
+    /// Sessions with their own theme keep it either way.
+    #[serde(default = "default_true")]
+    pub sync_theme_across_sessions: bool,
+    /// Treat the outer terminal as being in reverse video (swapping inherited
+    /// colors) or not, instead of asking it via DECRQM.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reverse_video: Option<bool>,
+    /// Leave empty cells unpainted so a translucent terminal background shows
+    /// through. Unset, this is guessed from what the terminal reports.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transparent_background: Option<bool>,
+    /// Background to assume when the outer terminal's can't be queried.
+    #[serde(default)]
+    pub fallback_background: FallbackBackground,
+    /// Milliseconds after startup to ask the outer terminal for its colors
+    /// again if it didn't answer at startup (0 disables the retry).
+    #[serde(default = "default_color_requery_delay_ms")]
+    pub color_requery_delay_ms: u64,
+    /// Shortest time in milliseconds between two queries of the outer
+    /// terminal's colors; re-queries asked for sooner wait for the next slot.
+    #[serde(default = "default_min_color_query_interval_ms")]
+    pub min_color_query_interval_ms: u64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            default_editor: EditorChoice::default(),
+            on_last_pane_exit: OnLastPaneExit::default(),
+            remain_on_exit: false,
+            restore_scroll_on_reattach: true,
+            detach_other_clients: false,
+            inherit_cursor_style: true,
+            theme: None,
+            pane_log: PaneLogSettings::default(),
+            pane_gap: 0,
+            status_rows: default_status_rows(),
+            preserve_scrollback: false,
+            render_backend: RenderBackend::default(),
+            startup_commands: Vec::new(),
+            key_chords: Vec::new(),
+            chord_timeout_ms: default_chord_timeout_ms(),
+            workspace_templates: Vec::new(),
+            nested_keys: NestedKeys::default(),
+            copy_on_select: false,
+            word_separators: default_word_separators(),
+            pane_reset: PaneReset::default(),
+            silence_timeout_secs: default_silence_timeout_secs(),
+            theme_file: None,
+            sync_theme_across_sessions: true,
+            reverse_video: None,
+            transparent_background: None,
+            fallback_background: FallbackBackground::default(),
+            color_requery_delay_ms: default_color_requery_delay_ms(),
+            min_color_query_interval_ms: default_min_color_query_interval_ms(),
+        }
+    }
+}
+
+/// Options applied to every pane that has an output log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PaneLogSettings {
+    /// Strip escape sequences so logs contain plain text.
+    pub strip_ansi: bool,
+    /// Rotate the log once it would grow past this many bytes.
+    pub max_bytes: Option<u64>,
+}
+
+impl Default for PaneLogSettings {
+    fn default() -> Self {
+        Self {
+            strip_ansi: false,
+            max_bytes: Some(10 * 1024 * 1024),
+        }
+    }
 }
 
 impl Settings {
@@ -191,6 +557,12 @@ impl Settings {
         Ok(())
     }
 
+    /// Directory of workspace template files, one JSON `WorkspaceTemplate`
+    /// per file.
+    pub fn templates_dir() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(APP_NAME).join(TEMPLATES_DIR))
+    }
+
     /// Get the path where settings are stored (for display to user).
     pub fn settings_path() -> Option<String> {
         Self::path().map(|p| p.to_string_lossy().to_string())
@@ -223,12 +595,123 @@ mod tests {
     fn settings_serialization() {
         let settings = Settings {
             default_editor: EditorChoice::Zed,
+            on_last_pane_exit: OnLastPaneExit::Respawn,
+            remain_on_exit: true,
+            restore_scroll_on_reattach: false,
+            detach_other_clients: true,
+            inherit_cursor_style: false,
+            theme: None,
+            pane_log: PaneLogSettings {
+                strip_ansi: true,
+                max_bytes: None,
+            },
+            pane_gap: 1,
+            status_rows: vec![StatusRow {
+                left: vec![StatusSegment::Mode],
+                center: vec![StatusSegment::Text("dev".to_string())],
+                right: vec![StatusSegment::Hints],
+            }],
+            preserve_scrollback: true,
+            render_backend: RenderBackend::Ansi,
+            startup_commands: vec!["split-vertical top".to_string()],
+            key_chords: vec![KeyChord {
+                keys: "M-g s".to_string(),
+                command: "split-vertical git status".to_string(),
+            }],
+            chord_timeout_ms: 750,
+            workspace_templates: vec![WorkspaceTemplate {
+                name: "dev".to_string(),
+                layout: TemplateLayout::Tiled,
+                panes: vec![PaneTemplate {
+                    command: Some("cargo watch -x test".to_string()),
+                    cwd: Some(PathBuf::from("packages/sandbox")),
+                    title: None,
+                }],
+            }],
+            nested_keys: NestedKeys::DoublePrefix,
+            copy_on_select: true,
+            word_separators: " /".to_string(),
+            pane_reset: PaneReset::Full,
+            silence_timeout_secs: 5,
+            theme_file: Some(PathBuf::from("/tmp/theme.itermcolors")),
+            sync_theme_across_sessions: false,
+            reverse_video: Some(true),
+            transparent_background: Some(false),
+            fallback_background: FallbackBackground::Ghostty,
+            color_requery_delay_ms: 1500,
+            min_color_query_interval_ms: 1000,
         };
 
         let json = serde_json::to_string_pretty(&settings).unwrap();
         let parsed: Settings = serde_json::from_str(&json).unwrap();
 
         assert_eq!(settings.default_editor, parsed.default_editor);
+        assert_eq!(settings.on_last_pane_exit, parsed.on_last_pane_exit);
+        assert_eq!(settings.remain_on_exit, parsed.remain_on_exit);
+        assert_eq!(
+            settings.restore_scroll_on_reattach,
+            parsed.restore_scroll_on_reattach
+        );
+        assert_eq!(settings.detach_other_clients, parsed.detach_other_clients);
+        assert_eq!(settings.inherit_cursor_style, parsed.inherit_cursor_style);
+        assert_eq!(settings.pane_log, parsed.pane_log);
+        assert_eq!(settings.pane_gap, parsed.pane_gap);
+        assert_eq!(settings.status_rows, parsed.status_rows);
+        assert_eq!(settings.startup_commands, parsed.startup_commands);
+        assert_eq!(settings.key_chords, parsed.key_chords);
+        assert_eq!(settings.chord_timeout_ms, parsed.chord_timeout_ms);
+        assert_eq!(settings.workspace_templates, parsed.workspace_templates);
+        assert_eq!(settings.nested_keys, parsed.nested_keys);
+        assert_eq!(settings.copy_on_select, parsed.copy_on_select);
+        assert_eq!(settings.word_separators, parsed.word_separators);
+        assert_eq!(settings.pane_reset, parsed.pane_reset);
+        assert_eq!(settings.silence_timeout_secs, parsed.silence_timeout_secs);
+        assert_eq!(settings.theme_file, parsed.theme_file);
+        assert_eq!(
+            settings.sync_theme_across_sessions,
+            parsed.sync_theme_across_sessions
+        );
+        assert_eq!(settings.reverse_video, parsed.reverse_video);
+        assert_eq!(
+            settings.transparent_background,
+            parsed.transparent_background
+        );
+        assert_eq!(settings.preserve_scrollback, parsed.preserve_scrollback);
+        assert_eq!(settings.render_backend, parsed.render_backend);
+        assert_eq!(settings.fallback_background, parsed.fallback_background);
+        assert_eq!(
+            settings.color_requery_delay_ms,
+            parsed.color_requery_delay_ms
+        );
+        assert_eq!(
+            settings.min_color_query_interval_ms,
+            parsed.min_color_query_interval_ms
+        );
+    }
+
+    #[test]
+    fn on_last_pane_exit_defaults_when_missing() {
+        let parsed: Settings = serde_json::from_str(r#"{"default_editor":"zed"}"#).unwrap();
+        assert_eq!(parsed.on_last_pane_exit, OnLastPaneExit::KeepEmpty);
+        assert!(parsed.inherit_cursor_style);
+        assert!(parsed.restore_scroll_on_reattach);
+        assert!(!parsed.detach_other_clients);
+        assert!(parsed.sync_theme_across_sessions);
+
+        let parsed: Settings =
+            serde_json::from_str(r#"{"on_last_pane_exit":"keep_empty"}"#).unwrap();
+        assert_eq!(parsed.on_last_pane_exit, OnLastPaneExit::KeepEmpty);
+    }
+
+    #[test]
+    fn pane_reset_defaults_to_soft() {
+        let parsed: Settings = serde_json::from_str("{}").unwrap();
+        assert_eq!(parsed.pane_reset, PaneReset::Soft);
+        assert_eq!(PaneReset::Soft.sequence(), b"\x1b[!p");
+
+        let parsed: Settings = serde_json::from_str(r#"{"pane_reset":"full"}"#).unwrap();
+        assert_eq!(parsed.pane_reset, PaneReset::Full);
+        assert_eq!(PaneReset::Full.sequence(), b"\x1bc");
     }
 
     #[test]
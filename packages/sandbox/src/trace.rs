@@ -0,0 +1,134 @@
+//! Optional `tracing` spans for diagnosing dmux in the field.
+//!
+//! With the `tracing` feature, `mux_span!` and `mux_event!` emit spans and
+//! events under the `dmux` target: the startup color query (with what it
+//! found), each pane spawn, and each rendered frame (whose duration appears
+//! when the span closes). Without it they expand to nothing and their
+//! arguments are never evaluated.
+//!
+//! Set `DMUX_TRACE_FILE` to a path to have `init_tracing_from_env` log there,
+//! since the terminal itself is busy drawing the UI.
+
+/// Variable naming the file `init_tracing_from_env` writes to.
+pub const TRACE_FILE_ENV: &str = "DMUX_TRACE_FILE";
+
+/// Stand-in for an entered span when the `tracing` feature is off.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoSpan;
+
+/// Enter a `dmux` span: `let _span = mux_span!("name", field = value);`.
+/// The span closes when the returned guard is dropped.
+#[cfg(feature = "tracing")]
+#[macro_export]
+macro_rules! mux_span {
+    ($($args:tt)*) => {
+        tracing::info_span!(target: "dmux", $($args)*).entered()
+    };
+}
+
+/// Enter a `dmux` span: `let _span = mux_span!("name", field = value);`.
+/// The span closes when the returned guard is dropped.
+#[cfg(not(feature = "tracing"))]
+#[macro_export]
+macro_rules! mux_span {
+    ($($args:tt)*) => {
+        $crate::trace::NoSpan
+    };
+}
+
+/// Emit a `dmux` event, with the same syntax as `tracing::info!`.
+#[cfg(feature = "tracing")]
+#[macro_export]
+macro_rules! mux_event {
+    ($($args:tt)*) => {
+        tracing::info!(target: "dmux", $($args)*)
+    };
+}
+
+/// Emit a `dmux` event, with the same syntax as `tracing::info!`.
+#[cfg(not(feature = "tracing"))]
+#[macro_export]
+macro_rules! mux_event {
+    ($($args:tt)*) => {{}};
+}
+
+/// A subscriber writing `dmux` spans and events, with span durations, to
+/// `path`. `RUST_LOG` overrides the default `dmux=info` filter. Keep the guard
+/// alive for as long as logging should continue; dropping it flushes.
+#[cfg(feature = "tracing")]
+pub fn file_subscriber(
+    path: &std::path::Path,
+) -> std::io::Result<(
+    impl tracing::Subscriber + Send + Sync,
+    tracing_appender::non_blocking::WorkerGuard,
+)> {
+    use tracing_subscriber::fmt::format::FmtSpan;
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::EnvFilter;
+
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    let (writer, guard) = tracing_appender::non_blocking(file);
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("dmux=info"));
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(false)
+        .with_span_events(FmtSpan::CLOSE);
+    Ok((
+        tracing_subscriber::registry().with(filter).with(layer),
+        guard,
+    ))
+}
+
+/// Install `file_subscriber(path)` as the global subscriber.
+#[cfg(feature = "tracing")]
+pub fn init_file_tracing(
+    path: &std::path::Path,
+) -> std::io::Result<tracing_appender::non_blocking::WorkerGuard> {
+    let (subscriber, guard) = file_subscriber(path)?;
+    tracing::subscriber::set_global_default(subscriber).map_err(std::io::Error::other)?;
+    Ok(guard)
+}
+
+/// Log to the file named by `DMUX_TRACE_FILE`, if it is set. Failures are
+/// reported on stderr, before the UI takes over the terminal.
+#[cfg(feature = "tracing")]
+pub fn init_tracing_from_env() -> Option<tracing_appender::non_blocking::WorkerGuard> {
+    let path = std::env::var_os(TRACE_FILE_ENV)?;
+    init_file_tracing(std::path::Path::new(&path))
+        .map_err(|e| eprintln!("Failed to start tracing to {:?}: {}", path, e))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn spans_reach_an_installed_subscriber() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("dmux.log");
+        let (subscriber, guard) = super::file_subscriber(&path).expect("subscriber");
+
+        tracing::subscriber::with_default(subscriber, || {
+            let _span = crate::mux_span!("render_frame", panes = 2);
+            crate::mux_event!(outcome = "drawn", "frame finished");
+        });
+        drop(guard);
+
+        let log = std::fs::read_to_string(&path).expect("log");
+        assert!(log.contains("render_frame"), "{log}");
+        assert!(log.contains("panes=2"), "{log}");
+        assert!(log.contains("frame finished"), "{log}");
+        assert!(log.contains("time.busy"), "{log}");
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    #[test]
+    fn macros_compile_to_nothing_without_the_feature() {
+        let span = crate::mux_span!("render_frame", panes = panic!("evaluated"));
+        crate::mux_event!(outcome = panic!("evaluated"));
+        assert_eq!(std::mem::size_of_val(&span), 0);
+    }
+}
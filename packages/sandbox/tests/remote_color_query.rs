@@ -0,0 +1,99 @@
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::thread;
+use std::time::Duration;
+
+use cmux_sandbox::mux::colors::{
+    query_outer_terminal_colors_remote, Passthrough, RemoteQueryOptions,
+};
+
+/// Simulated remote terminal on the far end of a socketpair. Answers OSC
+/// 10/11/12 queries after `latency`, splitting each reply in two to mimic a
+/// slow link, and ignores the first `drop_queries` queries entirely.
+fn spawn_remote_terminal(
+    mut peer: UnixStream,
+    latency: Duration,
+    mut drop_queries: usize,
+) -> thread::JoinHandle<usize> {
+    thread::spawn(move || {
+        let mut queries = 0;
+        let mut buf = [0u8; 256];
+        while let Ok(n @ 1..) = peer.read(&mut buf) {
+            let received = String::from_utf8_lossy(&buf[..n]).into_owned();
+            for (code, color) in [
+                (10, "ffff/ffff/ffff"),
+                (11, "1e1e/1e1e/2e2e"),
+                (12, "ffff/0000/0000"),
+            ] {
+                if !received.contains(&format!("]{};?", code)) {
+                    continue;
+                }
+                queries += 1;
+                if drop_queries > 0 {
+                    drop_queries -= 1;
+                    continue;
+                }
+                let reply = format!("\x1b]{};rgb:{}\x1b\\", code, color);
+                let (head, tail) = reply.as_bytes().split_at(reply.len() / 2);
+                thread::sleep(latency);
+                let _ = peer.write_all(head);
+                thread::sleep(latency / 2);
+                let _ = peer.write_all(tail);
+            }
+        }
+        queries
+    })
+}
+
+#[test]
+fn remote_query_survives_latency_and_stale_replies() {
+    let (local, mut peer) = UnixStream::pair().expect("socketpair");
+
+    // A late reply from some earlier query is still sitting in the pipe;
+    // without the pre-query drain it would be read as the foreground
+    peer.write_all(b"\x1b]11;rgb:0000/0000/0000\x1b\\")
+        .expect("write stale reply");
+
+    // Longer than the local query timeout, so only the idle timeout saves us
+    let remote = spawn_remote_terminal(peer, Duration::from_millis(250), 0);
+    let mut writer = local.try_clone().expect("clone socket");
+    let colors = query_outer_terminal_colors_remote(
+        &local,
+        &mut writer,
+        &RemoteQueryOptions {
+            idle_timeout: Duration::from_millis(500),
+            attempts: 1,
+            passthrough: Passthrough::None,
+        },
+    );
+    drop(writer);
+    drop(local);
+
+    assert_eq!(colors.foreground, Some((255, 255, 255)));
+    assert_eq!(colors.background, Some((30, 30, 46)));
+    assert_eq!(colors.cursor, Some((255, 0, 0)));
+    assert_eq!(remote.join().expect("remote thread"), 3);
+}
+
+#[test]
+fn remote_query_retries_unanswered_queries() {
+    let (local, peer) = UnixStream::pair().expect("socketpair");
+    let remote = spawn_remote_terminal(peer, Duration::from_millis(20), 1);
+    let mut writer = local.try_clone().expect("clone socket");
+    let colors = query_outer_terminal_colors_remote(
+        &local,
+        &mut writer,
+        &RemoteQueryOptions {
+            idle_timeout: Duration::from_millis(100),
+            attempts: 2,
+            passthrough: Passthrough::None,
+        },
+    );
+    drop(writer);
+    drop(local);
+
+    assert_eq!(colors.foreground, Some((255, 255, 255)));
+    assert_eq!(colors.background, Some((30, 30, 46)));
+    // The first foreground query was dropped and retried
+    assert_eq!(remote.join().expect("remote thread"), 4);
+}